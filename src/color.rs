@@ -0,0 +1,66 @@
+use std::{
+    env,
+    ffi::OsString,
+    io::{stdout, IsTerminal},
+};
+
+/// The colours output is highlighted with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Color {
+    Red,
+    Green,
+    Yellow,
+}
+
+impl Color {
+    /// text wrapped in the escape codes for this colour if enabled, or
+    /// unchanged if not.
+    pub(crate) fn paint(self, text: &str, enabled: bool) -> String {
+        if !enabled {
+            return text.to_string();
+        }
+
+        let code = match self {
+            Color::Red => 31,
+            Color::Green => 32,
+            Color::Yellow => 33,
+        };
+        return format!("\x1b[{}m{}\x1b[m", code, text);
+    }
+}
+
+/// Whether output to stdout should be coloured: only if it is a terminal,
+/// and neither --no-color nor the NO_COLOR environment variable say not to.
+pub(crate) fn stdout_color(no_color: bool) -> bool {
+    return color_enabled(no_color, env::var_os("NO_COLOR"), stdout().is_terminal());
+}
+
+/// Like stdout_color, but with the environment and terminal check given.
+/// NO_COLOR only counts if it is set to something, as its convention says.
+fn color_enabled(no_color: bool, no_color_env: Option<OsString>, is_terminal: bool) -> bool {
+    let env_disables = no_color_env.is_some_and(|value| !value.is_empty());
+    return is_terminal && !no_color && !env_disables;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_only_enabled_for_terminals() {
+        assert!(color_enabled(false, None, true));
+        assert!(color_enabled(false, Some(OsString::new()), true));
+        assert!(!color_enabled(false, None, false));
+        assert!(!color_enabled(true, None, true));
+        assert!(!color_enabled(false, Some(OsString::from("1")), true));
+    }
+
+    #[test]
+    fn test_paint_leaves_text_alone_when_disabled() {
+        assert_eq!(
+            "\x1b[32mmodified\x1b[m",
+            Color::Green.paint("modified", true)
+        );
+        assert_eq!("modified", Color::Green.paint("modified", false));
+    }
+}
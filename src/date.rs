@@ -0,0 +1,180 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::ValueEnum;
+
+/// The ways a commit's date can be shown, as git's `--date` names them.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq)]
+pub(crate) enum DateFormat {
+    /// Seconds since the epoch and the timezone, e.g. `1665000000 +0100`
+    Raw,
+    /// How long ago, e.g. `2 days ago`
+    Relative,
+    /// ISO 8601-like, e.g. `2022-10-05 21:00:00 +0100`
+    Iso,
+    /// Just the day, e.g. `2022-10-05`
+    Short,
+    /// Seconds since the epoch, e.g. `1665000000`
+    Unix,
+}
+
+impl DateFormat {
+    /// Show timestamp, in seconds since the epoch, in this format. The
+    /// date and time are those in timezone, an offset such as `+0100` as
+    /// commits record it.
+    pub(crate) fn format(self, timestamp: i64, timezone: &str) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or_default();
+        return self.format_at(timestamp, timezone, now);
+    }
+
+    /// Like format, but with the time relative dates count back from given.
+    fn format_at(self, timestamp: i64, timezone: &str, now: i64) -> String {
+        let local = timestamp + timezone_offset(timezone);
+        let (year, month, day) = civil_date(local.div_euclid(86400));
+        let seconds = local.rem_euclid(86400);
+
+        return match self {
+            DateFormat::Raw => format!("{} {}", timestamp, timezone),
+            DateFormat::Relative => relative_date(now - timestamp),
+            DateFormat::Iso => format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02} {}",
+                year,
+                month,
+                day,
+                seconds / 3600,
+                seconds / 60 % 60,
+                seconds % 60,
+                timezone
+            ),
+            DateFormat::Short => format!("{:04}-{:02}-{:02}", year, month, day),
+            DateFormat::Unix => timestamp.to_string(),
+        };
+    }
+}
+
+/// The offset in seconds of a timezone such as `+0100` or `-0730`, or 0 if
+/// it isn't one.
+fn timezone_offset(timezone: &str) -> i64 {
+    let (sign, digits) = match timezone.split_at_checked(1) {
+        Some(("+", digits)) => (1, digits),
+        Some(("-", digits)) => (-1, digits),
+        _ => return 0,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return 0;
+    }
+
+    let hours: i64 = digits[0..2].parse().unwrap_or_default();
+    let minutes: i64 = digits[2..4].parse().unwrap_or_default();
+    return sign * (hours * 3600 + minutes * 60);
+}
+
+/// The year, month and day of the given number of days since 1970-01-01,
+/// in the proleptic Gregorian calendar.
+fn civil_date(days: i64) -> (i64, i64, i64) {
+    // Count from 0000-03-01, so leap days fall at the end of each year
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    return (year, month, day);
+}
+
+/// How long ago something was, given the seconds since, rounded to the
+/// units git would use.
+fn relative_date(seconds: i64) -> String {
+    let ago = |count: i64, unit: &str| {
+        let plural = if count == 1 { "" } else { "s" };
+        return format!("{} {}{} ago", count, unit, plural);
+    };
+
+    if seconds < 0 {
+        return "in the future".to_string();
+    }
+    if seconds < 90 {
+        return ago(seconds, "second");
+    }
+    let minutes = (seconds + 30) / 60;
+    if minutes < 90 {
+        return ago(minutes, "minute");
+    }
+    let hours = (minutes + 30) / 60;
+    if hours < 36 {
+        return ago(hours, "hour");
+    }
+    let days = (hours + 12) / 24;
+    if days < 14 {
+        return ago(days, "day");
+    }
+    if days < 70 {
+        return ago((days + 3) / 7, "week");
+    }
+    if days < 365 {
+        return ago((days + 15) / 30, "month");
+    }
+    if days < 1825 {
+        let months = (days * 12 * 2 + 365) / (365 * 2);
+        return match months % 12 {
+            0 => ago(months / 12, "year"),
+            rest => {
+                let plural = if months / 12 == 1 { "" } else { "s" };
+                format!("{} year{}, {}", months / 12, plural, ago(rest, "month"))
+            }
+        };
+    }
+
+    return ago((days + 183) / 365, "year");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iso_date_is_in_the_commit_timezone() {
+        assert_eq!(
+            "2022-10-05 21:00:00 +0100",
+            DateFormat::Iso.format(1665000000, "+0100")
+        );
+        assert_eq!(
+            "2022-10-05 12:30:00 -0730",
+            DateFormat::Iso.format(1665000000, "-0730")
+        );
+        assert_eq!(
+            "1969-12-31 23:59:59 +0000",
+            DateFormat::Iso.format(-1, "+0000")
+        );
+        assert_eq!("2024-02-29", DateFormat::Short.format(1709208000, "+0000"));
+    }
+
+    #[test]
+    fn test_unix_date_ignores_the_timezone() {
+        assert_eq!("1665000000", DateFormat::Unix.format(1665000000, "+0100"));
+        assert_eq!("1665000000", DateFormat::Unix.format(1665000000, "-0730"));
+    }
+
+    #[test]
+    fn test_relative_date_rounds_like_git() {
+        let now = 1665000000;
+        let relative = |seconds| DateFormat::Relative.format_at(now - seconds, "+0000", now);
+
+        assert_eq!("1 second ago", relative(1));
+        assert_eq!("2 days ago", relative(2 * 86400));
+        assert_eq!("3 weeks ago", relative(21 * 86400));
+        assert_eq!("1 year, 6 months ago", relative(548 * 86400));
+        assert_eq!("in the future", relative(-10));
+    }
+}
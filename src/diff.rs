@@ -0,0 +1,365 @@
+use std::{collections::BTreeMap, fmt};
+
+/// Lines of unchanged context shown around each change in a unified diff.
+const CONTEXT: usize = 3;
+
+/// One step in turning the old lines into the new ones.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Edit<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+/// A path whose blob differs between two trees.
+#[derive(Debug, PartialEq)]
+pub(crate) enum TreeChange {
+    Added(String),
+    Deleted(String),
+    Modified(String),
+}
+
+impl fmt::Display for TreeChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            TreeChange::Added(path) => write!(f, "A\t{}", path),
+            TreeChange::Deleted(path) => write!(f, "D\t{}", path),
+            TreeChange::Modified(path) => write!(f, "M\t{}", path),
+        };
+    }
+}
+
+/// Diff two lists of lines by finding a shortest edit script, with Myers'
+/// algorithm.
+///
+/// Lines the two lists start and end with are matched up front, and the
+/// rest is split around the middle of a shortest script recursively, so
+/// this takes space proportional to the total length and time to that
+/// times the number of edits.
+pub(crate) fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Edit<'a>> {
+    let mut edits = Vec::with_capacity(old.len().max(new.len()));
+    diff_between(old, new, &mut edits);
+
+    // Like git, show the lines removed from a changed block before those
+    // added in their place
+    let mut start = 0;
+    while start < edits.len() {
+        let end = edits[start..]
+            .iter()
+            .position(|edit| matches!(edit, Edit::Equal(_)))
+            .map_or(edits.len(), |len| start + len);
+        edits[start..end].sort_by_key(|edit| matches!(edit, Edit::Insert(_)));
+        start = end + 1;
+    }
+
+    return edits;
+}
+
+/// Push the edits turning old into new onto edits.
+fn diff_between<'a>(old: &[&'a str], new: &[&'a str], edits: &mut Vec<Edit<'a>>) {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old_rest, new_rest) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    edits.extend(old[..prefix].iter().map(|line| Edit::Equal(line)));
+    if old_rest.is_empty() || new_rest.is_empty() {
+        edits.extend(old_rest.iter().map(|line| Edit::Delete(line)));
+        edits.extend(new_rest.iter().map(|line| Edit::Insert(line)));
+    } else {
+        let (x, y) = middle_snake(old_rest, new_rest);
+        diff_between(&old_rest[..x], &new_rest[..y], edits);
+        diff_between(&old_rest[x..], &new_rest[y..], edits);
+    }
+    edits.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| Edit::Equal(line)),
+    );
+}
+
+/// Where a shortest edit script from old to new, which must both be
+/// non-empty and differ in their first and last lines, can be split in
+/// two: a point reached by following it about halfway from either end.
+fn middle_snake(old: &[&str], new: &[&str]) -> (usize, usize) {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let delta = n - m;
+    let odd = delta % 2 != 0;
+
+    // forward[k] is how far along old the furthest path from the start on
+    // diagonal k (x - y = k) reaches, and backward[k] the same for paths
+    // from the end, counted back from it
+    let max_d = (n + m + 1) / 2 + 1;
+    let offset = max_d + 1;
+    let mut forward = vec![0isize; (2 * offset + 1) as usize];
+    let mut backward = vec![0isize; (2 * offset + 1) as usize];
+    let at = |k: isize| (k + offset) as usize;
+
+    for d in 0..max_d {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = match k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                true => forward[at(k + 1)],
+                false => forward[at(k - 1)] + 1,
+            };
+            let mut y = x - k;
+            let start = (x, y);
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[at(k)] = x;
+
+            if odd && (k - delta).abs() < d && x + backward[at(delta - k)] >= n {
+                return (start.0 as usize, start.1 as usize);
+            }
+        }
+
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = match k == -d || (k != d && backward[at(k - 1)] < backward[at(k + 1)]) {
+                true => backward[at(k + 1)],
+                false => backward[at(k - 1)] + 1,
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[(n - x - 1) as usize] == new[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[at(k)] = x;
+
+            if !odd && (k - delta).abs() <= d && x + forward[at(delta - k)] >= n {
+                return ((n - x) as usize, (m - y) as usize);
+            }
+        }
+    }
+
+    unreachable!("paths from either end always meet");
+}
+
+/// A unified diff from old to new, or an empty string if they are the same.
+pub(crate) fn unified_diff(old: &str, new: &str, old_name: &str, new_name: &str) -> String {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let edits = diff_lines(&old_lines, &new_lines);
+
+    // Group changes into hunks, merging those whose context would overlap
+    let mut hunks: Vec<(usize, usize)> = Vec::new();
+    for (i, edit) in edits.iter().enumerate() {
+        if matches!(edit, Edit::Equal(_)) {
+            continue;
+        }
+
+        match hunks.last_mut() {
+            Some((_, end)) if i - *end <= 2 * CONTEXT + 1 => *end = i,
+            _ => hunks.push((i, i)),
+        }
+    }
+
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let mut out = format!("--- {}\n+++ {}\n", old_name, new_name);
+    for (first, last) in hunks {
+        let start = first.saturating_sub(CONTEXT);
+        let end = (last + CONTEXT + 1).min(edits.len());
+
+        let in_old = |edit: &&Edit| !matches!(edit, Edit::Insert(_));
+        let in_new = |edit: &&Edit| !matches!(edit, Edit::Delete(_));
+        out += &format!(
+            "@@ -{} +{} @@\n",
+            hunk_range(
+                edits[..start].iter().filter(in_old).count(),
+                edits[start..end].iter().filter(in_old).count()
+            ),
+            hunk_range(
+                edits[..start].iter().filter(in_new).count(),
+                edits[start..end].iter().filter(in_new).count()
+            )
+        );
+
+        for edit in &edits[start..end] {
+            let (prefix, line) = match edit {
+                Edit::Equal(line) => (' ', line),
+                Edit::Delete(line) => ('-', line),
+                Edit::Insert(line) => ('+', line),
+            };
+            out.push(prefix);
+            out += line;
+            if !line.ends_with('\n') {
+                out += "\n\\ No newline at end of file\n";
+            }
+        }
+    }
+
+    return out;
+}
+
+/// Format one side of a hunk header, given the number of lines before the
+/// hunk and the number in it. Like git, a single line omits its count, and
+/// an empty side names the line before it.
+fn hunk_range(before: usize, len: usize) -> String {
+    return match len {
+        0 => format!("{},0", before),
+        1 => format!("{}", before + 1),
+        _ => format!("{},{}", before + 1, len),
+    };
+}
+
+/// Compare two flattened trees, mapping paths to modes and SHAs, returning
+/// the changes in path order. A change of mode alone counts as modified.
+pub(crate) fn tree_changes(
+    old: &BTreeMap<String, (String, String)>,
+    new: &BTreeMap<String, (String, String)>,
+) -> Vec<TreeChange> {
+    let mut changes = Vec::new();
+    for (path, entry) in old {
+        match new.get(path) {
+            None => changes.push(TreeChange::Deleted(path.clone())),
+            Some(new_entry) if new_entry != entry => {
+                changes.push(TreeChange::Modified(path.clone()))
+            }
+            Some(_) => {}
+        }
+    }
+    for path in new.keys() {
+        if !old.contains_key(path) {
+            changes.push(TreeChange::Added(path.clone()));
+        }
+    }
+
+    changes.sort_by(|a, b| change_path(a).cmp(change_path(b)));
+    return changes;
+}
+
+fn change_path(change: &TreeChange) -> &String {
+    return match change {
+        TreeChange::Added(path) | TreeChange::Deleted(path) | TreeChange::Modified(path) => path,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff_of_added_line() {
+        let old = "one\ntwo\nthree\nfour\nfive\nsix\n";
+        let new = "one\ntwo\nthree\nthree and a half\nfour\nfive\nsix\n";
+
+        assert_eq!(
+            "--- a/numbers\n+++ b/numbers\n\
+             @@ -1,6 +1,7 @@\n one\n two\n three\n+three and a half\n four\n five\n six\n",
+            unified_diff(old, new, "a/numbers", "b/numbers")
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_splits_distant_changes() {
+        let old: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let new: String = (1..=20)
+            .map(|i| match i {
+                2 => "two\n".to_string(),
+                19 => "nineteen\n".to_string(),
+                _ => format!("{}\n", i),
+            })
+            .collect();
+
+        let diff = unified_diff(&old, &new, "a", "b");
+
+        assert!(
+            diff.contains("@@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n"),
+            "{}",
+            diff
+        );
+        assert!(diff.contains("@@ -16,5 +16,5 @@\n"), "{}", diff);
+    }
+
+    #[test]
+    fn test_diff_lines_finds_shortest_scripts() {
+        // Small pseudo-random lists over a few distinct lines, so that they
+        // share plenty, checked against the longest common subsequence
+        let mut seed = 1u64;
+        let mut lines = |len: u64| -> Vec<&'static str> {
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                    ["a\n", "b\n", "c\n", "d\n"][(seed >> 33) as usize % 4]
+                })
+                .collect()
+        };
+        for case in 0..200 {
+            let old = lines(case % 13);
+            let new = lines(case % 11);
+
+            let mut lcs = vec![vec![0; new.len() + 1]; old.len() + 1];
+            for i in (0..old.len()).rev() {
+                for j in (0..new.len()).rev() {
+                    lcs[i][j] = match old[i] == new[j] {
+                        true => lcs[i + 1][j + 1] + 1,
+                        false => lcs[i + 1][j].max(lcs[i][j + 1]),
+                    };
+                }
+            }
+
+            let edits = diff_lines(&old, &new);
+            let (mut from, mut to, mut equal) = (vec![], vec![], 0);
+            for edit in &edits {
+                match edit {
+                    Edit::Equal(line) => {
+                        from.push(*line);
+                        to.push(*line);
+                        equal += 1;
+                    }
+                    Edit::Delete(line) => from.push(*line),
+                    Edit::Insert(line) => to.push(*line),
+                }
+            }
+            assert_eq!((old.clone(), new.clone()), (from, to));
+            assert_eq!(lcs[0][0], equal, "{:?} -> {:?}", old, new);
+        }
+    }
+
+    #[test]
+    fn test_diff_lines_of_long_files() {
+        let old: Vec<String> = (0..100_000).map(|i| format!("{}\n", i)).collect();
+        let mut new = old.clone();
+        new[50_000] = "changed\n".to_string();
+        new.insert(10, "inserted\n".to_string());
+        let old: Vec<&str> = old.iter().map(String::as_str).collect();
+        let new: Vec<&str> = new.iter().map(String::as_str).collect();
+
+        let changes: Vec<Edit> = diff_lines(&old, &new)
+            .into_iter()
+            .filter(|edit| !matches!(edit, Edit::Equal(_)))
+            .collect();
+
+        assert_eq!(
+            vec![
+                Edit::Insert("inserted\n"),
+                Edit::Delete("50000\n"),
+                Edit::Insert("changed\n")
+            ],
+            changes
+        );
+    }
+
+    #[test]
+    fn test_unified_diff_of_identical_content_is_empty() {
+        assert_eq!("", unified_diff("same\n", "same\n", "a", "b"));
+    }
+
+    #[test]
+    fn test_unified_diff_marks_missing_newline() {
+        assert_eq!(
+            "--- a\n+++ b\n@@ -1 +1 @@\n-old\n\\ No newline at end of file\n+new\n",
+            unified_diff("old", "new\n", "a", "b")
+        );
+    }
+}
@@ -1,4 +1,5 @@
 pub mod git_blob;
 pub mod git_commit;
 pub mod git_object;
+pub mod git_tag;
 pub mod git_tree;
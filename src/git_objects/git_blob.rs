@@ -1,6 +1,9 @@
 use std::any::Any;
 
-use crate::{git_objects::git_object::GitObjectData, repository::repository::Repository};
+use crate::{
+    git_objects::git_object::{GitObjectData, ObjectKind},
+    repository::repository::Repository,
+};
 
 use super::git_object::GitSerDe;
 
@@ -9,24 +12,36 @@ pub(crate) struct Blob {
     data: GitObjectData,
 }
 
+impl Blob {
+    /// The blob's content, borrowed rather than copied.
+    pub(crate) fn bytes(&self) -> &[u8] {
+        return &self.data.1;
+    }
+
+    /// The size of the blob's content in bytes.
+    pub(crate) fn len(&self) -> usize {
+        return self.data.1.len();
+    }
+}
+
 impl GitSerDe for Blob {
     fn serialize(&self) -> GitObjectData {
-        let GitObjectData(object_type, data) = &self.data;
-        return GitObjectData(object_type.clone(), data.clone());
+        let GitObjectData(kind, data) = &self.data;
+        return GitObjectData(*kind, data.clone());
     }
 
     fn deserialize(&mut self, data: GitObjectData) {
         self.data = data;
     }
 
-    fn get_repo(&self) -> &Repository {
-        return self.repo.as_ref().expect("No repo set");
+    fn repo(&self) -> Option<&Repository> {
+        return self.repo.as_ref();
     }
 
     fn new(repo: Option<Repository>, data: GitObjectData) -> Blob {
         let mut blob = Blob {
             repo,
-            data: GitObjectData("blob".to_string(), vec![]),
+            data: GitObjectData(ObjectKind::Blob, vec![]),
         };
 
         blob.deserialize(data);
@@ -37,3 +52,19 @@ impl GitSerDe for Blob {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes_and_len() {
+        let blob = Blob::new(
+            None,
+            GitObjectData(ObjectKind::Blob, b"Hello, world!\n".to_vec()),
+        );
+
+        assert_eq!(14, blob.len());
+        assert_eq!(b"Hello, world!\n", blob.bytes());
+    }
+}
@@ -1,6 +1,9 @@
 use std::{any::Any, collections::BTreeMap};
 
-use crate::{git_objects::git_object::GitObjectData, repository::repository::Repository};
+use crate::{
+    git_objects::git_object::{GitObjectData, KvlmParseError, ObjectKind},
+    repository::repository::Repository,
+};
 
 use super::git_object::GitSerDe;
 
@@ -10,30 +13,119 @@ pub(crate) struct Commit {
 }
 
 impl Commit {
+    /// Parse a commit, failing rather than panicking if it is malformed.
+    pub(crate) fn from_data(
+        repo: Option<Repository>,
+        data: GitObjectData,
+    ) -> Result<Commit, KvlmParseError> {
+        let kvlm = data.kvlm_parse(None, None)?;
+        return Ok(Commit { repo, kvlm });
+    }
+
     pub(crate) fn has_parent(&self) -> bool {
         return self.kvlm.contains_key("parent");
     }
 
-    pub(crate) fn parents(&self) -> &Vec<String> {
-        return self.kvlm.get("parent").unwrap();
+    /// The SHA of the commit's tree, if it names one.
+    pub(crate) fn tree(&self) -> Option<&str> {
+        return self.kvlm.get("tree").map(|tree| tree[0].as_str());
     }
-}
 
-impl GitSerDe for Commit {
-    fn new(repo: Option<Repository>, data: GitObjectData) -> Commit {
-        let mut commit = Commit {
-            repo: repo,
-            kvlm: BTreeMap::new(),
+    /// The parent SHAs, which are empty for a root commit.
+    pub(crate) fn parents(&self) -> &[String] {
+        return self
+            .kvlm
+            .get("parent")
+            .map(|parents| parents.as_slice())
+            .unwrap_or_default();
+    }
+
+    /// The commit message, or an empty string if there is none.
+    pub(crate) fn message(&self) -> &str {
+        return self
+            .kvlm
+            .get("")
+            .map(|message| message[0].as_str())
+            .unwrap_or_default();
+    }
+
+    /// The author's name and email, e.g. `A U Thor <author@example.com>`.
+    pub(crate) fn author(&self) -> &str {
+        return self.signature("author").0;
+    }
+
+    /// The committer's name and email.
+    pub(crate) fn committer(&self) -> &str {
+        return self.signature("committer").0;
+    }
+
+    /// The author timestamp and timezone as git's `--date=raw` shows them,
+    /// e.g. `1665000120 +0100`, or an empty string if the commit has no
+    /// well-formed author line.
+    pub(crate) fn author_date(&self) -> &str {
+        let (identity, _, _) = self.signature("author");
+        return self
+            .kvlm
+            .get("author")
+            .and_then(|values| values[0].get(identity.len() + 1..))
+            .unwrap_or_default();
+    }
+
+    /// The author timestamp in seconds since the epoch, or 0 if the commit
+    /// has no well-formed author line.
+    pub(crate) fn author_time(&self) -> i64 {
+        return self.signature("author").1;
+    }
+
+    /// The author's timezone offset, e.g. `+0100`, or an empty string if
+    /// the commit has no well-formed author line.
+    pub(crate) fn author_timezone(&self) -> &str {
+        return self.signature("author").2;
+    }
+
+    /// The committer timestamp in seconds since the epoch, or 0 if the
+    /// commit has no well-formed committer line.
+    pub(crate) fn commit_time(&self) -> i64 {
+        return self.signature("committer").1;
+    }
+
+    /// The ASCII-armoured GPG signature from the `gpgsig` header, if the
+    /// commit is signed.
+    pub(crate) fn gpg_signature(&self) -> Option<&str> {
+        return self.kvlm.get("gpgsig").map(|values| values[0].as_str());
+    }
+
+    /// The bytes the signature was made over: the commit as stored, less
+    /// its `gpgsig` header.
+    pub(crate) fn signed_payload(&self) -> Vec<u8> {
+        let mut kvlm = self.kvlm.clone();
+        kvlm.remove("gpgsig");
+        return GitObjectData::kvlm_serialize(ObjectKind::Commit, &kvlm).1;
+    }
+
+    /// Split a signature line, `<name> <<email>> <timestamp> <timezone>`,
+    /// into the identity, the timestamp and the timezone.
+    fn signature(&self, key: &str) -> (&str, i64, &str) {
+        let Some(line) = self.kvlm.get(key).map(|values| values[0].as_str()) else {
+            return ("", 0, "");
         };
 
-        commit.deserialize(data);
+        let mut parts = line.rsplitn(3, ' ');
+        let timezone = parts.next().unwrap_or_default();
+        return match (parts.next().map(str::parse), parts.next()) {
+            (Some(Ok(timestamp)), Some(identity)) => (identity, timestamp, timezone),
+            _ => (line, 0, ""),
+        };
+    }
+}
 
-        return commit;
+impl GitSerDe for Commit {
+    fn new(repo: Option<Repository>, data: GitObjectData) -> Commit {
+        return Commit::from_data(repo, data).expect("Could not parse the kvlm object.");
     }
 
     fn serialize(&self) -> GitObjectData {
-        let GitObjectData(_, data) = GitObjectData::kvlm_serialize(&self.kvlm);
-        return GitObjectData("commit".to_string(), data);
+        return GitObjectData::kvlm_serialize(ObjectKind::Commit, &self.kvlm);
     }
 
     fn deserialize(&mut self, data: GitObjectData) {
@@ -42,11 +134,37 @@ impl GitSerDe for Commit {
             .expect("Could not parse the kvlm object.");
     }
 
-    fn get_repo(&self) -> &Repository {
-        return self.repo.as_ref().expect("No repo set");
+    fn repo(&self) -> Option<&Repository> {
+        return self.repo.as_ref();
     }
 
     fn as_any(&self) -> &dyn Any {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use super::*;
+
+    #[test]
+    fn test_parses_merge_commit() {
+        let data =
+            fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/merge-commit"))
+                .unwrap();
+
+        let commit = Commit::new(None, GitObjectData(ObjectKind::Commit, data));
+
+        assert_eq!(
+            [
+                "7986eac7175b225734b71e501ff78b8146b1a54e",
+                "2d802a23df5fb8c4a03b3935ae3f11cb78105cf6"
+            ],
+            commit.parents()
+        );
+        assert_eq!("A U Thor <author@example.com>", commit.author());
+        assert_eq!("1665000120 +0100", commit.author_date());
+    }
+}
@@ -1,67 +1,233 @@
-use std::{any::Any, collections::BTreeMap, fs::File, io::Write, string::FromUtf8Error};
+use std::{
+    any::Any,
+    collections::BTreeMap,
+    fmt,
+    fs::{self, File},
+    io::Write,
+    process,
+    str::FromStr,
+    string::FromUtf8Error,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use crypto::{digest::Digest, sha1::Sha1};
-use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
 
 use crate::{
-    git_objects::{git_blob::Blob, git_commit::Commit, git_tree::Tree},
-    repository::repository::Repository,
+    git_objects::{git_blob::Blob, git_commit::Commit, git_tag::Tag, git_tree::Tree},
+    repository::repository::{HashAlgo, ReadObjectErrorType, Repository},
 };
 
 pub(crate) struct GitObject {}
 
+/// Distinguishes the temporary files of objects being written at once.
+static TEMP_OBJECT_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// The header fields of commits and tags in the order git writes them.
+const KVLM_KEY_ORDER: [&str; 11] = [
+    "tree",
+    "parent",
+    "object",
+    "type",
+    "tag",
+    "author",
+    "committer",
+    "tagger",
+    "encoding",
+    "mergetag",
+    "gpgsig",
+];
+
+/// The names an `encoding` header may give Latin-1 by, compared ignoring
+/// case. Each Latin-1 byte is the Unicode code point of the same value.
+const LATIN1_ENCODINGS: [&str; 4] = ["ISO-8859-1", "ISO8859-1", "latin1", "latin-1"];
+
+/// Whether kvlm declares its message to be Latin-1 rather than UTF-8.
+fn is_latin1(kvlm: &BTreeMap<String, Vec<String>>) -> bool {
+    return kvlm.get("encoding").is_some_and(|encoding| {
+        LATIN1_ENCODINGS
+            .iter()
+            .any(|name| encoding[0].eq_ignore_ascii_case(name))
+    });
+}
+
+/// The types of object git stores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum ObjectKind {
+    Blob,
+    Commit,
+    Tree,
+    Tag,
+}
+
+impl ObjectKind {
+    /// The name git uses for the kind, as in an object's header.
+    pub(crate) fn as_str(&self) -> &'static str {
+        return match self {
+            ObjectKind::Blob => "blob",
+            ObjectKind::Commit => "commit",
+            ObjectKind::Tree => "tree",
+            ObjectKind::Tag => "tag",
+        };
+    }
+}
+
+impl FromStr for ObjectKind {
+    type Err = ReadObjectErrorType;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return match s {
+            "blob" => Ok(ObjectKind::Blob),
+            "commit" => Ok(ObjectKind::Commit),
+            "tree" => Ok(ObjectKind::Tree),
+            "tag" => Ok(ObjectKind::Tag),
+            _ => Err(ReadObjectErrorType::UnknownObjectKindError(s.to_string())),
+        };
+    }
+}
+
+impl fmt::Display for ObjectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Padding rather than writing, so widths like {:6} line columns up
+        return f.pad(self.as_str());
+    }
+}
+
 #[derive(Debug)]
-pub(crate) struct GitObjectData(pub String, pub Vec<u8>);
+pub(crate) struct GitObjectData(pub ObjectKind, pub Vec<u8>);
+
+/// Why a commit or tag's content could not be parsed.
+#[derive(Debug, PartialEq)]
+pub(crate) enum KvlmParseError {
+    InvalidUtf8(FromUtf8Error),
+    /// The header starting at this offset has no terminating newline
+    Unterminated(usize),
+    /// The line starting at this offset is neither a header nor the blank
+    /// line before the message
+    InvalidHeader(usize),
+}
 
-impl GitObject {
-    pub(crate) fn new(repo: Option<Repository>, data: Option<GitObjectData>) -> Box<dyn GitSerDe> {
-        match data {
-            Some(data) => {
-                let GitObjectData(object_type, data) = data;
-                let boxed: Box<dyn GitSerDe> = match object_type.as_str() {
-                    "blob" => Box::new(Blob::new(repo, GitObjectData(object_type, data))),
-                    "commit" => Box::new(Commit::new(repo, GitObjectData(object_type, data))),
-                    "tree" => Box::new(Tree::new(repo, GitObjectData(object_type, data))),
-                    _ => panic!(),
-                };
+impl From<FromUtf8Error> for KvlmParseError {
+    fn from(e: FromUtf8Error) -> Self {
+        return KvlmParseError::InvalidUtf8(e);
+    }
+}
 
-                return boxed;
+impl fmt::Display for KvlmParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            KvlmParseError::InvalidUtf8(e) => write!(f, "invalid UTF-8: {}", e),
+            KvlmParseError::Unterminated(offset) => {
+                write!(f, "unterminated header at offset {}", offset)
             }
-            None => panic!(),
-        }
+            KvlmParseError::InvalidHeader(offset) => {
+                write!(f, "invalid header at offset {}", offset)
+            }
+        };
     }
+}
 
-    pub(crate) fn write_object(obj: Box<dyn GitSerDe>, actually_write: Option<bool>) -> String {
-        let GitObjectData(fmt, data_vec) = obj.serialize();
+impl GitObject {
+    #[allow(clippy::new_ret_no_self)]
+    pub(crate) fn new(repo: Option<Repository>, data: GitObjectData) -> Box<dyn GitSerDe> {
+        return match data.0 {
+            ObjectKind::Blob => Box::new(Blob::new(repo, data)),
+            ObjectKind::Commit => Box::new(Commit::new(repo, data)),
+            ObjectKind::Tree => Box::new(Tree::new(repo, data)),
+            ObjectKind::Tag => Box::new(Tag::new(repo, data)),
+        };
+    }
 
-        let data_string = String::from_utf8(data_vec.to_vec()).unwrap();
-        let data_length = data_string.len();
+    /// Hash obj, and unless actually_write is Some(false), store it as a
+    /// loose object, returning its SHA.
+    pub(crate) fn write_object(
+        obj: Box<dyn GitSerDe>,
+        actually_write: Option<bool>,
+    ) -> Result<String, ReadObjectErrorType> {
+        let GitObjectData(kind, data_vec) = obj.serialize();
 
         let result = [
-            fmt.as_bytes(),
-            &[b' '],
-            data_length.to_string().as_bytes(),
-            &[b'\x00'],
-            data_string.as_bytes(),
+            kind.as_str().as_bytes(),
+            b" ",
+            data_vec.len().to_string().as_bytes(),
+            b"\x00",
+            data_vec.as_slice(),
         ]
         .concat();
 
-        let mut sha = Sha1::new();
-        sha.input(&result);
-        let hash = sha.result_str();
+        // Objects outside a repository can only be hashed, and get git's
+        // default algorithm
+        let hash = obj
+            .repo()
+            .map_or(HashAlgo::Sha1, |repo| repo.hash_algo())
+            .digest(&result);
 
         match actually_write {
             Some(false) => {}
             _ => {
-                let path = obj
-                    .get_repo()
-                    .repo_file(&["objects", &hash[0..2], &hash[2..]], Some(true));
-                let f = File::create(path).unwrap();
-                ZlibDecoder::new(f).write_all(&result).unwrap();
+                // Objects are named by their content, so one already stored,
+                // loose or packed, needn't be written again
+                if obj.get_repo().has_object(&hash) {
+                    return Ok(hash);
+                }
+
+                let compression = obj.get_repo().compression()?;
+                let path = obj.get_repo().loose_object_path(&hash);
+                let dir = path.parent().unwrap();
+                fs::create_dir_all(dir).map_err(ReadObjectErrorType::IO)?;
+
+                // Write to a temporary file and rename it into place, so a
+                // concurrent reader or writer never sees part of an object.
+                // A temporary file left by a failed write is removed.
+                let temp = path.with_file_name(format!(
+                    "tmp_obj_{}_{}",
+                    process::id(),
+                    TEMP_OBJECT_COUNTER.fetch_add(1, Ordering::Relaxed)
+                ));
+                let written = File::create(&temp).and_then(|f| {
+                    let mut encoder = ZlibEncoder::new(f, compression);
+                    encoder.write_all(&result)?;
+                    encoder.finish()?;
+                    return fs::rename(&temp, &path);
+                });
+                if let Err(e) = written {
+                    let _ = fs::remove_file(&temp);
+                    return Err(ReadObjectErrorType::IO(e));
+                }
             }
         };
 
-        return hash;
+        return Ok(hash);
+    }
+}
+
+/// An object parsed into its concrete type, for callers which would
+/// rather match on it than downcast a `Box<dyn GitSerDe>`.
+pub(crate) enum ParsedObject {
+    Blob(Blob),
+    Tree(Tree),
+    Commit(Commit),
+    Tag(Tag),
+}
+
+impl ParsedObject {
+    /// The kind of object this is.
+    pub(crate) fn kind(&self) -> ObjectKind {
+        return match self {
+            ParsedObject::Blob(_) => ObjectKind::Blob,
+            ParsedObject::Tree(_) => ObjectKind::Tree,
+            ParsedObject::Commit(_) => ObjectKind::Commit,
+            ParsedObject::Tag(_) => ObjectKind::Tag,
+        };
+    }
+
+    /// The object as a trait object, as returned by `read_object`.
+    pub(crate) fn into_boxed(self) -> Box<dyn GitSerDe> {
+        return match self {
+            ParsedObject::Blob(blob) => Box::new(blob),
+            ParsedObject::Tree(tree) => Box::new(tree),
+            ParsedObject::Commit(commit) => Box::new(commit),
+            ParsedObject::Tag(tag) => Box::new(tag),
+        };
     }
 }
 
@@ -71,23 +237,19 @@ impl GitObjectData {
         &self,
         start: Option<usize>,
         dct: Option<BTreeMap<String, Vec<String>>>,
-    ) -> Result<BTreeMap<String, Vec<String>>, FromUtf8Error> {
-        let mut dict = match dct {
-            None => BTreeMap::new(),
-            Some(d) => d,
-        };
-
-        let start = match start {
-            None => 0,
-            Some(s) => s,
-        };
+    ) -> Result<BTreeMap<String, Vec<String>>, KvlmParseError> {
+        let mut dict = dct.unwrap_or_default();
+        let start = start.unwrap_or_default();
 
         let GitObjectData(_, data_vec) = self;
-        let line: Vec<u8> = data_vec.iter().skip(start).cloned().collect();
-        let mut _spc = line.iter().position(|b| b == &b' ');
-        let spc = *_spc.get_or_insert(start) + start;
-        let mut _nl = line.iter().position(|b| b == &b'\n');
-        let nl = *_nl.get_or_insert(start) + start;
+        let rest = data_vec.get(start..).unwrap_or_default();
+        let spc = rest.iter().position(|b| b == &b' ').map(|pos| start + pos);
+        // Every line, header or blank, must end in a newline
+        let nl = rest
+            .iter()
+            .position(|b| b == &b'\n')
+            .map(|pos| start + pos)
+            .ok_or(KvlmParseError::Unterminated(start))?;
 
         // If space appears before a newline, we have a keyword and there's
         // still more to parse.
@@ -95,19 +257,27 @@ impl GitObjectData {
         // Base case
         // ---------
         //
-        // If newline appears first (or there's no space at all, in which case position() returns None), we
-        // assume a blank line. A blank line means the remainder of the data is the message.
-        let parsing_message = nl < spc;
-
-        if parsing_message {
-            assert!(nl == start);
-            dict.insert(
-                String::from(""),
-                vec![String::from_utf8(data_vec[start + 1..].to_vec())?],
-            );
+        // If newline appears first (or there's no space at all), we assume a
+        // blank line. A blank line means the remainder of the data is the
+        // message.
+        let spc = match spc {
+            Some(spc) if spc < nl => spc,
+            _ => {
+                if nl != start {
+                    return Err(KvlmParseError::InvalidHeader(start));
+                }
+                // The message is in the encoding the headers declare, which
+                // is UTF-8 unless they say otherwise
+                let message = &data_vec[start + 1..];
+                let message = match is_latin1(&dict) {
+                    true => message.iter().map(|b| *b as char).collect(),
+                    false => String::from_utf8(message.to_vec())?,
+                };
+                dict.insert(String::from(""), vec![message]);
 
-            return Ok(dict);
-        }
+                return Ok(dict);
+            }
+        };
 
         // Recursive case
         // --------------
@@ -124,11 +294,11 @@ impl GitObjectData {
                 .iter()
                 .skip(end + 1)
                 .position(|b| b == &b'\n')
-                .unwrap()
+                .ok_or(KvlmParseError::Unterminated(start))?
                 + end
                 + 1;
 
-            if data_vec[end + 1] != b' ' {
+            if data_vec.get(end + 1) != Some(&b' ') {
                 break;
             }
         }
@@ -139,31 +309,55 @@ impl GitObjectData {
 
         // Don't overwrite existing data contents
         dict.entry(key)
-            .and_modify(|v| v.extend_from_slice(&[value.clone()]))
-            .or_insert(vec![value.clone()]);
+            .and_modify(|v| v.push(value.clone()))
+            .or_insert(vec![value]);
 
         return self.kvlm_parse(Some(end + 1), Some(dict));
     }
 
-    pub(crate) fn kvlm_serialize(kvlm: &BTreeMap<String, Vec<String>>) -> GitObjectData {
+    pub(crate) fn kvlm_serialize(
+        kind: ObjectKind,
+        kvlm: &BTreeMap<String, Vec<String>>,
+    ) -> GitObjectData {
         let mut str = String::from("");
 
-        for key in kvlm.keys() {
+        // Known keys go first, in the order git writes them, so that
+        // re-serializing a parsed object reproduces it (and its SHA) exactly
+        let known = KVLM_KEY_ORDER
+            .iter()
+            .filter(|key| kvlm.contains_key(**key))
+            .map(|key| key.to_string());
+        let others = kvlm
+            .keys()
+            .filter(|key| !KVLM_KEY_ORDER.contains(&key.as_str()))
+            .cloned();
+        let keys: Vec<String> = known.chain(others).collect();
+
+        for key in &keys {
             // Skip the message itself
-            if key == "" {
+            if key.is_empty() {
                 continue;
             }
 
             let val = kvlm.get(key).unwrap();
             for v in val {
-                str += &(key.to_owned() + &" " + &(v.replace("\n", "\n ")) + "\n")
+                str += &(key.to_owned() + " " + &(v.replace('\n', "\n ")) + "\n")
             }
         }
 
-        // Append message
-        str += &("\n".to_owned() + kvlm.get("").unwrap()[0].as_str());
+        // Append message, after the blank line which ends the headers even
+        // when there is no message, encoding it back to Latin-1 if that is
+        // what it was read as
+        str += "\n";
+        let mut data = str.into_bytes();
+        if let Some(message) = kvlm.get("").and_then(|message| message.first()) {
+            match is_latin1(kvlm) && message.chars().all(|c| (c as u32) < 0x100) {
+                true => data.extend(message.chars().map(|c| c as u8)),
+                false => data.extend_from_slice(message.as_bytes()),
+            }
+        }
 
-        return GitObjectData("".to_string(), str.as_bytes().to_vec());
+        return GitObjectData(kind, data);
     }
 }
 
@@ -179,8 +373,13 @@ pub(crate) trait GitSerDe {
     /// Deserialise the object
     fn deserialize(&mut self, data: GitObjectData);
 
+    /// The wrapped Repository object, if there is one
+    fn repo(&self) -> Option<&Repository>;
+
     /// Obtain the wrapped Repository object
-    fn get_repo(&self) -> &Repository;
+    fn get_repo(&self) -> &Repository {
+        return self.repo().expect("No repo set");
+    }
 
     fn get_data(&self) -> GitObjectData {
         return self.serialize();
@@ -223,12 +422,10 @@ commit works...
 ";
 
     #[test]
-    fn test_can_parse_tree_key_value() -> Result<(), FromUtf8Error> {
+    fn test_can_parse_tree_key_value() -> Result<(), KvlmParseError> {
         let commit = COMMIT_EXAMPLE;
-        let commit_object = GitObjectData(
-            "commit".to_string(),
-            String::from(commit).as_bytes().to_vec(),
-        );
+        let commit_object =
+            GitObjectData(ObjectKind::Commit, String::from(commit).as_bytes().to_vec());
 
         let parsed = commit_object.kvlm_parse(None, None)?;
         assert_eq!(
@@ -242,12 +439,10 @@ commit works...
     }
 
     #[test]
-    fn test_can_parse_parent_key_value() -> Result<(), FromUtf8Error> {
+    fn test_can_parse_parent_key_value() -> Result<(), KvlmParseError> {
         let commit = COMMIT_EXAMPLE;
-        let commit_object = GitObjectData(
-            "commit".to_string(),
-            String::from(commit).as_bytes().to_vec(),
-        );
+        let commit_object =
+            GitObjectData(ObjectKind::Commit, String::from(commit).as_bytes().to_vec());
 
         let parsed = commit_object.kvlm_parse(None, None)?;
         assert_eq!(
@@ -259,12 +454,10 @@ commit works...
     }
 
     #[test]
-    fn test_can_parse_author_key_value() -> Result<(), FromUtf8Error> {
+    fn test_can_parse_author_key_value() -> Result<(), KvlmParseError> {
         let commit = COMMIT_EXAMPLE;
-        let commit_object = GitObjectData(
-            "commit".to_string(),
-            String::from(commit).as_bytes().to_vec(),
-        );
+        let commit_object =
+            GitObjectData(ObjectKind::Commit, String::from(commit).as_bytes().to_vec());
 
         let parsed = commit_object.kvlm_parse(None, None)?;
         assert_eq!(
@@ -276,12 +469,10 @@ commit works...
     }
 
     #[test]
-    fn test_can_parse_committer_key_value() -> Result<(), FromUtf8Error> {
+    fn test_can_parse_committer_key_value() -> Result<(), KvlmParseError> {
         let commit = COMMIT_EXAMPLE;
-        let commit_object = GitObjectData(
-            "commit".to_string(),
-            String::from(commit).as_bytes().to_vec(),
-        );
+        let commit_object =
+            GitObjectData(ObjectKind::Commit, String::from(commit).as_bytes().to_vec());
 
         let parsed = commit_object.kvlm_parse(None, None)?;
         assert_eq!(
@@ -293,12 +484,10 @@ commit works...
     }
 
     #[test]
-    fn test_can_parse_commit_message() -> Result<(), FromUtf8Error> {
+    fn test_can_parse_commit_message() -> Result<(), KvlmParseError> {
         let commit = COMMIT_EXAMPLE;
-        let commit_object = GitObjectData(
-            "commit".to_string(),
-            String::from(commit).as_bytes().to_vec(),
-        );
+        let commit_object =
+            GitObjectData(ObjectKind::Commit, String::from(commit).as_bytes().to_vec());
 
         let parsed = commit_object.kvlm_parse(None, None)?;
         assert_eq!(
@@ -315,12 +504,10 @@ commit works...
     }
 
     #[test]
-    fn test_can_parse_gpgsig_key_value() -> Result<(), FromUtf8Error> {
+    fn test_can_parse_gpgsig_key_value() -> Result<(), KvlmParseError> {
         let commit = COMMIT_EXAMPLE;
-        let commit_object = GitObjectData(
-            "commit".to_string(),
-            String::from(commit).as_bytes().to_vec(),
-        );
+        let commit_object =
+            GitObjectData(ObjectKind::Commit, String::from(commit).as_bytes().to_vec());
 
         let parsed = commit_object.kvlm_parse(None, None)?;
 
@@ -349,19 +536,159 @@ Dsu+R6iJ+fYrbCCZSELWGA4Z
     }
 
     #[test]
-    fn test_can_round_trip_serialized_objects() -> Result<(), FromUtf8Error> {
+    fn test_can_round_trip_serialized_objects() -> Result<(), KvlmParseError> {
         let commit = COMMIT_EXAMPLE;
-        let commit_object = GitObjectData(
-            "commit".to_string(),
-            String::from(commit).as_bytes().to_vec(),
-        );
+        let commit_object =
+            GitObjectData(ObjectKind::Commit, String::from(commit).as_bytes().to_vec());
 
         let parsed_src = commit_object.kvlm_parse(None, None)?;
-        let serialized = GitObjectData::kvlm_serialize(&parsed_src);
+        let serialized = GitObjectData::kvlm_serialize(ObjectKind::Commit, &parsed_src);
         let parsed_serialized = serialized.kvlm_parse(None, None)?;
 
         assert_eq!(parsed_src, parsed_serialized);
 
         return Ok(());
     }
+
+    #[test]
+    fn test_serialize_reproduces_original_bytes() -> Result<(), KvlmParseError> {
+        let commit_object = GitObjectData(
+            ObjectKind::Commit,
+            String::from(COMMIT_EXAMPLE).as_bytes().to_vec(),
+        );
+
+        let parsed = commit_object.kvlm_parse(None, None)?;
+        let GitObjectData(_, serialized) =
+            GitObjectData::kvlm_serialize(ObjectKind::Commit, &parsed);
+
+        assert_eq!(COMMIT_EXAMPLE, String::from_utf8(serialized)?);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_extracts_signature_and_signed_payload() {
+        let commit = Commit::new(
+            None,
+            GitObjectData(ObjectKind::Commit, COMMIT_EXAMPLE.as_bytes().to_vec()),
+        );
+
+        let signature = commit.gpg_signature().unwrap();
+        assert!(signature.starts_with("-----BEGIN PGP SIGNATURE-----\n\niQJPBAAB"));
+        assert!(signature.ends_with("=SBeJ\n-----END PGP SIGNATURE-----"));
+
+        let (headers, message) = COMMIT_EXAMPLE.split_once("gpgsig ").unwrap();
+        let (_, message) = message.split_once("\n\n").unwrap();
+        assert_eq!(
+            format!("{}\n{}", headers, message),
+            String::from_utf8(commit.signed_payload()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_serializes_kvlm_without_message() -> Result<(), KvlmParseError> {
+        let kvlm = BTreeMap::from([(
+            "tree".to_string(),
+            vec!["4f0ae14e719a707c91ad1885e38bd18e7d214629".to_string()],
+        )]);
+
+        let serialized = GitObjectData::kvlm_serialize(ObjectKind::Commit, &kvlm);
+
+        assert_eq!(
+            b"tree 4f0ae14e719a707c91ad1885e38bd18e7d214629\n\n".to_vec(),
+            serialized.1
+        );
+        assert_eq!(
+            Some(&vec![String::new()]),
+            serialized.kvlm_parse(None, None)?.get("")
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_reads_latin1_message() -> Result<(), KvlmParseError> {
+        let mut data = b"tree 4f0ae14e719a707c91ad1885e38bd18e7d214629\n\
+            author A U Thor <author@example.com> 1665000120 +0100\n\
+            committer A U Thor <author@example.com> 1665000120 +0100\n\
+            encoding ISO-8859-1\n\n"
+            .to_vec();
+        data.extend(b"Caf\xe9 cr\xe8me\n");
+        let commit_object = GitObjectData(ObjectKind::Commit, data.clone());
+
+        let parsed = commit_object.kvlm_parse(None, None)?;
+
+        assert_eq!("ISO-8859-1", parsed["encoding"][0]);
+        assert_eq!("Café crème\n", parsed[""][0]);
+        assert_eq!(
+            data,
+            GitObjectData::kvlm_serialize(ObjectKind::Commit, &parsed).1
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_rejects_unterminated_header() {
+        let commit_object = GitObjectData(
+            ObjectKind::Commit,
+            b"tree 4f0ae14e719a707c91ad1885e38bd18e7d214629\nparent 5f350c20".to_vec(),
+        );
+
+        assert_eq!(
+            Some(KvlmParseError::Unterminated(46)),
+            commit_object.kvlm_parse(None, None).err()
+        );
+    }
+
+    #[test]
+    fn test_rejects_headers_without_blank_line() {
+        for (data, offset) in [
+            (&b"tree 4f0ae14e719a707c91ad1885e38bd18e7d214629\n"[..], 46),
+            (b"tree 4f0ae14e719a707c91ad1885e38bd18e7d214629\nfoo", 46),
+            (b"", 0),
+        ] {
+            let commit_object = GitObjectData(ObjectKind::Commit, data.to_vec());
+
+            assert_eq!(
+                Some(KvlmParseError::Unterminated(offset)),
+                commit_object.kvlm_parse(None, None).err()
+            );
+        }
+    }
+
+    #[test]
+    fn test_rejects_header_without_value() {
+        let commit_object = GitObjectData(
+            ObjectKind::Commit,
+            b"tree\nparent 5f350c20eb579d51d0a3b1fafc8fce3f26d9d61a\n\nMessage\n".to_vec(),
+        );
+
+        assert_eq!(
+            Some(KvlmParseError::InvalidHeader(0)),
+            commit_object.kvlm_parse(None, None).err()
+        );
+    }
+
+    #[test]
+    fn test_parses_object_kinds() {
+        for kind in [
+            ObjectKind::Blob,
+            ObjectKind::Commit,
+            ObjectKind::Tree,
+            ObjectKind::Tag,
+        ] {
+            assert_eq!(Some(kind), kind.as_str().parse::<ObjectKind>().ok());
+        }
+    }
+
+    #[test]
+    fn test_rejects_unknown_object_kinds() {
+        for name in ["", "Blob", "commits", "delta"] {
+            assert!(matches!(
+                name.parse::<ObjectKind>(),
+                Err(ReadObjectErrorType::UnknownObjectKindError(unknown)) if unknown == name
+            ));
+        }
+    }
 }
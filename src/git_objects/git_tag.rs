@@ -0,0 +1,59 @@
+use std::{any::Any, collections::BTreeMap};
+
+use crate::{
+    git_objects::git_object::{GitObjectData, KvlmParseError, ObjectKind},
+    repository::repository::Repository,
+};
+
+use super::git_object::GitSerDe;
+
+/// An annotated tag. Like a commit, a tag is a key-value list with message,
+/// naming the tagged object, its type, the tag's name and the tagger.
+pub(crate) struct Tag {
+    repo: Option<Repository>,
+    pub(crate) kvlm: BTreeMap<String, Vec<String>>,
+}
+
+impl Tag {
+    /// Parse a tag, failing rather than panicking if it is malformed.
+    pub(crate) fn from_data(
+        repo: Option<Repository>,
+        data: GitObjectData,
+    ) -> Result<Tag, KvlmParseError> {
+        let kvlm = data.kvlm_parse(None, None)?;
+        return Ok(Tag { repo, kvlm });
+    }
+
+    /// The SHA of the tagged object.
+    pub(crate) fn object(&self) -> &str {
+        return self
+            .kvlm
+            .get("object")
+            .map(|object| object[0].as_str())
+            .unwrap_or_default();
+    }
+}
+
+impl GitSerDe for Tag {
+    fn new(repo: Option<Repository>, data: GitObjectData) -> Tag {
+        return Tag::from_data(repo, data).expect("Could not parse the kvlm object.");
+    }
+
+    fn serialize(&self) -> GitObjectData {
+        return GitObjectData::kvlm_serialize(ObjectKind::Tag, &self.kvlm);
+    }
+
+    fn deserialize(&mut self, data: GitObjectData) {
+        self.kvlm = data
+            .kvlm_parse(None, None)
+            .expect("Could not parse the kvlm object.");
+    }
+
+    fn repo(&self) -> Option<&Repository> {
+        return self.repo.as_ref();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -1,26 +1,107 @@
-use std::any::Any;
+use std::{any::Any, fmt};
 
-use crate::repository::repository::Repository;
+use crate::repository::repository::{HashAlgo, Repository};
 
-use super::git_object::{GitObjectData, GitSerDe};
+use super::git_object::{GitObjectData, GitSerDe, ObjectKind};
 
 pub(crate) struct Tree {
     pub(crate) items: Vec<Leaf>,
     repo: Option<Repository>,
 }
 
+impl<'a> IntoIterator for &'a Tree {
+    type Item = &'a Leaf;
+    type IntoIter = std::slice::Iter<'a, Leaf>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        return self.iter();
+    }
+}
+
+/// Why a tree object's content could not be parsed.
+#[derive(Debug, PartialEq)]
+pub(crate) enum TreeParseError {
+    /// The entry starting at this offset runs past the end of the tree
+    Truncated(usize),
+    /// The entry starting at this offset has a path which is not UTF-8
+    InvalidPath(usize),
+    InvalidMode(String),
+}
+
+impl fmt::Display for TreeParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        return match self {
+            TreeParseError::Truncated(offset) => {
+                write!(f, "truncated tree entry at offset {}", offset)
+            }
+            TreeParseError::InvalidPath(offset) => {
+                write!(f, "invalid path in tree entry at offset {}", offset)
+            }
+            TreeParseError::InvalidMode(mode) => write!(f, "invalid tree entry mode {:?}", mode),
+        };
+    }
+}
+
+/// The modes git allows in a tree: a subtree, a regular or executable file,
+/// a symlink and a submodule commit.
+const VALID_MODES: [&str; 5] = ["40000", "100644", "100755", "120000", "160000"];
+
+impl Tree {
+    /// Iterate over the tree's entries in order.
+    pub(crate) fn iter(&self) -> std::slice::Iter<'_, Leaf> {
+        return self.items.iter();
+    }
+
+    /// The entry named path, if the tree has one. Only direct entries are
+    /// considered, so path may not contain a separator.
+    pub(crate) fn get(&self, path: &str) -> Option<&Leaf> {
+        return self.items.iter().find(|leaf| leaf.path() == path);
+    }
+
+    /// Add leaf to the tree, where git's ordering puts it, replacing any
+    /// entry with the same name. A tree doesn't cache its SHA, so writing
+    /// it again after changing it always gives the new one.
+    pub(crate) fn insert(&mut self, leaf: Leaf) {
+        self.remove(leaf.path());
+        let key = leaf.sort_key();
+        let pos = self.items.partition_point(|item| item.sort_key() < key);
+        self.items.insert(pos, leaf);
+    }
+
+    /// Remove the entry named path, returning it if there was one.
+    pub(crate) fn remove(&mut self, path: &str) -> Option<Leaf> {
+        // The entry sorts as path if it's a file, or path/ if it's a tree
+        for key in [path.to_string(), format!("{}/", path)] {
+            let pos = self.items.partition_point(|item| item.sort_key() < key);
+            if self.items.get(pos).is_some_and(|item| item.path() == path) {
+                return Some(self.items.remove(pos));
+            }
+        }
+
+        return None;
+    }
+
+    /// Parse a tree, failing rather than panicking if it is malformed.
+    pub(crate) fn from_data(
+        repo: Option<Repository>,
+        data: GitObjectData,
+    ) -> Result<Tree, TreeParseError> {
+        let algo = repo
+            .as_ref()
+            .map_or(HashAlgo::Sha1, |repo| repo.hash_algo());
+        log::debug!("Deserializing tree object...");
+        let items = tree_parse(data, algo.raw_len())?;
+
+        return Ok(Tree { repo, items });
+    }
+}
+
 impl GitSerDe for Tree {
     fn new(repo: Option<Repository>, data: GitObjectData) -> Self
     where
         Self: Sized,
     {
-        let mut tree = Tree {
-            repo,
-            items: Vec::new(),
-        };
-        log::debug!("Deserializing tree object...");
-        tree.deserialize(data);
-        return tree;
+        return Tree::from_data(repo, data).expect("Could not parse the tree object.");
     }
 
     fn serialize(&self) -> GitObjectData {
@@ -28,11 +109,15 @@ impl GitSerDe for Tree {
     }
 
     fn deserialize(&mut self, data: GitObjectData) {
-        self.items = tree_parse(data);
+        let algo = self
+            .repo
+            .as_ref()
+            .map_or(HashAlgo::Sha1, |repo| repo.hash_algo());
+        self.items = tree_parse(data, algo.raw_len()).expect("Could not parse the tree object.");
     }
 
-    fn get_repo(&self) -> &Repository {
-        return self.repo.as_ref().expect("No repo set");
+    fn repo(&self) -> Option<&Repository> {
+        return self.repo.as_ref();
     }
 
     fn as_any(&self) -> &dyn Any {
@@ -45,68 +130,200 @@ impl GitSerDe for Tree {
 #[derive(Debug)]
 pub(crate) struct Leaf(pub(crate) String, pub(crate) String, pub(crate) String);
 
+impl Leaf {
+    /// The file mode, as stored, e.g. `100644` or `40000`.
+    pub(crate) fn mode(&self) -> &str {
+        return &self.0;
+    }
+
+    /// The entry's name within its tree.
+    pub(crate) fn path(&self) -> &str {
+        return &self.1;
+    }
+
+    /// The SHA of the blob, tree or commit the entry refers to.
+    pub(crate) fn sha(&self) -> &str {
+        return &self.2;
+    }
+
+    /// What git orders a tree's entries by: the name, as if it ended in a
+    /// `/` for a subtree.
+    pub(crate) fn sort_key(&self) -> String {
+        return match self.mode() {
+            "40000" => format!("{}/", self.path()),
+            _ => self.path().to_string(),
+        };
+    }
+
+    /// The mode as git displays it: six digits, so trees are shown as
+    /// `040000` though they are stored as `40000`.
+    pub(crate) fn padded_mode(&self) -> String {
+        return format!("{:0>6}", self.0);
+    }
+}
+
 fn tree_serialize(tree: &Tree) -> GitObjectData {
-    let mut serialized = String::new();
-    for leaf in &tree.items {
-        serialized.push_str(format!("{} {}\x00{}", leaf.0, leaf.1, leaf.2).as_str());
+    let mut serialized = Vec::new();
+    for leaf in tree {
+        serialized.extend_from_slice(format!("{} {}\x00", leaf.mode(), leaf.path()).as_bytes());
+        // The SHA is stored as raw bytes, not as its hex representation
+        serialized.extend(hex::decode(leaf.sha()).expect("Invalid SHA in tree leaf"));
     }
 
-    return GitObjectData(String::from("tree"), serialized.as_bytes().to_vec());
+    return GitObjectData(ObjectKind::Tree, serialized);
 }
 
-fn tree_parse(raw: GitObjectData) -> Vec<Leaf> {
+/// Parse a tree whose SHAs are sha_len raw bytes long.
+fn tree_parse(raw: GitObjectData, sha_len: usize) -> Result<Vec<Leaf>, TreeParseError> {
     let mut pos = 0;
     let max = raw.1.len();
     let mut tree = Vec::new();
 
     while pos < max {
-        if let Some((new_pos, leaf)) = tree_parse_one(&raw.1, Some(pos)) {
-            tree.push(leaf);
-            pos = new_pos;
-        }
+        let (new_pos, leaf) = tree_parse_one(&raw.1, Some(pos), sha_len)?;
+        tree.push(leaf);
+        pos = new_pos;
     }
 
-    return tree;
+    return Ok(tree);
 }
 
-fn tree_parse_one(raw: &Vec<u8>, start: Option<usize>) -> Option<(usize, Leaf)> {
-    let start = match start {
-        None => 0,
-        Some(start) => start,
-    };
+fn tree_parse_one(
+    raw: &[u8],
+    start: Option<usize>,
+    sha_len: usize,
+) -> Result<(usize, Leaf), TreeParseError> {
+    let start = start.unwrap_or_default();
 
     log::debug!("Running tree_parse_one with start argument of {:?}", start);
 
-    // Find the space terminating the file mode value
-    let x = raw.iter().skip(start).position(|b| b == &b' ')? + start;
-    assert!(x - start == 5 || x - start == 6);
-
-    // Read the file mode
-    let mode = &raw[start..x];
-    log::debug!(
-        "Mode slice is: {:?}",
-        String::from_utf8(mode.to_vec()).unwrap()
-    );
+    // Find the space terminating the file mode value, and read the mode
+    let x = raw
+        .iter()
+        .skip(start)
+        .position(|b| b == &b' ')
+        .ok_or(TreeParseError::Truncated(start))?
+        + start;
+    let mode = String::from_utf8_lossy(&raw[start..x]).to_string();
+    log::debug!("Mode slice is: {:?}", mode);
+    if !VALID_MODES.contains(&mode.as_str()) {
+        return Err(TreeParseError::InvalidMode(mode));
+    }
 
     // Find the NUL terminator of the path value and read the path
-    let y = raw.iter().skip(x).position(|b| b == &b'\x00')? + x;
-    let path = &raw[x + 1..y];
-    log::debug!(
-        "Path slice is: {:?}",
-        String::from_utf8(path.to_vec()).unwrap()
-    );
+    let y = raw
+        .iter()
+        .skip(x)
+        .position(|b| b == &b'\x00')
+        .ok_or(TreeParseError::Truncated(start))?
+        + x;
+    let path = String::from_utf8(raw[x + 1..y].to_vec())
+        .map_err(|_| TreeParseError::InvalidPath(start))?;
+    log::debug!("Path slice is: {:?}", path);
 
     // Read the SHA and convert it to a hex string
-    let sha = hex::encode(&raw[y + 1..y + 21]);
+    let end = y + 1 + sha_len;
+    let sha = hex::encode(
+        raw.get(y + 1..end)
+            .ok_or(TreeParseError::Truncated(start))?,
+    );
 
     log::debug!("SHA slice is: {:02X?}", sha);
 
-    return Some((
-        y + 21,
-        Leaf(
-            String::from_utf8(mode.to_vec()).expect("Could not parse mode from tree object"),
-            String::from_utf8(path.to_vec()).expect("Could not parse path from tree object"),
-            sha,
-        ),
-    ));
+    return Ok((end, Leaf(mode, path, sha)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(mode: &str, path: &str) -> Vec<u8> {
+        let mut entry = format!("{} {}\x00", mode, path).into_bytes();
+        entry.extend([0xab; 20]);
+        return entry;
+    }
+
+    #[test]
+    fn test_parses_entries() {
+        let mut raw = entry("100644", "hello.txt");
+        raw.extend(entry("40000", "src"));
+
+        let tree = Tree::from_data(None, GitObjectData(ObjectKind::Tree, raw)).unwrap();
+
+        let paths: Vec<&str> = tree.iter().map(|leaf| leaf.path()).collect();
+        assert_eq!(vec!["hello.txt", "src"], paths);
+        let src = tree.get("src").unwrap();
+        assert_eq!("40000", src.mode());
+        assert_eq!("ab".repeat(20), src.sha());
+        assert!(tree.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_insert_keeps_git_order() {
+        let leaf =
+            |mode: &str, path: &str| Leaf(mode.to_string(), path.to_string(), "ab".repeat(20));
+        let mut tree = Tree::from_data(None, GitObjectData(ObjectKind::Tree, vec![])).unwrap();
+
+        tree.insert(leaf("100644", "foo.txt"));
+        tree.insert(leaf("40000", "foo"));
+        tree.insert(leaf("100644", "foo-bar"));
+        tree.insert(leaf("100644", "README"));
+        tree.insert(leaf("100755", "foo.txt"));
+
+        // The subtree foo sorts as foo/, after foo.txt
+        let paths: Vec<&str> = tree.iter().map(|leaf| leaf.path()).collect();
+        assert_eq!(vec!["README", "foo-bar", "foo.txt", "foo"], paths);
+        assert_eq!("100755", tree.get("foo.txt").unwrap().mode());
+
+        let mut expected = entry("100644", "README");
+        expected.extend(entry("100644", "foo-bar"));
+        expected.extend(entry("100755", "foo.txt"));
+        expected.extend(entry("40000", "foo"));
+        assert_eq!(expected, tree.serialize().1);
+
+        assert_eq!("foo-bar", tree.remove("foo-bar").unwrap().path());
+        assert!(tree.remove("foo-bar").is_none());
+        assert_eq!(3, tree.iter().count());
+    }
+
+    #[test]
+    fn test_padded_mode() {
+        let leaf = |mode: &str| Leaf(mode.to_string(), "path".to_string(), "ab".repeat(20));
+
+        assert_eq!("040000", leaf("40000").padded_mode());
+        assert_eq!("100644", leaf("100644").padded_mode());
+    }
+
+    #[test]
+    fn test_rejects_truncated_entry() {
+        let mut raw = entry("100644", "hello.txt");
+        let second = raw.len();
+        raw.extend(entry("100644", "lorem.txt"));
+        raw.truncate(raw.len() - 5);
+
+        assert_eq!(
+            Some(TreeParseError::Truncated(second)),
+            tree_parse(GitObjectData(ObjectKind::Tree, raw), 20).err()
+        );
+        assert_eq!(
+            Some(TreeParseError::Truncated(0)),
+            tree_parse(
+                GitObjectData(ObjectKind::Tree, b"100644 no-nul".to_vec()),
+                20
+            )
+            .err()
+        );
+    }
+
+    #[test]
+    fn test_rejects_invalid_mode() {
+        assert_eq!(
+            Some(TreeParseError::InvalidMode("100664".to_string())),
+            tree_parse(
+                GitObjectData(ObjectKind::Tree, entry("100664", "hello.txt")),
+                20
+            )
+            .err()
+        );
+    }
 }
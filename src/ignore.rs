@@ -0,0 +1,217 @@
+/// The patterns of `.gitignore` files and `.git/info/exclude`, saying which
+/// untracked paths git should leave alone.
+#[derive(Debug, Default)]
+pub(crate) struct IgnoreRules {
+    patterns: Vec<IgnorePattern>,
+}
+
+#[derive(Debug)]
+struct IgnorePattern {
+    /// The directory of the file the pattern came from, relative to the
+    /// root of the worktree, ending in `/` unless it is the root
+    base: String,
+    glob: String,
+    /// Whether the pattern started `!`, re-including what it matches
+    negated: bool,
+    /// Whether the pattern ended `/`, so only matches directories
+    dir_only: bool,
+    /// Whether the pattern has a `/` before its end, so matches paths
+    /// from base rather than names at any depth below it
+    anchored: bool,
+}
+
+impl IgnoreRules {
+    /// Add the patterns in text, the content of an ignore file in the
+    /// directory base, relative to the root of the worktree. Patterns
+    /// added later take precedence, so files should be added from the
+    /// least to the most specific.
+    pub(crate) fn add(&mut self, text: &str, base: &str) {
+        let base = match base.trim_end_matches('/') {
+            "" => String::new(),
+            base => format!("{}/", base),
+        };
+
+        for line in text.lines() {
+            let line = trim_unescaped_spaces(line);
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            let (dir_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line),
+            };
+            if line.is_empty() {
+                continue;
+            }
+
+            self.patterns.push(IgnorePattern {
+                base: base.clone(),
+                anchored: line.contains('/'),
+                glob: line.strip_prefix('/').unwrap_or(line).to_string(),
+                negated,
+                dir_only,
+            });
+        }
+    }
+
+    /// Whether path, relative to the root of the worktree and separated by
+    /// `/`, is ignored. The last pattern matching it decides.
+    pub(crate) fn is_ignored(&self, path: &str, is_dir: bool) -> bool {
+        for pattern in self.patterns.iter().rev() {
+            if pattern.dir_only && !is_dir {
+                continue;
+            }
+            let Some(relative) = path.strip_prefix(&pattern.base) else {
+                continue;
+            };
+
+            let subject = match pattern.anchored {
+                true => relative,
+                false => relative.rsplit('/').next().unwrap_or(relative),
+            };
+            if wildmatch(pattern.glob.as_bytes(), subject.as_bytes()) {
+                return !pattern.negated;
+            }
+        }
+
+        return false;
+    }
+}
+
+/// line without trailing spaces, unless they are escaped with a backslash.
+fn trim_unescaped_spaces(line: &str) -> &str {
+    let trimmed = line.trim_end_matches(' ');
+    if trimmed.ends_with('\\') && trimmed.len() < line.len() {
+        return &line[..trimmed.len() + 1];
+    }
+
+    return trimmed;
+}
+
+/// Whether text matches the glob pattern as git matches ignore patterns:
+/// `*` and `?` don't match `/`, `**` between slashes matches any number of
+/// directories, `[...]` matches a class of characters, and `\` escapes the
+/// next character.
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    if let Some(rest) = pattern.strip_prefix(b"**") {
+        // **/ matches nothing or a run of whole directories, and a final
+        // ** matches everything left
+        let rest = match rest.first() {
+            None => return true,
+            Some(b'/') => &rest[1..],
+            Some(_) => return wildmatch(&pattern[1..], text),
+        };
+        return (0..=text.len())
+            .filter(|start| *start == 0 || text[start - 1] == b'/')
+            .any(|start| wildmatch(rest, &text[start..]));
+    }
+
+    return match (pattern.first(), text.first()) {
+        (None, _) => text.is_empty(),
+        (Some(b'*'), _) => {
+            wildmatch(&pattern[1..], text)
+                || (text.first().is_some_and(|c| *c != b'/') && wildmatch(pattern, &text[1..]))
+        }
+        (Some(b'?'), Some(c)) => *c != b'/' && wildmatch(&pattern[1..], &text[1..]),
+        (Some(b'['), Some(c)) => match class_matches(&pattern[1..], *c) {
+            Some((true, rest)) => *c != b'/' && wildmatch(rest, &text[1..]),
+            Some((false, _)) => false,
+            // An unclosed [ is taken literally
+            None => *c == b'[' && wildmatch(&pattern[1..], &text[1..]),
+        },
+        (Some(b'\\'), Some(c)) if pattern.len() > 1 => {
+            pattern[1] == *c && wildmatch(&pattern[2..], &text[1..])
+        }
+        (Some(p), Some(c)) => p == c && wildmatch(&pattern[1..], &text[1..]),
+        (Some(_), None) => false,
+    };
+}
+
+/// Whether c is in the character class at the start of pattern, just after
+/// its `[`, and the rest of the pattern after the closing `]`, or None if
+/// the class isn't closed.
+fn class_matches(pattern: &[u8], c: u8) -> Option<(bool, &[u8])> {
+    let (negated, mut pos) = match pattern.first() {
+        Some(b'!' | b'^') => (true, 1),
+        _ => (false, 0),
+    };
+
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let start = *pattern.get(pos)?;
+        if start == b']' && !first {
+            return Some((matched != negated, &pattern[pos + 1..]));
+        }
+        first = false;
+
+        let start = match start {
+            b'\\' => {
+                pos += 1;
+                *pattern.get(pos)?
+            }
+            start => start,
+        };
+        if pattern.get(pos + 1) == Some(&b'-')
+            && pattern.get(pos + 2).is_some_and(|end| *end != b']')
+        {
+            let end = pattern[pos + 2];
+            matched |= (start..=end).contains(&c);
+            pos += 3;
+        } else {
+            matched |= start == c;
+            pos += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_patterns_match_names_at_any_depth_unless_anchored() {
+        let mut rules = IgnoreRules::default();
+        rules.add("*.o\n/build\ndocs/*.html\n", "");
+        rules.add("# generated\ntmp/\n", "src");
+
+        assert!(rules.is_ignored("main.o", false));
+        assert!(rules.is_ignored("src/lib/util.o", false));
+        assert!(rules.is_ignored("build", true));
+        assert!(!rules.is_ignored("src/build", true));
+        assert!(rules.is_ignored("docs/index.html", false));
+        assert!(!rules.is_ignored("docs/api/index.html", false));
+        assert!(rules.is_ignored("src/nested/tmp", true));
+        assert!(!rules.is_ignored("src/nested/tmp", false));
+        assert!(!rules.is_ignored("tmp", true));
+    }
+
+    #[test]
+    fn test_later_negations_re_include() {
+        let mut rules = IgnoreRules::default();
+        rules.add("*.log\n!keep.log\n", "");
+        rules.add("keep.log\n", "old");
+
+        assert!(rules.is_ignored("debug.log", false));
+        assert!(!rules.is_ignored("keep.log", false));
+        assert!(rules.is_ignored("old/keep.log", false));
+    }
+
+    #[test]
+    fn test_wildmatch_double_stars_and_classes() {
+        assert!(wildmatch(b"**/target", b"target"));
+        assert!(wildmatch(b"**/target", b"a/b/target"));
+        assert!(wildmatch(b"logs/**", b"logs/a/b.txt"));
+        assert!(wildmatch(b"a/**/b", b"a/b"));
+        assert!(wildmatch(b"a/**/b", b"a/x/y/b"));
+        assert!(!wildmatch(b"a/*/b", b"a/x/y/b"));
+        assert!(wildmatch(b"file[0-9].txt", b"file7.txt"));
+        assert!(!wildmatch(b"file[!0-9].txt", b"file7.txt"));
+        assert!(wildmatch(b"\\#notes", b"#notes"));
+    }
+}
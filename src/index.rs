@@ -0,0 +1,360 @@
+use std::fs::Metadata;
+
+use crate::repository::repository::{HashAlgo, ReadObjectErrorType};
+
+/// The staging area, as found in .git/index.
+///
+/// The index is a 12 byte header ("DIRC", a version number and the number
+/// of entries) followed by the entries sorted by path, any extensions, then
+/// a trailer holding the hash of everything before it. Only version 2 is
+/// supported.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Index {
+    pub(crate) entries: Vec<IndexEntry>,
+}
+
+/// A staged file: the stat data it had when staged, its mode and blob SHA,
+/// and its path relative to the root of the worktree.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct IndexEntry {
+    pub(crate) ctime: (u32, u32),
+    pub(crate) mtime: (u32, u32),
+    pub(crate) dev: u32,
+    pub(crate) ino: u32,
+    /// The file mode, e.g. 0o100644
+    pub(crate) mode: u32,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) size: u32,
+    pub(crate) sha: String,
+    pub(crate) assume_valid: bool,
+    /// Zero, or during a merge, which side of the conflict this is
+    pub(crate) stage: u8,
+    /// Separated by `/` whatever the platform
+    pub(crate) path: String,
+}
+
+const INDEX_HEADER_SIZE: usize = 12;
+const INDEX_VERSION: u32 = 2;
+
+/// The length of an entry's fixed-size fields before its SHA.
+const ENTRY_STAT_SIZE: usize = 40;
+
+/// The length of an extension's signature and size.
+const EXTENSION_HEADER_SIZE: usize = 8;
+
+/// Path lengths of this or more are stored as this in an entry's flags.
+const MAX_NAME_LENGTH: usize = 0xfff;
+
+impl IndexEntry {
+    /// An entry for the file at path whose content has the given SHA,
+    /// taking its stat data and mode from metadata. The stat fields are
+    /// truncated to 32 bits, as git does.
+    #[cfg(unix)]
+    pub(crate) fn new(path: String, sha: String, metadata: &Metadata) -> IndexEntry {
+        use std::os::unix::fs::MetadataExt;
+
+        let mode = if metadata.file_type().is_symlink() {
+            0o120000
+        } else if metadata.mode() & 0o111 != 0 {
+            0o100755
+        } else {
+            0o100644
+        };
+
+        return IndexEntry {
+            ctime: (metadata.ctime() as u32, metadata.ctime_nsec() as u32),
+            mtime: (metadata.mtime() as u32, metadata.mtime_nsec() as u32),
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            mode,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.size() as u32,
+            sha,
+            path,
+            ..Default::default()
+        };
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn new(path: String, sha: String, metadata: &Metadata) -> IndexEntry {
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|mtime| mtime.duration_since(std::time::UNIX_EPOCH).ok())
+            .unwrap_or_default();
+
+        return IndexEntry {
+            mtime: (mtime.as_secs() as u32, mtime.subsec_nanos()),
+            mode: 0o100644,
+            size: metadata.len() as u32,
+            sha,
+            path,
+            ..Default::default()
+        };
+    }
+}
+
+impl Index {
+    /// Parse an index whose SHAs and trailer use algo, checking the trailer
+    /// matches the rest of the index.
+    pub(crate) fn parse(data: &[u8], algo: HashAlgo) -> Result<Index, ReadObjectErrorType> {
+        let invalid = |message: &str| ReadObjectErrorType::InvalidIndexError(message.to_string());
+        let read_u32 = |pos: usize| u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap());
+
+        if data.len() < INDEX_HEADER_SIZE + algo.raw_len() || &data[0..4] != b"DIRC" {
+            return Err(invalid("not an index"));
+        }
+        if read_u32(4) != INDEX_VERSION {
+            return Err(invalid("unsupported index version"));
+        }
+
+        let count = read_u32(8) as usize;
+        let end = data.len() - algo.raw_len();
+
+        // A trailer of zeros means git was told not to bother hashing the
+        // index, with index.skipHash
+        let trailer = &data[end..];
+        if trailer.iter().any(|b| *b != 0) && hex::encode(trailer) != algo.digest(&data[..end]) {
+            return Err(invalid("checksum mismatch"));
+        }
+
+        let mut entries = Vec::with_capacity(count);
+        let mut pos = INDEX_HEADER_SIZE;
+        for _ in 0..count {
+            let flags_pos = pos + ENTRY_STAT_SIZE + algo.raw_len();
+            if flags_pos + 2 > end {
+                return Err(invalid("truncated entry"));
+            }
+            let flags = u16::from_be_bytes([data[flags_pos], data[flags_pos + 1]]);
+            if flags & 0x4000 != 0 {
+                return Err(invalid("extended flags are not supported"));
+            }
+
+            // Long paths can't be measured from the flags, so always look
+            // for the NUL terminating the path
+            let path_start = flags_pos + 2;
+            let path_len = data[path_start..end]
+                .iter()
+                .position(|b| b == &b'\x00')
+                .ok_or_else(|| invalid("unterminated path"))?;
+            let path = String::from_utf8(data[path_start..path_start + path_len].to_vec())
+                .map_err(|_| invalid("path is not UTF-8"))?;
+
+            entries.push(IndexEntry {
+                ctime: (read_u32(pos), read_u32(pos + 4)),
+                mtime: (read_u32(pos + 8), read_u32(pos + 12)),
+                dev: read_u32(pos + 16),
+                ino: read_u32(pos + 20),
+                mode: read_u32(pos + 24),
+                uid: read_u32(pos + 28),
+                gid: read_u32(pos + 32),
+                size: read_u32(pos + 36),
+                sha: hex::encode(&data[pos + ENTRY_STAT_SIZE..flags_pos]),
+                assume_valid: flags & 0x8000 != 0,
+                stage: ((flags >> 12) & 0x3) as u8,
+                path,
+            });
+
+            pos += entry_size(algo, path_len);
+        }
+
+        if pos > end {
+            return Err(invalid("truncated entry"));
+        }
+
+        // Extensions, such as the TREE cache and REUC's record of resolved
+        // conflicts, follow the entries. Each is a four byte signature and
+        // a length, and all are optional unless their signature starts
+        // with something other than a capital letter.
+        while pos < end {
+            if pos + EXTENSION_HEADER_SIZE > end {
+                return Err(invalid("truncated extension"));
+            }
+            let signature = &data[pos..pos + 4];
+            let size = read_u32(pos + 4) as usize;
+            if !signature[0].is_ascii_uppercase() {
+                return Err(invalid(&format!(
+                    "unsupported extension {}",
+                    String::from_utf8_lossy(signature)
+                )));
+            }
+
+            pos += EXTENSION_HEADER_SIZE + size;
+            if pos > end {
+                return Err(invalid("truncated extension"));
+            }
+        }
+
+        return Ok(Index { entries });
+    }
+
+    /// Serialise the index, SHAs, trailer and all, using algo. Extensions
+    /// aren't written, since nothing here keeps them up to date.
+    pub(crate) fn serialize(&self, algo: HashAlgo) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(b"DIRC");
+        data.extend_from_slice(&INDEX_VERSION.to_be_bytes());
+        data.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+
+        for entry in &self.entries {
+            let start = data.len();
+            for field in [
+                entry.ctime.0,
+                entry.ctime.1,
+                entry.mtime.0,
+                entry.mtime.1,
+                entry.dev,
+                entry.ino,
+                entry.mode,
+                entry.uid,
+                entry.gid,
+                entry.size,
+            ] {
+                data.extend_from_slice(&field.to_be_bytes());
+            }
+            data.extend(hex::decode(&entry.sha).expect("Invalid SHA in index entry"));
+
+            let flags = (entry.assume_valid as u16) << 15
+                | (entry.stage as u16 & 0x3) << 12
+                | entry.path.len().min(MAX_NAME_LENGTH) as u16;
+            data.extend_from_slice(&flags.to_be_bytes());
+            data.extend_from_slice(entry.path.as_bytes());
+
+            // The path is NUL-terminated and padded to a multiple of 8 bytes
+            data.resize(start + entry_size(algo, entry.path.len()), b'\x00');
+        }
+
+        let trailer = hex::decode(algo.digest(&data)).unwrap();
+        data.extend(trailer);
+        return data;
+    }
+
+    /// Sort the entries by path, then stage, as git requires.
+    pub(crate) fn sort(&mut self) {
+        self.entries
+            .sort_by(|a, b| (a.path.as_bytes(), a.stage).cmp(&(b.path.as_bytes(), b.stage)));
+    }
+}
+
+/// The size of an entry with a path path_len bytes long, including the NUL
+/// padding after it.
+fn entry_size(algo: HashAlgo, path_len: usize) -> usize {
+    let unpadded = ENTRY_STAT_SIZE + algo.raw_len() + 2 + path_len;
+    return (unpadded + 8) & !7;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(path: &str) -> IndexEntry {
+        return IndexEntry {
+            mtime: (1665000000, 123),
+            mode: 0o100644,
+            size: 14,
+            sha: "af5626b4a114abcb82d63db7c8082c3c4756e51b".to_string(),
+            path: path.to_string(),
+            ..Default::default()
+        };
+    }
+
+    #[test]
+    fn test_round_trips_entries() -> Result<(), ReadObjectErrorType> {
+        let index = Index {
+            entries: vec![entry("hello.txt"), entry("src/a-rather-longer-name.rs")],
+        };
+
+        let data = index.serialize(HashAlgo::Sha1);
+
+        assert_eq!(b"DIRC\x00\x00\x00\x02\x00\x00\x00\x02", &data[0..12]);
+        // Both entries are padded to a multiple of 8 bytes
+        assert_eq!(12 + 72 + 96 + 20, data.len());
+        assert_eq!(index, Index::parse(&data, HashAlgo::Sha1)?);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_skips_tree_extension() -> Result<(), ReadObjectErrorType> {
+        // Written by git add, then git write-tree, which added the TREE
+        // extension
+        let data = std::fs::read(
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/index-tree"),
+        )
+        .unwrap();
+        assert!(data.windows(4).any(|window| window == b"TREE"));
+
+        let index = Index::parse(&data, HashAlgo::Sha1)?;
+
+        let staged: Vec<(&str, &str)> = index
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.sha.as_str()))
+            .collect();
+        assert_eq!(
+            vec![
+                ("hello.txt", "af5626b4a114abcb82d63db7c8082c3c4756e51b"),
+                ("src/main.rs", "f328e4d9d04c31d0d70d16d21a07d1613be9d577"),
+            ],
+            staged
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_rejects_unknown_required_extension() {
+        let mut data = Index::default().serialize(HashAlgo::Sha1);
+        data.truncate(INDEX_HEADER_SIZE);
+        data.extend_from_slice(b"link\x00\x00\x00\x00");
+        data.extend(hex::decode(HashAlgo::Sha1.digest(&data)).unwrap());
+
+        assert!(matches!(
+            Index::parse(&data, HashAlgo::Sha1),
+            Err(ReadObjectErrorType::InvalidIndexError(message))
+                if message == "unsupported extension link"
+        ));
+    }
+
+    #[test]
+    fn test_rejects_corrupt_index() {
+        let index = Index {
+            entries: vec![entry("hello.txt")],
+        };
+        let mut data = index.serialize(HashAlgo::Sha1);
+        data[INDEX_HEADER_SIZE + ENTRY_STAT_SIZE + 20 + 2] = b'j';
+
+        assert!(matches!(
+            Index::parse(&data, HashAlgo::Sha1),
+            Err(ReadObjectErrorType::InvalidIndexError(message)) if message == "checksum mismatch"
+        ));
+    }
+
+    #[test]
+    fn test_sorts_by_path_then_stage() {
+        let mut conflicted = entry("b");
+        conflicted.stage = 2;
+        let mut index = Index {
+            entries: vec![conflicted, entry("b"), entry("a/c"), entry("a.txt")],
+        };
+
+        index.sort();
+
+        let order: Vec<(&str, u8)> = index
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.stage))
+            .collect();
+        assert_eq!(vec![("a.txt", 0), ("a/c", 0), ("b", 0), ("b", 2)], order);
+    }
+
+    #[test]
+    fn test_rejects_non_index_data() {
+        assert!(matches!(
+            Index::parse(b"PACK\x00\x00\x00\x02", HashAlgo::Sha1),
+            Err(ReadObjectErrorType::InvalidIndexError(_))
+        ));
+    }
+}
@@ -1,22 +1,31 @@
+#![allow(clippy::needless_return, clippy::module_inception)]
+
+mod color;
+mod date;
+mod diff;
 mod git_objects;
+mod ignore;
+mod index;
+mod pack;
 mod repository;
 
 use std::{
-    collections::HashSet,
-    fs::{create_dir_all, File},
-    io::{stdout, Read, Write},
+    collections::{BTreeMap, HashSet},
+    fs::{self, File},
+    io::{stdin, stdout, BufWriter, IsTerminal, Read, Write},
     path::Path,
+    process::{self, Command, Stdio},
 };
 
-use clap::{Parser, Subcommand};
-use git_objects::{
-    git_commit::Commit,
-    git_object::GitObjectData,
-    git_tree::{Leaf, Tree},
+use clap::{Parser, Subcommand, ValueEnum};
+use color::{stdout_color, Color};
+use date::DateFormat;
+use git_objects::git_object::{GitObjectData, ObjectKind, ParsedObject};
+use repository::repository::{
+    shorten_ref, Change, HashAlgo, IdentityRole, LsFilesFilter, MergeOutcome, ReadObjectErrorType,
 };
-use repository::repository::ReadObjectErrorType;
 
-use crate::{git_objects::git_object::GitObject, repository::repository::Repository};
+use crate::{git_objects::git_object::GitObject, pack::Pack, repository::repository::Repository};
 
 /// The stupid content tracker
 #[derive(Parser, Debug)]
@@ -25,21 +34,55 @@ struct Args {
     /// The command to run: add, checkout, commit, ...
     #[command(subcommand)]
     command: Option<GitCommands>,
+
+    /// Use the repository at this git directory rather than searching
+    /// for one.
+    #[arg(long, global = true, value_name = "PATH")]
+    git_dir: Option<String>,
+
+    /// Use this directory as the root of the worktree.
+    #[arg(long, global = true, value_name = "PATH")]
+    work_tree: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Debug)]
+enum LogFormat {
+    /// git's default human-readable format, with raw dates
+    Medium,
+    /// A graphviz digraph of the commits
+    Graphviz,
+    /// An array of commit objects
+    Json,
 }
 
 #[derive(Subcommand, Debug)]
 enum GitCommands {
     Init {
         directory: String,
+
+        /// The hash algorithm to name objects with
+        #[arg(long, value_name = "format", default_value = "sha1", value_parser = ["sha1", "sha256"])]
+        object_format: String,
     },
 
     /// Provider content of repository objects.
     #[command(name = "cat-file", about)]
     CatFile {
+        /// List the name and type of every object in the repository.
+        #[arg(long, conflicts_with_all = ["type", "object", "size"])]
+        batch_all_objects: bool,
+        /// Show the size of the object, rather than its content.
+        #[arg(short = 's', value_name = "object", conflicts_with_all = ["type", "object"])]
+        size: Option<String>,
+        /// Pretty-print the object's content, whatever its type.
+        #[arg(short = 'p', value_name = "object", conflicts_with_all = ["type", "object", "size"])]
+        pretty: Option<String>,
         /// Specify the type
-        r#type: String,
+        #[arg(required_unless_present_any = ["batch_all_objects", "size", "pretty"])]
+        r#type: Option<String>,
         /// The object to display
-        object: String,
+        #[arg(required_unless_present_any = ["batch_all_objects", "size", "pretty"])]
+        object: Option<String>,
     },
 
     /// Compute object ID and optionally creates a blob from a file.
@@ -53,183 +96,1826 @@ enum GitCommands {
         #[arg(short, long)]
         write: bool,
 
-        /// Read object from <path>
-        path: String,
+        /// Read the object from standard input instead of from a file
+        #[arg(long)]
+        stdin: bool,
+
+        /// Print the SHA without writing the object, even with --write.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Read objects from <paths>
+        #[arg(required_unless_present = "stdin")]
+        paths: Vec<String>,
+    },
+
+    /// Count unpacked number of objects and their disk consumption.
+    #[command(name = "count-objects", about)]
+    CountObjects {
+        /// Report in more detail, including packed objects.
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Verify the connectivity and validity of the objects in the database.
+    Fsck,
+
+    /// Unpack objects from a packed archive.
+    #[command(name = "unpack-objects", about)]
+    UnpackObjects {
+        /// The pack to unpack; read from stdin if omitted.
+        pack: Option<String>,
+    },
+
+    /// Create a packed archive of objects.
+    #[command(name = "pack-objects", about)]
+    PackObjects {
+        /// Write the pack to <base-name>-<checksum>.pack, with its index
+        #[arg(value_name = "base-name")]
+        base_name: String,
+    },
+
+    /// Validate packed git archive files.
+    #[command(name = "verify-pack", about)]
+    VerifyPack {
+        /// List each object in the pack
+        #[arg(short, long)]
+        verbose: bool,
+
+        /// The pack to verify
+        pack: String,
+    },
+
+    /// Check the GPG signature of a commit.
+    #[command(name = "verify-commit", about)]
+    VerifyCommit {
+        /// The commit to verify.
+        commit: String,
     },
 
     /// Display history of a given commit.
     Log {
+        /// How to print the history
+        #[arg(long, value_enum, default_value_t = LogFormat::Graphviz)]
+        format: LogFormat,
+
+        /// Write the history to a file rather than to stdout
+        #[arg(short, long)]
+        output: Option<String>,
+
+        /// Abbreviate commits to at least this many digits, keeping them
+        /// unambiguous. Only the medium format has room for this.
+        #[arg(long, value_name = "n", num_args = 0..=1, require_equals = true, default_missing_value = "7")]
+        short: Option<usize>,
+
+        /// How to show author dates in the medium format
+        #[arg(long, value_enum, default_value_t = DateFormat::Raw)]
+        date: DateFormat,
+
+        /// Start at the tips of every branch and tag, and HEAD
+        #[arg(long, conflicts_with = "commit")]
+        all: bool,
+
         /// Commit to start at
         commit: Option<String>,
+
+        /// Don't colour the output, even on a terminal
+        #[arg(long)]
+        no_color: bool,
+
+        /// Only show commits which change these paths
+        #[arg(last = true)]
+        paths: Vec<String>,
+    },
+
+    /// List commit objects in reverse chronological order.
+    #[command(name = "rev-list", about)]
+    RevList {
+        /// Commits to start from.
+        #[arg(required = true)]
+        commits: Vec<String>,
+    },
+
+    /// Show the reference log.
+    Reflog {
+        /// The ref whose log to show
+        #[arg(default_value = "HEAD")]
+        reference: String,
+    },
+
+    /// Give an object a human readable name based on an available ref.
+    Describe {
+        /// Use any tag, including lightweight ones
+        #[arg(long)]
+        tags: bool,
+
+        #[arg(default_value = "HEAD")]
+        commit: String,
+    },
+
+    /// Show various types of objects.
+    Show {
+        /// Show a commit's changes from its first parent
+        #[arg(short, long)]
+        patch: bool,
+
+        #[arg(default_value = "HEAD")]
+        object: String,
+    },
+
+    /// Show changes between two blobs, or two trees or commits. Without
+    /// them, show the changes not yet staged, or with --staged, those
+    /// staged for the next commit.
+    Diff {
+        /// Compare HEAD with the index, rather than the index with the
+        /// worktree
+        #[arg(long, visible_alias = "cached", conflicts_with = "old")]
+        staged: bool,
+
+        #[arg(requires = "new")]
+        old: Option<String>,
+
+        new: Option<String>,
+    },
+
+    /// Find as good common ancestors as possible for a merge.
+    #[command(name = "merge-base", about)]
+    MergeBase {
+        /// Output all merge bases instead of just one
+        #[arg(short, long)]
+        all: bool,
+
+        commit: String,
+
+        other: String,
+    },
+
+    /// Show which of the given branches contain each recent commit.
+    #[command(name = "show-branch", about)]
+    ShowBranch {
+        #[arg(required = true)]
+        refs: Vec<String>,
     },
 
     /// Pretty-print a tree object.
     #[command(name = "ls-tree", about)]
     LsTree {
+        /// Show the size of blob entries.
+        #[arg(short, long)]
+        long: bool,
+
+        /// List the whole tree, with paths from its root, whatever the
+        /// current directory.
+        #[arg(long)]
+        full_tree: bool,
+
         /// The object to show.
         object: String,
+
+        /// Only show these paths. A path ending in / shows the directory's
+        /// contents.
+        paths: Vec<String>,
+    },
+
+    /// List the files in the index and the worktree.
+    #[command(name = "ls-files", about)]
+    LsFiles {
+        /// List the files in the index. This is the default if nothing
+        /// else is asked for.
+        #[arg(short, long)]
+        cached: bool,
+
+        /// List untracked files which aren't ignored.
+        #[arg(short, long)]
+        others: bool,
+
+        /// List files which differ from the index, including deleted ones.
+        #[arg(short, long)]
+        modified: bool,
+
+        /// List files missing from the worktree.
+        #[arg(short, long)]
+        deleted: bool,
     },
 
     /// Checkout a commit inside of a directory.
     #[command(about)]
     Checkout {
+        /// Check out into a non-empty directory, overwriting any files
+        /// which are in the tree.
+        #[arg(short, long)]
+        force: bool,
+
         /// The commit or tree to checkout.
         commit: String,
 
-        /// The EMPTY directory to checkout on.
+        /// The directory to checkout on, which must be EMPTY unless forced.
+        /// Without one, the worktree is checked out on and the index is
+        /// updated to match.
+        path: Option<String>,
+    },
+
+    /// List, create, or delete branches.
+    Branch {
+        /// Delete a branch.
+        #[arg(short, long, requires = "name")]
+        delete: bool,
+
+        /// Reset <name> to <start-point>, even if <name> exists already.
+        #[arg(short, long)]
+        force: bool,
+
+        /// The branch to create or delete.
+        name: Option<String>,
+
+        /// The commit the new branch will point at.
+        #[arg(default_value = "HEAD")]
+        start_point: String,
+    },
+
+    /// List, create, or delete tags.
+    Tag {
+        /// Delete a tag.
+        #[arg(short, long, requires = "name", conflicts_with = "list")]
+        delete: bool,
+
+        /// List tags, only those matching <name> as a pattern if it is
+        /// given.
+        #[arg(short, long)]
+        list: bool,
+
+        /// The tag to create or delete, or the pattern to list.
+        name: Option<String>,
+
+        /// The object the new tag will point at.
+        #[arg(default_value = "HEAD")]
+        object: String,
+    },
+
+    /// Update the object name stored in a ref safely.
+    #[command(name = "update-ref", about)]
+    UpdateRef {
+        /// The reason for the update, recorded in the reflog.
+        #[arg(short, value_name = "reason", default_value = "")]
+        message: String,
+
+        /// Create a reflog for the ref if it doesn't have one.
+        #[arg(long)]
+        create_reflog: bool,
+
+        /// The ref to update, e.g. refs/heads/master.
+        reference: String,
+
+        /// The object the ref will point at.
+        new_value: String,
+    },
+
+    /// Read, modify and delete symbolic refs.
+    #[command(name = "symbolic-ref", about)]
+    SymbolicRef {
+        /// Shorten the ref output, e.g. refs/heads/master to master.
+        #[arg(long)]
+        short: bool,
+
+        /// The symbolic ref to read or update, usually HEAD.
+        name: String,
+
+        /// The ref the symbolic ref should point to.
+        target: Option<String>,
+    },
+
+    /// Clone a local repository into a new directory.
+    Clone {
+        /// The repository to clone from.
+        source: String,
+
+        /// The EMPTY directory to clone into.
+        directory: String,
+    },
+
+    /// Pack loose objects to save space.
+    Gc,
+
+    /// Stage files, and every file in directories, for the next commit.
+    Add {
+        /// The files or directories to stage.
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+
+    /// Move or rename a tracked file.
+    Mv {
+        /// Replace the destination if it exists.
+        #[arg(short, long)]
+        force: bool,
+
+        /// The tracked file to move.
+        source: String,
+
+        /// The new path, or a directory to move the file into.
+        destination: String,
+    },
+
+    /// Delete loose objects which no ref or HEAD can reach.
+    Prune {
+        /// List the objects which would be deleted, without deleting them.
+        #[arg(short = 'n', long)]
+        dry_run: bool,
+    },
+
+    /// Show a git logical variable.
+    Var {
+        #[arg(value_parser = ["GIT_AUTHOR_IDENT", "GIT_COMMITTER_IDENT"])]
+        variable: String,
+    },
+
+    /// Output information on each ref.
+    #[command(name = "for-each-ref", about)]
+    ForEachRef {
+        /// How to print each ref, interpolating %(refname), %(objectname)
+        /// and %(objecttype).
+        #[arg(long, default_value = "%(objectname) %(objecttype)\t%(refname)")]
+        format: String,
+
+        /// Only show refs matching this name or glob, e.g. refs/tags/*.
+        pattern: Option<String>,
+    },
+
+    /// Print the object each name refers to.
+    #[command(name = "rev-parse", about)]
+    RevParse {
+        /// Print the short name of the ref each name refers to instead,
+        /// e.g. master for HEAD when on master, or HEAD when detached.
+        #[arg(long)]
+        abbrev_ref: bool,
+
+        /// Abbreviate each object to at least this many digits, keeping it
+        /// unambiguous.
+        #[arg(long, value_name = "n", num_args = 0..=1, require_equals = true, default_missing_value = "7", conflicts_with = "abbrev_ref")]
+        short: Option<usize>,
+
+        /// Check that the one name given is a single object which exists,
+        /// and print it
+        #[arg(long, conflicts_with_all = ["abbrev_ref", "short", "git_dir", "show_toplevel"])]
+        verify: bool,
+
+        /// With --verify, fail quietly with exit status 1 instead
+        #[arg(short, long, requires = "verify")]
+        quiet: bool,
+
+        /// Print the path of the git directory first
+        #[arg(long)]
+        git_dir: bool,
+
+        /// Print the path of the top of the worktree first
+        #[arg(long)]
+        show_toplevel: bool,
+
+        /// The names to parse, e.g. HEAD.
+        #[arg(required_unless_present_any = ["git_dir", "show_toplevel"])]
+        names: Vec<String>,
+    },
+
+    /// Show the commit which last changed each line of a file.
+    Blame {
+        /// The commit whose version of the file to show.
+        commit: String,
+
+        /// The file to show.
         path: String,
     },
+
+    /// Show what has changed in the index and the worktree.
+    Status {
+        /// Print a line per path in the stable format scripts can parse.
+        #[arg(long)]
+        porcelain: bool,
+
+        /// Don't colour the output, even on a terminal
+        #[arg(long)]
+        no_color: bool,
+    },
+
+    /// Replace the index with the contents of a tree.
+    #[command(name = "read-tree", about)]
+    ReadTree {
+        /// The tree, or a commit whose tree, to read.
+        tree: String,
+    },
+
+    /// Create a tree object from the index, printing its SHA.
+    #[command(name = "write-tree", about)]
+    WriteTree {
+        /// Print the SHA of the tree without writing it.
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Switch the worktree and HEAD to a branch, or detach onto a commit.
+    Switch {
+        /// The branch or commit to switch to.
+        target: String,
+    },
+
+    /// Join another line of history into the current one.
+    Merge {
+        /// The branch or commit to merge into HEAD.
+        commit: String,
+    },
+
+    /// Apply the changes introduced by an existing commit.
+    #[command(name = "cherry-pick", about)]
+    CherryPick {
+        /// The commit to apply onto HEAD.
+        commit: String,
+    },
 }
 
-fn main() -> Result<(), ReadObjectErrorType> {
+fn main() {
     env_logger::init();
     let args = Args::parse();
+    Repository::set_dir_options(args.git_dir, args.work_tree);
 
-    return match args.command {
-        Some(GitCommands::Init { directory: path }) => {
-            Repository::repo_create(Path::new(&path)).map_err(ReadObjectErrorType::IO)
-        }
-        Some(GitCommands::CatFile { r#type, object }) => {
-            cat_file(r#type, &object).map_err(ReadObjectErrorType::IO)
+    // Like git, report failures in words and exit with 128
+    if let Err(e) = run(args.command) {
+        eprintln!("fatal: {}", e);
+        process::exit(128);
+    }
+}
+
+fn run(command: Option<GitCommands>) -> Result<(), ReadObjectErrorType> {
+    return match command {
+        Some(GitCommands::Init {
+            directory: path,
+            object_format,
+        }) => init(&path, &object_format),
+        Some(GitCommands::CatFile {
+            batch_all_objects: true,
+            ..
+        }) => cat_all_objects(),
+        Some(GitCommands::CatFile {
+            size: Some(object), ..
+        }) => cat_size(&object),
+        Some(GitCommands::CatFile {
+            pretty: Some(object),
+            ..
+        }) => cat_pretty(&object),
+        Some(GitCommands::CatFile { r#type, object, .. }) => {
+            cat_file(r#type.unwrap(), &object.unwrap())
         }
         Some(GitCommands::HashObject {
             r#type,
             write,
-            path,
-        }) => hash_file(r#type, write, path).map_err(ReadObjectErrorType::IO),
-        Some(GitCommands::Log { commit }) => match commit {
-            Some(commit) => print_log(commit),
-            None => print_log("HEAD".to_string()),
+            stdin,
+            dry_run,
+            paths,
+        }) => hash_file(r#type, write && !dry_run, stdin, &paths),
+        Some(GitCommands::CountObjects { verbose }) => count_objects(verbose),
+        Some(GitCommands::Fsck) => fsck(),
+        Some(GitCommands::UnpackObjects { pack }) => unpack_objects(pack),
+        Some(GitCommands::PackObjects { base_name }) => pack_objects(base_name),
+        Some(GitCommands::VerifyPack { verbose, pack }) => verify_pack(verbose, pack),
+        Some(GitCommands::VerifyCommit { commit }) => verify_commit(&commit),
+        Some(GitCommands::Log {
+            format,
+            output,
+            short,
+            date,
+            all,
+            commit,
+            no_color,
+            paths,
+        }) => print_log(
+            all,
+            commit,
+            &paths,
+            format,
+            output.as_deref(),
+            short,
+            date,
+            no_color,
+        ),
+        Some(GitCommands::RevList { commits }) => rev_list(commits),
+        Some(GitCommands::Reflog { reference }) => reflog(&reference),
+        Some(GitCommands::Describe { tags, commit }) => describe(tags, &commit),
+        Some(GitCommands::Show { patch, object }) => show(patch, &object),
+        Some(GitCommands::Diff { staged, old, new }) => match (old, new) {
+            (Some(old), Some(new)) => diff(&old, &new),
+            _ => diff_index(staged),
         },
-        Some(GitCommands::LsTree { object }) => ls_tree(&object),
-        Some(GitCommands::Checkout { commit, path }) => checkout(commit, path),
-        None => Ok({}),
+        Some(GitCommands::MergeBase { all, commit, other }) => merge_base(all, &commit, &other),
+        Some(GitCommands::ShowBranch { refs }) => show_branch(&refs),
+        Some(GitCommands::LsTree {
+            long,
+            full_tree,
+            object,
+            paths,
+        }) => ls_tree(long, full_tree, &object, &paths),
+        Some(GitCommands::LsFiles {
+            cached,
+            others,
+            modified,
+            deleted,
+        }) => ls_files(LsFilesFilter {
+            cached: cached || !(others || modified || deleted),
+            others,
+            modified,
+            deleted,
+        }),
+        Some(GitCommands::Checkout {
+            force,
+            commit,
+            path,
+        }) => checkout(force, commit, path),
+        Some(GitCommands::Branch {
+            delete,
+            force,
+            name,
+            start_point,
+        }) => branch(delete, force, name, start_point),
+        Some(GitCommands::Tag {
+            delete,
+            list,
+            name,
+            object,
+        }) => tag(delete, list, name, object),
+        Some(GitCommands::UpdateRef {
+            message,
+            create_reflog,
+            reference,
+            new_value,
+        }) => update_ref(&message, create_reflog, &reference, new_value),
+        Some(GitCommands::SymbolicRef {
+            short,
+            name,
+            target,
+        }) => symbolic_ref(short, &name, target),
+        Some(GitCommands::Switch { target }) => switch(&target),
+        Some(GitCommands::Clone { source, directory }) => clone(source, &directory),
+        Some(GitCommands::Gc) => gc(),
+        Some(GitCommands::Prune { dry_run }) => prune(dry_run),
+        Some(GitCommands::Add { paths }) => add(&paths),
+        Some(GitCommands::Mv {
+            force,
+            source,
+            destination,
+        }) => mv(force, &source, &destination),
+        Some(GitCommands::Var { variable }) => var(&variable),
+        Some(GitCommands::ForEachRef { format, pattern }) => {
+            for_each_ref(&format, pattern.as_deref())
+        }
+        Some(GitCommands::RevParse {
+            verify: true,
+            quiet,
+            names,
+            ..
+        }) => verify_revision(quiet, &names),
+        Some(GitCommands::RevParse {
+            abbrev_ref,
+            short,
+            git_dir,
+            show_toplevel,
+            names,
+            ..
+        }) => rev_parse(abbrev_ref, short, git_dir, show_toplevel, &names),
+        Some(GitCommands::Blame { commit, path }) => blame(&commit, &path),
+        Some(GitCommands::Status {
+            porcelain,
+            no_color,
+        }) => status(porcelain, no_color),
+        Some(GitCommands::ReadTree { tree }) => read_tree(&tree),
+        Some(GitCommands::WriteTree { dry_run }) => write_tree(dry_run),
+        Some(GitCommands::Merge { commit }) => merge(&commit),
+        Some(GitCommands::CherryPick { commit }) => cherry_pick(&commit),
+        None => Ok(()),
     };
 }
 
-fn cat_file(r#type: String, object: &String) -> Result<(), std::io::Error> {
-    let repo = Repository::repo_find(String::from(object), None)
+fn init(path: &str, object_format: &str) -> Result<(), ReadObjectErrorType> {
+    let path = Path::new(path);
+    let existing = Repository::repo_create(path, HashAlgo::from_name(object_format).unwrap())
+        .map_err(ReadObjectErrorType::IO)?;
+    if existing {
+        let git_dir = path
+            .join(".git")
+            .canonicalize()
+            .map_err(ReadObjectErrorType::IO)?;
+        println!(
+            "Reinitialized existing Git repository in {}/",
+            git_dir.display()
+        );
+    }
+
+    return Ok(());
+}
+
+fn cat_file(r#type: String, object: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
         .expect("No git directory when required")
         .unwrap();
 
-    let sha = repo.object_find(String::from(object), Some(r#type), None);
-    let object = repo.read_object(sha).unwrap();
+    let mut out = BufWriter::new(stdout().lock());
+    cat_object(&repo, r#type.parse()?, object, &mut out)?;
+
+    return out.flush().map_err(ReadObjectErrorType::IO);
+}
+
+/// Write the raw content of object, peeled to kind, to out in full.
+fn cat_object(
+    repo: &Repository,
+    kind: ObjectKind,
+    object: &str,
+    out: &mut impl Write,
+) -> Result<(), ReadObjectErrorType> {
+    let sha = repo.object_find(String::from(object), Some(kind), None)?;
+    let object = repo.read_object(sha)?;
 
     let GitObjectData(_, data) = object.serialize();
-    stdout().write(data.as_slice())?;
+    return out
+        .write_all(data.as_slice())
+        .map_err(ReadObjectErrorType::IO);
+}
+
+fn cat_pretty(object: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let sha = repo.object_find(String::from(object), None, None)?;
+    let mut out = BufWriter::new(stdout().lock());
+    repo.cat_pretty(&sha, stdout().is_terminal(), &mut out)?;
+
+    return out.flush().map_err(ReadObjectErrorType::IO);
+}
+
+fn cat_size(object: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    return object_size(&repo, object).map(|size| println!("{}", size));
+}
+
+/// The size of object, taken from its header without parsing its content.
+fn object_size(repo: &Repository, object: &str) -> Result<u64, ReadObjectErrorType> {
+    let sha = repo.object_find(String::from(object), None, None)?;
+    return Ok(repo.stat_object(&sha)?.1);
+}
+
+fn cat_all_objects() -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    // Like git, list each object once, in SHA order, wherever it is stored
+    let mut objects = BTreeMap::new();
+    for object in repo.iter_objects() {
+        let (sha, kind) = object?;
+        objects.insert(sha, kind);
+    }
+
+    for (sha, kind) in objects {
+        println!("{} {}", sha, kind);
+    }
 
     return Ok(());
 }
 
-fn hash_file(r#type: String, write: bool, path: String) -> Result<(), std::io::Error> {
+fn hash_file(
+    r#type: String,
+    write: bool,
+    stdin: bool,
+    paths: &[String],
+) -> Result<(), ReadObjectErrorType> {
+    let kind: ObjectKind = r#type.parse()?;
     let repo = match write {
-        true => Some(Repository::new(&Path::new("."), false)),
+        true => Some(Repository::new(Path::new("."), false)),
         false => None,
     };
 
-    let fd = File::open(path)?;
-    let sha = object_hash(fd, r#type, repo);
-    stdout().write(format!("{:?}", sha).as_bytes())?;
+    // Like git, standard input is hashed before any files
+    if stdin {
+        let sha = object_hash(std::io::stdin(), kind, repo.clone());
+        println!("{}", sha.map_err(ReadObjectErrorType::IO)?);
+    }
+
+    // Files are converted as add would, if there's a repository to say how
+    let filters = Repository::repo_find(".".to_string(), Some(false))
+        .ok()
+        .flatten();
+    return hash_paths(paths, kind, repo, filters.as_ref(), &mut stdout())
+        .map_err(ReadObjectErrorType::IO);
+}
+
+/// Hash each of the blobs at paths, storing them in repo if given, and
+/// converting their content as filters says files in it should be.
+fn hash_paths(
+    paths: &[String],
+    kind: ObjectKind,
+    repo: Option<Repository>,
+    filters: Option<&Repository>,
+    out: &mut impl Write,
+) -> Result<(), std::io::Error> {
+    for path in paths {
+        let data = match filters {
+            Some(filters) if kind == ObjectKind::Blob => filters.clean_file(fs::read(path)?),
+            _ => fs::read(path)?,
+        };
+        let sha = object_hash(&data[..], kind, repo.clone())?;
+        writeln!(out, "{}", sha)?;
+    }
 
     return Ok(());
 }
 
 fn object_hash(
-    mut fd: File,
-    fmt: String,
+    mut fd: impl Read,
+    kind: ObjectKind,
     repo: Option<Repository>,
 ) -> Result<String, std::io::Error> {
     let mut data = Vec::<u8>::new();
     fd.read_to_end(&mut data)?;
 
-    let write_object =
-        GitObject::write_object(GitObject::new(repo, Some(GitObjectData(fmt, data))), None);
-    return Ok(write_object);
+    // Only objects with a repository to go in are written
+    let write = repo.is_some();
+    let object = GitObject::new(repo, GitObjectData(kind, data));
+    return GitObject::write_object(object, Some(write)).map_err(|e| match e {
+        ReadObjectErrorType::IO(e) => e,
+        e => std::io::Error::other(e.to_string()),
+    });
 }
 
-fn print_log(commit: String) -> Result<(), ReadObjectErrorType> {
+fn count_objects(verbose: bool) -> Result<(), ReadObjectErrorType> {
     let repo = Repository::repo_find(".".to_string(), None)
         .expect("No git directory when required")
         .unwrap();
 
-    print!("digraph wyaglog{{");
-    let sha = repo.object_find(commit.clone(), None, None);
-    repo.log_graphviz(sha, &mut HashSet::new())?;
-    print!("}}");
+    let counts = repo.count_objects()?;
+    println!("count: {}", counts.count);
+    println!("size: {}", counts.size / 1024);
+    if verbose {
+        println!("in-pack: {}", counts.in_pack);
+        println!("packs: {}", counts.packs);
+        println!("size-pack: {}", counts.size_pack / 1024);
+    }
+
+    return Ok(());
+}
+
+fn fsck() -> Result<(), ReadObjectErrorType> {
+    let mut repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+    // Nothing fsck reads should be trusted without checking it
+    repo.set_verify_objects(true);
+
+    let problems = repo.fsck()?;
+    for problem in &problems {
+        println!("{}", problem);
+    }
+
+    if problems.iter().any(|problem| problem.is_error()) {
+        std::process::exit(1);
+    }
 
     return Ok(());
 }
 
-fn ls_tree(object: &String) -> Result<(), ReadObjectErrorType> {
+fn unpack_objects(path: Option<String>) -> Result<(), ReadObjectErrorType> {
     let repo = Repository::repo_find(".".to_string(), None)
         .expect("No git directory when required")
         .unwrap();
-    let sha = repo.object_find(object.clone(), Some("tree".to_owned()), None);
-    let object = repo.read_object(sha)?;
-    let object = object
-        .as_any()
-        .downcast_ref::<Tree>()
-        .expect("Not a Tree object.");
-
-    for item in &object.items {
-        let Leaf(mode, path, sha) = item;
-        let GitObjectData(fmt, _) = (*repo.read_object(sha.to_string())?).get_data();
-        println!(
-            "{} {} {}\t{}",
-            "0".repeat(6 - mode.len()) + mode.as_str(),
-            fmt,
-            &sha,
-            path
-        )
+
+    let pack = match path {
+        Some(path) => Pack::open(Path::new(&path))?,
+        None => {
+            let mut data = Vec::new();
+            stdin()
+                .read_to_end(&mut data)
+                .map_err(ReadObjectErrorType::IO)?;
+            Pack::from_bytes(data)?
+        }
+    };
+
+    let written = repo.unpack_objects(&pack)?;
+    println!("Unpacked {} objects", written.len());
+    if !pack.has_index() {
+        println!("No index to check them against, so their SHAs were not verified");
     }
+
     return Ok(());
 }
 
-fn checkout(commit: String, path: String) -> Result<(), ReadObjectErrorType> {
+fn pack_objects(base_name: String) -> Result<(), ReadObjectErrorType> {
     let repo = Repository::repo_find(".".to_string(), None)
         .expect("No git directory when required")
         .unwrap();
 
-    let object = repo.read_object(repo.object_find(commit, None, None))?;
+    let mut shas = Vec::new();
+    for line in stdin().lines() {
+        let line = line.map_err(ReadObjectErrorType::IO)?;
+        if !line.trim().is_empty() {
+            shas.push(repo.object_find(line.trim().to_string(), None, None)?);
+        }
+    }
 
-    // If the object is a commit, grab its tree
-    let commit = object.as_any().downcast_ref::<Commit>();
+    println!("{}", repo.pack_objects(&shas, &base_name)?);
 
-    let tree_candidate = match commit {
-        Some(commit) => repo.read_object(
-            commit
-                .kvlm
-                .get("tree")
-                .ok_or(ReadObjectErrorType::TreeNotFoundError)?[0]
-                .clone(),
-        )?,
-        None => object,
-    };
+    return Ok(());
+}
 
-    let tree = match tree_candidate.as_any().downcast_ref::<Tree>() {
-        Some(tree) => tree,
-        None => panic!("Not a tree object!"),
-    };
+fn verify_pack(verbose: bool, path: String) -> Result<(), ReadObjectErrorType> {
+    // Like git, accept the pack's index in place of the pack itself
+    let path = Path::new(&path).with_extension("pack");
+    let pack = Pack::open(&path)?;
+    pack.verify_checksum()?;
 
-    let path = Path::new(&path);
-    if path.exists() {
-        if !path.is_dir() {
-            return Err(ReadObjectErrorType::InvalidPathError);
+    let contents = pack.contents()?;
+    if verbose {
+        let mut chain_lengths = BTreeMap::new();
+        for info in &contents {
+            let line = format!(
+                "{} {:6} {} {} {}",
+                info.sha, info.object_type, info.size, info.packed_size, info.offset
+            );
+            match &info.delta {
+                Some((depth, base)) => println!("{} {} {}", line, depth, base),
+                None => println!("{}", line),
+            }
+            *chain_lengths
+                .entry(info.delta.as_ref().map_or(0, |(depth, _)| *depth))
+                .or_insert(0) += 1;
         }
 
-        let is_empty = path
-            .read_dir()
-            .map_err(ReadObjectErrorType::IO)?
-            .next()
-            .is_none();
-        if !is_empty {
-            return Err(ReadObjectErrorType::InvalidPathError);
+        for (depth, count) in chain_lengths {
+            let objects = if count == 1 { "object" } else { "objects" };
+            match depth {
+                0 => println!("non delta: {} {}", count, objects),
+                _ => println!("chain length = {}: {} {}", depth, count, objects),
+            }
         }
-    } else {
-        create_dir_all(&path).map_err(ReadObjectErrorType::IO)?;
     }
 
-    repo.tree_checkout(tree, &path.canonicalize().map_err(ReadObjectErrorType::IO)?)?;
+    println!("{}: ok", path.display());
+
+    return Ok(());
+}
+
+fn verify_commit(commit: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let sha = repo.object_find(commit.to_string(), Some(ObjectKind::Commit), None)?;
+    let commit = repo.read_commit(&sha)?;
+    let Some(signature) = commit.gpg_signature() else {
+        return Err(ReadObjectErrorType::NoSignatureError(sha));
+    };
+
+    // Like git, hand the checking to gpg, which reads a detached signature
+    // from a file and what was signed from stdin
+    let signature_path = std::env::temp_dir().join(format!("wyag-signature-{}", process::id()));
+    fs::write(&signature_path, format!("{}\n", signature)).map_err(ReadObjectErrorType::IO)?;
+    let program = repo
+        .config_get("gpg", "program")
+        .unwrap_or_else(|| "gpg".to_string());
+    let gpg = Command::new(program)
+        .arg("--verify")
+        .arg(&signature_path)
+        .arg("-")
+        .stdin(Stdio::piped())
+        .spawn();
+    let status = gpg.and_then(|mut gpg| {
+        gpg.stdin
+            .take()
+            .expect("gpg has a stdin")
+            .write_all(&commit.signed_payload())?;
+        return gpg.wait();
+    });
+    fs::remove_file(&signature_path).map_err(ReadObjectErrorType::IO)?;
+
+    if !status.map_err(ReadObjectErrorType::IO)?.success() {
+        process::exit(1);
+    }
+
+    return Ok(());
+}
+
+#[allow(clippy::too_many_arguments)]
+fn print_log(
+    all: bool,
+    commit: Option<String>,
+    paths: &[String],
+    format: LogFormat,
+    output: Option<&str>,
+    short: Option<usize>,
+    date: DateFormat,
+    no_color: bool,
+) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let start = match (all, commit) {
+        (true, _) => repo.ref_tips()?,
+        (false, Some(commit)) => vec![repo.object_find(commit, None, None)?],
+        (false, None) => {
+            if repo.head_commit()?.is_none() {
+                let branch = repo.current_branch()?.unwrap_or_default();
+                return Err(ReadObjectErrorType::UnbornBranchError(branch));
+            }
+            vec![repo.object_find("HEAD".to_string(), None, None)?]
+        }
+    };
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => Box::new(File::create(path).map_err(ReadObjectErrorType::IO)?),
+        None => Box::new(stdout()),
+    };
+    match format {
+        LogFormat::Medium => {
+            // Only a terminal gets colours, never a file
+            let color = output.is_none() && stdout_color(no_color);
+            repo.log_medium(&start, paths, short, date, color, &mut out)?
+        }
+        LogFormat::Graphviz => repo.log_graphviz(&start, paths, &mut out)?,
+        LogFormat::Json => repo.log_json(&start, paths, &mut out)?,
+    }
 
     return Ok(());
 }
+
+fn reflog(reference: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    // Accept short names, as the other commands do
+    let full_name = repo
+        .ref_full_name(reference)?
+        .ok_or_else(|| ReadObjectErrorType::InvalidRefError(reference.to_string()))?;
+
+    for (n, entry) in repo.reflog(&full_name)?.iter().enumerate() {
+        println!(
+            "{} {}@{{{}}}: {}",
+            &entry.new[0..7],
+            reference,
+            n,
+            entry.message
+        );
+    }
+
+    return Ok(());
+}
+
+fn describe(tags: bool, commit: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let sha = repo.object_find(commit.to_string(), Some(ObjectKind::Commit), None)?;
+    println!("{}", repo.describe(&sha, tags)?);
+
+    return Ok(());
+}
+
+fn show(patch: bool, object: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let sha = repo.object_find(object.to_string(), None, None)?;
+    repo.show(&sha, patch, stdout().is_terminal(), &mut stdout())?;
+
+    return Ok(());
+}
+
+fn diff(old: &str, new: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let GitObjectData(old_type, old_data) = repo
+        .read_object(repo.object_find(old.to_string(), None, None)?)?
+        .get_data();
+    let GitObjectData(new_type, new_data) = repo
+        .read_object(repo.object_find(new.to_string(), None, None)?)?
+        .get_data();
+
+    // Commits are compared by their trees
+    let tree_of = |name: &str| repo.object_find(name.to_string(), Some(ObjectKind::Tree), None);
+
+    if old_type == ObjectKind::Blob && new_type == ObjectKind::Blob {
+        print!(
+            "{}",
+            diff::unified_diff(
+                &String::from_utf8_lossy(&old_data),
+                &String::from_utf8_lossy(&new_data),
+                &format!("a/{}", old),
+                &format!("b/{}", new),
+            )
+        );
+    } else {
+        for change in repo.diff_trees(&tree_of(old)?, &tree_of(new)?)? {
+            println!("{}", change);
+        }
+    }
+
+    return Ok(());
+}
+
+fn diff_index(staged: bool) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let mut out = BufWriter::new(stdout().lock());
+    repo.diff_index(staged, &mut out)?;
+    return out.flush().map_err(ReadObjectErrorType::IO);
+}
+
+fn show_branch(refs: &[String]) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let mut out = BufWriter::new(stdout().lock());
+    repo.show_branch(refs, &mut out)?;
+    return out.flush().map_err(ReadObjectErrorType::IO);
+}
+
+fn merge_base(all: bool, commit: &str, other: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let commit = repo.object_find(commit.to_string(), Some(ObjectKind::Commit), None)?;
+    let other = repo.object_find(other.to_string(), Some(ObjectKind::Commit), None)?;
+
+    let bases = repo.merge_bases(&commit, &other)?;
+    let count = if all { bases.len() } else { 1 };
+    for base in bases.iter().take(count) {
+        println!("{}", base);
+    }
+
+    // Like git, fail when the commits share no history
+    if bases.is_empty() {
+        std::process::exit(1);
+    }
+
+    return Ok(());
+}
+
+fn rev_list(commits: Vec<String>) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let mut start = Vec::new();
+    for commit in commits {
+        start.push(repo.object_find(commit, Some(ObjectKind::Commit), None)?);
+    }
+
+    for sha in repo.walk_history(&start, HashSet::new()) {
+        println!("{}", sha?);
+    }
+
+    return Ok(());
+}
+
+fn ls_files(filter: LsFilesFilter) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let mut out = BufWriter::new(stdout().lock());
+    for path in repo.ls_files(filter)? {
+        writeln!(out, "{}", path).map_err(ReadObjectErrorType::IO)?;
+    }
+    return out.flush().map_err(ReadObjectErrorType::IO);
+}
+
+fn ls_tree(
+    long: bool,
+    full_tree: bool,
+    object: &str,
+    paths: &[String],
+) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+    let sha = repo.object_find(object.to_string(), Some(ObjectKind::Tree), None)?;
+
+    // Like git, paths are relative to the current directory, which is the
+    // directory listed by default, unless the whole tree is asked for
+    let tree_path = |path: &str| -> Result<String, ReadObjectErrorType> {
+        let mut tree_path = repo
+            .worktree_relative(Path::new(path))?
+            .to_string_lossy()
+            .replace(std::path::MAIN_SEPARATOR, "/");
+        if !tree_path.is_empty() && (path.ends_with('/') || Path::new(path).file_name().is_none()) {
+            tree_path.push('/');
+        }
+        return Ok(tree_path);
+    };
+    let (prefix, paths) = match full_tree {
+        true => (String::new(), paths.to_vec()),
+        false => (
+            tree_path(".")?,
+            match paths.is_empty() {
+                true => vec![tree_path(".")?],
+                false => paths
+                    .iter()
+                    .map(|path| tree_path(path))
+                    .collect::<Result<Vec<String>, ReadObjectErrorType>>()?,
+            },
+        ),
+    };
+
+    for leaf in repo.ls_tree(&sha, &paths)? {
+        let entry = repo.read_object_typed(leaf.sha().to_string())?;
+
+        // Like git, pad the size to line up, and show none for trees
+        let size = match (long, &entry) {
+            (false, _) => String::new(),
+            (true, ParsedObject::Blob(blob)) => format!(" {:>7}", blob.len()),
+            (true, _) => format!(" {:>7}", "-"),
+        };
+        println!(
+            "{} {} {}{}\t{}",
+            leaf.padded_mode(),
+            entry.kind(),
+            leaf.sha(),
+            size,
+            relative_to(leaf.path(), &prefix)
+        )
+    }
+    return Ok(());
+}
+
+/// path, which is from the root of a tree, as seen from dir, which ends in a
+/// / unless it is the root.
+fn relative_to(path: &str, dir: &str) -> String {
+    // Comparing path as a directory finds dir's own ancestors too
+    let path = format!("{}/", path);
+    let mut dir = dir;
+    let mut up = String::new();
+    while !path.starts_with(dir) {
+        dir = dir[..dir.len() - 1]
+            .rfind('/')
+            .map_or("", |end| &dir[..=end]);
+        up.push_str("../");
+    }
+
+    return match (&path[dir.len()..], up.is_empty()) {
+        ("", true) => "./".to_string(),
+        ("", false) => up,
+        (rest, _) => format!("{}{}", up, &rest[..rest.len() - 1]),
+    };
+}
+
+fn checkout(force: bool, commit: String, path: Option<String>) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    // Commits, and tags of them, are peeled to their tree
+    let sha = repo.object_find(commit, Some(ObjectKind::Tree), None)?;
+    let Some(path) = path else {
+        return repo.checkout_worktree(&sha, force);
+    };
+
+    let tree = match repo.read_object_typed(sha.clone())? {
+        ParsedObject::Tree(tree) => tree,
+        other => {
+            return Err(ReadObjectErrorType::UnexpectedObjectTypeError(
+                sha,
+                other.kind(),
+            ))
+        }
+    };
+
+    let path = Path::new(&path);
+    if path.exists() {
+        if !path.is_dir() {
+            return Err(ReadObjectErrorType::InvalidPathError);
+        }
+
+        let is_empty = path
+            .read_dir()
+            .map_err(ReadObjectErrorType::IO)?
+            .next()
+            .is_none();
+        if !is_empty && !force {
+            return Err(ReadObjectErrorType::InvalidPathError);
+        }
+    }
+
+    repo.tree_checkout(&tree, path, force)?;
+
+    return Ok(());
+}
+
+fn branch(
+    delete: bool,
+    force: bool,
+    name: Option<String>,
+    start_point: String,
+) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    return match (name, delete) {
+        (Some(name), true) => repo.branch_delete(&name),
+        (Some(name), false) => {
+            let sha = repo.object_find(start_point, Some(ObjectKind::Commit), None)?;
+            repo.branch_create(&name, &sha, force)
+        }
+        (None, _) => {
+            let current = repo.current_branch()?;
+            for name in repo.ref_list("refs/heads")?.keys() {
+                let name = name.trim_start_matches("refs/heads/");
+                let marker = match current.as_deref() == Some(name) {
+                    true => "*",
+                    false => " ",
+                };
+                println!("{} {}", marker, name);
+            }
+
+            Ok(())
+        }
+    };
+}
+
+fn tag(
+    delete: bool,
+    list: bool,
+    name: Option<String>,
+    object: String,
+) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    return match (name, delete, list) {
+        (Some(name), true, _) => {
+            let sha = repo.tag_delete(&name)?;
+            println!("Deleted tag '{}' (was {})", name, repo.abbreviate(&sha, 7)?);
+            Ok(())
+        }
+        (Some(name), false, false) => {
+            let sha = repo.object_find(object, None, None)?;
+            repo.tag_create(&name, &sha)
+        }
+        (pattern, _, _) => {
+            for name in repo.tag_list(pattern.as_deref())? {
+                println!("{}", name);
+            }
+            Ok(())
+        }
+    };
+}
+
+fn update_ref(
+    message: &str,
+    create_reflog: bool,
+    reference: &str,
+    new_value: String,
+) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let sha = repo.object_find(new_value, None, None)?;
+
+    // Like git, updating HEAD while on a branch moves the branch
+    return repo.ref_update(reference, &sha, message, create_reflog);
+}
+
+fn symbolic_ref(
+    short: bool,
+    name: &str,
+    target: Option<String>,
+) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    if let Some(target) = target {
+        return repo.symbolic_ref_set(name, &target);
+    }
+
+    let reference = repo.symbolic_ref(name)?;
+    match short {
+        true => println!("{}", shorten_ref(&reference)),
+        false => println!("{}", reference),
+    }
+
+    return Ok(());
+}
+
+fn switch(target: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    return repo.switch(target);
+}
+
+fn merge(commit: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let head = repo.ref_resolve("HEAD")?.unwrap_or_default();
+    match repo.merge(commit)? {
+        MergeOutcome::UpToDate => println!("Already up to date."),
+        MergeOutcome::FastForward(sha) => {
+            println!("Updating {}..{}", &head[0..7], &sha[0..7]);
+            println!("Fast-forward");
+        }
+        MergeOutcome::Merged(_) => println!("Merge made by the 'three-way' strategy."),
+        MergeOutcome::Conflicted(paths) => {
+            for path in paths {
+                println!("CONFLICT: Merge conflict in {}", path);
+            }
+            println!("Automatic merge failed; fix conflicts and then commit the result.");
+            std::process::exit(1);
+        }
+    }
+
+    return Ok(());
+}
+
+fn cherry_pick(commit: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let sha = repo.object_find(commit.to_string(), Some(ObjectKind::Commit), None)?;
+    let picked = repo.cherry_pick(&sha)?;
+
+    let branch = repo
+        .current_branch()?
+        .unwrap_or_else(|| "detached HEAD".to_string());
+    let message = repo.read_commit(&picked)?.message().to_string();
+    println!(
+        "[{} {}] {}",
+        branch,
+        repo.abbreviate(&picked, 7)?,
+        message.lines().next().unwrap_or_default()
+    );
+
+    return Ok(());
+}
+
+fn clone(source: String, directory: &str) -> Result<(), ReadObjectErrorType> {
+    let source = Repository::repo_find(source, None)
+        .map_err(ReadObjectErrorType::IO)?
+        .unwrap();
+
+    Repository::repo_clone(&source, Path::new(directory))?;
+    return Ok(());
+}
+
+fn gc() -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    repo.gc()?;
+    return Ok(());
+}
+
+fn add(paths: &[String]) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let mut relative = Vec::with_capacity(paths.len());
+    for path in paths {
+        relative.push(repo.worktree_relative(Path::new(path))?);
+    }
+
+    return repo.add(&relative);
+}
+
+fn mv(force: bool, source: &str, destination: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let source = repo.worktree_relative(Path::new(source))?;
+    let destination = repo.worktree_relative(Path::new(destination))?;
+    return repo.mv(&source, &destination, force);
+}
+
+fn prune(dry_run: bool) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    // Like git, only list what is pruned when it isn't actually done
+    if dry_run {
+        for sha in repo.prune(true)? {
+            println!("{} {}", sha, repo.read_object_typed(sha.clone())?.kind());
+        }
+    } else {
+        repo.prune(false)?;
+    }
+
+    return Ok(());
+}
+
+fn var(variable: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let role = match variable {
+        "GIT_AUTHOR_IDENT" => IdentityRole::Author,
+        _ => IdentityRole::Committer,
+    };
+    println!("{}", repo.identity(role)?);
+
+    return Ok(());
+}
+
+fn for_each_ref(format: &str, pattern: Option<&str>) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    return repo.for_each_ref(pattern, format, &mut stdout());
+}
+
+fn blame(commit: &str, path: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let commit = repo.object_find(commit.to_string(), Some(ObjectKind::Commit), None)?;
+    let path = repo
+        .worktree_relative(Path::new(path))?
+        .to_string_lossy()
+        .replace(std::path::MAIN_SEPARATOR, "/");
+    let lines = repo.blame(&commit, &path)?;
+
+    // Like git blame -s, with line numbers padded to the widest
+    let width = lines.len().to_string().len();
+    for (number, line) in lines.iter().enumerate() {
+        println!(
+            "{} {:>width$}) {}",
+            repo.abbreviate(&line.commit, 8)?,
+            number + 1,
+            line.content,
+            width = width
+        );
+    }
+
+    return Ok(());
+}
+
+fn status(porcelain: bool, no_color: bool) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let color = !porcelain && stdout_color(no_color);
+    return write_status(&repo, porcelain, color, &mut stdout().lock());
+}
+
+/// Write the status of repo to out, as git does: porcelain, or long with
+/// staged changes in green and the rest in red if color is set.
+fn write_status(
+    repo: &Repository,
+    porcelain: bool,
+    color: bool,
+    out: &mut impl Write,
+) -> Result<(), ReadObjectErrorType> {
+    let statuses = repo.status()?;
+    if porcelain {
+        for status in &statuses {
+            writeln!(out, "{}", status).map_err(ReadObjectErrorType::IO)?;
+        }
+        return Ok(());
+    }
+
+    let mut lines = vec![match repo.current_branch()? {
+        Some(branch) => format!("On branch {}", branch),
+        None => "HEAD detached".to_string(),
+    }];
+
+    let label = |change: Change| match change {
+        Change::Added => Some("new file:"),
+        Change::Modified => Some("modified:"),
+        Change::Deleted => Some("deleted:"),
+        _ => None,
+    };
+    let red = |text: String| format!("\t{}", Color::Red.paint(&text, color));
+    let green = |text: String| format!("\t{}", Color::Green.paint(&text, color));
+    let mut unmerged = Vec::new();
+    let mut staged = Vec::new();
+    let mut unstaged = Vec::new();
+    let mut untracked = Vec::new();
+    for status in &statuses {
+        match status.staged {
+            Change::Unmerged => unmerged.push(red(format!("both modified:   {}", status.path))),
+            Change::Untracked => untracked.push(red(status.path.clone())),
+            change => {
+                if let Some(label) = label(change) {
+                    staged.push(green(format!("{:<12}{}", label, status.path)));
+                }
+                if let Some(label) = label(status.unstaged) {
+                    unstaged.push(red(format!("{:<12}{}", label, status.path)));
+                }
+            }
+        }
+    }
+
+    let sections = [
+        ("Unmerged paths:", unmerged),
+        ("Changes to be committed:", staged),
+        ("Changes not staged for commit:", unstaged),
+        ("Untracked files:", untracked),
+    ];
+    let sections = sections
+        .into_iter()
+        .filter(|(_, section)| !section.is_empty());
+    for (number, (heading, section)) in sections.enumerate() {
+        if number > 0 {
+            lines.push(String::new());
+        }
+        lines.push(heading.to_string());
+        lines.extend(section);
+    }
+
+    if statuses.is_empty() {
+        lines.push("nothing to commit, working tree clean".to_string());
+    }
+
+    for line in lines {
+        writeln!(out, "{}", line).map_err(ReadObjectErrorType::IO)?;
+    }
+
+    return Ok(());
+}
+
+fn read_tree(name: &str) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let sha = repo.object_find(name.to_string(), Some(ObjectKind::Tree), None)?;
+    return repo.write_index(&repo.read_tree(&sha)?);
+}
+
+fn write_tree(dry_run: bool) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    println!("{}", repo.write_tree(&repo.index()?, Some(!dry_run))?);
+
+    return Ok(());
+}
+
+fn verify_revision(quiet: bool, names: &[String]) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    let sha = match names {
+        [name] => repo.verify_revision(name),
+        _ => Err(ReadObjectErrorType::NotARevisionError(names.join(" "))),
+    };
+    match sha {
+        Ok(sha) => println!("{}", sha),
+        Err(_) if quiet => process::exit(1),
+        Err(e) => return Err(e),
+    }
+
+    return Ok(());
+}
+
+fn rev_parse(
+    abbrev_ref: bool,
+    short: Option<usize>,
+    git_dir: bool,
+    show_toplevel: bool,
+    names: &[String],
+) -> Result<(), ReadObjectErrorType> {
+    let repo = Repository::repo_find(".".to_string(), None)
+        .expect("No git directory when required")
+        .unwrap();
+
+    if git_dir {
+        println!("{}", repo.git_dir().display());
+    }
+    if show_toplevel {
+        println!("{}", repo.worktree().display());
+    }
+
+    for name in names {
+        if abbrev_ref {
+            println!("{}", repo.abbrev_ref(name)?);
+            continue;
+        }
+
+        let sha = repo.object_find(name.clone(), None, None)?;
+        match short {
+            Some(short) => println!("{}", repo.abbreviate(&sha, short)?),
+            None => println!("{}", sha),
+        }
+    }
+
+    return Ok(());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::repository::tests::write_raw_object;
+
+    #[test]
+    fn test_git_dir_and_work_tree_are_global_options() {
+        let args = Args::try_parse_from([
+            "wyag",
+            "status",
+            "--git-dir",
+            "repo/.git",
+            "--work-tree",
+            "repo",
+        ])
+        .unwrap();
+        assert_eq!(Some("repo/.git".to_string()), args.git_dir);
+        assert_eq!(Some("repo".to_string()), args.work_tree);
+    }
+
+    #[test]
+    fn test_branch_delete_needs_a_name() {
+        assert!(Args::try_parse_from(["wyag", "branch", "-d"]).is_err());
+        assert!(Args::try_parse_from(["wyag", "branch", "-d", "topic"]).is_ok());
+    }
+
+    #[test]
+    fn test_dry_run_belongs_to_the_commands_which_take_it() {
+        let args = Args::try_parse_from(["wyag", "write-tree", "--dry-run"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(GitCommands::WriteTree { dry_run: true })
+        ));
+        let args =
+            Args::try_parse_from(["wyag", "hash-object", "-t", "blob", "-w", "--dry-run", "a"])
+                .unwrap();
+        assert!(matches!(
+            args.command,
+            Some(GitCommands::HashObject {
+                dry_run: true,
+                write: true,
+                ..
+            })
+        ));
+
+        assert!(Args::try_parse_from(["wyag", "status", "--dry-run"]).is_err());
+        assert!(Args::try_parse_from(["wyag", "--dry-run", "write-tree"]).is_err());
+    }
+
+    #[test]
+    fn test_prune_parses_its_own_dry_run() {
+        for flag in ["-n", "--dry-run"] {
+            let args = Args::try_parse_from(["wyag", "prune", flag]).unwrap();
+            assert!(matches!(
+                args.command,
+                Some(GitCommands::Prune { dry_run: true })
+            ));
+        }
+
+        let args = Args::try_parse_from(["wyag", "prune"]).unwrap();
+        assert!(matches!(
+            args.command,
+            Some(GitCommands::Prune { dry_run: false })
+        ));
+    }
+
+    #[test]
+    fn test_object_hash_reads_from_any_reader() -> Result<(), std::io::Error> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("hello.txt");
+        std::fs::write(&path, b"hello\n")?;
+
+        let from_file = object_hash(File::open(&path)?, ObjectKind::Blob, None)?;
+        let from_pipe = object_hash(&b"hello\n"[..], ObjectKind::Blob, None)?;
+
+        assert_eq!("ce013625030ba8dba906f756967f9e9ca394464a", from_file);
+        assert_eq!(from_file, from_pipe);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_hash_paths_prints_a_sha_per_file_in_order() -> Result<(), std::io::Error> {
+        let dir = tempfile::tempdir()?;
+        let mut paths = Vec::new();
+        for (name, content) in [("c", "third\n"), ("a", "first\n"), ("b", "second\n")] {
+            let path = dir.path().join(name);
+            std::fs::write(&path, content)?;
+            paths.push(path.to_string_lossy().to_string());
+        }
+
+        let mut out = Vec::new();
+        hash_paths(&paths, ObjectKind::Blob, None, None, &mut out)?;
+
+        let expected: Vec<String> = ["third\n", "first\n", "second\n"]
+            .iter()
+            .map(|content| object_hash(content.as_bytes(), ObjectKind::Blob, None))
+            .collect::<Result<_, _>>()?;
+        assert_eq!(expected.join("\n") + "\n", String::from_utf8_lossy(&out));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_cat_object_writes_large_blob_in_full() -> Result<(), ReadObjectErrorType> {
+        let dir = tempfile::tempdir().map_err(ReadObjectErrorType::IO)?;
+        Repository::repo_create(dir.path(), HashAlgo::Sha1).map_err(ReadObjectErrorType::IO)?;
+        let repo = Repository::new(dir.path(), false);
+        let data: Vec<u8> = (0..5 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+        let sha = object_hash(&data[..], ObjectKind::Blob, Some(repo.clone()))
+            .map_err(ReadObjectErrorType::IO)?;
+
+        let mut out = Vec::new();
+        cat_object(&repo, ObjectKind::Blob, &sha, &mut out)?;
+
+        assert_eq!(data.len(), out.len());
+        assert!(data == out);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_object_size_does_not_parse_trees() -> Result<(), ReadObjectErrorType> {
+        let dir = tempfile::tempdir().map_err(ReadObjectErrorType::IO)?;
+        Repository::repo_create(dir.path(), HashAlgo::Sha1).map_err(ReadObjectErrorType::IO)?;
+        let repo = Repository::new(dir.path(), false);
+
+        // A large tree whose last entry is invalid, so parsing it would fail
+        let mut data = Vec::new();
+        for i in 0..10000 {
+            data.extend_from_slice(format!("100644 file-{:05}\x00", i).as_bytes());
+            data.extend([0xab; 20]);
+        }
+        data.extend_from_slice(b"100664 invalid\x00");
+        data.extend([0xab; 20]);
+        let sha = "0123456789abcdef0123456789abcdef01234567";
+        write_raw_object(
+            &repo,
+            sha,
+            &[format!("tree {}\x00", data.len()).as_bytes(), &data].concat(),
+        );
+        assert!(matches!(
+            repo.read_object(sha.to_string()),
+            Err(ReadObjectErrorType::InvalidTreeError(..))
+        ));
+
+        assert_eq!(data.len() as u64, object_size(&repo, sha)?);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_write_status_colours_only_when_asked() -> Result<(), ReadObjectErrorType> {
+        let dir = tempfile::tempdir().map_err(ReadObjectErrorType::IO)?;
+        Repository::repo_create(dir.path(), HashAlgo::Sha1).map_err(ReadObjectErrorType::IO)?;
+        let repo = Repository::new(dir.path(), false);
+        fs::write(dir.path().join("untracked.txt"), "hello\n").map_err(ReadObjectErrorType::IO)?;
+
+        // Piped output, as stdout_color decides for anything but a terminal
+        let mut piped = Vec::new();
+        write_status(&repo, false, false, &mut piped)?;
+        let piped = String::from_utf8(piped).map_err(ReadObjectErrorType::FromUtf8Error)?;
+        assert!(piped.contains("\tuntracked.txt\n"), "{}", piped);
+        assert!(!piped.contains('\x1b'), "{}", piped);
+
+        let mut coloured = Vec::new();
+        write_status(&repo, false, true, &mut coloured)?;
+        let coloured = String::from_utf8(coloured).map_err(ReadObjectErrorType::FromUtf8Error)?;
+        assert!(
+            coloured.contains("\t\x1b[31muntracked.txt\x1b[m\n"),
+            "{}",
+            coloured
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_relative_to_walks_up_from_dir() {
+        assert_eq!("src/main.rs", relative_to("src/main.rs", ""));
+        assert_eq!("main.rs", relative_to("src/main.rs", "src/"));
+        assert_eq!("../Cargo.toml", relative_to("Cargo.toml", "src/"));
+        assert_eq!("../../src2/a", relative_to("src2/a", "src/bin/"));
+        assert_eq!("./", relative_to("src", "src/"));
+        assert_eq!("../", relative_to("src", "src/bin/"));
+    }
+}
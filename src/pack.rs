@@ -0,0 +1,1309 @@
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crypto::{digest::Digest, sha1::Sha1};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression, Crc};
+
+use crate::{git_objects::git_object::ObjectKind, repository::repository::ReadObjectErrorType};
+
+/// A packfile, as found in .git/objects/pack/*.pack.
+///
+/// A pack is a 12 byte header ("PACK", a version number and the number of
+/// objects) followed by the objects themselves and a SHA-1 trailer over
+/// everything before it. Each object starts with a variable-length header
+/// holding its type and inflated size, followed by its zlib-deflated body.
+///
+/// A pack opened from a file is read entry by entry as objects are looked
+/// up, so opening one costs no more than reading its header and index.
+pub(crate) struct Pack {
+    path: PathBuf,
+    data: PackData,
+    /// The length of the whole pack, trailer included
+    len: u64,
+    count: u32,
+    index: Option<PackIndex>,
+    /// Resolved objects which deltas have been applied to. The pack is
+    /// kept open by its repository, so these last across lookups.
+    base_cache: Mutex<BaseCache>,
+}
+
+/// Resolved delta bases, by offset, holding no more than limit bytes of
+/// content in all.
+struct BaseCache {
+    objects: HashMap<u64, (ObjectKind, Vec<u8>)>,
+    size: usize,
+    limit: usize,
+}
+
+/// Where the bytes of a pack come from: a file, read from as entries are
+/// needed, or a pack received whole, e.g. on stdin.
+enum PackData {
+    File(Mutex<File>),
+    Memory(Vec<u8>),
+}
+
+/// A version 2 pack index (.idx), mapping the SHA of every object in a pack
+/// to its offset.
+///
+/// After the magic number and version comes a fanout table of 256
+/// cumulative counts of objects by first SHA byte, then the sorted SHAs,
+/// their CRC32s and their offsets. Offsets with the high bit set index into
+/// a trailing table of 8-byte offsets for packs larger than 2GiB.
+pub(crate) struct PackIndex {
+    fanout: [u32; 256],
+    /// Sorted hex SHAs; lowercase hex sorts the same way as the raw bytes
+    shas: Vec<String>,
+    offsets: Vec<u64>,
+}
+
+/// The type of an entry in a pack. Deltas record where to find the object
+/// they must be applied to: an earlier offset in the same pack, or a SHA.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PackObjectType {
+    Commit,
+    Tree,
+    Blob,
+    Tag,
+    OfsDelta(u64),
+    RefDelta(String),
+}
+
+/// A single object read from a pack.
+#[derive(Debug)]
+pub(crate) struct PackEntry {
+    /// Where the entry's header starts in the pack
+    pub(crate) offset: u64,
+    pub(crate) object_type: PackObjectType,
+    /// The inflated body: the object's content, or delta instructions
+    pub(crate) data: Vec<u8>,
+    /// How many bytes the entry takes up in the pack, header included
+    pub(crate) packed_size: usize,
+}
+
+/// What `verify-pack -v` reports about each object in a pack.
+#[derive(Debug)]
+pub(crate) struct PackObjectInfo {
+    pub(crate) sha: String,
+    pub(crate) object_type: ObjectKind,
+    /// The size of the entry's inflated body; for deltas, of the delta itself
+    pub(crate) size: usize,
+    pub(crate) packed_size: usize,
+    pub(crate) offset: u64,
+    /// For deltas, the length of the chain to a full object and the SHA of
+    /// the immediate base
+    pub(crate) delta: Option<(u32, String)>,
+}
+
+const PACK_HEADER_SIZE: usize = 12;
+const PACK_TRAILER_SIZE: usize = 20;
+
+/// How much the bases cached for a pack may hold, git's default for
+/// core.deltaBaseCacheLimit.
+const BASE_CACHE_LIMIT: usize = 96 * 1024 * 1024;
+
+/// Enough for the longest entry header: a type and size of up to 64 bits,
+/// then a delta's base offset or SHA.
+const MAX_ENTRY_HEADER_SIZE: usize = 32;
+
+impl PackObjectType {
+    /// The kind of object stored, or None for deltas, whose kind is that of
+    /// their base.
+    pub(crate) fn kind(&self) -> Option<ObjectKind> {
+        return match self {
+            PackObjectType::Commit => Some(ObjectKind::Commit),
+            PackObjectType::Tree => Some(ObjectKind::Tree),
+            PackObjectType::Blob => Some(ObjectKind::Blob),
+            PackObjectType::Tag => Some(ObjectKind::Tag),
+            PackObjectType::OfsDelta(_) | PackObjectType::RefDelta(_) => None,
+        };
+    }
+}
+
+impl Pack {
+    /// Read and validate the header of the pack at path, along with its
+    /// index if there is one alongside it.
+    pub(crate) fn open(path: &Path) -> Result<Pack, ReadObjectErrorType> {
+        let mut file = File::open(path).map_err(ReadObjectErrorType::IO)?;
+        let len = file.metadata().map_err(ReadObjectErrorType::IO)?.len();
+        let mut header = [0; PACK_HEADER_SIZE];
+        if len >= (PACK_HEADER_SIZE + PACK_TRAILER_SIZE) as u64 {
+            file.read_exact(&mut header)
+                .map_err(ReadObjectErrorType::IO)?;
+        }
+        let count = Pack::parse_header(&header, len)?;
+
+        let mut pack = Pack::new(PackData::File(Mutex::new(file)), len, count);
+        pack.path = path.to_path_buf();
+
+        let index_path = path.with_extension("idx");
+        if index_path.is_file() {
+            pack.index = Some(PackIndex::parse(
+                &fs::read(&index_path).map_err(ReadObjectErrorType::IO)?,
+            )?);
+        }
+
+        return Ok(pack);
+    }
+
+    /// Validate the header of a pack held in memory, e.g. one read from
+    /// stdin. Such a pack has no index.
+    pub(crate) fn from_bytes(data: Vec<u8>) -> Result<Pack, ReadObjectErrorType> {
+        let len = data.len() as u64;
+        let count = Pack::parse_header(data.get(..PACK_HEADER_SIZE).unwrap_or(&[]), len)?;
+
+        return Ok(Pack::new(PackData::Memory(data), len, count));
+    }
+
+    fn new(data: PackData, len: u64, count: u32) -> Pack {
+        return Pack {
+            path: PathBuf::new(),
+            data,
+            len,
+            count,
+            index: None,
+            base_cache: Mutex::new(BaseCache {
+                objects: HashMap::new(),
+                size: 0,
+                limit: BASE_CACHE_LIMIT,
+            }),
+        };
+    }
+
+    /// Validate the header of a pack len bytes long, returning the number
+    /// of objects it declares.
+    fn parse_header(header: &[u8], len: u64) -> Result<u32, ReadObjectErrorType> {
+        if len < (PACK_HEADER_SIZE + PACK_TRAILER_SIZE) as u64 || &header[0..4] != b"PACK" {
+            return Err(ReadObjectErrorType::InvalidPackError(
+                "not a packfile".to_string(),
+            ));
+        }
+
+        let version = u32::from_be_bytes(header[4..8].try_into().unwrap());
+        if version != 2 && version != 3 {
+            return Err(ReadObjectErrorType::InvalidPackError(format!(
+                "unsupported pack version {}",
+                version
+            )));
+        }
+
+        let count = u32::from_be_bytes(header[8..12].try_into().unwrap());
+        log::debug!("Read pack with {} objects", count);
+
+        return Ok(count);
+    }
+
+    /// Where the entries end and the trailer begins.
+    fn content_len(&self) -> u64 {
+        return self.len - PACK_TRAILER_SIZE as u64;
+    }
+
+    /// Check the trailing SHA-1 matches the rest of the pack.
+    pub(crate) fn verify_checksum(&self) -> Result<(), ReadObjectErrorType> {
+        let mut sha = Sha1::new();
+        let mut buf = vec![0; 64 * 1024];
+        let mut pos = 0;
+        while pos < self.content_len() {
+            let wanted = buf.len().min((self.content_len() - pos) as usize);
+            let read = self
+                .data
+                .read_at(pos, &mut buf[..wanted])
+                .map_err(ReadObjectErrorType::IO)?;
+            if read == 0 {
+                return Err(ReadObjectErrorType::InvalidPackError(
+                    "truncated pack".to_string(),
+                ));
+            }
+            sha.input(&buf[..read]);
+            pos += read as u64;
+        }
+
+        let mut trailer = [0; PACK_TRAILER_SIZE];
+        self.data
+            .read_at(self.content_len(), &mut trailer)
+            .map_err(ReadObjectErrorType::IO)?;
+        if sha.result_str() != hex::encode(trailer) {
+            return Err(ReadObjectErrorType::InvalidPackError(
+                "pack checksum mismatch".to_string(),
+            ));
+        }
+
+        return Ok(());
+    }
+
+    pub(crate) fn has_index(&self) -> bool {
+        return self.index.is_some();
+    }
+
+    /// The offset of object sha in the pack, looked up in the pack's index.
+    /// Returns None if the object isn't in the pack or there is no index.
+    pub(crate) fn find_offset(&self, sha: &str) -> Option<u64> {
+        return self.index.as_ref()?.find_offset(sha);
+    }
+
+    /// The SHA of every object in the pack starting with prefix. Requires an
+    /// index.
+    pub(crate) fn find_prefix(&self, prefix: &str) -> Vec<String> {
+        return match &self.index {
+            Some(index) => index.find_prefix(prefix),
+            None => vec![],
+        };
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        return &self.path;
+    }
+
+    /// The number of objects the pack header declares.
+    pub(crate) fn object_count(&self) -> u32 {
+        return self.count;
+    }
+
+    /// Iterate over every entry in the pack, in the order they are stored.
+    pub(crate) fn entries(&self) -> PackEntries<'_> {
+        return PackEntries {
+            pack: self,
+            offset: PACK_HEADER_SIZE as u64,
+            remaining: self.count,
+        };
+    }
+
+    /// Read the entry whose header starts at offset.
+    pub(crate) fn entry_at(&self, offset: u64) -> Result<PackEntry, ReadObjectErrorType> {
+        let end = self.content_len();
+        let (object_type, size, pos) = self.entry_header(offset)?;
+
+        // The declared size can't be trusted to allocate up front, and
+        // reading one byte past it is enough to tell it was a lie
+        let (data, total_in) = self
+            .data
+            .with_reader(pos, end, |reader| {
+                let mut decoder = ZlibDecoder::new(reader);
+                let mut data = Vec::with_capacity(size.min((end - pos) as usize));
+                decoder
+                    .by_ref()
+                    .take(size as u64 + 1)
+                    .read_to_end(&mut data)?;
+                return Ok((data, decoder.total_in()));
+            })
+            .map_err(ReadObjectErrorType::IO)?;
+        if data.len() != size {
+            return Err(ReadObjectErrorType::InvalidPackError(format!(
+                "entry at offset {} inflated to {} bytes, expected {}",
+                offset,
+                data.len(),
+                size
+            )));
+        }
+
+        let packed_size = (pos + total_in - offset) as usize;
+
+        return Ok(PackEntry {
+            offset,
+            object_type,
+            data,
+            packed_size,
+        });
+    }
+
+    /// Parse the header of the entry at offset, returning its type, the
+    /// inflated size of its body and where the body starts.
+    fn entry_header(
+        &self,
+        offset: u64,
+    ) -> Result<(PackObjectType, usize, u64), ReadObjectErrorType> {
+        let mut header = [0; MAX_ENTRY_HEADER_SIZE];
+        let available = self
+            .data
+            .read_at(offset, &mut header)
+            .map_err(ReadObjectErrorType::IO)?
+            .min(self.content_len().saturating_sub(offset) as usize);
+        let mut read = 0;
+        let mut read_byte = || {
+            let byte = header[..available].get(read).copied().ok_or_else(|| {
+                ReadObjectErrorType::InvalidPackError(format!(
+                    "truncated entry at offset {}",
+                    offset
+                ))
+            });
+            read += 1;
+            return byte;
+        };
+
+        // Type and size: the first byte holds a continuation bit, three type
+        // bits and the low four bits of the size. Further bytes each hold a
+        // continuation bit and seven more bits of the size.
+        let too_long = || {
+            ReadObjectErrorType::InvalidPackError(format!(
+                "entry header at offset {} is too long",
+                offset
+            ))
+        };
+        let mut byte = read_byte()?;
+        let type_id = (byte >> 4) & 0x7;
+        let mut size = (byte & 0xf) as u64;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            byte = read_byte()?;
+            if shift >= u64::BITS {
+                return Err(too_long());
+            }
+            size |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+        }
+        let size = usize::try_from(size).map_err(|_| too_long())?;
+
+        let object_type = match type_id {
+            1 => PackObjectType::Commit,
+            2 => PackObjectType::Tree,
+            3 => PackObjectType::Blob,
+            4 => PackObjectType::Tag,
+            6 => {
+                // The distance back to the base, big-endian, with one added to
+                // every byte but the last so no value has two encodings
+                byte = read_byte()?;
+                let mut distance = (byte & 0x7f) as u64;
+                while byte & 0x80 != 0 {
+                    byte = read_byte()?;
+                    if distance >= 1 << (u64::BITS - 7) {
+                        return Err(too_long());
+                    }
+                    distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+                }
+
+                let base = offset
+                    .checked_sub(distance)
+                    .filter(|_| distance > 0)
+                    .ok_or_else(|| {
+                        ReadObjectErrorType::InvalidPackError(format!(
+                            "delta at offset {} has a base before the start of the pack",
+                            offset
+                        ))
+                    })?;
+                PackObjectType::OfsDelta(base)
+            }
+            7 => {
+                let mut sha = [0; 20];
+                for b in sha.iter_mut() {
+                    *b = read_byte()?;
+                }
+                PackObjectType::RefDelta(hex::encode(sha))
+            }
+            _ => {
+                return Err(ReadObjectErrorType::InvalidPackError(format!(
+                    "unknown object type {} at offset {}",
+                    type_id, offset
+                )))
+            }
+        };
+
+        return Ok((object_type, size, offset + read as u64));
+    }
+
+    /// Find the object sha in the pack, returning its type and content.
+    /// The index is used to locate the object if there is one; otherwise
+    /// every entry is resolved and hashed until it is found.
+    pub(crate) fn find_object(
+        &self,
+        sha: &str,
+    ) -> Result<Option<(ObjectKind, Vec<u8>)>, ReadObjectErrorType> {
+        return match self.offset_of(sha)? {
+            Some(offset) => Ok(Some(self.object_at(offset)?)),
+            None => Ok(None),
+        };
+    }
+
+    /// Find the object sha in the pack, returning its kind and size
+    /// without reconstructing it. Deltas record the size of their result,
+    /// so only a deltified object's own entry has to be inflated.
+    pub(crate) fn find_header(
+        &self,
+        sha: &str,
+    ) -> Result<Option<(ObjectKind, u64)>, ReadObjectErrorType> {
+        let Some(offset) = self.offset_of(sha)? else {
+            return Ok(None);
+        };
+
+        let (object_type, size, _) = self.entry_header(offset)?;
+        let size = match object_type.kind() {
+            Some(_) => size,
+            None => delta_sizes(&self.entry_at(offset)?.data)?.1,
+        };
+
+        return Ok(Some((self.kind_at(offset)?, size as u64)));
+    }
+
+    /// The offset of object sha, from the index if there is one, or else by
+    /// scanning the pack.
+    fn offset_of(&self, sha: &str) -> Result<Option<u64>, ReadObjectErrorType> {
+        if self.index.is_some() {
+            return Ok(self.find_offset(sha));
+        }
+
+        for entry in self.entries() {
+            let offset = entry?.offset;
+            let (object_type, data) = self.object_at(offset)?;
+            if object_sha(object_type, &data) == sha {
+                return Ok(Some(offset));
+            }
+        }
+
+        return Ok(None);
+    }
+
+    /// Read the object at offset, reconstructing it from its delta chain if
+    /// it is deltified. Returns the object's type and full content.
+    pub(crate) fn object_at(
+        &self,
+        offset: u64,
+    ) -> Result<(ObjectKind, Vec<u8>), ReadObjectErrorType> {
+        // Follow the chain of deltas down to a base object, remembering the
+        // deltas to apply on the way back up
+        let mut chain = Vec::new();
+        let mut current = offset;
+        let (object_type, mut data) = loop {
+            if let Some(base) = self.base_cache.lock().unwrap().objects.get(&current) {
+                break base.clone();
+            }
+
+            let entry = self.entry_at(current)?;
+            let base = match entry.object_type {
+                PackObjectType::OfsDelta(base) => base,
+                PackObjectType::RefDelta(ref sha) => self.offset_of(sha)?.ok_or_else(|| {
+                    ReadObjectErrorType::InvalidPackError(format!(
+                        "delta base {} is not in the pack",
+                        sha
+                    ))
+                })?,
+                object_type => break (object_type.kind().unwrap(), entry.data),
+            };
+
+            if chain.len() as u32 > self.count {
+                return Err(ReadObjectErrorType::InvalidPackError(format!(
+                    "delta chain at offset {} loops",
+                    offset
+                )));
+            }
+
+            chain.push((current, entry.data));
+            current = base;
+        };
+
+        if !chain.is_empty() {
+            self.cache_base(current, object_type, &data);
+        }
+
+        while let Some((delta_offset, delta)) = chain.pop() {
+            data = apply_delta(&data, &delta)?;
+
+            // Everything but the requested object was the base of a delta
+            if delta_offset != offset {
+                self.cache_base(delta_offset, object_type, &data);
+            }
+        }
+
+        return Ok((object_type, data));
+    }
+
+    /// Describe every object in the pack, in pack order.
+    pub(crate) fn contents(&self) -> Result<Vec<PackObjectInfo>, ReadObjectErrorType> {
+        let mut contents = Vec::new();
+        for entry in self.entries() {
+            let entry = entry?;
+            let (object_type, data) = self.object_at(entry.offset)?;
+
+            let delta = match entry.object_type {
+                PackObjectType::OfsDelta(base) => {
+                    let (base_type, base_data) = self.object_at(base)?;
+                    Some((
+                        self.delta_depth(entry.offset)?,
+                        object_sha(base_type, &base_data),
+                    ))
+                }
+                PackObjectType::RefDelta(ref sha) => {
+                    Some((self.delta_depth(entry.offset)?, sha.clone()))
+                }
+                _ => None,
+            };
+
+            contents.push(PackObjectInfo {
+                sha: object_sha(object_type, &data),
+                object_type,
+                size: entry.data.len(),
+                packed_size: entry.packed_size,
+                offset: entry.offset,
+                delta,
+            });
+        }
+
+        return Ok(contents);
+    }
+
+    /// The SHA and kind of every object in the pack. With an index, only
+    /// entry headers need to be read; without one, every object has to be
+    /// resolved and hashed.
+    pub(crate) fn objects(&self) -> Result<Vec<(String, ObjectKind)>, ReadObjectErrorType> {
+        let mut objects = Vec::new();
+        match &self.index {
+            Some(index) => {
+                for (sha, offset) in index.shas.iter().zip(&index.offsets) {
+                    objects.push((sha.clone(), self.kind_at(*offset)?));
+                }
+            }
+            None => {
+                for entry in self.entries() {
+                    let (object_type, data) = self.object_at(entry?.offset)?;
+                    objects.push((object_sha(object_type, &data), object_type));
+                }
+            }
+        }
+
+        return Ok(objects);
+    }
+
+    /// The kind of the object at offset, found by following its delta
+    /// chain to a full object without inflating any of them.
+    fn kind_at(&self, offset: u64) -> Result<ObjectKind, ReadObjectErrorType> {
+        let mut current = offset;
+        for _ in 0..=self.count {
+            current = match self.entry_header(current)?.0 {
+                PackObjectType::OfsDelta(base) => base,
+                PackObjectType::RefDelta(ref sha) => self.offset_of(sha)?.ok_or_else(|| {
+                    ReadObjectErrorType::InvalidPackError(format!(
+                        "delta base {} is not in the pack",
+                        sha
+                    ))
+                })?,
+                object_type => return Ok(object_type.kind().unwrap()),
+            };
+        }
+
+        return Err(ReadObjectErrorType::InvalidPackError(format!(
+            "delta chain at offset {} loops",
+            offset
+        )));
+    }
+
+    /// Count the deltas between the entry at offset and a full object.
+    fn delta_depth(&self, offset: u64) -> Result<u32, ReadObjectErrorType> {
+        let mut depth = 0;
+        let mut current = offset;
+        loop {
+            current = match self.entry_header(current)?.0 {
+                PackObjectType::OfsDelta(base) => base,
+                PackObjectType::RefDelta(ref sha) => self.offset_of(sha)?.ok_or_else(|| {
+                    ReadObjectErrorType::InvalidPackError(format!(
+                        "delta base {} is not in the pack",
+                        sha
+                    ))
+                })?,
+                _ => return Ok(depth),
+            };
+
+            depth += 1;
+            if depth > self.count {
+                return Err(ReadObjectErrorType::InvalidPackError(format!(
+                    "delta chain at offset {} loops",
+                    offset
+                )));
+            }
+        }
+    }
+
+    /// Remember data as the object at offset. Once the cache is full, it
+    /// is emptied to make room, and an object larger than the whole cache
+    /// isn't kept at all.
+    fn cache_base(&self, offset: u64, object_type: ObjectKind, data: &[u8]) {
+        let mut cache = self.base_cache.lock().unwrap();
+        if data.len() > cache.limit || cache.objects.contains_key(&offset) {
+            return;
+        }
+        if cache.size + data.len() > cache.limit {
+            cache.objects.clear();
+            cache.size = 0;
+        }
+
+        cache.size += data.len();
+        cache.objects.insert(offset, (object_type, data.to_vec()));
+    }
+}
+
+impl PackData {
+    /// Fill as much of buf as the pack has from pos, returning how many
+    /// bytes were read.
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        return self.with_reader(pos, u64::MAX, |reader| {
+            let mut read = 0;
+            while read < buf.len() {
+                match reader.read(&mut buf[read..]) {
+                    Ok(0) => break,
+                    Ok(n) => read += n,
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+            return Ok(read);
+        });
+    }
+
+    /// Call f with a reader over the bytes from pos up to end. A file is
+    /// locked for as long as f runs, so readers on other threads can't
+    /// move its position.
+    fn with_reader<T>(
+        &self,
+        pos: u64,
+        end: u64,
+        f: impl FnOnce(&mut dyn Read) -> io::Result<T>,
+    ) -> io::Result<T> {
+        let limit = end.saturating_sub(pos);
+        return match self {
+            PackData::File(file) => {
+                let mut file = file.lock().unwrap();
+                file.seek(SeekFrom::Start(pos))?;
+                f(&mut (&mut *file).take(limit))
+            }
+            PackData::Memory(data) => {
+                let start = data.len().min(pos as usize);
+                f(&mut (&data[start..]).take(limit))
+            }
+        };
+    }
+}
+
+/// Rebuild an object from its base and a delta.
+///
+/// A delta starts with the sizes of the base and of the result, then
+/// contains instructions which either copy a range of the base or insert
+/// literal bytes. Sizes use the same little-endian base-128 encoding as
+/// pack entry headers, minus the type bits.
+pub(crate) fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>, ReadObjectErrorType> {
+    let invalid = |message: &str| ReadObjectErrorType::InvalidPackError(message.to_string());
+    let (base_size, result_size, mut pos) = delta_sizes(delta)?;
+    if base_size != base.len() {
+        return Err(invalid("delta base has the wrong size"));
+    }
+
+    // result_size comes from the delta, so isn't trusted to allocate by
+    let mut result = Vec::with_capacity(result_size.min(base.len() + delta.len()));
+    while pos < delta.len() {
+        if result.len() > result_size {
+            return Err(invalid("delta produced an object of the wrong size"));
+        }
+
+        let instruction = delta[pos];
+        pos += 1;
+
+        if instruction & 0x80 != 0 {
+            // Copy: the low four bits say which bytes of the offset follow,
+            // the next three which bytes of the size
+            let mut fields = [0usize; 2];
+            let mut bit = 0;
+            for (field, bytes) in fields.iter_mut().zip([4, 3]) {
+                for i in 0..bytes {
+                    if instruction & (1 << bit) != 0 {
+                        let byte = *delta.get(pos).ok_or_else(|| invalid("truncated delta"))?;
+                        pos += 1;
+                        *field |= (byte as usize) << (i * 8);
+                    }
+                    bit += 1;
+                }
+            }
+
+            let [offset, size] = fields;
+            let size = if size == 0 { 0x10000 } else { size };
+            let copied = base
+                .get(offset..offset + size)
+                .ok_or_else(|| invalid("delta copies past the end of its base"))?;
+            result.extend_from_slice(copied);
+        } else if instruction != 0 {
+            // Insert: the instruction is the number of literal bytes
+            let inserted = delta
+                .get(pos..pos + instruction as usize)
+                .ok_or_else(|| invalid("truncated delta"))?;
+            pos += inserted.len();
+            result.extend_from_slice(inserted);
+        } else {
+            return Err(invalid("reserved delta instruction"));
+        }
+    }
+
+    if result.len() != result_size {
+        return Err(invalid("delta produced an object of the wrong size"));
+    }
+
+    return Ok(result);
+}
+
+/// Read the base and result sizes from the start of a delta, returning
+/// them and where the instructions begin.
+fn delta_sizes(delta: &[u8]) -> Result<(usize, usize, usize), ReadObjectErrorType> {
+    let mut pos = 0;
+    let invalid = |message: &str| ReadObjectErrorType::InvalidPackError(message.to_string());
+    let mut read_size = || -> Result<usize, ReadObjectErrorType> {
+        let mut size = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = *delta.get(pos).ok_or_else(|| invalid("truncated delta"))?;
+            pos += 1;
+            if shift >= u64::BITS {
+                return Err(invalid("delta size is too long"));
+            }
+            size |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return usize::try_from(size).map_err(|_| invalid("delta size is too long"));
+            }
+        }
+    };
+
+    let base_size = read_size()?;
+    let result_size = read_size()?;
+    return Ok((base_size, result_size, pos));
+}
+
+impl PackIndex {
+    pub(crate) fn parse(data: &[u8]) -> Result<PackIndex, ReadObjectErrorType> {
+        let invalid = |message: &str| ReadObjectErrorType::InvalidPackError(message.to_string());
+        let read_u32 = |pos: usize| u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap());
+
+        if data.len() < 8 + 256 * 4 || &data[0..4] != b"\xfftOc" {
+            return Err(invalid("not a version 2 pack index"));
+        }
+        if read_u32(4) != 2 {
+            return Err(invalid("unsupported pack index version"));
+        }
+
+        let mut fanout = [0; 256];
+        for (i, count) in fanout.iter_mut().enumerate() {
+            *count = read_u32(8 + i * 4);
+        }
+        // Each entry counts the SHAs up to and including its first byte, so
+        // a corrupt index can't be trusted to slice the SHAs by them
+        if fanout.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(invalid("pack index fanout table out of order"));
+        }
+
+        let count = fanout[255] as usize;
+        let shas_start = 8 + 256 * 4;
+        let crcs_start = shas_start + count * 20;
+        let offsets_start = crcs_start + count * 4;
+        let large_offsets_start = offsets_start + count * 4;
+        if data.len() < large_offsets_start + 40 {
+            return Err(invalid("truncated pack index"));
+        }
+
+        let shas = (0..count)
+            .map(|i| hex::encode(&data[shas_start + i * 20..shas_start + (i + 1) * 20]))
+            .collect();
+
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = read_u32(offsets_start + i * 4);
+            if offset & 0x8000_0000 == 0 {
+                offsets.push(offset as u64);
+                continue;
+            }
+
+            let pos = large_offsets_start + (offset & 0x7fff_ffff) as usize * 8;
+            let large = data
+                .get(pos..pos + 8)
+                .ok_or_else(|| invalid("large offset out of range"))?;
+            offsets.push(u64::from_be_bytes(large.try_into().unwrap()));
+        }
+
+        return Ok(PackIndex {
+            fanout,
+            shas,
+            offsets,
+        });
+    }
+
+    /// The offset of object sha, found by narrowing the search to SHAs with
+    /// the same first byte using the fanout table, then binary searching.
+    pub(crate) fn find_offset(&self, sha: &str) -> Option<u64> {
+        let candidates = self.fanout_range(sha)?;
+        let position = self.shas[candidates.clone()]
+            .binary_search_by(|candidate| candidate.as_str().cmp(sha))
+            .ok()?;
+
+        return Some(self.offsets[candidates.start + position]);
+    }
+
+    /// Every SHA in the index starting with prefix.
+    pub(crate) fn find_prefix(&self, prefix: &str) -> Vec<String> {
+        let candidates = match self.fanout_range(prefix) {
+            Some(candidates) => &self.shas[candidates],
+            None => return vec![],
+        };
+
+        let start = candidates.partition_point(|candidate| candidate.as_str() < prefix);
+        return candidates[start..]
+            .iter()
+            .take_while(|candidate| candidate.starts_with(prefix))
+            .cloned()
+            .collect();
+    }
+
+    /// The range of indices of SHAs sharing the first byte of sha.
+    fn fanout_range(&self, sha: &str) -> Option<std::ops::Range<usize>> {
+        let first = u8::from_str_radix(sha.get(0..2)?, 16).ok()? as usize;
+        let start = match first {
+            0 => 0,
+            _ => self.fanout[first - 1] as usize,
+        };
+
+        return Some(start..self.fanout[first] as usize);
+    }
+}
+
+/// Iterator over the entries of a pack, created by Pack::entries.
+pub(crate) struct PackEntries<'a> {
+    pack: &'a Pack,
+    offset: u64,
+    remaining: u32,
+}
+
+impl Iterator for PackEntries<'_> {
+    type Item = Result<PackEntry, ReadObjectErrorType>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        self.remaining -= 1;
+        let entry = self.pack.entry_at(self.offset);
+        match &entry {
+            Ok(entry) => self.offset += entry.packed_size as u64,
+            // The following entries can't be located without this one
+            Err(_) => self.remaining = 0,
+        }
+
+        return Some(entry);
+    }
+}
+
+/// Build a pack, and its version 2 index, holding objects as full
+/// zlib-compressed entries. Returns the pack, the index and the pack's
+/// checksum, which git uses to name both files.
+pub(crate) fn write_pack(
+    objects: &[(ObjectKind, Vec<u8>)],
+) -> Result<(Vec<u8>, Vec<u8>, String), ReadObjectErrorType> {
+    let mut pack = Vec::new();
+    pack.extend_from_slice(b"PACK");
+    pack.extend_from_slice(&2u32.to_be_bytes());
+    pack.extend_from_slice(&(objects.len() as u32).to_be_bytes());
+
+    // (SHA, CRC32 of the packed entry, offset) for the index
+    let mut entries = Vec::with_capacity(objects.len());
+    for (object_type, data) in objects {
+        let type_id = match object_type {
+            ObjectKind::Commit => 1,
+            ObjectKind::Tree => 2,
+            ObjectKind::Blob => 3,
+            ObjectKind::Tag => 4,
+        };
+
+        let offset = pack.len();
+
+        // The inverse of the header parsing in entry_at
+        let mut size = data.len();
+        let mut byte = (type_id << 4) | (size & 0xf) as u8;
+        size >>= 4;
+        while size != 0 {
+            pack.push(byte | 0x80);
+            byte = (size & 0x7f) as u8;
+            size >>= 7;
+        }
+        pack.push(byte);
+
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).map_err(ReadObjectErrorType::IO)?;
+        pack.extend(encoder.finish().map_err(ReadObjectErrorType::IO)?);
+
+        let mut crc = Crc::new();
+        crc.update(&pack[offset..]);
+        entries.push((object_sha(*object_type, data), crc.sum(), offset as u64));
+    }
+
+    let pack_sha = sha1_bytes(&pack);
+    pack.extend_from_slice(&pack_sha);
+
+    entries.sort();
+    entries.dedup_by(|a, b| a.0 == b.0);
+
+    let mut index = Vec::new();
+    index.extend_from_slice(b"\xfftOc");
+    index.extend_from_slice(&2u32.to_be_bytes());
+    let mut fanout = [0u32; 256];
+    for (sha, _, _) in &entries {
+        let first = u8::from_str_radix(&sha[0..2], 16).unwrap() as usize;
+        for count in &mut fanout[first..] {
+            *count += 1;
+        }
+    }
+    for count in fanout {
+        index.extend_from_slice(&count.to_be_bytes());
+    }
+    for (sha, _, _) in &entries {
+        index.extend(hex::decode(sha).unwrap());
+    }
+    for (_, crc, _) in &entries {
+        index.extend_from_slice(&crc.to_be_bytes());
+    }
+
+    // Offsets too large for 31 bits live in a table of their own
+    let mut large_offsets = Vec::new();
+    for (_, _, offset) in &entries {
+        let small = match u32::try_from(*offset) {
+            Ok(offset) if offset & 0x8000_0000 == 0 => offset,
+            _ => {
+                large_offsets.extend_from_slice(&offset.to_be_bytes());
+                0x8000_0000 | (large_offsets.len() / 8 - 1) as u32
+            }
+        };
+        index.extend_from_slice(&small.to_be_bytes());
+    }
+    index.extend(large_offsets);
+
+    index.extend_from_slice(&pack_sha);
+    let index_sha = sha1_bytes(&index);
+    index.extend_from_slice(&index_sha);
+
+    return Ok((pack, index, hex::encode(pack_sha)));
+}
+
+fn sha1_bytes(data: &[u8]) -> [u8; 20] {
+    let mut sha = Sha1::new();
+    sha.input(data);
+
+    let mut result = [0; 20];
+    sha.result(&mut result);
+    return result;
+}
+
+/// The SHA of an object with the given type and content.
+pub(crate) fn object_sha(object_type: ObjectKind, data: &[u8]) -> String {
+    let mut sha = Sha1::new();
+    sha.input(format!("{} {}\x00", object_type, data.len()).as_bytes());
+    sha.input(data);
+
+    return sha.result_str();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PACK_NAME: &str = "pack-b94941be983091701f3c5aa2af12ca1fdf7e9ac2.pack";
+
+    fn fixture_path() -> PathBuf {
+        return Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("fixtures")
+            .join(PACK_NAME);
+    }
+
+    fn fixture_pack() -> Pack {
+        return Pack::open(&fixture_path()).unwrap();
+    }
+
+    /// The fixture pack without its index alongside it.
+    fn unindexed_pack() -> (tempfile::TempDir, Pack) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PACK_NAME);
+        fs::copy(fixture_path(), &path).unwrap();
+        let pack = Pack::open(&path).unwrap();
+
+        return (dir, pack);
+    }
+
+    #[test]
+    fn test_reads_pack_header() {
+        let pack = fixture_pack();
+
+        assert_eq!(7, pack.object_count());
+        assert_eq!(7, pack.entries().count());
+    }
+
+    #[test]
+    fn test_extracts_known_blob() -> Result<(), ReadObjectErrorType> {
+        let (_dir, unindexed) = unindexed_pack();
+
+        for pack in [fixture_pack(), unindexed] {
+            let (object_type, data) = pack
+                .find_object("af5626b4a114abcb82d63db7c8082c3c4756e51b")?
+                .expect("Blob not found in pack");
+
+            assert_eq!(ObjectKind::Blob, object_type);
+            assert_eq!(b"Hello, world!\n".to_vec(), data);
+        }
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_index_finds_offsets() {
+        let pack = fixture_pack();
+
+        // As reported by git verify-pack -v
+        for (sha, offset) in [
+            ("15d5816c70fa28446d886015d58d8ecc70e287c5", 12),
+            ("af5626b4a114abcb82d63db7c8082c3c4756e51b", 288),
+            ("7f68d45bf7b349c1de891e5c21970a3affc20bbd", 528),
+            ("6d5a12c1b4b3519e227158b4c2ad92fa80a87041", 639),
+        ] {
+            assert_eq!(Some(offset), pack.find_offset(sha), "{}", sha);
+        }
+        assert_eq!(
+            None,
+            pack.find_offset("0000000000000000000000000000000000000000")
+        );
+        assert_eq!(
+            vec![
+                "7f4f2c353dca6407630de31852888aed1db3cc95",
+                "7f68d45bf7b349c1de891e5c21970a3affc20bbd"
+            ],
+            pack.find_prefix("7f")
+        );
+    }
+
+    #[test]
+    fn test_index_reads_large_offsets() -> Result<(), ReadObjectErrorType> {
+        let small = [0x11; 20];
+        let large = [0x22; 20];
+
+        let mut idx = b"\xfftOc".to_vec();
+        idx.extend(2u32.to_be_bytes());
+        for i in 0..256 {
+            let count: u32 = match i {
+                0..=0x10 => 0,
+                0x11..=0x21 => 1,
+                _ => 2,
+            };
+            idx.extend(count.to_be_bytes());
+        }
+        idx.extend(small);
+        idx.extend(large);
+        idx.extend([0; 8]); // CRCs
+        idx.extend(12u32.to_be_bytes());
+        idx.extend(0x8000_0000u32.to_be_bytes());
+        idx.extend(0x1_0000_0000u64.to_be_bytes());
+        idx.extend([0; 40]); // Checksums
+
+        let index = PackIndex::parse(&idx)?;
+
+        assert_eq!(Some(12), index.find_offset(&hex::encode(small)));
+        assert_eq!(Some(0x1_0000_0000), index.find_offset(&hex::encode(large)));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_index_rejects_decreasing_fanout() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/pack-b94941be983091701f3c5aa2af12ca1fdf7e9ac2.idx");
+        let mut idx = fs::read(path).unwrap();
+        assert!(PackIndex::parse(&idx).is_ok());
+
+        // Claim more SHAs start with 0x00 than with anything up to 0xff
+        idx[8..12].copy_from_slice(&100u32.to_be_bytes());
+
+        assert!(matches!(
+            PackIndex::parse(&idx),
+            Err(ReadObjectErrorType::InvalidPackError(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolves_ofs_delta() -> Result<(), ReadObjectErrorType> {
+        let pack = fixture_pack();
+        let sha = "7f68d45bf7b349c1de891e5c21970a3affc20bbd";
+        let entry = pack.entry_at(pack.find_offset(sha).unwrap())?;
+        assert_eq!(PackObjectType::OfsDelta(311), entry.object_type);
+
+        let (object_type, data) = pack.find_object(sha)?.expect("Delta not found in pack");
+
+        assert_eq!(ObjectKind::Blob, object_type);
+        assert_eq!(sha, object_sha(object_type, &data));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_resolves_ref_delta() -> Result<(), ReadObjectErrorType> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/pack-ca10097dcd59748bdd64508aab23e1bd26afe1b7.pack");
+        let pack = Pack::open(&path)?;
+        let sha = "7f68d45bf7b349c1de891e5c21970a3affc20bbd";
+        let entry = pack.entry_at(pack.find_offset(sha).unwrap())?;
+        assert_eq!(
+            PackObjectType::RefDelta("15042b20474c33f34eb9a8f091ed90f34bcd2d31".to_string()),
+            entry.object_type
+        );
+
+        let (object_type, data) = pack.find_object(sha)?.expect("Delta not found in pack");
+
+        assert_eq!(ObjectKind::Blob, object_type);
+        assert_eq!(sha, object_sha(object_type, &data));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_finds_delta_header_without_resolving() -> Result<(), ReadObjectErrorType> {
+        let pack = fixture_pack();
+        let sha = "7f68d45bf7b349c1de891e5c21970a3affc20bbd";
+
+        let (object_type, data) = pack.find_object(sha)?.expect("Delta not found in pack");
+
+        assert_eq!(
+            Some((object_type, data.len() as u64)),
+            pack.find_header(sha)?
+        );
+        assert_eq!(None, pack.find_header(&"0".repeat(40))?);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_resolves_delta_without_index() -> Result<(), ReadObjectErrorType> {
+        let (_dir, pack) = unindexed_pack();
+        let sha = "7f68d45bf7b349c1de891e5c21970a3affc20bbd";
+
+        let (object_type, data) = pack.find_object(sha)?.expect("Delta not found in pack");
+
+        assert_eq!(sha, object_sha(object_type, &data));
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_caches_bases_up_to_a_limit() -> Result<(), ReadObjectErrorType> {
+        let pack = fixture_pack();
+        let delta = "7f68d45bf7b349c1de891e5c21970a3affc20bbd";
+        let base_offset = 311;
+
+        pack.find_object(delta)?;
+        assert!(pack
+            .base_cache
+            .lock()
+            .unwrap()
+            .objects
+            .contains_key(&base_offset));
+
+        // A base larger than the whole cache isn't kept
+        let pack = fixture_pack();
+        pack.base_cache.lock().unwrap().limit = 1024;
+        let (_, data) = pack.find_object(delta)?.unwrap();
+        assert_eq!(delta, object_sha(ObjectKind::Blob, &data));
+        let cache = pack.base_cache.lock().unwrap();
+        assert!(cache.objects.is_empty());
+        assert_eq!(0, cache.size);
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_apply_delta_copies_and_inserts() -> Result<(), ReadObjectErrorType> {
+        let base = b"Hello, world!";
+        let delta = [
+            13,   // Base size
+            14,   // Result size
+            0x91, // Copy with one offset byte and one size byte...
+            0,    // ...from offset 0...
+            7,    // ...of 7 bytes: "Hello, "
+            7,    // Insert the next 7 bytes
+            b'w', b'y', b'a', b'g', b'g', b'e', b'r',
+        ];
+
+        assert_eq!(b"Hello, wyagger".to_vec(), apply_delta(base, &delta)?);
+        assert!(apply_delta(b"short", &delta).is_err());
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_rejects_oversized_varints() {
+        let pack_of = |entry: &[u8]| {
+            let mut data = b"PACK".to_vec();
+            data.extend(2u32.to_be_bytes());
+            data.extend(1u32.to_be_bytes());
+            data.extend(entry);
+            data.extend([0; PACK_TRAILER_SIZE]);
+            return Pack::from_bytes(data).unwrap();
+        };
+        let invalid = |result: Result<PackEntry, ReadObjectErrorType>| {
+            return matches!(result, Err(ReadObjectErrorType::InvalidPackError(_)));
+        };
+
+        // A size running past 64 bits
+        assert!(invalid(pack_of(&[0xff; 12]).entry_at(12)));
+
+        // A blob claiming to be 2^53 bytes, whose body is far shorter
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"small").unwrap();
+        let mut entry = vec![0xb0, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x02];
+        entry.extend(encoder.finish().unwrap());
+        assert!(invalid(pack_of(&entry).entry_at(12)));
+
+        // An offset delta whose distance back overflows
+        let mut entry = vec![0x60];
+        entry.extend([0xff; 12]);
+        assert!(invalid(pack_of(&entry).entry_at(12)));
+
+        assert!(matches!(
+            apply_delta(b"", &[0xff; 12]),
+            Err(ReadObjectErrorType::InvalidPackError(_))
+        ));
+    }
+
+    #[test]
+    fn test_contents_describes_every_object() -> Result<(), ReadObjectErrorType> {
+        let pack = fixture_pack();
+
+        let contents = pack.contents()?;
+
+        assert_eq!(pack.object_count() as usize, contents.len());
+        let delta = contents
+            .iter()
+            .find(|info| info.sha == "7f68d45bf7b349c1de891e5c21970a3affc20bbd")
+            .unwrap();
+        assert_eq!(ObjectKind::Blob, delta.object_type);
+        assert_eq!((20, 32, 528), (delta.size, delta.packed_size, delta.offset));
+        assert_eq!(
+            Some((1, "15042b20474c33f34eb9a8f091ed90f34bcd2d31".to_string())),
+            delta.delta
+        );
+
+        return Ok(());
+    }
+
+    #[test]
+    fn test_verifies_checksum() {
+        let mut data = fs::read(fixture_path()).unwrap();
+        assert!(Pack::from_bytes(data.clone())
+            .unwrap()
+            .verify_checksum()
+            .is_ok());
+
+        data[20] ^= 0xff;
+        assert!(Pack::from_bytes(data).unwrap().verify_checksum().is_err());
+    }
+
+    #[test]
+    fn test_rejects_non_pack_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bogus.pack");
+        fs::write(&path, [b'X'; 40]).unwrap();
+
+        assert!(matches!(
+            Pack::open(&path),
+            Err(ReadObjectErrorType::InvalidPackError(_))
+        ));
+    }
+}
@@ -1,21 +1,39 @@
 pub mod repository {
     use std::{
-        collections::HashSet,
-        fs::{create_dir_all, File},
+        cmp::Reverse,
+        collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap, HashSet},
+        fmt,
+        fs::{self, create_dir_all, set_permissions, File, Permissions},
         io::{self, Read, Write},
         num::ParseIntError,
         path::{Path, PathBuf, MAIN_SEPARATOR},
         string::FromUtf8Error,
+        sync::{Arc, Mutex, OnceLock},
+        thread,
+        time::{SystemTime, UNIX_EPOCH},
     };
 
     use configparser::ini::{Ini, IniDefault};
-    use flate2::read::ZlibDecoder;
+    use crypto::{digest::Digest, sha1::Sha1, sha2::Sha256};
+    use flate2::{read::ZlibDecoder, Compression};
+    use serde::Serialize;
 
-    use crate::git_objects::{
-        git_blob::Blob,
-        git_commit::Commit,
-        git_object::{GitObjectData, GitSerDe},
-        git_tree::{Leaf, Tree},
+    use crate::{
+        color::Color,
+        date::DateFormat,
+        diff::{diff_lines, tree_changes, unified_diff, Edit, TreeChange},
+        git_objects::{
+            git_blob::Blob,
+            git_commit::Commit,
+            git_object::{
+                GitObject, GitObjectData, GitSerDe, KvlmParseError, ObjectKind, ParsedObject,
+            },
+            git_tag::Tag,
+            git_tree::{Leaf, Tree, TreeParseError},
+        },
+        ignore::IgnoreRules,
+        index::{Index, IndexEntry},
+        pack::{object_sha, write_pack, Pack},
     };
 
     /// A git repository
@@ -24,6 +42,15 @@ pub mod repository {
         worktree: PathBuf,
         git_dir: PathBuf,
         config: Ini,
+        /// The global then the system config, consulted in that order for
+        /// anything the repository's own config doesn't set
+        fallback_config: Vec<Ini>,
+        /// Whether loose objects are checked against their names when read
+        verify_objects: bool,
+        /// The packs here and in alternates, opened the first time one is
+        /// needed and shared by every clone of the repository, so that
+        /// each is only opened, and its index read, once
+        packs: Arc<Mutex<Option<Vec<Arc<Pack>>>>>,
     }
 
     #[derive(Debug)]
@@ -33,51 +60,372 @@ pub mod repository {
         ParseIntError(ParseIntError),
         TreeNotFoundError,
         InvalidPathError,
+        CheckoutConflictError(PathBuf),
+        ObjectNotFoundError(String),
+        AmbiguousObjectError(String, Vec<String>),
+        BranchExistsError(String),
+        BranchNotFoundError(String),
+        BranchCheckedOutError(String, PathBuf),
+        TagExistsError(String),
+        TagNotFoundError(String),
+        NotASymbolicRefError(String),
+        SymbolicRefDepthError(String),
+        InvalidRefError(String),
+        InvalidPackError(String),
+        NoTagsError(String),
+        UnexpectedObjectTypeError(String, ObjectKind),
+        InvalidTreeError(String, TreeParseError),
+        InvalidKvlmError(String, ObjectKind, KvlmParseError),
+        PathOutsideRepositoryError(PathBuf),
+        InvalidReflogError(String, usize),
+        MalformedObjectError(String, String),
+        InvalidFormatError(String),
+        NoIdentityError(IdentityRole),
+        UnknownObjectKindError(String),
+        InvalidIndexError(String),
+        PathNotTrackedError(PathBuf),
+        PathExistsError(PathBuf),
+        LockExistsError(PathBuf),
+        HashMismatchError(String, String),
+        NoSignatureError(String),
+        ApplyConflictError(Vec<String>),
+        NotARepositoryError(PathBuf),
+        MissingConfigError(PathBuf),
+        InvalidConfigValueError(String, String),
+        UnsupportedVersionError(Option<String>),
+        UnknownObjectFormatError(String),
+        UnbornBranchError(String),
+        MissingReflogError(String),
+        ReflogOutOfRangeError(String, usize),
+        NotARevisionError(String),
+        TooManyRefsError(usize),
+    }
+
+    /// The hash function naming a repository's objects, chosen by its
+    /// `extensions.objectFormat` setting.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub(crate) enum HashAlgo {
+        Sha1,
+        Sha256,
+    }
+
+    impl HashAlgo {
+        pub(crate) fn from_name(name: &str) -> Option<HashAlgo> {
+            return match name {
+                "sha1" => Some(HashAlgo::Sha1),
+                "sha256" => Some(HashAlgo::Sha256),
+                _ => None,
+            };
+        }
+
+        pub(crate) fn name(&self) -> &'static str {
+            return match self {
+                HashAlgo::Sha1 => "sha1",
+                HashAlgo::Sha256 => "sha256",
+            };
+        }
+
+        /// The hex digest of data.
+        pub(crate) fn digest(&self, data: &[u8]) -> String {
+            let mut hasher: Box<dyn Digest> = match self {
+                HashAlgo::Sha1 => Box::new(Sha1::new()),
+                HashAlgo::Sha256 => Box::new(Sha256::new()),
+            };
+            hasher.input(data);
+
+            return hasher.result_str();
+        }
+
+        /// The length of a digest in bytes, as stored in trees.
+        pub(crate) fn raw_len(&self) -> usize {
+            return match self {
+                HashAlgo::Sha1 => 20,
+                HashAlgo::Sha256 => 32,
+            };
+        }
+
+        /// The length of a digest as a hex object name.
+        pub(crate) fn hex_len(&self) -> usize {
+            return self.raw_len() * 2;
+        }
+    }
+
+    /// The config shared by every user of the machine.
+    const SYSTEM_CONFIG: &str = "/etc/gitconfig";
+
+    /// How many tags describe weighs up before picking the closest.
+    const DESCRIBE_CANDIDATES: usize = 10;
+
+    /// The most refs show_branch can compare, one per bit of a mask.
+    const SHOW_BRANCH_MAX_REFS: usize = 64;
+
+    /// The longest loose object header we will read, which comfortably fits
+    /// the longest type name and a 64-bit size.
+    const MAX_OBJECT_HEADER_LEN: usize = 32;
+
+    /// How deep alternates listing alternates of their own are followed,
+    /// as git limits it.
+    const MAX_ALTERNATE_DEPTH: usize = 5;
+
+    /// The --git-dir and --work-tree given on the command line, which
+    /// repo_find consults ahead of GIT_DIR and GIT_WORK_TREE.
+    static DIR_OPTIONS: OnceLock<DirOptions> = OnceLock::new();
+
+    #[derive(Debug, Default)]
+    struct DirOptions {
+        git_dir: Option<String>,
+        work_tree: Option<String>,
+    }
+
+    /// How many symbolic refs are followed from a ref before giving up, as
+    /// git limits it, so a cycle of them is an error rather than a hang.
+    const MAX_SYMREF_DEPTH: usize = 5;
+
+    /// What HEAD (or any other ref) points at: another ref, by its full
+    /// name, or a commit when detached.
+    #[derive(Debug, PartialEq)]
+    pub(crate) enum Head {
+        Symbolic(String),
+        Detached(String),
+    }
+
+    impl fmt::Display for ReadObjectErrorType {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            return match self {
+                ReadObjectErrorType::FromUtf8Error(e) => write!(f, "invalid UTF-8: {}", e),
+                ReadObjectErrorType::IO(e) => write!(f, "I/O error: {}", e),
+                ReadObjectErrorType::ParseIntError(e) => write!(f, "invalid number: {}", e),
+                ReadObjectErrorType::TreeNotFoundError => write!(f, "commit has no tree"),
+                ReadObjectErrorType::InvalidPathError => write!(f, "invalid path"),
+                ReadObjectErrorType::CheckoutConflictError(path) => write!(
+                    f,
+                    "checkout would overwrite existing file {}",
+                    path.display()
+                ),
+                ReadObjectErrorType::ObjectNotFoundError(name) => {
+                    write!(f, "not a valid object name: '{}'", name)
+                }
+                ReadObjectErrorType::AmbiguousObjectError(name, candidates) => write!(
+                    f,
+                    "ambiguous object name '{}', candidates: {}",
+                    name,
+                    candidates.join(", ")
+                ),
+                ReadObjectErrorType::BranchExistsError(name) => {
+                    write!(f, "a branch named '{}' already exists", name)
+                }
+                ReadObjectErrorType::BranchCheckedOutError(name, worktree) => write!(
+                    f,
+                    "cannot delete branch '{}' checked out at '{}'",
+                    name,
+                    worktree.display()
+                ),
+                ReadObjectErrorType::BranchNotFoundError(name) => {
+                    write!(f, "branch '{}' not found", name)
+                }
+                ReadObjectErrorType::TagExistsError(name) => {
+                    write!(f, "tag '{}' already exists", name)
+                }
+                ReadObjectErrorType::TagNotFoundError(name) => {
+                    write!(f, "tag '{}' not found", name)
+                }
+                ReadObjectErrorType::NotASymbolicRefError(name) => {
+                    write!(f, "ref {} is not a symbolic ref", name)
+                }
+                ReadObjectErrorType::SymbolicRefDepthError(name) => {
+                    write!(f, "ref {} points through too many symbolic refs", name)
+                }
+                ReadObjectErrorType::InvalidRefError(name) => {
+                    write!(f, "invalid ref name '{}'", name)
+                }
+                ReadObjectErrorType::InvalidPackError(message) => {
+                    write!(f, "bad packfile: {}", message)
+                }
+                ReadObjectErrorType::NoTagsError(sha) => {
+                    write!(f, "no tags can describe '{}'", sha)
+                }
+                ReadObjectErrorType::UnexpectedObjectTypeError(name, object_type) => {
+                    write!(f, "'{}' is an unexpected {} object", name, object_type)
+                }
+                ReadObjectErrorType::InvalidTreeError(sha, e) => {
+                    write!(f, "bad tree {}: {}", sha, e)
+                }
+                ReadObjectErrorType::InvalidKvlmError(sha, kind, e) => {
+                    write!(f, "bad {} {}: {}", kind, sha, e)
+                }
+                ReadObjectErrorType::PathOutsideRepositoryError(path) => {
+                    write!(f, "{} is outside repository", path.display())
+                }
+                ReadObjectErrorType::InvalidReflogError(reference, line) => {
+                    write!(f, "malformed reflog for {} at line {}", reference, line)
+                }
+                ReadObjectErrorType::MalformedObjectError(sha, reason) => {
+                    write!(f, "malformed object {}: {}", sha, reason)
+                }
+                ReadObjectErrorType::InvalidFormatError(format) => {
+                    write!(f, "unsupported format '{}'", format)
+                }
+                ReadObjectErrorType::NoIdentityError(role) => write!(
+                    f,
+                    "unable to determine {} identity: set user.name and user.email",
+                    role.name()
+                ),
+                ReadObjectErrorType::UnknownObjectKindError(kind) => {
+                    write!(f, "unknown object type '{}'", kind)
+                }
+                ReadObjectErrorType::InvalidIndexError(message) => {
+                    write!(f, "bad index file: {}", message)
+                }
+                ReadObjectErrorType::PathNotTrackedError(path) => {
+                    write!(f, "{} is not under version control", path.display())
+                }
+                ReadObjectErrorType::PathExistsError(path) => {
+                    write!(f, "destination {} exists", path.display())
+                }
+                ReadObjectErrorType::LockExistsError(lock) => write!(
+                    f,
+                    "unable to lock: {} exists; another process may be running",
+                    lock.display()
+                ),
+                ReadObjectErrorType::HashMismatchError(sha, actual) => {
+                    write!(f, "object {} hashes to {}", sha, actual)
+                }
+                ReadObjectErrorType::NoSignatureError(sha) => {
+                    write!(f, "no signature found in {}", sha)
+                }
+                ReadObjectErrorType::ApplyConflictError(paths) => {
+                    write!(f, "conflicting changes to {}", paths.join(", "))
+                }
+                ReadObjectErrorType::NotARepositoryError(path) => {
+                    write!(f, "not a git repository: {}", path.display())
+                }
+                ReadObjectErrorType::MissingConfigError(path) => {
+                    write!(f, "configuration file {} is missing", path.display())
+                }
+                ReadObjectErrorType::InvalidConfigValueError(key, value) => {
+                    write!(f, "bad config value '{}' for '{}'", value, key)
+                }
+                ReadObjectErrorType::UnsupportedVersionError(Some(version)) => {
+                    write!(f, "unsupported repositoryformatversion {}", version)
+                }
+                ReadObjectErrorType::UnsupportedVersionError(None) => {
+                    write!(f, "repositoryformatversion is missing")
+                }
+                ReadObjectErrorType::UnknownObjectFormatError(format) => {
+                    write!(f, "unknown object format '{}'", format)
+                }
+                ReadObjectErrorType::MissingReflogError(reference) => {
+                    write!(f, "no reflog for '{}'", reference)
+                }
+                ReadObjectErrorType::ReflogOutOfRangeError(reference, count) => {
+                    write!(f, "log for '{}' only has {} entries", reference, count)
+                }
+                ReadObjectErrorType::NotARevisionError(name) => {
+                    write!(f, "'{}' is not a single revision", name)
+                }
+                ReadObjectErrorType::TooManyRefsError(max) => {
+                    write!(f, "cannot handle more than {} refs", max)
+                }
+                ReadObjectErrorType::UnbornBranchError(branch) => write!(
+                    f,
+                    "your current branch '{}' does not have any commits yet",
+                    branch
+                ),
+            };
+        }
     }
 
     impl Repository {
-        /// Creates a new [`Repository`].
+        /// Creates a new [`Repository`] for the worktree at path. Unless
+        /// forced, this opens an existing repository as open does.
         ///
         /// # Panics
         ///
-        /// Panics if .
+        /// Panics if the repository can't be opened.
         pub(crate) fn new(path: &Path, force: bool) -> Repository {
-            let worktree = path.to_path_buf();
-            let git_dir = worktree.join(".git");
+            let repo = match force {
+                true => Repository::with_dirs(path.to_path_buf(), path.join(".git"), true),
+                false => Repository::open(path),
+            };
+            return repo.unwrap_or_else(|e| panic!("{}", e));
+        }
+
+        /// Open the repository whose worktree is at path, failing if it
+        /// isn't a repository, has no config, or uses a format version or
+        /// object format this doesn't support.
+        pub(crate) fn open(path: &Path) -> Result<Repository, ReadObjectErrorType> {
+            return Repository::with_dirs(path.to_path_buf(), path.join(".git"), false);
+        }
+
+        /// Open the bare repository at path, which has no worktree of its
+        /// own, so its worktree is the git directory itself.
+        fn new_bare(path: &Path) -> Repository {
+            return Repository::with_dirs(path.to_path_buf(), path.to_path_buf(), false)
+                .unwrap_or_else(|e| panic!("{}", e));
+        }
+
+        /// Open the repository with the given worktree and git directory.
+        /// Forcing skips every check, for a repository yet to be created.
+        fn with_dirs(
+            worktree: PathBuf,
+            git_dir: PathBuf,
+            force: bool,
+        ) -> Result<Repository, ReadObjectErrorType> {
             let config = Ini::new();
 
             let mut me = Repository {
                 worktree,
                 git_dir,
                 config,
+                fallback_config: Repository::fallback_config_from(
+                    |name| std::env::var(name).ok(),
+                    Path::new(SYSTEM_CONFIG),
+                ),
+                verify_objects: false,
+                packs: Arc::default(),
             };
 
             if !(force || me.git_dir.is_dir()) {
-                panic!("Not a Git repository {:#?}", path)
+                return Err(ReadObjectErrorType::NotARepositoryError(me.worktree));
             }
 
             // Read the config
             let repo_config = me.repo_file(&["config"], None);
 
             if repo_config.exists() {
-                me.config.load(&repo_config).unwrap();
+                me.config.load(&repo_config).map_err(|e| {
+                    ReadObjectErrorType::IO(io::Error::new(io::ErrorKind::InvalidData, e))
+                })?;
             } else if !force {
-                panic!("Configuration file missing")
+                return Err(ReadObjectErrorType::MissingConfigError(repo_config));
             }
 
             if !force {
+                // Version 1 repositories may use extensions, such as a
+                // different object format
                 let version = me.config.get("core", "repositoryformatversion");
-                if version != Some(String::from("0")) {
-                    panic!("Unsupported repositoryformatversion {:?}", version);
+                if version != Some(String::from("0")) && version != Some(String::from("1")) {
+                    return Err(ReadObjectErrorType::UnsupportedVersionError(version));
+                }
+                if let Some(format) = me.config.get("extensions", "objectFormat") {
+                    if HashAlgo::from_name(&format).is_none() {
+                        return Err(ReadObjectErrorType::UnknownObjectFormatError(format));
+                    }
                 }
             }
 
-            return me;
+            return Ok(me);
         }
 
-        /// Create a new repository at path
-        pub fn repo_create(path: &Path) -> Result<(), io::Error> {
-            let mut repo = Repository::new(&path, true);
+        /// Create a new repository at path. If path already holds a
+        /// repository it is left untouched and true is returned, as git's
+        /// init reinitializes rather than overwriting anything.
+        pub fn repo_create(path: &Path, object_format: HashAlgo) -> Result<bool, io::Error> {
+            let mut repo = Repository::new(path, true);
+
+            if repo.git_dir.is_dir() {
+                return Ok(true);
+            }
 
             // Make sure the path either doesn't exist, or is empty
             if repo.worktree.exists() {
@@ -107,15 +455,113 @@ pub mod repository {
             .unwrap();
 
             // .git/HEAD
+            let branch = repo
+                .config_get("init", "defaultBranch")
+                .unwrap_or_else(|| "master".to_string());
             f = File::create(repo.repo_file(&["HEAD"], None)).unwrap();
-            writeln!(f, "ref: refs/heads/master").unwrap();
+            writeln!(f, "ref: refs/heads/{}", branch).unwrap();
 
             repo.config = Repository::repo_default_config();
+            if object_format != HashAlgo::Sha1 {
+                repo.config
+                    .setstr("core", "repositoryformatversion", Some("1"));
+                repo.config
+                    .setstr("extensions", "objectFormat", Some(object_format.name()));
+            }
             repo.config
                 .write(repo.repo_file(&["config"], None))
                 .unwrap();
 
-            return Ok(());
+            return Ok(false);
+        }
+
+        /// Clone the local repository source into a new repository at path.
+        ///
+        /// Every object and tag is copied, source's branches become remote
+        /// branches under refs/remotes/origin, and the branch source's HEAD
+        /// is on is created locally and checked out.
+        pub(crate) fn repo_clone(
+            source: &Repository,
+            path: &Path,
+        ) -> Result<Repository, ReadObjectErrorType> {
+            let existing = Repository::repo_create(path, source.hash_algo())
+                .map_err(ReadObjectErrorType::IO)?;
+            if existing {
+                return Err(ReadObjectErrorType::IO(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} is already a repository", path.display()),
+                )));
+            }
+            let mut repo = Repository::open(path)?;
+
+            for (sha, object) in source.loose_objects()? {
+                let dest = repo.loose_object_path(&sha);
+                create_dir_all(dest.parent().unwrap()).map_err(ReadObjectErrorType::IO)?;
+                fs::copy(object, dest).map_err(ReadObjectErrorType::IO)?;
+            }
+
+            let pack_dir = source.objects_dir().join("pack");
+            if pack_dir.is_dir() {
+                for entry in pack_dir.read_dir().map_err(ReadObjectErrorType::IO)? {
+                    let entry = entry.map_err(ReadObjectErrorType::IO)?;
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    if name.ends_with(".pack") || name.ends_with(".idx") {
+                        let dest = repo.repo_file(&["objects", "pack", &name], Some(true));
+                        fs::copy(entry.path(), dest).map_err(ReadObjectErrorType::IO)?;
+                    }
+                }
+            }
+
+            for (name, sha) in source.ref_list("refs/heads")? {
+                let branch = name.trim_start_matches("refs/heads/");
+                repo.ref_write(&format!("refs/remotes/origin/{}", branch), &sha)?;
+            }
+            for (name, sha) in source.ref_list("refs/tags")? {
+                repo.ref_write(&name, &sha)?;
+            }
+
+            let source_path = source
+                .worktree
+                .canonicalize()
+                .map_err(ReadObjectErrorType::IO)?;
+            repo.config.setstr(
+                "remote \"origin\"",
+                "url",
+                Some(&source_path.to_string_lossy()),
+            );
+            repo.config.setstr(
+                "remote \"origin\"",
+                "fetch",
+                Some("+refs/heads/*:refs/remotes/origin/*"),
+            );
+
+            let head = source.ref_read("HEAD")?;
+            if let Some(Head::Symbolic(target)) = &head {
+                let branch = target.trim_start_matches("refs/heads/");
+                let remote_branch = format!("refs/remotes/origin/{}", branch);
+                if let Some(sha) = source.ref_resolve(target)? {
+                    repo.ref_write(target, &sha)?;
+                    repo.symbolic_ref_set("refs/remotes/origin/HEAD", &remote_branch)?;
+
+                    let section = format!("branch \"{}\"", branch);
+                    repo.config.setstr(&section, "remote", Some("origin"));
+                    repo.config.setstr(&section, "merge", Some(target));
+                }
+            }
+            repo.config
+                .write(repo.repo_file(&["config"], None))
+                .map_err(ReadObjectErrorType::IO)?;
+
+            if let Some(head) = head {
+                repo.update_head(&head)?;
+            }
+
+            // A source without commits leaves nothing to check out
+            if let Some(sha) = repo.ref_resolve("HEAD")? {
+                repo.checkout_worktree(&repo.commit_tree_sha(&sha)?, false)?;
+            }
+
+            return Ok(repo);
         }
 
         fn repo_default_config() -> Ini {
@@ -133,12 +579,246 @@ pub mod repository {
             return config;
         }
 
+        /// The value of key in section, from the repository's config, else
+        /// the global config, ~/.gitconfig, else the system config,
+        /// /etc/gitconfig.
+        pub(crate) fn config_get(&self, section: &str, key: &str) -> Option<String> {
+            return self.config.get(section, key).or_else(|| {
+                self.fallback_config
+                    .iter()
+                    .find_map(|config| config.get(section, key))
+            });
+        }
+
+        /// The global and system configs which exist, read through env and
+        /// with the system config at system. Like git, GIT_CONFIG_NOSYSTEM
+        /// skips the system config.
+        fn fallback_config_from(env: impl Fn(&str) -> Option<String>, system: &Path) -> Vec<Ini> {
+            let mut paths = Vec::new();
+            if let Some(home) = env("HOME") {
+                paths.push(Path::new(&home).join(".gitconfig"));
+            }
+            if env("GIT_CONFIG_NOSYSTEM").is_none() {
+                paths.push(system.to_path_buf());
+            }
+
+            return paths
+                .iter()
+                .filter_map(|path| {
+                    let mut config = Ini::new();
+                    config.load(path).ok()?;
+                    Some(config)
+                })
+                .collect();
+        }
+
+        /// The hash function naming this repository's objects.
+        pub(crate) fn hash_algo(&self) -> HashAlgo {
+            return self
+                .config
+                .get("extensions", "objectFormat")
+                .and_then(|format| HashAlgo::from_name(&format))
+                .unwrap_or(HashAlgo::Sha1);
+        }
+
+        /// Check that loose objects hash to their names when they are read,
+        /// so a corrupt or renamed object is an error rather than being
+        /// trusted. This means hashing every object read, so it is off
+        /// unless asked for.
+        pub(crate) fn set_verify_objects(&mut self, verify: bool) {
+            self.verify_objects = verify;
+        }
+
+        /// The zlib level loose objects are written with, from
+        /// `core.looseCompression`, or else `core.compression`. Like git,
+        /// this defaults to level 1, which favours speed over size, -1
+        /// means zlib's own default, and any other value outside 0 to 9 is
+        /// an error.
+        pub(crate) fn compression(&self) -> Result<Compression, ReadObjectErrorType> {
+            for key in ["loosecompression", "compression"] {
+                let Some(value) = self.config_get("core", key) else {
+                    continue;
+                };
+
+                return match value.trim().parse::<i32>() {
+                    Ok(-1) => Ok(Compression::default()),
+                    Ok(level @ 0..=9) => Ok(Compression::new(level as u32)),
+                    _ => Err(ReadObjectErrorType::InvalidConfigValueError(
+                        format!("core.{}", key),
+                        value,
+                    )),
+                };
+            }
+
+            return Ok(Compression::fast());
+        }
+
+        /// Who is making a change, in the given role.
+        ///
+        /// Like git, the `GIT_AUTHOR_*` or `GIT_COMMITTER_*` environment
+        /// variables take precedence over user.name and user.email in the
+        /// config, as found by config_get. The time is now, in UTC, unless
+        /// `GIT_AUTHOR_DATE` or `GIT_COMMITTER_DATE` gives one as
+        /// `<timestamp> <timezone>`.
+        pub(crate) fn identity(&self, role: IdentityRole) -> Result<Identity, ReadObjectErrorType> {
+            return self.identity_from(role, |name| std::env::var(name).ok());
+        }
+
+        fn identity_from(
+            &self,
+            role: IdentityRole,
+            env: impl Fn(&str) -> Option<String>,
+        ) -> Result<Identity, ReadObjectErrorType> {
+            let prefix = role.env_prefix();
+            let lookup = |var: &str, key: &str| {
+                return env(&format!("{}_{}", prefix, var))
+                    .or_else(|| self.config_get("user", key))
+                    .filter(|value| !value.is_empty());
+            };
+
+            let (Some(name), Some(email)) = (lookup("NAME", "name"), lookup("EMAIL", "email"))
+            else {
+                return Err(ReadObjectErrorType::NoIdentityError(role));
+            };
+
+            let (timestamp, timezone) = match env(&format!("{}_DATE", prefix)) {
+                Some(date) => date
+                    .trim_start_matches('@')
+                    .split_once(' ')
+                    .and_then(|(timestamp, timezone)| {
+                        Some((timestamp.parse::<i64>().ok()?, timezone.to_string()))
+                    })
+                    .ok_or(ReadObjectErrorType::InvalidFormatError(date))?,
+                None => {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |since| since.as_secs() as i64);
+                    (now, "+0000".to_string())
+                }
+            };
+
+            return Ok(Identity {
+                name,
+                email,
+                timestamp,
+                timezone,
+            });
+        }
+
+        /// Who to record in a reflog as having moved a ref.
+        ///
+        /// Like git, a missing identity doesn't stop refs from moving: the
+        /// committer's name falls back to the login name, from `USER` or
+        /// `LOGNAME`, and their email to `<login>@<hostname>`.
+        fn reflog_identity(&self) -> Result<Identity, ReadObjectErrorType> {
+            return self.reflog_identity_from(|name| std::env::var(name).ok());
+        }
+
+        fn reflog_identity_from(
+            &self,
+            env: impl Fn(&str) -> Option<String>,
+        ) -> Result<Identity, ReadObjectErrorType> {
+            let env = |name: &str| env(name).filter(|value| !value.is_empty());
+            let login = env("USER")
+                .or_else(|| env("LOGNAME"))
+                .unwrap_or_else(|| "unknown".to_string());
+            let host = env("HOSTNAME")
+                .or_else(|| {
+                    let hostname = fs::read_to_string("/etc/hostname").ok()?;
+                    Some(hostname.trim().to_string()).filter(|host| !host.is_empty())
+                })
+                .unwrap_or_else(|| "localhost".to_string());
+
+            let configured = |var: &str, key: &str| {
+                return env(var).or_else(|| self.config_get("user", key).filter(|v| !v.is_empty()));
+            };
+            return self.identity_from(IdentityRole::Committer, |name| match name {
+                "GIT_COMMITTER_NAME" => configured(name, "name").or_else(|| Some(login.clone())),
+                "GIT_COMMITTER_EMAIL" => {
+                    configured(name, "email").or_else(|| Some(format!("{}@{}", login, host)))
+                }
+                _ => env(name),
+            });
+        }
+
         /// Find a repository directory
         ///
-        /// Recurse up the directory tree, all the way to /, until a .git directory is found
+        /// Recurse up the directory tree, all the way to /, until a .git directory, or a bare
+        /// repository, is found, unless --git-dir and --work-tree, or the GIT_DIR and
+        /// GIT_WORK_TREE environment variables, say where it is
         pub fn repo_find(
             path: String,
             required: Option<bool>,
+        ) -> Result<Option<Repository>, io::Error> {
+            let options = DIR_OPTIONS.get_or_init(DirOptions::default);
+            return Repository::repo_find_from(path, required, |name| {
+                let option = match name {
+                    "GIT_DIR" => options.git_dir.clone(),
+                    "GIT_WORK_TREE" => options.work_tree.clone(),
+                    _ => None,
+                };
+                return option.or_else(|| std::env::var(name).ok());
+            });
+        }
+
+        /// Have repo_find use git_dir and work_tree, as given by --git-dir
+        /// and --work-tree, in place of GIT_DIR and GIT_WORK_TREE. Only the
+        /// first call has any effect.
+        pub(crate) fn set_dir_options(git_dir: Option<String>, work_tree: Option<String>) {
+            let _ = DIR_OPTIONS.set(DirOptions { git_dir, work_tree });
+        }
+
+        /// Find a repository as repo_find does, reading the environment
+        /// through env.
+        ///
+        /// As with git, GIT_DIR names the git directory, so no search is
+        /// made, and GIT_WORK_TREE the root of the worktree; relative paths
+        /// in either are relative to path. A GIT_DIR without a GIT_WORK_TREE
+        /// has path as its worktree, unless the repository is bare.
+        fn repo_find_from(
+            path: String,
+            required: Option<bool>,
+            env: impl Fn(&str) -> Option<String>,
+        ) -> Result<Option<Repository>, io::Error> {
+            let env = |name: &str| env(name).filter(|value| !value.is_empty());
+            let work_tree = env("GIT_WORK_TREE")
+                .map(|work_tree| Path::new(&path).join(work_tree).canonicalize())
+                .transpose()?;
+
+            let mut repo = match env("GIT_DIR") {
+                Some(git_dir) => {
+                    let git_dir = Path::new(&path).join(git_dir).canonicalize()?;
+                    let mut repo = Repository::with_dirs(
+                        Path::new(&path).canonicalize()?,
+                        git_dir.clone(),
+                        false,
+                    )
+                    .map_err(|e| match e {
+                        ReadObjectErrorType::IO(e) => e,
+                        e => io::Error::new(io::ErrorKind::InvalidData, e.to_string()),
+                    })?;
+                    if repo.config.getbool("core", "bare").ok().flatten() == Some(true) {
+                        repo.worktree = git_dir;
+                    }
+                    repo
+                }
+                None => match Repository::repo_search(path, required)? {
+                    Some(repo) => repo,
+                    None => return Ok(None),
+                },
+            };
+
+            if let Some(work_tree) = work_tree {
+                repo.worktree = work_tree;
+            }
+
+            return Ok(Some(repo));
+        }
+
+        /// Search path and its parents for a repository.
+        fn repo_search(
+            path: String,
+            required: Option<bool>,
         ) -> Result<Option<Repository>, io::Error> {
             let my_path = Path::new(&path).canonicalize()?;
 
@@ -147,34 +827,162 @@ pub mod repository {
                 return Ok(Some(Repository::new(&my_path, false)));
             }
 
+            // A bare repository keeps HEAD, objects and refs at its top level
+            let is_bare = my_path.join("HEAD").is_file()
+                && my_path.join("objects").is_dir()
+                && my_path.join("refs").is_dir();
+            if is_bare {
+                return Ok(Some(Repository::new_bare(&my_path)));
+            }
+
             // If we haven't returned, recurse in parent
             let parent = my_path.parent();
 
             return match parent {
                 // Bottom case, the root directory, is represented by None
                 None => {
-                    let required = match required {
-                        Some(v) => v,
-                        None => true,
-                    };
-
-                    if required {
+                    if required.unwrap_or(true) {
                         panic!("No git directory.")
                     } else {
                         return Ok(None);
                     }
                 }
                 Some(parent) => {
-                    Repository::repo_find(String::from(parent.to_str().unwrap()), required)
+                    Repository::repo_search(String::from(parent.to_str().unwrap()), required)
+                }
+            };
+        }
+
+        /// Translate path, absolute or relative to the current directory,
+        /// into a path relative to the root of the worktree.
+        pub(crate) fn worktree_relative(
+            &self,
+            path: &Path,
+        ) -> Result<PathBuf, ReadObjectErrorType> {
+            let cwd = std::env::current_dir().map_err(ReadObjectErrorType::IO)?;
+            return self.worktree_relative_to(path, &cwd);
+        }
+
+        fn worktree_relative_to(
+            &self,
+            path: &Path,
+            cwd: &Path,
+        ) -> Result<PathBuf, ReadObjectErrorType> {
+            let absolute = cwd.join(path);
+
+            // The path may name a file which has been deleted, so only the
+            // part of it which exists can be canonicalized
+            let mut existing = absolute.as_path();
+            let mut missing = Vec::new();
+            let canonical = loop {
+                match existing.canonicalize() {
+                    Ok(canonical) => break canonical,
+                    Err(_) => match (existing.parent(), existing.file_name()) {
+                        (Some(parent), Some(name)) => {
+                            missing.push(name);
+                            existing = parent;
+                        }
+                        _ => return Err(ReadObjectErrorType::PathOutsideRepositoryError(absolute)),
+                    },
                 }
             };
+            let canonical = missing
+                .iter()
+                .rev()
+                .fold(canonical, |path, name| path.join(name));
+
+            let worktree = self
+                .worktree
+                .canonicalize()
+                .map_err(ReadObjectErrorType::IO)?;
+            return match canonical.strip_prefix(&worktree) {
+                Ok(relative) if !relative.starts_with(".git") => Ok(relative.to_path_buf()),
+                _ => Err(ReadObjectErrorType::PathOutsideRepositoryError(absolute)),
+            };
+        }
+
+        /// The git directory, usually .git in the worktree.
+        pub fn git_dir(&self) -> &Path {
+            return &self.git_dir;
+        }
+
+        /// The root of the worktree, which for a bare repository is the
+        /// git directory.
+        pub fn worktree(&self) -> &Path {
+            return &self.worktree;
+        }
+
+        /// The directory loose objects and packs are stored under.
+        pub fn objects_dir(&self) -> PathBuf {
+            return self.repo_path(&["objects"]);
+        }
+
+        /// The object directories listed in objects/info/alternates, and in
+        /// theirs in turn, which objects missing from this repository are
+        /// borrowed from. Relative paths are relative to the objects
+        /// directory listing them.
+        fn alternate_dirs(&self) -> Vec<PathBuf> {
+            let mut dirs: Vec<PathBuf> = Vec::new();
+            let mut pending = vec![(self.objects_dir(), 0)];
+            while let Some((dir, depth)) = pending.pop() {
+                if depth == MAX_ALTERNATE_DEPTH {
+                    continue;
+                }
+                let Ok(text) = fs::read_to_string(dir.join("info").join("alternates")) else {
+                    continue;
+                };
+
+                let mut found = Vec::new();
+                for line in text.lines() {
+                    let line = line.trim_end();
+                    if line.is_empty() || line.starts_with('#') {
+                        continue;
+                    }
+                    let alternate = dir.join(line);
+                    if alternate.is_dir()
+                        && alternate != self.objects_dir()
+                        && !dirs.contains(&alternate)
+                    {
+                        dirs.push(alternate.clone());
+                        found.push((alternate, depth + 1));
+                    }
+                }
+                // Follow each alternate's own alternates in the order listed
+                pending.extend(found.into_iter().rev());
+            }
+
+            return dirs;
+        }
+
+        /// The file the loose object sha is stored in, whether or not it
+        /// exists. Objects are both read from and written to here.
+        pub(crate) fn loose_object_path(&self, sha: &str) -> PathBuf {
+            return loose_object_file(&self.objects_dir(), sha);
+        }
+
+        /// The file holding the loose object sha, here or in an alternate.
+        fn find_loose_object(&self, sha: &str) -> Option<PathBuf> {
+            // Alternates are only read once the object isn't found here
+            return std::iter::once(self.loose_object_path(sha))
+                .chain(
+                    std::iter::once_with(|| self.alternate_dirs())
+                        .flatten()
+                        .map(|dir| loose_object_file(&dir, sha)),
+                )
+                .find(|path| path.is_file());
+        }
+
+        /// The file ref name, such as HEAD or refs/heads/master, is stored
+        /// in, whether or not it exists.
+        pub fn ref_path(&self, name: &str) -> PathBuf {
+            return self.repo_path(&name.split('/').collect::<Vec<&str>>());
         }
 
         /// Computes a path under the Repository's gitdir
         fn repo_path(&self, path_segments: &[&str]) -> PathBuf {
             return self
                 .git_dir
-                .join(&path_segments.join(String::from(MAIN_SEPARATOR).as_str()));
+                .join(path_segments.join(String::from(MAIN_SEPARATOR).as_str()));
         }
 
         ///Same as repo_path, but create dirname(path_segments) if absent.  For
@@ -182,7 +990,7 @@ pub mod repository {
         ///.git/refs/remotes/origin.
         pub fn repo_file(&self, path_segments: &[&str], mkdir: Option<bool>) -> PathBuf {
             return match self.repo_dir(&path_segments[0..path_segments.len() - 1], mkdir) {
-                Ok(_) => self.repo_path(&path_segments),
+                Ok(_) => self.repo_path(path_segments),
                 Err(e) => panic!("{}", e),
             };
         }
@@ -193,7 +1001,7 @@ pub mod repository {
             path_segments: &[&str],
             mkdir: Option<bool>,
         ) -> Result<PathBuf, io::Error> {
-            let repo_path = self.repo_path(&path_segments);
+            let repo_path = self.repo_path(path_segments);
 
             if repo_path.exists() {
                 if repo_path.is_dir() {
@@ -220,123 +1028,8027 @@ pub mod repository {
             &self,
             sha: String,
         ) -> Result<Box<dyn GitSerDe>, ReadObjectErrorType> {
-            log::debug!("Retrieving file for object '{}'", sha);
-            let path = self.repo_file(&["objects", &sha[0..2], &sha[2..]], None);
-            log::debug!("Found file {:?}", path);
-            let f = File::open(path).map_err(ReadObjectErrorType::IO)?;
+            return Ok(self.read_object_typed(sha)?.into_boxed());
+        }
 
-            let mut raw = Vec::new();
-            ZlibDecoder::new(f)
-                .read_to_end(&mut raw)
-                .map_err(ReadObjectErrorType::IO)?;
+        /// Read object sha from the repository, parsed into its concrete
+        /// type so it can be matched on rather than downcast.
+        pub(crate) fn read_object_typed(
+            &self,
+            sha: String,
+        ) -> Result<ParsedObject, ReadObjectErrorType> {
+            let (kind, data) = self.read_raw_object(&sha)?;
 
-            log::debug!("Determining type of object '{}'", sha);
-            let x = raw.iter().position(|b| b == &b' ').unwrap();
-            let object_type = String::from_utf8(raw[0..x].to_vec()).unwrap();
-            log::debug!("Object is of type {:?}", object_type);
-
-            log::debug!("Determining size of object '{}'", sha);
-            let mut _y = raw.iter().skip(x).position(|b| b == &b'\x00');
-            let y = *_y.get_or_insert(x) + x;
-            let size = String::from_utf8(raw[x + 1..y].to_vec())
-                .map_err(ReadObjectErrorType::FromUtf8Error)?
-                .parse::<usize>()
-                .map_err(ReadObjectErrorType::ParseIntError)?;
-            log::debug!("Object is {} bytes large", size);
+            let data = GitObjectData(kind, data);
+            let repo = Some(self.clone());
+            return Ok(match kind {
+                ObjectKind::Blob => ParsedObject::Blob(Blob::new(repo, data)),
+                ObjectKind::Tree => ParsedObject::Tree(
+                    Tree::from_data(repo, data)
+                        .map_err(|e| ReadObjectErrorType::InvalidTreeError(sha.clone(), e))?,
+                ),
+                ObjectKind::Commit => ParsedObject::Commit(
+                    Commit::from_data(repo, data)
+                        .map_err(|e| ReadObjectErrorType::InvalidKvlmError(sha.clone(), kind, e))?,
+                ),
+                ObjectKind::Tag => ParsedObject::Tag(
+                    Tag::from_data(repo, data)
+                        .map_err(|e| ReadObjectErrorType::InvalidKvlmError(sha.clone(), kind, e))?,
+                ),
+            });
+        }
 
-            if size != raw.len() - y - 1 {
-                panic!("Malformed object '{}': bad length", sha)
-            }
+        /// The kind and content of object sha exactly as stored, without
+        /// parsing it, so it can be copied elsewhere byte for byte.
+        pub(crate) fn read_raw_object(
+            &self,
+            sha: &str,
+        ) -> Result<(ObjectKind, Vec<u8>), ReadObjectErrorType> {
+            log::debug!("Retrieving file for object '{}'", sha);
 
-            log::debug!("Obtaining object data for '{}'", sha);
-            let object_data = &raw[y + 1..];
-            log::debug!("Object has data with {:?} bytes", object_data.len());
+            return if let Some(path) = self.find_loose_object(sha) {
+                log::debug!("Found file {:?}", path);
+                let (kind, size, decoder) = self.read_loose_header(sha, &path)?;
+                log::debug!("Object is a {} of {} bytes", kind, size);
 
-            return match object_type.as_str() {
-                "commit" => Ok(Box::new(Commit::new(
-                    Some(self.clone()),
-                    GitObjectData(object_type, object_data.to_vec()),
-                ))),
-                "blob" => Ok(Box::new(Blob::new(
-                    Some(self.clone()),
-                    GitObjectData(object_type, object_data.to_vec()),
-                ))),
-                "tree" => Ok(Box::new(Tree::new(
-                    Some(self.clone()),
-                    GitObjectData(object_type, object_data.to_vec()),
-                ))),
-                _ => panic!("Unknown type"),
+                // Reading one byte past the declared size is enough to tell
+                // that the object is longer than it claims
+                let mut data = Vec::new();
+                decoder
+                    .take(size + 1)
+                    .read_to_end(&mut data)
+                    .map_err(ReadObjectErrorType::IO)?;
+                if data.len() as u64 != size {
+                    return Err(ReadObjectErrorType::MalformedObjectError(
+                        sha.to_string(),
+                        "bad length".to_string(),
+                    ));
+                }
+                if self.verify_objects {
+                    let header = format!("{} {}\x00", kind, data.len());
+                    self.check_object_hash(sha, &[header.as_bytes(), &data].concat())?;
+                }
+
+                Ok((kind, data))
+            } else {
+                log::debug!("No loose object for '{}', searching packs", sha);
+                self.read_packed_object(sha)?
+                    .ok_or_else(|| ReadObjectErrorType::ObjectNotFoundError(sha.to_string()))
             };
         }
 
-        pub(crate) fn object_find(
+        /// The kind and size of object sha, read from its header alone so
+        /// that large trees and commits aren't parsed just to be measured.
+        pub(crate) fn stat_object(
             &self,
-            name: String,
-            _fmt: Option<String>,
-            _follow: Option<bool>,
-        ) -> String {
-            return name;
+            sha: &str,
+        ) -> Result<(ObjectKind, u64), ReadObjectErrorType> {
+            if let Some(path) = self.find_loose_object(sha) {
+                let (kind, size, _) = self.read_loose_header(sha, &path)?;
+                return Ok((kind, size));
+            }
+
+            return self
+                .find_in_packs(|pack| pack.find_header(sha))?
+                .ok_or_else(|| ReadObjectErrorType::ObjectNotFoundError(sha.to_string()));
         }
 
-        pub(crate) fn log_graphviz(
+        /// Inflate the header of the loose object sha at path, returning its
+        /// kind, its declared size and a decoder positioned at its content.
+        ///
+        /// Only the header is inflated, so a corrupt or hostile object can't
+        /// make us inflate an unbounded amount of data before we know how
+        /// large it claims to be.
+        fn read_loose_header(
             &self,
-            sha: String,
-            seen: &mut HashSet<String>,
-        ) -> Result<(), ReadObjectErrorType> {
-            if !seen.insert(sha.clone()) {
-                return Ok(());
-            }
-
-            log::debug!("Reading object '{}'...", sha);
-            let object = self.read_object(sha.clone())?;
-            log::debug!("Found object '{}' with type {:?}", sha, object.get_data());
-            let commit = Commit::new(Some(self.clone()), object.get_data());
+            sha: &str,
+            path: &Path,
+        ) -> Result<(ObjectKind, u64, ZlibDecoder<File>), ReadObjectErrorType> {
+            let malformed = |reason: &str| {
+                ReadObjectErrorType::MalformedObjectError(sha.to_string(), reason.to_string())
+            };
+            let mut decoder = ZlibDecoder::new(File::open(path).map_err(ReadObjectErrorType::IO)?);
 
-            log::debug!("Checking for parent commit...");
-            if !commit.has_parent() {
-                // Base case: the initial commit
-                return Ok(());
+            let mut header = Vec::new();
+            let mut byte = [0u8];
+            while header.last() != Some(&b'\x00') {
+                if header.len() == MAX_OBJECT_HEADER_LEN {
+                    return Err(malformed("header too long"));
+                }
+                decoder
+                    .read_exact(&mut byte)
+                    .map_err(ReadObjectErrorType::IO)?;
+                header.push(byte[0]);
             }
 
-            let parents = commit.parents();
+            header.pop();
+            let header = String::from_utf8(header).map_err(ReadObjectErrorType::FromUtf8Error)?;
+            let (kind, size) = header
+                .split_once(' ')
+                .ok_or_else(|| malformed("bad header"))?;
+            let size = size
+                .parse::<u64>()
+                .map_err(ReadObjectErrorType::ParseIntError)?;
+
+            return Ok((kind.parse()?, size, decoder));
+        }
 
-            for p in parents {
-                print!("c_{} -> c_{};", sha, p);
-                self.log_graphviz(p.to_string(), seen)?;
+        /// Check that raw, an object's header and content, hashes to sha.
+        fn check_object_hash(&self, sha: &str, raw: &[u8]) -> Result<(), ReadObjectErrorType> {
+            let actual = self.hash_algo().digest(raw);
+            if actual != sha {
+                return Err(ReadObjectErrorType::HashMismatchError(
+                    sha.to_string(),
+                    actual,
+                ));
             }
 
             return Ok(());
         }
 
-        pub(crate) fn tree_checkout(
+        /// Every object stored in the repository, loose ones then packed
+        /// ones, with its kind, which is read from object headers alone
+        /// where possible. An object stored more than once, say both loose
+        /// and packed, is yielded once for each copy.
+        ///
+        /// This is for listing objects, as `cat-file --batch-all-objects`
+        /// does. fsck, count_objects, gc and prune walk loose_objects and
+        /// packs themselves, as they need each object's file, content or
+        /// pack rather than just its name and kind.
+        pub(crate) fn iter_objects(
             &self,
-            tree: &Tree,
-            path: &PathBuf,
-        ) -> Result<(), ReadObjectErrorType> {
-            for Leaf(_, object_path, sha) in &tree.items {
-                let object = self.read_object(sha.clone())?;
-                let dest = path.join(object_path);
-
-                let tree = object.as_any().downcast_ref::<Tree>();
-                match tree {
-                    Some(tree) => {
-                        create_dir_all(dest).map_err(ReadObjectErrorType::IO)?;
-                        self.tree_checkout(tree, &path)?;
-                    }
-                    None => {
-                        let blob = object.as_any().downcast_ref::<Blob>();
-                        match blob {
-                            Some(blob) => {
-                                let mut f = File::create(dest).map_err(ReadObjectErrorType::IO)?;
-                                let GitObjectData(_, blob_data) = blob.get_data();
-                                f.write_all(blob_data.as_slice())
-                                    .map_err(ReadObjectErrorType::IO)?;
-                            }
-                            None => panic!(),
-                        }
-                    }
-                };
-            }
+        ) -> impl Iterator<Item = Result<(String, ObjectKind), ReadObjectErrorType>> + '_ {
+            let loose = flatten_results(self.loose_objects()).map(|object| {
+                let (sha, path) = object?;
+                let (kind, _, _) = self.read_loose_header(&sha, &path)?;
+                return Ok((sha, kind));
+            });
+
+            // Packs are only opened once the loose objects have been yielded
+            let packed = std::iter::once_with(|| self.packs())
+                .flat_map(flatten_results)
+                .flat_map(|pack| flatten_results(pack.and_then(|pack| pack.objects())));
+
+            return loose.chain(packed);
+        }
+
+        /// Look for object sha in the repository's packfiles, and then its
+        /// alternates', returning its type and content if found.
+        fn read_packed_object(
+            &self,
+            sha: &str,
+        ) -> Result<Option<(ObjectKind, Vec<u8>)>, ReadObjectErrorType> {
+            return self.find_in_packs(|pack| {
+                let object = pack.find_object(sha)?;
+                if object.is_some() {
+                    log::debug!("Found '{}' in {:?}", sha, pack.path());
+                }
+                return Ok(object);
+            });
+        }
+
+        /// The first result found by calling find on each pack in turn,
+        /// here and then in alternates. Like git, the packs are looked for
+        /// again before giving up, as one written since they were opened,
+        /// say by a gc, may hold what is wanted.
+        fn find_in_packs<T>(
+            &self,
+            find: impl Fn(&Pack) -> Result<Option<T>, ReadObjectErrorType>,
+        ) -> Result<Option<T>, ReadObjectErrorType> {
+            for rescan in [false, true] {
+                for pack in self.packs_with_alternates(rescan)? {
+                    if let Some(found) = find(&pack)? {
+                        return Ok(Some(found));
+                    }
+                }
+            }
+
+            return Ok(None);
+        }
+
+        /// Every packfile in .git/objects/pack.
+        pub(crate) fn packs(&self) -> Result<Vec<Arc<Pack>>, ReadObjectErrorType> {
+            let dir = self.objects_dir();
+            let mut packs = self.packs_with_alternates(true)?;
+            packs.retain(|pack| pack.path().starts_with(&dir));
+            return Ok(packs);
+        }
+
+        /// Every packfile here, then those of each alternate. The packs
+        /// opened before are reused, unless rescan is set, when packs
+        /// added since are opened and those deleted since are dropped.
+        fn packs_with_alternates(
+            &self,
+            rescan: bool,
+        ) -> Result<Vec<Arc<Pack>>, ReadObjectErrorType> {
+            let mut cached = self.packs.lock().unwrap();
+            if let (Some(packs), false) = (cached.as_ref(), rescan) {
+                return Ok(packs.clone());
+            }
+
+            let mut paths = pack_paths(&self.objects_dir())?;
+            for dir in self.alternate_dirs() {
+                paths.extend(pack_paths(&dir)?);
+            }
+
+            let mut opened = cached.take().unwrap_or_default();
+            let mut packs = Vec::with_capacity(paths.len());
+            for path in paths {
+                let pack = match opened.iter().position(|pack| pack.path() == path) {
+                    Some(position) => opened.swap_remove(position),
+                    None => Arc::new(Pack::open(&path)?),
+                };
+                packs.push(pack);
+            }
+
+            *cached = Some(packs.clone());
+            return Ok(packs);
+        }
+
+        /// Whether the object sha is stored, loose or in a pack, here or in
+        /// an alternate. Only the indexes of the packs, which are read once
+        /// when the packs are opened, are consulted, so this is much
+        /// cheaper than reading the object. If the packs can't be opened,
+        /// the object is taken not to be in them.
+        pub(crate) fn has_object(&self, sha: &str) -> bool {
+            if sha.len() != self.hash_algo().hex_len()
+                || !sha.chars().all(|c| c.is_ascii_hexdigit())
+            {
+                return false;
+            }
+            if self.find_loose_object(sha).is_some() {
+                return true;
+            }
+
+            return self
+                .find_in_packs(|pack| Ok(pack.find_offset(sha)))
+                .is_ok_and(|offset| offset.is_some());
+        }
+
+        /// Read the index, which is empty if nothing has been staged.
+        pub(crate) fn index(&self) -> Result<Index, ReadObjectErrorType> {
+            let path = self.repo_path(&["index"]);
+            if !path.is_file() {
+                return Ok(Index::default());
+            }
+
+            let data = fs::read(path).map_err(ReadObjectErrorType::IO)?;
+            return Index::parse(&data, self.hash_algo());
+        }
+
+        /// Replace the index with index.
+        pub(crate) fn write_index(&self, index: &Index) -> Result<(), ReadObjectErrorType> {
+            return write_atomic(
+                &self.repo_path(&["index"]),
+                &index.serialize(self.hash_algo()),
+            );
+        }
+
+        /// An index staging exactly the contents of tree sha, as `git
+        /// read-tree` makes. Entries have no stat data, so every file looks
+        /// modified until it is staged again.
+        pub(crate) fn read_tree(&self, sha: &str) -> Result<Index, ReadObjectErrorType> {
+            let mut index = Index::default();
+            for (path, (mode, sha)) in self.flatten_tree(sha)? {
+                index.entries.push(IndexEntry {
+                    mode: u32::from_str_radix(&mode, 8).unwrap(),
+                    sha,
+                    path,
+                    ..Default::default()
+                });
+            }
+
+            index.sort();
+            return Ok(index);
+        }
+
+        /// Map the path of every entry below tree sha, other than subtrees,
+        /// to its mode and SHA. Paths are joined with `/` from the root of
+        /// the tree.
+        pub(crate) fn flatten_tree(
+            &self,
+            tree_sha: &str,
+        ) -> Result<BTreeMap<String, (String, String)>, ReadObjectErrorType> {
+            let mut files = BTreeMap::new();
+            let mut pending = vec![(tree_sha.to_string(), String::new())];
+            while let Some((sha, prefix)) = pending.pop() {
+                let ParsedObject::Tree(tree) = self.read_object_typed(sha.clone())? else {
+                    return Err(ReadObjectErrorType::TreeNotFoundError);
+                };
+
+                for leaf in &tree {
+                    let path = format!("{}{}", prefix, leaf.path());
+                    if leaf.mode() == "40000" {
+                        pending.push((leaf.sha().to_string(), path + "/"));
+                    } else {
+                        files.insert(path, (leaf.mode().to_string(), leaf.sha().to_string()));
+                    }
+                }
+            }
+
+            return Ok(files);
+        }
+
+        /// Write a tree for every directory in index, returning the SHA of
+        /// the root tree, as `git write-tree` does. Conflicts must be
+        /// resolved first. The trees are only hashed if actually_write is
+        /// Some(false), as for write_object.
+        pub(crate) fn write_tree(
+            &self,
+            index: &Index,
+            actually_write: Option<bool>,
+        ) -> Result<String, ReadObjectErrorType> {
+            let mut files = BTreeMap::new();
+            for entry in &index.entries {
+                if entry.stage != 0 {
+                    return Err(ReadObjectErrorType::InvalidIndexError(format!(
+                        "{} is unmerged",
+                        entry.path
+                    )));
+                }
+
+                let mode = format!("{:o}", entry.mode);
+                files.insert(entry.path.clone(), (mode, entry.sha.clone()));
+            }
+
+            return self.build_tree(&files, actually_write);
+        }
+
+        /// Write a tree for every directory holding files, which map paths
+        /// separated by `/` to modes and SHAs as flatten_tree gives them,
+        /// returning the SHA of the root tree. Nothing is written if
+        /// actually_write is Some(false).
+        pub(crate) fn build_tree(
+            &self,
+            files: &BTreeMap<String, (String, String)>,
+            actually_write: Option<bool>,
+        ) -> Result<String, ReadObjectErrorType> {
+            // The tree of each directory, by its path, with the root as the
+            // empty path
+            let empty = || Tree::new(Some(self.clone()), GitObjectData(ObjectKind::Tree, vec![]));
+            let mut dirs: BTreeMap<String, Tree> = BTreeMap::from([(String::new(), empty())]);
+            for (path, (mode, sha)) in files {
+                let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+                dirs.entry(dir.to_string())
+                    .or_insert_with(empty)
+                    .insert(Leaf(mode.clone(), name.to_string(), sha.clone()));
+
+                let mut ancestor = dir;
+                while let Some((parent, _)) = ancestor.rsplit_once('/') {
+                    dirs.entry(parent.to_string()).or_insert_with(empty);
+                    ancestor = parent;
+                }
+            }
+
+            // Deeper directories sort after their parents, so writing them
+            // in reverse writes each tree before the one containing it
+            let mut root = String::new();
+            while let Some((dir, tree)) = dirs.pop_last() {
+                let sha = GitObject::write_object(Box::new(tree), actually_write)?;
+
+                if dir.is_empty() {
+                    root = sha;
+                    continue;
+                }
+                let (parent, name) = dir.rsplit_once('/').unwrap_or(("", &dir));
+                dirs.get_mut(parent).unwrap().insert(Leaf(
+                    "40000".to_string(),
+                    name.to_string(),
+                    sha,
+                ));
+            }
+
+            return Ok(root);
+        }
+
+        /// Move the tracked file source to dest, both relative to the root
+        /// of the worktree, and rename its index entry to match, keeping its
+        /// SHA and stat data. If dest is a directory, the file is moved into
+        /// it. An existing file at dest is only replaced with force.
+        pub(crate) fn mv(
+            &self,
+            source: &Path,
+            dest: &Path,
+            force: bool,
+        ) -> Result<(), ReadObjectErrorType> {
+            let mut index = self.index()?;
+            let source_path = source.to_string_lossy();
+            let Some(position) = index
+                .entries
+                .iter()
+                .position(|entry| entry.path == source_path && entry.stage == 0)
+            else {
+                return Err(ReadObjectErrorType::PathNotTrackedError(
+                    source.to_path_buf(),
+                ));
+            };
+
+            let mut dest = dest.to_path_buf();
+            if self.worktree.join(&dest).is_dir() {
+                if let Some(name) = source.file_name() {
+                    dest.push(name);
+                }
+            }
+            if self.worktree.join(&dest).exists() && !force {
+                return Err(ReadObjectErrorType::PathExistsError(dest));
+            }
+
+            fs::rename(self.worktree.join(source), self.worktree.join(&dest))
+                .map_err(ReadObjectErrorType::IO)?;
+
+            // Whatever was staged at dest has been overwritten
+            let mut entry = index.entries.remove(position);
+            entry.path = dest.to_string_lossy().to_string();
+            index.entries.retain(|staged| staged.path != entry.path);
+            index.entries.push(entry);
+            index.sort();
+
+            return self.write_index(&index);
+        }
+
+        /// Stage the files at paths, relative to the root of the worktree,
+        /// and every file below those which are directories.
+        ///
+        /// Staging a large directory is dominated by hashing, so the blobs
+        /// are hashed and written on several threads, and the index is only
+        /// written once they all have been.
+        pub(crate) fn add(&self, paths: &[PathBuf]) -> Result<(), ReadObjectErrorType> {
+            let mut files = Vec::new();
+            for path in paths {
+                let metadata = self
+                    .worktree
+                    .join(path)
+                    .symlink_metadata()
+                    .map_err(ReadObjectErrorType::IO)?;
+                if !metadata.is_dir() {
+                    files.push(path.to_path_buf());
+                    continue;
+                }
+                for file in self.walk_worktree_from(path) {
+                    files.push(file?);
+                }
+            }
+
+            let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+            let chunk_size = files.len().div_ceil(threads).max(1);
+            let staged = thread::scope(|scope| {
+                let workers: Vec<_> = files
+                    .chunks(chunk_size)
+                    .map(|chunk| {
+                        scope.spawn(|| {
+                            return chunk
+                                .iter()
+                                .map(|path| self.stage_file(path))
+                                .collect::<Result<Vec<_>, _>>();
+                        })
+                    })
+                    .collect();
+
+                return workers
+                    .into_iter()
+                    .map(|worker| worker.join().expect("Staging thread panicked"))
+                    .collect::<Result<Vec<_>, _>>();
+            })?;
+            let staged: Vec<IndexEntry> = staged.into_iter().flatten().collect();
+
+            // Each file replaces whatever was staged at its path
+            let mut index = self.index()?;
+            let added: HashSet<&str> = staged.iter().map(|entry| entry.path.as_str()).collect();
+            index
+                .entries
+                .retain(|entry| !added.contains(entry.path.as_str()));
+            index.entries.extend(staged);
+            index.sort();
+
+            return self.write_index(&index);
+        }
+
+        /// Every file in the worktree which isn't ignored, relative to its
+        /// root, as `add .` would stage them. The .git directory, nested
+        /// repositories and ignored directories are skipped entirely, with
+        /// what is ignored read from .git/info/exclude and the .gitignore
+        /// file of each directory on the way.
+        pub(crate) fn walk_worktree(&self) -> WorktreeWalk {
+            return WorktreeWalk {
+                repo: self.clone(),
+                rules: IgnoreRules::default(),
+                pending: vec![PathBuf::new()],
+                files: Vec::new(),
+            };
+        }
+
+        /// Like walk_worktree, but only the files below the directory dir,
+        /// relative to the root of the worktree. The ignore files of the
+        /// directories above dir still apply, and nothing is yielded if dir
+        /// itself is ignored.
+        fn walk_worktree_from(&self, dir: &Path) -> WorktreeWalk {
+            let mut walk = self.walk_worktree();
+            walk.pending.clear();
+
+            let mut above = PathBuf::new();
+            for component in dir.components() {
+                walk.read_ignore_file(&above);
+                above.push(component);
+                if walk.is_ignored(&above, true) {
+                    return walk;
+                }
+            }
+
+            walk.pending.push(dir.to_path_buf());
+            return walk;
+        }
+
+        /// Write the blob for the file at path, relative to the root of the
+        /// worktree, returning its index entry. Symlinks are stored as the
+        /// path they point to.
+        fn stage_file(&self, path: &Path) -> Result<IndexEntry, ReadObjectErrorType> {
+            let (metadata, data) = self.read_worktree_file(path)?;
+            let blob = Blob::new(Some(self.clone()), GitObjectData(ObjectKind::Blob, data));
+            let sha = GitObject::write_object(Box::new(blob), None)?;
+            return Ok(IndexEntry::new(
+                path.to_string_lossy().to_string(),
+                sha,
+                &metadata,
+            ));
+        }
+
+        /// The metadata and content of the file at path, relative to the
+        /// root of the worktree, with the content of a symlink being the
+        /// path it points to. A file's content is converted to what would
+        /// be stored for it, as clean_file does.
+        fn read_worktree_file(
+            &self,
+            path: &Path,
+        ) -> Result<(fs::Metadata, Vec<u8>), ReadObjectErrorType> {
+            let full = self.worktree.join(path);
+            let metadata = full.symlink_metadata().map_err(ReadObjectErrorType::IO)?;
+            let data = if metadata.file_type().is_symlink() {
+                let target = fs::read_link(&full).map_err(ReadObjectErrorType::IO)?;
+                target.to_string_lossy().as_bytes().to_vec()
+            } else {
+                self.clean_file(fs::read(&full).map_err(ReadObjectErrorType::IO)?)
+            };
+
+            return Ok((metadata, data));
+        }
+
+        /// How core.autocrlf says line endings should be converted.
+        fn autocrlf(&self) -> AutoCrlf {
+            return match self.config_get("core", "autocrlf").as_deref() {
+                Some("input") => AutoCrlf::Input,
+                Some("true" | "yes" | "on" | "1") => AutoCrlf::True,
+                _ => AutoCrlf::False,
+            };
+        }
+
+        /// Convert data, a file's content in the worktree, to what is
+        /// stored for it. Unless core.autocrlf is false, text files have
+        /// their CRLFs replaced with LFs.
+        pub(crate) fn clean_file(&self, data: Vec<u8>) -> Vec<u8> {
+            if self.autocrlf() == AutoCrlf::False || is_binary(&data) || !data.contains(&b'\r') {
+                return data;
+            }
+
+            let mut clean = Vec::with_capacity(data.len());
+            for (n, byte) in data.iter().enumerate() {
+                if *byte != b'\r' || data.get(n + 1) != Some(&b'\n') {
+                    clean.push(*byte);
+                }
+            }
+            return clean;
+        }
+
+        /// Convert data, a blob's content, to what is written to the
+        /// worktree for it. If core.autocrlf is true, text files have their
+        /// LFs replaced with CRLFs, unless they already contain CRs, which
+        /// conversion would stop from round-tripping.
+        fn smudge_file(&self, data: &[u8]) -> Vec<u8> {
+            if self.autocrlf() != AutoCrlf::True || is_binary(data) || data.contains(&b'\r') {
+                return data.to_vec();
+            }
+
+            let mut smudged = Vec::with_capacity(data.len());
+            for byte in data {
+                if *byte == b'\n' {
+                    smudged.push(b'\r');
+                }
+                smudged.push(*byte);
+            }
+            return smudged;
+        }
+
+        /// Every path which differs between HEAD, the index and the
+        /// worktree, in order, followed by the untracked paths. A directory
+        /// holding nothing tracked is listed rather than its contents.
+        pub(crate) fn status(&self) -> Result<Vec<FileStatus>, ReadObjectErrorType> {
+            let head = match self.head_commit()? {
+                Some(commit) => self.flatten_tree(
+                    commit
+                        .tree()
+                        .ok_or(ReadObjectErrorType::TreeNotFoundError)?,
+                )?,
+                None => BTreeMap::new(),
+            };
+
+            // The staged entry at each path, or None if it has conflicting
+            // entries from a merge instead
+            let index = self.index()?;
+            let index_mtime = self.index_mtime();
+            let staged: BTreeMap<&str, Option<&IndexEntry>> = index
+                .entries
+                .iter()
+                .map(|entry| (entry.path.as_str(), (entry.stage == 0).then_some(entry)))
+                .collect();
+
+            let mut tracked = BTreeMap::new();
+            for (path, entry) in &staged {
+                let (staged, unstaged) = match entry {
+                    None => (Change::Unmerged, Change::Unmerged),
+                    Some(entry) => (
+                        match head.get(*path) {
+                            None => Change::Added,
+                            Some((mode, sha))
+                                if *sha != entry.sha || *mode != format!("{:o}", entry.mode) =>
+                            {
+                                Change::Modified
+                            }
+                            Some(_) => Change::Unmodified,
+                        },
+                        self.worktree_change(entry, index_mtime)?,
+                    ),
+                };
+                tracked.insert(*path, (staged, unstaged));
+            }
+            for path in head.keys() {
+                if !staged.contains_key(path.as_str()) {
+                    tracked.insert(path.as_str(), (Change::Deleted, Change::Unmodified));
+                }
+            }
+
+            let mut statuses: Vec<FileStatus> = tracked
+                .into_iter()
+                .filter(|(_, changes)| *changes != (Change::Unmodified, Change::Unmodified))
+                .map(|(path, (staged, unstaged))| FileStatus {
+                    path: path.to_string(),
+                    staged,
+                    unstaged,
+                })
+                .collect();
+
+            let mut tracked_dirs = HashSet::new();
+            for path in staged.keys() {
+                for (end, _) in path.match_indices('/') {
+                    tracked_dirs.insert(&path[..end]);
+                }
+            }
+
+            let mut untracked = BTreeSet::new();
+            for file in self.walk_worktree() {
+                let file = file?;
+                let path = file.to_string_lossy().replace(MAIN_SEPARATOR, "/");
+                if staged.contains_key(path.as_str()) {
+                    continue;
+                }
+
+                let outermost = path
+                    .match_indices('/')
+                    .map(|(end, _)| &path[..end])
+                    .find(|dir| !tracked_dirs.contains(dir));
+                untracked.insert(match outermost {
+                    Some(dir) => format!("{}/", dir),
+                    None => path.clone(),
+                });
+            }
+            statuses.extend(untracked.into_iter().map(|path| FileStatus {
+                path,
+                staged: Change::Untracked,
+                unstaged: Change::Untracked,
+            }));
+
+            return Ok(statuses);
+        }
+
+        /// The paths ls-files lists for filter, in git's order: untracked
+        /// files first, then each index entry once for every one of
+        /// cached, deleted and modified it counts as. Deleted files count
+        /// as modified too, and ignored files aren't untracked.
+        pub(crate) fn ls_files(
+            &self,
+            filter: LsFilesFilter,
+        ) -> Result<Vec<String>, ReadObjectErrorType> {
+            let index = self.index()?;
+            let mut paths = Vec::new();
+
+            if filter.others {
+                let tracked: HashSet<&str> = index
+                    .entries
+                    .iter()
+                    .map(|entry| entry.path.as_str())
+                    .collect();
+                let mut others = BTreeSet::new();
+                for file in self.walk_worktree() {
+                    let path = file?.to_string_lossy().replace(MAIN_SEPARATOR, "/");
+                    if !tracked.contains(path.as_str()) {
+                        others.insert(path);
+                    }
+                }
+                paths.extend(others);
+            }
+
+            let index_mtime = self.index_mtime();
+            for entry in &index.entries {
+                if filter.cached {
+                    paths.push(entry.path.clone());
+                }
+                if !filter.deleted && !filter.modified {
+                    continue;
+                }
+
+                let change = self.worktree_change(entry, index_mtime)?;
+                if filter.deleted && change == Change::Deleted {
+                    paths.push(entry.path.clone());
+                }
+                if filter.modified && change != Change::Unmodified {
+                    paths.push(entry.path.clone());
+                }
+            }
+
+            return Ok(paths);
+        }
+
+        /// When the index was last written, if there is one.
+        fn index_mtime(&self) -> Option<(u32, u32)> {
+            return fs::metadata(self.repo_path(&["index"]))
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+                .map(|mtime| (mtime.as_secs() as u32, mtime.subsec_nanos()));
+        }
+
+        /// How the worktree's copy of entry differs from it, in an index
+        /// last written at index_mtime. Only the kind of file is compared,
+        /// rather than its executable bit, if `core.filemode` is false.
+        ///
+        /// Like git, a file whose size and mtime match those staged is taken
+        /// to be unchanged without hashing it, unless it was staged so close
+        /// to the index being written that it could have changed unnoticed
+        /// since.
+        fn worktree_change(
+            &self,
+            entry: &IndexEntry,
+            index_mtime: Option<(u32, u32)>,
+        ) -> Result<Change, ReadObjectErrorType> {
+            // Submodules are repositories of their own
+            if entry.mode == 0o160000 {
+                return Ok(Change::Unmodified);
+            }
+
+            let path = Path::new(&entry.path);
+            let metadata = match self.worktree.join(path).symlink_metadata() {
+                Ok(metadata) if !metadata.is_dir() => metadata,
+                _ => return Ok(Change::Deleted),
+            };
+
+            let current = IndexEntry::new(entry.path.clone(), String::new(), &metadata);
+            let filemode = !matches!(
+                self.config_get("core", "filemode").as_deref(),
+                Some("false" | "no" | "off" | "0")
+            );
+            let mode_changed = match filemode {
+                true => current.mode != entry.mode,
+                false => (current.mode == 0o120000) != (entry.mode == 0o120000),
+            };
+            if mode_changed {
+                return Ok(Change::Modified);
+            }
+
+            let racy = index_mtime.is_none_or(|index_mtime| entry.mtime >= index_mtime);
+            if current.mtime == entry.mtime && current.size == entry.size && !racy {
+                return Ok(Change::Unmodified);
+            }
+
+            let (_, data) = self.read_worktree_file(path)?;
+            return Ok(match self.hash_blob(data)? != entry.sha {
+                true => Change::Modified,
+                false => Change::Unmodified,
+            });
+        }
+
+        /// Resolve name to the SHA of an object in the repository.
+        ///
+        /// name may be HEAD, a full or abbreviated (at least four characters)
+        /// hash, a full ref path, the short name of a tag, branch or remote
+        /// branch, `<ref>@{n}` for where ref was n moves ago by its reflog,
+        /// `<rev>^n` for the nth parent of a commit, `<rev>~n` for its nth
+        /// first-parent ancestor, or `<name>:<path>` for an entry in a tree.
+        ///
+        /// If fmt gives an object type, an object of another type is peeled
+        /// to one of that type: a tag to the object it tags, and a commit to
+        /// its tree. Peeling can be turned off by setting follow to false.
+        pub(crate) fn object_find(
+            &self,
+            name: String,
+            fmt: Option<ObjectKind>,
+            follow: Option<bool>,
+        ) -> Result<String, ReadObjectErrorType> {
+            let mut sha = if let Some((rev, path)) = name.split_once(':') {
+                self.resolve_path(rev, path)?
+                    .ok_or_else(|| ReadObjectErrorType::ObjectNotFoundError(name.clone()))?
+            } else if let Some((rev, operator, n)) = ancestry_suffix(&name) {
+                let sha = self.object_find(rev.to_string(), Some(ObjectKind::Commit), None)?;
+                self.ancestor(&sha, operator, n)?
+                    .ok_or_else(|| ReadObjectErrorType::ObjectNotFoundError(name.clone()))?
+            } else if let Some((reference, n)) = reflog_selector(&name) {
+                self.reflog_find(reference, n)?
+            } else {
+                let mut candidates = self.object_resolve(&name)?;
+                match candidates.len() {
+                    0 => return Err(ReadObjectErrorType::ObjectNotFoundError(name)),
+                    1 => candidates.remove(0),
+                    _ => return Err(ReadObjectErrorType::AmbiguousObjectError(name, candidates)),
+                }
+            };
+
+            let Some(fmt) = fmt else {
+                return Ok(sha);
+            };
+
+            loop {
+                let object = self.read_object(sha.clone())?;
+                let kind = match object.as_any() {
+                    any if any.is::<Commit>() => ObjectKind::Commit,
+                    any if any.is::<Tree>() => ObjectKind::Tree,
+                    any if any.is::<Tag>() => ObjectKind::Tag,
+                    _ => ObjectKind::Blob,
+                };
+                if kind == fmt {
+                    return Ok(sha);
+                }
+
+                sha = match (kind, fmt) {
+                    (ObjectKind::Tag, _) if follow != Some(false) => object
+                        .as_any()
+                        .downcast_ref::<Tag>()
+                        .unwrap()
+                        .object()
+                        .to_string(),
+                    (ObjectKind::Commit, ObjectKind::Tree) if follow != Some(false) => {
+                        self.commit_tree_sha(&sha)?
+                    }
+                    _ => return Err(ReadObjectErrorType::UnexpectedObjectTypeError(name, kind)),
+                };
+            }
+        }
+
+        /// The SHA of the single object name refers to, as `rev-parse
+        /// --verify` checks: unlike object_find, a ref or full SHA naming an
+        /// object which isn't in the repository is rejected. An ambiguous
+        /// abbreviation fails as it does for object_find.
+        pub(crate) fn verify_revision(&self, name: &str) -> Result<String, ReadObjectErrorType> {
+            let sha = match self.object_find(name.to_string(), None, None) {
+                Err(ReadObjectErrorType::ObjectNotFoundError(_)) => None,
+                result => Some(result?),
+            };
+
+            return sha
+                .filter(|sha| self.has_object(sha))
+                .ok_or_else(|| ReadObjectErrorType::NotARevisionError(name.to_string()));
+        }
+
+        /// The SHA of what `<rev>:<path>` names: the entry at path in the
+        /// tree of rev, or with an empty rev, the file staged at path in
+        /// the index. Paths starting ./ or ../ are relative to the current
+        /// directory. None if there is nothing at path.
+        pub(crate) fn resolve_path(
+            &self,
+            rev: &str,
+            path: &str,
+        ) -> Result<Option<String>, ReadObjectErrorType> {
+            let path = match path.starts_with("./") || path.starts_with("../") {
+                true => self
+                    .worktree_relative(Path::new(path))?
+                    .to_string_lossy()
+                    .replace(MAIN_SEPARATOR, "/"),
+                false => path.to_string(),
+            };
+
+            if rev.is_empty() {
+                return Ok(self
+                    .index()?
+                    .entries
+                    .into_iter()
+                    .find(|entry| entry.stage == 0 && entry.path == path)
+                    .map(|entry| entry.sha));
+            }
+
+            let sha = self.object_find(rev.to_string(), None, None)?;
+            return self.tree_entry(&sha, &path);
+        }
+
+        /// The SHA of the entry at path, separated by `/`, within a tree or
+        /// a commit's tree. An empty path names the tree itself.
+        fn tree_entry(&self, sha: &str, path: &str) -> Result<Option<String>, ReadObjectErrorType> {
+            let mut current = match self.read_object(sha.to_string())?.get_data().0 {
+                ObjectKind::Commit => self.commit_tree_sha(sha)?,
+                _ => sha.to_string(),
+            };
+
+            for component in path.split('/').filter(|c| !c.is_empty()) {
+                let object = self.read_object(current)?;
+                let Some(tree) = object.as_any().downcast_ref::<Tree>() else {
+                    return Ok(None);
+                };
+                match tree.get(component) {
+                    Some(leaf) => current = leaf.sha().to_string(),
+                    None => return Ok(None),
+                }
+            }
+
+            return Ok(Some(current));
+        }
+
+        /// The SHAs of the objects, loose or packed, here or in an
+        /// alternate, starting with prefix, which must be lowercase hex at
+        /// least two digits long.
+        fn objects_with_prefix(&self, prefix: &str) -> Result<Vec<String>, ReadObjectErrorType> {
+            let mut objects = Vec::new();
+            for dir in std::iter::once(self.objects_dir()).chain(self.alternate_dirs()) {
+                let dir = dir.join(&prefix[0..2]);
+                if !dir.is_dir() {
+                    continue;
+                }
+                for entry in dir.read_dir().map_err(ReadObjectErrorType::IO)? {
+                    let file_name = entry.map_err(ReadObjectErrorType::IO)?.file_name();
+                    let rest = file_name.to_string_lossy();
+                    let sha = prefix[0..2].to_string() + &rest;
+                    if rest.starts_with(&prefix[2..]) && !objects.contains(&sha) {
+                        objects.push(sha);
+                    }
+                }
+            }
+
+            for pack in self.packs_with_alternates(true)? {
+                for sha in pack.find_prefix(prefix) {
+                    if !objects.contains(&sha) {
+                        objects.push(sha);
+                    }
+                }
+            }
+
+            return Ok(objects);
+        }
+
+        /// The shortest prefix of sha, at least min_len digits long, which
+        /// names no other object in the repository. Like git, no
+        /// abbreviation is shorter than four digits.
+        pub(crate) fn abbreviate(
+            &self,
+            sha: &str,
+            min_len: usize,
+        ) -> Result<String, ReadObjectErrorType> {
+            let min_len = min_len.clamp(4, sha.len());
+
+            // Any object sharing the first min_len digits must be told
+            // apart by the digit after the longest prefix they share
+            let mut len = min_len;
+            for other in self.objects_with_prefix(&sha[0..min_len])? {
+                let shared = sha
+                    .bytes()
+                    .zip(other.bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                if shared < sha.len() {
+                    len = len.max(shared + 1);
+                }
+            }
+
+            return Ok(sha[0..len].to_string());
+        }
+
+        /// The commit `<sha>^n` names if operator is `^`, or `<sha>~n` if
+        /// it is `~`, or None if sha has no such ancestor.
+        fn ancestor(
+            &self,
+            sha: &str,
+            operator: char,
+            n: usize,
+        ) -> Result<Option<String>, ReadObjectErrorType> {
+            if operator == '^' {
+                return match n {
+                    0 => Ok(Some(sha.to_string())),
+                    _ => Ok(self.read_commit(sha)?.parents().get(n - 1).cloned()),
+                };
+            }
+
+            let mut sha = sha.to_string();
+            for _ in 0..n {
+                match self.read_commit(&sha)?.parents().first() {
+                    Some(parent) => sha = parent.clone(),
+                    None => return Ok(None),
+                }
+            }
+
+            return Ok(Some(sha));
+        }
+
+        /// The SHA reference pointed to n moves ago, by its reflog. An empty
+        /// reference means the current branch, as in `@{1}`.
+        fn reflog_find(&self, reference: &str, n: usize) -> Result<String, ReadObjectErrorType> {
+            let full_name = match reference {
+                "" => match self.current_branch()? {
+                    Some(branch) => format!("refs/heads/{}", branch),
+                    None => "HEAD".to_string(),
+                },
+                _ => self
+                    .ref_full_name(reference)?
+                    .ok_or_else(|| ReadObjectErrorType::InvalidRefError(reference.to_string()))?,
+            };
+
+            let mut reflog = self.reflog(&full_name)?;
+            if reflog.is_empty() {
+                return Err(ReadObjectErrorType::MissingReflogError(full_name));
+            }
+            if n >= reflog.len() {
+                return Err(ReadObjectErrorType::ReflogOutOfRangeError(
+                    full_name,
+                    reflog.len(),
+                ));
+            }
+
+            return Ok(reflog.swap_remove(n).new);
+        }
+
+        /// The full name of the existing ref called name, which may be
+        /// short, e.g. "master" for "refs/heads/master".
+        pub(crate) fn ref_full_name(
+            &self,
+            name: &str,
+        ) -> Result<Option<String>, ReadObjectErrorType> {
+            if check_ref_name(name).is_err() {
+                return Ok(None);
+            }
+
+            for full_name in [
+                name.to_string(),
+                format!("refs/heads/{}", name),
+                format!("refs/tags/{}", name),
+                format!("refs/remotes/{}", name),
+            ] {
+                if self.ref_read(&full_name)?.is_some() {
+                    return Ok(Some(full_name));
+                }
+            }
+
+            return Ok(None);
+        }
+
+        fn object_resolve(&self, name: &str) -> Result<Vec<String>, ReadObjectErrorType> {
+            let name = name.trim();
+            if name.is_empty() {
+                return Ok(vec![]);
+            }
+
+            if name == "HEAD" {
+                return Ok(self.ref_resolve("HEAD")?.into_iter().collect());
+            }
+
+            let mut candidates = Vec::new();
+
+            let is_hash = name.len() >= 4
+                && name.len() <= self.hash_algo().hex_len()
+                && name.chars().all(|c| c.is_ascii_hexdigit());
+            if is_hash {
+                candidates = self.objects_with_prefix(&name.to_lowercase())?;
+            }
+
+            // A name which can't be a ref isn't looked for as one
+            if check_ref_name(name).is_err() {
+                return Ok(candidates);
+            }
+
+            for reference in [
+                name.to_string(),
+                format!("refs/tags/{}", name),
+                format!("refs/heads/{}", name),
+                format!("refs/remotes/{}", name),
+            ] {
+                if let Some(sha) = self.ref_resolve(&reference)? {
+                    if !candidates.contains(&sha) {
+                        candidates.push(sha);
+                    }
+                }
+            }
+
+            return Ok(candidates);
+        }
+
+        /// Follow reference (e.g. "HEAD" or "refs/heads/master") through any
+        /// symbolic "ref: " indirections to the SHA it points at. Returns
+        /// None if the reference, or anything it points to, doesn't exist
+        /// yet, as is the case for HEAD in a freshly created repository.
+        pub(crate) fn ref_resolve(
+            &self,
+            reference: &str,
+        ) -> Result<Option<String>, ReadObjectErrorType> {
+            let mut name = reference.to_string();
+            for _ in 0..=MAX_SYMREF_DEPTH {
+                name = match self.ref_read(&name)? {
+                    Some(Head::Symbolic(target)) => target,
+                    Some(Head::Detached(sha)) => return Ok(Some(sha)),
+                    None => return Ok(None),
+                };
+            }
+
+            return Err(ReadObjectErrorType::SymbolicRefDepthError(
+                reference.to_string(),
+            ));
+        }
+
+        /// The references which have a reflog, HEAD first if it has one.
+        fn reflog_names(&self) -> Result<Vec<String>, ReadObjectErrorType> {
+            let logs = self.git_dir.join("logs");
+            let mut names = Vec::new();
+            if logs.join("HEAD").is_file() {
+                names.push("HEAD".to_string());
+            }
+
+            let mut pending = vec!["refs".to_string()];
+            while let Some(name) = pending.pop() {
+                let path = logs.join(&name);
+                if path.is_file() {
+                    names.push(name);
+                } else if path.is_dir() {
+                    for entry in path.read_dir().map_err(ReadObjectErrorType::IO)? {
+                        let entry = entry.map_err(ReadObjectErrorType::IO)?;
+                        pending.push(format!("{}/{}", name, entry.file_name().to_string_lossy()));
+                    }
+                }
+            }
+
+            return Ok(names);
+        }
+
+        /// Read the reflog of reference, e.g. "HEAD" or "refs/heads/master",
+        /// newest entry first so that entry n is `<reference>@{n}`. A
+        /// reference without a reflog has no entries.
+        pub(crate) fn reflog(
+            &self,
+            reference: &str,
+        ) -> Result<Vec<ReflogEntry>, ReadObjectErrorType> {
+            let path = self.git_dir.join("logs").join(reference);
+            if !path.is_file() {
+                return Ok(vec![]);
+            }
+
+            let mut entries = Vec::new();
+            let content = fs::read_to_string(path).map_err(ReadObjectErrorType::IO)?;
+            for (number, line) in content.lines().enumerate() {
+                let invalid =
+                    || ReadObjectErrorType::InvalidReflogError(reference.to_string(), number + 1);
+
+                // <old> <new> <name> <<email>> <timestamp> <timezone>\t<message>
+                let (header, message) = line.split_once('\t').unwrap_or((line, ""));
+                let (old, rest) = header.split_once(' ').ok_or_else(invalid)?;
+                let (new, rest) = rest.split_once(' ').ok_or_else(invalid)?;
+                let mut signature = rest.rsplitn(3, ' ');
+                let timezone = signature.next().ok_or_else(invalid)?;
+                let timestamp = signature
+                    .next()
+                    .and_then(|timestamp| timestamp.parse().ok())
+                    .ok_or_else(invalid)?;
+                let who = signature.next().ok_or_else(invalid)?;
+
+                entries.push(ReflogEntry {
+                    old: old.to_string(),
+                    new: new.to_string(),
+                    who: who.to_string(),
+                    timestamp,
+                    timezone: timezone.to_string(),
+                    message: message.to_string(),
+                });
+            }
+
+            entries.reverse();
+            return Ok(entries);
+        }
+
+        /// Read reference without following it: a "ref: " line is returned as
+        /// Head::Symbolic, anything else as the SHA it contains. A loose ref
+        /// takes precedence over one in packed-refs. Returns None if the
+        /// reference doesn't exist.
+        pub(crate) fn ref_read(
+            &self,
+            reference: &str,
+        ) -> Result<Option<Head>, ReadObjectErrorType> {
+            check_ref_name(reference)?;
+            let path = self.ref_path(reference);
+            if !path.is_file() {
+                return Ok(self.packed_refs()?.remove(reference).map(Head::Detached));
+            }
+
+            let data = fs::read_to_string(path).map_err(ReadObjectErrorType::IO)?;
+            let data = data.trim_end();
+
+            return Ok(Some(match data.strip_prefix("ref: ") {
+                Some(target) => Head::Symbolic(target.to_string()),
+                None => Head::Detached(data.to_string()),
+            }));
+        }
+
+        /// The ref that symbolic ref name (usually HEAD) points to, e.g.
+        /// "refs/heads/master".
+        pub(crate) fn symbolic_ref(&self, name: &str) -> Result<String, ReadObjectErrorType> {
+            return match self.ref_read(name)? {
+                Some(Head::Symbolic(target)) => Ok(target),
+                _ => Err(ReadObjectErrorType::NotASymbolicRefError(name.to_string())),
+            };
+        }
+
+        /// Make name a symbolic ref pointing at target, which must be a full
+        /// ref name under refs/.
+        pub(crate) fn symbolic_ref_set(
+            &self,
+            name: &str,
+            target: &str,
+        ) -> Result<(), ReadObjectErrorType> {
+            check_ref_name(name)?;
+            check_ref_name(target)?;
+            if !target.starts_with("refs/") {
+                return Err(ReadObjectErrorType::InvalidRefError(target.to_string()));
+            }
+
+            return write_atomic(
+                &self.ref_path(name),
+                format!("ref: {}\n", target).as_bytes(),
+            );
+        }
+
+        /// List every reference under prefix (e.g. "refs/heads"), loose or
+        /// packed, keyed by its full name and mapped to the SHA it resolves
+        /// to.
+        pub(crate) fn ref_list(
+            &self,
+            prefix: &str,
+        ) -> Result<BTreeMap<String, String>, ReadObjectErrorType> {
+            let mut refs: BTreeMap<String, String> = self
+                .packed_refs()?
+                .into_iter()
+                .filter(|(name, _)| name.starts_with(&format!("{}/", prefix)))
+                .collect();
+
+            // Loose refs are written over packed ones, so take precedence
+            self.loose_ref_list(prefix, &mut refs)?;
+            return Ok(refs);
+        }
+
+        /// Add every loose reference under prefix to refs.
+        fn loose_ref_list(
+            &self,
+            prefix: &str,
+            refs: &mut BTreeMap<String, String>,
+        ) -> Result<(), ReadObjectErrorType> {
+            let path = self.git_dir.join(prefix);
+            if !path.is_dir() {
+                return Ok(());
+            }
+
+            for entry in path.read_dir().map_err(ReadObjectErrorType::IO)? {
+                let entry = entry.map_err(ReadObjectErrorType::IO)?;
+                let name = format!("{}/{}", prefix, entry.file_name().to_string_lossy());
+
+                if entry.path().is_dir() {
+                    self.loose_ref_list(&name, refs)?;
+                } else if let Some(sha) = self.ref_resolve(&name)? {
+                    refs.insert(name, sha);
+                }
+            }
+
+            return Ok(());
+        }
+
+        /// The refs in the packed-refs file, which `git pack-refs` and
+        /// `git gc` move loose refs into, keyed by full name and mapped to
+        /// their SHA.
+        fn packed_refs(&self) -> Result<BTreeMap<String, String>, ReadObjectErrorType> {
+            let mut refs = BTreeMap::new();
+            let path = self.repo_path(&["packed-refs"]);
+            if !path.is_file() {
+                return Ok(refs);
+            }
+
+            let data = fs::read_to_string(&path).map_err(ReadObjectErrorType::IO)?;
+            for line in data.lines() {
+                // A peeled line, starting ^, gives what the tag before it
+                // points to, which callers find by reading the tag instead
+                if line.starts_with('#') || line.starts_with('^') || line.is_empty() {
+                    continue;
+                }
+
+                let (sha, name) = line
+                    .split_once(' ')
+                    .ok_or_else(|| ReadObjectErrorType::InvalidRefError(line.to_string()))?;
+                refs.insert(name.to_string(), sha.to_string());
+            }
+
+            return Ok(refs);
+        }
+
+        /// Write every ref matching pattern (or all refs, if there is none)
+        /// to out, one per line, expanding `%(refname)`, `%(objectname)` and
+        /// `%(objecttype)` in format.
+        ///
+        /// Like git, a pattern matches a ref either literally up to a slash,
+        /// so `refs/heads` matches every branch, or as a glob in which `*`
+        /// and `?` do not match a slash.
+        pub(crate) fn for_each_ref(
+            &self,
+            pattern: Option<&str>,
+            format: &str,
+            out: &mut impl Write,
+        ) -> Result<(), ReadObjectErrorType> {
+            for (name, sha) in self.ref_list("refs")? {
+                if pattern.is_some_and(|pattern| !ref_matches(pattern, &name)) {
+                    continue;
+                }
+
+                let mut line = String::new();
+                let mut rest = format;
+                while let Some(start) = rest.find("%(") {
+                    line += &rest[..start];
+                    let end = rest[start..]
+                        .find(')')
+                        .map(|end| start + end)
+                        .ok_or_else(|| {
+                            ReadObjectErrorType::InvalidFormatError(rest[start..].to_string())
+                        })?;
+
+                    line += &match &rest[start + 2..end] {
+                        "refname" => name.clone(),
+                        "objectname" => sha.clone(),
+                        "objecttype" => self.read_object(sha.clone())?.get_data().0.to_string(),
+                        _ => {
+                            return Err(ReadObjectErrorType::InvalidFormatError(
+                                rest[start..=end].to_string(),
+                            ))
+                        }
+                    };
+                    rest = &rest[end + 1..];
+                }
+                line += rest;
+
+                writeln!(out, "{}", line).map_err(ReadObjectErrorType::IO)?;
+            }
+
+            return Ok(());
+        }
+
+        /// Point ref_path (e.g. "refs/heads/master") at sha, creating any
+        /// missing parent directories under the git directory.
+        ///
+        /// The new value is written to a lock file alongside the ref and
+        /// renamed into place, so readers never see a partially written ref.
+        pub(crate) fn ref_write(
+            &self,
+            ref_path: &str,
+            sha: &str,
+        ) -> Result<(), ReadObjectErrorType> {
+            check_ref_name(ref_path)?;
+            let path = self.ref_path(ref_path);
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent).map_err(ReadObjectErrorType::IO)?;
+            }
+
+            return write_atomic(&path, format!("{}\n", sha).as_bytes());
+        }
+
+        /// Point reference at sha as `git update-ref` does, following any
+        /// symbolic refs to the ref which is actually written, and record
+        /// the movement with message in the reflog of each ref involved.
+        /// Moving the branch HEAD is on is recorded in HEAD's reflog too.
+        ///
+        /// Reflogs are written where core.logAllRefUpdates asks for them,
+        /// where one already exists, or for every ref if create_reflog is
+        /// set.
+        pub(crate) fn ref_update(
+            &self,
+            reference: &str,
+            sha: &str,
+            message: &str,
+            create_reflog: bool,
+        ) -> Result<(), ReadObjectErrorType> {
+            check_ref_name(reference)?;
+            let mut names = vec![reference.to_string()];
+            while let Some(Head::Symbolic(target)) = self.ref_read(names.last().unwrap())? {
+                if names.len() > MAX_SYMREF_DEPTH {
+                    return Err(ReadObjectErrorType::SymbolicRefDepthError(
+                        reference.to_string(),
+                    ));
+                }
+                names.push(target);
+            }
+            let target = names.last().unwrap().clone();
+            if !names.contains(&"HEAD".to_string())
+                && self.ref_read("HEAD")? == Some(Head::Symbolic(target.clone()))
+            {
+                names.push("HEAD".to_string());
+            }
+
+            // Anything wrong with who is moving the ref is found before it
+            // moves, so the ref and its reflog can't disagree
+            let who = self.reflog_identity()?;
+            let old = self.ref_resolve(&target)?;
+            self.ref_write(&target, sha)?;
+
+            for name in names {
+                if create_reflog || self.logs_ref_updates(&name) {
+                    self.reflog_append(&name, old.as_deref(), sha, &who, message)?;
+                }
+            }
+
+            return Ok(());
+        }
+
+        /// Whether moving reference should be recorded in its reflog without
+        /// being asked. Like git, core.logAllRefUpdates defaults to true
+        /// unless the repository is bare, which logs HEAD, branches, remote
+        /// branches and notes, and "always" logs every ref. A ref which
+        /// already has a reflog always has it added to.
+        fn logs_ref_updates(&self, reference: &str) -> bool {
+            if self.git_dir.join("logs").join(reference).is_file() {
+                return true;
+            }
+
+            let setting = self.config_get("core", "logallrefupdates");
+            return match setting.as_deref() {
+                Some("always") => true,
+                Some("false" | "no" | "off" | "0") => false,
+                None if self.worktree == self.git_dir => false,
+                _ => {
+                    reference == "HEAD"
+                        || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+                            .iter()
+                            .any(|prefix| reference.starts_with(prefix))
+                }
+            };
+        }
+
+        /// Append the movement of reference from old, or from nothing, to
+        /// new by who to its reflog, creating the reflog if need be.
+        fn reflog_append(
+            &self,
+            reference: &str,
+            old: Option<&str>,
+            new: &str,
+            who: &Identity,
+            message: &str,
+        ) -> Result<(), ReadObjectErrorType> {
+            let path = self.git_dir.join("logs").join(reference);
+            if let Some(parent) = path.parent() {
+                create_dir_all(parent).map_err(ReadObjectErrorType::IO)?;
+            }
+
+            // Like git, runs of whitespace, newlines included, become one
+            // space so that each entry stays on its own line
+            let message = message
+                .split_ascii_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            let zero = "0".repeat(self.hash_algo().hex_len());
+            let mut log = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .map_err(ReadObjectErrorType::IO)?;
+            return writeln!(log, "{} {} {}\t{}", old.unwrap_or(&zero), new, who, message)
+                .map_err(ReadObjectErrorType::IO);
+        }
+
+        /// Rewrite HEAD, either as a symbolic ref to a branch or detached at
+        /// a commit.
+        pub(crate) fn update_head(&self, head: &Head) -> Result<(), ReadObjectErrorType> {
+            return match head {
+                Head::Symbolic(reference) => self.symbolic_ref_set("HEAD", reference),
+                Head::Detached(sha) => {
+                    write_atomic(&self.repo_path(&["HEAD"]), format!("{}\n", sha).as_bytes())
+                }
+            };
+        }
+
+        /// The name of the branch HEAD points to, or None if HEAD is detached.
+        pub(crate) fn current_branch(&self) -> Result<Option<String>, ReadObjectErrorType> {
+            return match self.ref_read("HEAD")? {
+                Some(Head::Symbolic(target)) => {
+                    Ok(target.strip_prefix("refs/heads/").map(String::from))
+                }
+                _ => Ok(None),
+            };
+        }
+
+        /// The short name of the ref name refers to, as `rev-parse
+        /// --abbrev-ref` prints it. A symbolic ref gives the ref it points
+        /// to, so HEAD gives the current branch, or HEAD itself when
+        /// detached. Like git, name may leave off refs/, refs/tags/,
+        /// refs/heads/ or refs/remotes/.
+        pub(crate) fn abbrev_ref(&self, name: &str) -> Result<String, ReadObjectErrorType> {
+            for prefix in ["", "refs/", "refs/tags/", "refs/heads/", "refs/remotes/"] {
+                let reference = format!("{}{}", prefix, name);
+                match self.ref_read(&reference)? {
+                    Some(Head::Symbolic(target)) => return Ok(shorten_ref(&target).to_string()),
+                    Some(Head::Detached(_)) => return Ok(shorten_ref(&reference).to_string()),
+                    None => {}
+                }
+            }
+
+            return Err(ReadObjectErrorType::ObjectNotFoundError(name.to_string()));
+        }
+
+        /// Create branch name pointing at sha. An existing branch is only
+        /// replaced if force is set.
+        pub(crate) fn branch_create(
+            &self,
+            name: &str,
+            sha: &str,
+            force: bool,
+        ) -> Result<(), ReadObjectErrorType> {
+            check_ref_name(name)?;
+            let reference = format!("refs/heads/{}", name);
+            if self.ref_read(&reference)?.is_some() && !force {
+                return Err(ReadObjectErrorType::BranchExistsError(name.to_string()));
+            }
+
+            return self.ref_write(&reference, sha);
+        }
+
+        /// Delete branch name, whether it is a loose ref or in packed-refs.
+        /// Like git, the branch HEAD is on can't be deleted.
+        pub(crate) fn branch_delete(&self, name: &str) -> Result<(), ReadObjectErrorType> {
+            check_ref_name(name)?;
+            if self.current_branch()?.as_deref() == Some(name) {
+                return Err(ReadObjectErrorType::BranchCheckedOutError(
+                    name.to_string(),
+                    self.worktree.clone(),
+                ));
+            }
+            let reference = format!("refs/heads/{}", name);
+            let path = self.ref_path(&reference);
+            let loose = path.is_file();
+            if loose {
+                fs::remove_file(path).map_err(ReadObjectErrorType::IO)?;
+            }
+            let packed = self.packed_ref_delete(&reference)?;
+
+            if !loose && packed.is_none() {
+                return Err(ReadObjectErrorType::BranchNotFoundError(name.to_string()));
+            }
+            return self.reflog_delete(&reference);
+        }
+
+        /// Create a lightweight tag name pointing at sha, failing if the tag
+        /// exists.
+        pub(crate) fn tag_create(&self, name: &str, sha: &str) -> Result<(), ReadObjectErrorType> {
+            check_ref_name(name)?;
+            let reference = format!("refs/tags/{}", name);
+            if self.ref_read(&reference)?.is_some() {
+                return Err(ReadObjectErrorType::TagExistsError(name.to_string()));
+            }
+
+            return self.ref_write(&reference, sha);
+        }
+
+        /// Delete tag name, whether it is a loose ref or in packed-refs,
+        /// returning the SHA it pointed to.
+        pub(crate) fn tag_delete(&self, name: &str) -> Result<String, ReadObjectErrorType> {
+            check_ref_name(name)?;
+            let reference = format!("refs/tags/{}", name);
+            let path = self.ref_path(&reference);
+            let loose = match path.is_file() {
+                true => {
+                    let sha = fs::read_to_string(&path).map_err(ReadObjectErrorType::IO)?;
+                    fs::remove_file(&path).map_err(ReadObjectErrorType::IO)?;
+                    Some(sha.trim_end().to_string())
+                }
+                false => None,
+            };
+            let packed = self.packed_ref_delete(&reference)?;
+
+            let sha = loose
+                .or(packed)
+                .ok_or_else(|| ReadObjectErrorType::TagNotFoundError(name.to_string()))?;
+            self.reflog_delete(&reference)?;
+            return Ok(sha);
+        }
+
+        /// Delete the reflog of reference, which goes with it when it's
+        /// deleted, if it has one.
+        fn reflog_delete(&self, reference: &str) -> Result<(), ReadObjectErrorType> {
+            let path = self.git_dir.join("logs").join(reference);
+            if path.is_file() {
+                fs::remove_file(path).map_err(ReadObjectErrorType::IO)?;
+            }
+
+            return Ok(());
+        }
+
+        /// Drop reference, and the peeled value following it, from the
+        /// packed-refs file, returning the SHA it had, if it was there.
+        fn packed_ref_delete(
+            &self,
+            reference: &str,
+        ) -> Result<Option<String>, ReadObjectErrorType> {
+            let path = self.repo_path(&["packed-refs"]);
+            if !path.is_file() {
+                return Ok(None);
+            }
+
+            let data = fs::read_to_string(&path).map_err(ReadObjectErrorType::IO)?;
+            let mut kept = String::with_capacity(data.len());
+            let mut deleted = None;
+            let mut dropping = false;
+            for line in data.lines() {
+                // A peeled line, starting ^, belongs to the ref before it
+                if line.starts_with('^') && dropping {
+                    continue;
+                }
+
+                dropping = match line.split_once(' ') {
+                    Some((sha, name)) if name == reference && !line.starts_with('#') => {
+                        deleted = Some(sha.to_string());
+                        true
+                    }
+                    _ => false,
+                };
+                if !dropping {
+                    kept.push_str(line);
+                    kept.push('\n');
+                }
+            }
+
+            if deleted.is_some() {
+                write_atomic(&path, kept.as_bytes())?;
+            }
+            return Ok(deleted);
+        }
+
+        /// The names of the tags, loose or packed, without refs/tags/, in
+        /// order. With a pattern, only those matching it as a glob are
+        /// listed.
+        pub(crate) fn tag_list(
+            &self,
+            pattern: Option<&str>,
+        ) -> Result<Vec<String>, ReadObjectErrorType> {
+            return Ok(self
+                .ref_list("refs/tags")?
+                .into_keys()
+                .map(|name| name.trim_start_matches("refs/tags/").to_string())
+                .filter(|name| {
+                    pattern.is_none_or(|pattern| glob_matches(pattern.as_bytes(), name.as_bytes()))
+                })
+                .collect());
+        }
+
+        /// The SHA and path of every loose object.
+        fn loose_objects(&self) -> Result<Vec<(String, PathBuf)>, ReadObjectErrorType> {
+            let mut objects = Vec::new();
+
+            for entry in self
+                .repo_path(&["objects"])
+                .read_dir()
+                .map_err(ReadObjectErrorType::IO)?
+            {
+                let entry = entry.map_err(ReadObjectErrorType::IO)?;
+                let name = entry.file_name().to_string_lossy().to_string();
+
+                // Loose objects live in directories named after the first
+                // two hex digits of their SHA
+                let is_fanout = name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit());
+                if !is_fanout || !entry.path().is_dir() {
+                    continue;
+                }
+
+                for object in entry.path().read_dir().map_err(ReadObjectErrorType::IO)? {
+                    let object = object.map_err(ReadObjectErrorType::IO)?;
+                    // Skip the temporary files of objects being written
+                    let rest = object.file_name().to_string_lossy().to_string();
+                    if object.path().is_file() && rest.chars().all(|c| c.is_ascii_hexdigit()) {
+                        objects.push((format!("{}{}", name, rest), object.path()));
+                    }
+                }
+            }
+
+            objects.sort();
+            return Ok(objects);
+        }
+
+        /// Check every object in the repository, returning the problems
+        /// found.
+        ///
+        /// Loose objects must hash to their file name and have a header
+        /// length matching their content, and packs must match their
+        /// checksum. Objects are read one at a time, keeping only the links
+        /// each makes to others, so that any an object names but which
+        /// don't exist are reported as missing, and any which nothing
+        /// refers to, neither another object nor a root as prune finds
+        /// them, as dangling.
+        pub(crate) fn fsck(&self) -> Result<Vec<FsckProblem>, ReadObjectErrorType> {
+            let mut problems = Vec::new();
+            let mut objects = BTreeMap::new();
+            let mut links = BTreeMap::new();
+
+            for (sha, path) in self.loose_objects()? {
+                let (object_type, size, decoder) = match self.read_loose_header(&sha, &path) {
+                    Ok(header) => header,
+                    Err(ReadObjectErrorType::IO(_)) => {
+                        problems.push(FsckProblem::Corrupt(sha, "cannot inflate".to_string()));
+                        continue;
+                    }
+                    Err(_) => {
+                        problems.push(FsckProblem::Corrupt(sha, "malformed header".to_string()));
+                        continue;
+                    }
+                };
+
+                // As when reading an object, one byte past the declared size
+                // is enough to tell it is longer than it claims
+                let mut data = Vec::new();
+                if decoder.take(size + 1).read_to_end(&mut data).is_err() {
+                    problems.push(FsckProblem::Corrupt(sha, "cannot inflate".to_string()));
+                    continue;
+                }
+                if data.len() as u64 != size {
+                    problems.push(FsckProblem::LengthMismatch(sha));
+                    continue;
+                }
+
+                let header = format!("{} {}\x00", object_type, size);
+                if let Err(ReadObjectErrorType::HashMismatchError(sha, actual)) =
+                    self.check_object_hash(&sha, &[header.as_bytes(), &data].concat())
+                {
+                    problems.push(FsckProblem::HashMismatch(sha, actual));
+                    continue;
+                }
+
+                self.fsck_links(&sha, object_type, data, &mut links, &mut problems);
+                objects.insert(sha, object_type);
+            }
+
+            for pack in self.packs()? {
+                let checked = pack.verify_checksum().and_then(|_| {
+                    for entry in pack.entries() {
+                        let (object_type, data) = pack.object_at(entry?.offset)?;
+                        let sha = object_sha(object_type, &data);
+                        self.fsck_links(&sha, object_type, data, &mut links, &mut problems);
+                        objects.insert(sha, object_type);
+                    }
+                    return Ok(());
+                });
+
+                if let Err(error) = checked {
+                    problems.push(FsckProblem::BadPack(
+                        pack.path().to_path_buf(),
+                        error.to_string(),
+                    ));
+                }
+            }
+
+            for (sha, object_type) in &links {
+                if !objects.contains_key(sha) {
+                    problems.push(FsckProblem::Missing(*object_type, sha.clone()));
+                }
+            }
+
+            let roots: HashSet<String> = self.root_objects()?.into_iter().collect();
+            for (sha, object_type) in &objects {
+                if !links.contains_key(sha) && !roots.contains(sha) {
+                    problems.push(FsckProblem::Dangling(*object_type, sha.clone()));
+                }
+            }
+
+            return Ok(problems);
+        }
+
+        /// Add the objects the object sha of object_type, with content
+        /// data, links to, and the type each should be, to links. If the
+        /// object can't be parsed, that is added to problems instead.
+        fn fsck_links(
+            &self,
+            sha: &str,
+            object_type: ObjectKind,
+            data: Vec<u8>,
+            links: &mut BTreeMap<String, ObjectKind>,
+            problems: &mut Vec<FsckProblem>,
+        ) {
+            let data = GitObjectData(object_type, data);
+            let corrupt =
+                |e: &dyn fmt::Display| FsckProblem::Corrupt(sha.to_string(), e.to_string());
+            match object_type {
+                ObjectKind::Commit | ObjectKind::Tag => match data.kvlm_parse(None, None) {
+                    Ok(kvlm) => {
+                        let tagged = kvlm
+                            .get("type")
+                            .and_then(|types| types.first()?.parse().ok())
+                            .unwrap_or(ObjectKind::Commit);
+                        for (key, linked_type) in [
+                            ("tree", ObjectKind::Tree),
+                            ("parent", ObjectKind::Commit),
+                            ("object", tagged),
+                        ] {
+                            for linked in kvlm.get(key).into_iter().flatten() {
+                                links.insert(linked.clone(), linked_type);
+                            }
+                        }
+                    }
+                    Err(e) => problems.push(corrupt(&e)),
+                },
+                ObjectKind::Tree => match Tree::from_data(Some(self.clone()), data) {
+                    Ok(tree) => {
+                        // Submodule commits live in another repository
+                        for leaf in tree.iter().filter(|leaf| leaf.mode() != "160000") {
+                            let linked_type = match leaf.mode().starts_with("40") {
+                                true => ObjectKind::Tree,
+                                false => ObjectKind::Blob,
+                            };
+                            links.insert(leaf.sha().to_string(), linked_type);
+                        }
+                    }
+                    Err(e) => problems.push(corrupt(&e)),
+                },
+                ObjectKind::Blob => {}
+            }
+        }
+
+        /// The SHA of every object reachable from roots, including the roots
+        /// themselves, following tags to their objects, commits to their
+        /// trees and parents, and trees to their entries.
+        ///
+        /// The walk keeps its own stack of objects to visit rather than
+        /// recursing, so long histories and deep trees can't overflow.
+        pub(crate) fn reachable_objects(
+            &self,
+            roots: &[String],
+        ) -> Result<HashSet<String>, ReadObjectErrorType> {
+            let mut pending = roots.to_vec();
+            let mut reachable = HashSet::new();
+            while let Some(sha) = pending.pop() {
+                if !reachable.insert(sha.clone()) {
+                    continue;
+                }
+
+                match self.read_object_typed(sha)? {
+                    ParsedObject::Tag(tag) => pending.push(tag.object().to_string()),
+                    ParsedObject::Commit(commit) => {
+                        pending.extend(commit.kvlm.get("tree").into_iter().flatten().cloned());
+                        pending.extend(commit.parents().iter().cloned());
+                    }
+                    ParsedObject::Tree(tree) => {
+                        // Submodule commits live in another repository
+                        for leaf in tree.iter().filter(|leaf| leaf.mode() != "160000") {
+                            pending.push(leaf.sha().to_string());
+                        }
+                    }
+                    ParsedObject::Blob(_) => {}
+                }
+            }
+
+            return Ok(reachable);
+        }
+
+        /// The objects kept whether or not anything else refers to them:
+        /// those named by a ref, HEAD, the index or a reflog.
+        fn root_objects(&self) -> Result<Vec<String>, ReadObjectErrorType> {
+            let mut roots: Vec<String> = self.ref_list("refs")?.into_values().collect();
+            roots.extend(self.ref_resolve("HEAD")?);
+            // Submodule commits staged as gitlinks live in another repository
+            let index = self.index()?;
+            let staged = index
+                .entries
+                .into_iter()
+                .filter(|entry| entry.mode != 0o160000);
+            roots.extend(staged.map(|entry| entry.sha));
+            for reference in self.reflog_names()? {
+                for entry in self.reflog(&reference)? {
+                    // Skip the null SHA of a ref's creation, and anything
+                    // the reflog still names after it was pruned by git
+                    for sha in [entry.old, entry.new] {
+                        if !sha.bytes().all(|b| b == b'0') && self.has_object(&sha) {
+                            roots.push(sha);
+                        }
+                    }
+                }
+            }
+
+            return Ok(roots);
+        }
+
+        /// Delete every loose object which is not reachable from a ref,
+        /// HEAD, the index or a reflog, returning their SHAs. With dry_run,
+        /// they are only listed.
+        pub(crate) fn prune(&self, dry_run: bool) -> Result<Vec<String>, ReadObjectErrorType> {
+            let roots = self.root_objects()?;
+            let reachable = self.reachable_objects(&roots)?;
+
+            let mut pruned = Vec::new();
+            for (sha, path) in self.loose_objects()? {
+                if reachable.contains(&sha) {
+                    continue;
+                }
+
+                if !dry_run {
+                    fs::remove_file(&path).map_err(ReadObjectErrorType::IO)?;
+                    if let Some(dir) = path.parent() {
+                        let _ = fs::remove_dir(dir);
+                    }
+                }
+                pruned.push(sha);
+            }
+
+            return Ok(pruned);
+        }
+
+        /// Write the objects named by shas into a new pack and index,
+        /// `<base>-<checksum>.pack` and `<base>-<checksum>.idx`, returning
+        /// the checksum.
+        pub(crate) fn pack_objects(
+            &self,
+            shas: &[String],
+            base: &str,
+        ) -> Result<String, ReadObjectErrorType> {
+            let mut objects = Vec::with_capacity(shas.len());
+            for sha in shas {
+                // Re-serializing a parsed object could change its SHA
+                objects.push(self.read_raw_object(sha)?);
+            }
+
+            let (pack, index, checksum) = write_pack(&objects)?;
+            fs::write(format!("{}-{}.pack", base, checksum), pack)
+                .map_err(ReadObjectErrorType::IO)?;
+            fs::write(format!("{}-{}.idx", base, checksum), index)
+                .map_err(ReadObjectErrorType::IO)?;
+
+            return Ok(checksum);
+        }
+
+        /// Move every loose object into a new pack under objects/pack,
+        /// returning how many were packed.
+        ///
+        /// Each loose object is only deleted once it has been read back from
+        /// the new pack, so running out of space or a bad pack loses
+        /// nothing. With no loose objects, nothing is written.
+        pub(crate) fn gc(&self) -> Result<usize, ReadObjectErrorType> {
+            let loose = self.loose_objects()?;
+            if loose.is_empty() {
+                return Ok(0);
+            }
+
+            // Pack indexes are only written with SHA-1 names
+            if self.hash_algo() != HashAlgo::Sha1 {
+                return Err(ReadObjectErrorType::InvalidPackError(format!(
+                    "cannot pack {} objects",
+                    self.hash_algo().name()
+                )));
+            }
+
+            let shas: Vec<String> = loose.iter().map(|(sha, _)| sha.clone()).collect();
+            let pack_dir = self
+                .repo_dir(&["objects", "pack"], Some(true))
+                .map_err(ReadObjectErrorType::IO)?;
+            let checksum = self.pack_objects(&shas, &pack_dir.join("pack").to_string_lossy())?;
+            let pack_path = pack_dir.join(format!("pack-{}.pack", checksum));
+
+            // Every object is read back before any is deleted, and a pack
+            // that fails this is removed rather than left to be used
+            let read_back = || {
+                let pack = Pack::open(&pack_path)?;
+                pack.verify_checksum()?;
+                for (sha, _) in &loose {
+                    let packed = pack.find_object(sha)?;
+                    if packed
+                        .is_none_or(|(object_type, data)| object_sha(object_type, &data) != *sha)
+                    {
+                        return Err(ReadObjectErrorType::InvalidPackError(format!(
+                            "{} could not be read back from pack-{}",
+                            sha, checksum
+                        )));
+                    }
+                }
+                return Ok(());
+            };
+            if let Err(e) = read_back() {
+                let _ = fs::remove_file(&pack_path);
+                let _ = fs::remove_file(pack_path.with_extension("idx"));
+                return Err(e);
+            }
+
+            for (_, path) in &loose {
+                fs::remove_file(path).map_err(ReadObjectErrorType::IO)?;
+                // The fan-out directory goes too once it's empty
+                if let Some(dir) = path.parent() {
+                    let _ = fs::remove_dir(dir);
+                }
+            }
+
+            return Ok(loose.len());
+        }
+
+        /// Write every object in pack to the repository as a loose object,
+        /// returning their SHAs in pack order.
+        ///
+        /// Each object is checked against the SHA the pack's index records
+        /// for its offset, if the pack has one. Without one, as for a pack
+        /// read from stdin, nothing is there to check them against.
+        pub(crate) fn unpack_objects(
+            &self,
+            pack: &Pack,
+        ) -> Result<Vec<String>, ReadObjectErrorType> {
+            pack.verify_checksum()?;
+
+            let mut written = Vec::new();
+            for entry in pack.entries() {
+                let offset = entry?.offset;
+                let (object_type, data) = pack.object_at(offset)?;
+
+                let object = GitObject::new(Some(self.clone()), GitObjectData(object_type, data));
+                let sha = GitObject::write_object(object, None)?;
+
+                let claimed = pack.has_index().then(|| pack.find_offset(&sha));
+                if claimed.is_some_and(|claimed| claimed != Some(offset)) {
+                    return Err(ReadObjectErrorType::InvalidPackError(format!(
+                        "object at offset {} does not match its SHA",
+                        offset
+                    )));
+                }
+
+                log::debug!("Unpacked '{}' from offset {}", sha, offset);
+                written.push(sha);
+            }
+
+            return Ok(written);
+        }
+
+        /// Count the objects stored in the repository and the disk space
+        /// they take up.
+        pub(crate) fn count_objects(&self) -> Result<ObjectCounts, ReadObjectErrorType> {
+            let mut counts = ObjectCounts::default();
+
+            for (_, path) in self.loose_objects()? {
+                counts.count += 1;
+                counts.size += disk_usage(&path.metadata().map_err(ReadObjectErrorType::IO)?);
+            }
+
+            for pack in self.packs()? {
+                counts.packs += 1;
+                counts.in_pack += pack.object_count() as usize;
+                // Unlike loose objects, git counts packs by their length, and
+                // includes the index, where there is one on disk
+                counts.size_pack += pack
+                    .path()
+                    .metadata()
+                    .map_err(ReadObjectErrorType::IO)?
+                    .len();
+                counts.size_pack += pack
+                    .path()
+                    .with_extension("idx")
+                    .metadata()
+                    .map_or(0, |metadata| metadata.len());
+            }
+
+            return Ok(counts);
+        }
+
+        /// Walk the ancestry of the start commits, yielding each reachable
+        /// commit exactly once, most recently committed first. Commits
+        /// already in seen, and their ancestors reachable only through them,
+        /// are skipped.
+        pub(crate) fn walk_history(&self, start: &[String], seen: HashSet<String>) -> HistoryWalk {
+            let mut walk = HistoryWalk {
+                repo: self.clone(),
+                queue: BinaryHeap::new(),
+                seen,
+                sequence: 0,
+            };
+
+            for sha in start {
+                walk.push(sha.clone());
+            }
+
+            return walk;
+        }
+
+        /// The best common ancestors of commits a and b, newest first.
+        ///
+        /// Common ancestors which are themselves ancestors of another
+        /// common ancestor are discarded, so a criss-cross merge can have
+        /// more than one base. If one commit is an ancestor of the other,
+        /// it is the only base.
+        pub(crate) fn merge_bases(
+            &self,
+            a: &str,
+            b: &str,
+        ) -> Result<Vec<String>, ReadObjectErrorType> {
+            let ancestors_of_b = self
+                .walk_history(&[b.to_string()], HashSet::new())
+                .collect::<Result<HashSet<String>, ReadObjectErrorType>>()?;
+
+            let mut candidates = Vec::new();
+            for sha in self.walk_history(&[a.to_string()], HashSet::new()) {
+                let sha = sha?;
+                if ancestors_of_b.contains(&sha) {
+                    candidates.push(sha);
+                }
+            }
+
+            // Everything reachable from a candidate, other than the
+            // candidate itself, is a worse base
+            let mut redundant = HashSet::new();
+            for candidate in &candidates {
+                if redundant.contains(candidate) {
+                    continue;
+                }
+
+                let parents = self.read_commit(candidate)?.parents().to_vec();
+                for sha in self.walk_history(&parents, redundant.clone()) {
+                    redundant.insert(sha?);
+                }
+            }
+
+            return Ok(candidates
+                .into_iter()
+                .filter(|sha| !redundant.contains(sha))
+                .collect());
+        }
+
+        /// The commits at the tips of every ref and HEAD, without repeats,
+        /// for walking the whole history as `log --all` does. Tags are
+        /// peeled to what they tag, and refs which don't lead to a commit
+        /// are left out.
+        pub(crate) fn ref_tips(&self) -> Result<Vec<String>, ReadObjectErrorType> {
+            let mut tips = Vec::new();
+            let refs = self.ref_list("refs")?.into_values();
+            for mut sha in refs.chain(self.ref_resolve("HEAD")?) {
+                loop {
+                    match self.read_object_typed(sha.clone())? {
+                        ParsedObject::Tag(tag) => sha = tag.object().to_string(),
+                        ParsedObject::Commit(_) if !tips.contains(&sha) => {
+                            tips.push(sha);
+                            break;
+                        }
+                        _ => break,
+                    }
+                }
+            }
+
+            return Ok(tips);
+        }
+
+        /// Name commit sha after the nearest tag in its history, as
+        /// `<tag>-<n>-g<short sha>` where n counts the commits since the tag,
+        /// or just `<tag>` if the commit is tagged. Only annotated tags are
+        /// considered unless lightweight is set.
+        pub(crate) fn describe(
+            &self,
+            sha: &str,
+            lightweight: bool,
+        ) -> Result<String, ReadObjectErrorType> {
+            // The commit each usable tag names. Like git, an annotated tag
+            // is preferred to a lightweight one on the same commit.
+            let mut tagged: BTreeMap<String, (bool, String)> = BTreeMap::new();
+            for (name, mut target) in self.ref_list("refs/tags")? {
+                let mut annotated = false;
+                loop {
+                    let object = self.read_object(target.clone())?;
+                    match object.as_any().downcast_ref::<Tag>() {
+                        Some(tag) => target = tag.object().to_string(),
+                        None => break,
+                    }
+                    annotated = true;
+                }
+
+                if annotated || lightweight {
+                    let name = name.trim_start_matches("refs/tags/").to_string();
+                    let entry = tagged.entry(target).or_insert((annotated, name.clone()));
+                    if annotated && !entry.0 {
+                        *entry = (annotated, name);
+                    }
+                }
+            }
+
+            // Like git, consider the first few tags found walking back from
+            // the commit, and pick the one fewest commits away
+            let mut best: Option<(usize, &String)> = None;
+            let mut candidates = 0;
+            for commit in self.walk_history(&[sha.to_string()], HashSet::new()) {
+                let commit = commit?;
+                let Some((_, name)) = tagged.get(&commit) else {
+                    continue;
+                };
+
+                let tag_history = self
+                    .walk_history(&[commit], HashSet::new())
+                    .collect::<Result<HashSet<String>, ReadObjectErrorType>>()?;
+                let depth = self.walk_history(&[sha.to_string()], tag_history).count();
+                if best.is_none_or(|(best_depth, _)| depth < best_depth) {
+                    best = Some((depth, name));
+                }
+
+                candidates += 1;
+                if candidates == DESCRIBE_CANDIDATES {
+                    break;
+                }
+            }
+
+            return match best {
+                None => Err(ReadObjectErrorType::NoTagsError(sha.to_string())),
+                Some((0, name)) => Ok(name.clone()),
+                Some((depth, name)) => Ok(format!("{}-{}-g{}", name, depth, &sha[0..7])),
+            };
+        }
+
+        /// The history of the start commits, newest first, as walk_history
+        /// gives it. If any paths are given, only the commits which change
+        /// something at one of them are kept, as `git log -- <path>` does.
+        fn log_walk<'a>(
+            &'a self,
+            start: &[String],
+            paths: &'a [String],
+        ) -> impl Iterator<Item = Result<String, ReadObjectErrorType>> + 'a {
+            return self
+                .walk_history(start, HashSet::new())
+                .filter_map(move |sha| {
+                    let touched = match &sha {
+                        Ok(sha) if !paths.is_empty() => self.commit_touches(sha, paths),
+                        _ => Ok(true),
+                    };
+                    return match touched {
+                        Ok(true) => Some(sha),
+                        Ok(false) => None,
+                        Err(e) => Some(Err(e)),
+                    };
+                });
+        }
+
+        /// Whether commit sha changed the file or directory at any of paths
+        /// from its first parent. A root commit changes whatever it has.
+        pub(crate) fn commit_touches(
+            &self,
+            sha: &str,
+            paths: &[String],
+        ) -> Result<bool, ReadObjectErrorType> {
+            let commit = self.read_commit(sha)?;
+            for path in paths {
+                let before = match commit.parents().first() {
+                    Some(parent) => self.tree_entry(parent, path)?,
+                    None => None,
+                };
+                if self.tree_entry(sha, path)? != before {
+                    return Ok(true);
+                }
+            }
+
+            return Ok(false);
+        }
+
+        /// Write the history of the start commits to out in git's default
+        /// "medium" format, with dates shown raw, as `--date=raw` would.
+        /// Merges list the short SHAs of their parents. Commits are shown
+        /// abbreviated to at least abbrev digits if it is given. Only
+        /// commits changing paths are shown if there are any, as for
+        /// log_walk. Author dates are shown in the date format, and the
+        /// commit lines are yellow if color is set.
+        pub(crate) fn log_medium(
+            &self,
+            start: &[String],
+            paths: &[String],
+            abbrev: Option<usize>,
+            date: DateFormat,
+            color: bool,
+            out: &mut impl Write,
+        ) -> Result<(), ReadObjectErrorType> {
+            for (n, sha) in self.log_walk(start, paths).enumerate() {
+                let sha = sha?;
+                let commit = self.read_commit(&sha)?;
+
+                if n > 0 {
+                    writeln!(out).map_err(ReadObjectErrorType::IO)?;
+                }
+                let name = match abbrev {
+                    Some(abbrev) => self.abbreviate(&sha, abbrev)?,
+                    None => sha.clone(),
+                };
+                let header = Color::Yellow.paint(&format!("commit {}", name), color);
+                writeln!(out, "{}", header).map_err(ReadObjectErrorType::IO)?;
+                if commit.parents().len() > 1 {
+                    let parents = commit
+                        .parents()
+                        .iter()
+                        .map(|parent| self.abbreviate(parent, abbrev.unwrap_or(7)))
+                        .collect::<Result<Vec<String>, ReadObjectErrorType>>()?;
+                    writeln!(out, "Merge: {}", parents.join(" "))
+                        .map_err(ReadObjectErrorType::IO)?;
+                }
+                let date = match date {
+                    DateFormat::Raw => commit.author_date().to_string(),
+                    date => date.format(commit.author_time(), commit.author_timezone()),
+                };
+                writeln!(out, "Author: {}\nDate:   {}\n", commit.author(), date)
+                    .map_err(ReadObjectErrorType::IO)?;
+                for line in commit.message().lines() {
+                    writeln!(out, "    {}", line).map_err(ReadObjectErrorType::IO)?;
+                }
+            }
+
+            return Ok(());
+        }
+
+        /// Write the history of the start commits, limited to paths as
+        /// log_walk describes, to out as a JSON array, with an object per
+        /// commit.
+        pub(crate) fn log_json(
+            &self,
+            start: &[String],
+            paths: &[String],
+            out: &mut impl Write,
+        ) -> Result<(), ReadObjectErrorType> {
+            let mut entries = Vec::new();
+            for sha in self.log_walk(start, paths) {
+                let sha = sha?;
+                let commit = self.read_commit(&sha)?;
+
+                entries.push(LogEntry {
+                    parents: commit.parents().to_vec(),
+                    author: commit.author().to_string(),
+                    committer: commit.committer().to_string(),
+                    date: commit.author_time(),
+                    message: commit.message().to_string(),
+                    sha,
+                });
+            }
+
+            serde_json::to_writer_pretty(&mut *out, &entries)
+                .map_err(|e| ReadObjectErrorType::IO(e.into()))?;
+            return writeln!(out).map_err(ReadObjectErrorType::IO);
+        }
+
+        /// Write the history of the start commits, limited to paths as for
+        /// log_walk, to out as a graphviz digraph, declaring a node
+        /// labelled with the short SHA and summary line for every commit,
+        /// and an edge from each commit to its parents.
+        pub(crate) fn log_graphviz(
+            &self,
+            start: &[String],
+            paths: &[String],
+            out: &mut impl Write,
+        ) -> Result<(), ReadObjectErrorType> {
+            write!(out, "digraph wyaglog{{").map_err(ReadObjectErrorType::IO)?;
+
+            for sha in self.log_walk(start, paths) {
+                let sha = sha?;
+                log::debug!("Reading commit '{}'...", sha);
+                let commit = self.read_commit(&sha)?;
+
+                let summary = commit.message().lines().next().unwrap_or_default();
+                let label = dot_escape(&format!("{} {}", &sha[0..7], summary));
+                write!(out, "c_{} [label=\"{}\"];", sha, label).map_err(ReadObjectErrorType::IO)?;
+
+                if !commit.has_parent() {
+                    // The initial commit has no edges to draw
+                    continue;
+                }
+
+                for p in commit.parents() {
+                    write!(out, "c_{} -> c_{};", sha, p).map_err(ReadObjectErrorType::IO)?;
+                }
+            }
+
+            return write!(out, "}}").map_err(ReadObjectErrorType::IO);
+        }
+
+        /// Write which of refs contain each recent commit to out, as `git
+        /// show-branch` does: a header line per ref, then the commits from
+        /// the tips down to the newest one all of them contain, each with a
+        /// column per ref. A column is '*' if the commit is on the current
+        /// branch, '+' if it is on another of the refs, or '-' for a merge,
+        /// and each commit is named by how it is reached from a tip. Like
+        /// git, merges on only one of the refs are left out, and a single
+        /// ref just shows its tip.
+        pub(crate) fn show_branch(
+            &self,
+            refs: &[String],
+            out: &mut impl Write,
+        ) -> Result<(), ReadObjectErrorType> {
+            let mut names: Vec<String> = Vec::new();
+            for name in refs {
+                if !names.contains(name) {
+                    names.push(name.clone());
+                }
+            }
+            if names.len() > SHOW_BRANCH_MAX_REFS {
+                return Err(ReadObjectErrorType::TooManyRefsError(SHOW_BRANCH_MAX_REFS));
+            }
+
+            let current = self.current_branch()?;
+            let mut tips = Vec::new();
+            let mut head_at = None;
+            for (i, name) in names.iter().enumerate() {
+                let sha = self.object_find(name.clone(), Some(ObjectKind::Commit), None)?;
+                if current.is_some() && self.abbrev_ref(name).ok() == current {
+                    head_at.get_or_insert(i);
+                }
+                tips.push(sha);
+            }
+
+            // Walk back from the tips, newest first, so every child of a
+            // commit has passed its mask on before the commit is reached
+            let all = u64::MAX.checked_shr(64 - names.len() as u32).unwrap_or(0);
+            let mut masks = HashMap::new();
+            for (i, sha) in tips.iter().enumerate() {
+                *masks.entry(sha.clone()).or_insert(0) |= 1u64 << i;
+            }
+            let mut walked = Vec::new();
+            let mut parents = HashMap::new();
+            for sha in self.walk_history(&tips, HashSet::new()) {
+                let sha = sha?;
+                let commit = self.read_commit(&sha)?;
+                let mask = masks[&sha];
+                for parent in commit.parents() {
+                    *masks.entry(parent.clone()).or_insert(0) |= mask;
+                }
+
+                parents.insert(sha.clone(), commit.parents().to_vec());
+                walked.push(sha);
+                if mask == all {
+                    break;
+                }
+            }
+
+            let commits = graph_order(&walked, &parents);
+            let commit_names = show_branch_names(&commits, &parents, &names, &tips);
+
+            let columns = names.len() > 1;
+            if columns {
+                for (i, (name, sha)) in names.iter().zip(&tips).enumerate() {
+                    let mark = if head_at == Some(i) { '*' } else { '!' };
+                    let subject = self.commit_subject(sha)?;
+                    writeln!(out, "{}{} [{}] {}", " ".repeat(i), mark, name, subject)
+                        .map_err(ReadObjectErrorType::IO)?;
+                }
+                writeln!(out, "{}", "-".repeat(names.len())).map_err(ReadObjectErrorType::IO)?;
+            }
+
+            for sha in &commits {
+                let mask: u64 = masks[sha];
+                let is_merge = parents[sha].len() > 1;
+                if is_merge && mask.count_ones() == 1 && !tips.contains(sha) {
+                    continue;
+                }
+
+                let name = match commit_names.get(sha) {
+                    Some(name) => name.clone(),
+                    None => self.abbreviate(sha, 7)?,
+                };
+                let mut line = format!("[{}] {}", name, self.commit_subject(sha)?);
+                if columns {
+                    let marks = (0..names.len())
+                        .map(|i| match mask & (1u64 << i) {
+                            0 => ' ',
+                            _ if is_merge => '-',
+                            _ if head_at == Some(i) => '*',
+                            _ => '+',
+                        })
+                        .collect::<String>();
+                    line = format!("{} {}", marks, line);
+                }
+                writeln!(out, "{}", line).map_err(ReadObjectErrorType::IO)?;
+
+                if mask == all {
+                    break;
+                }
+            }
+
+            return Ok(());
+        }
+
+        /// The first line of the message of commit sha.
+        fn commit_subject(&self, sha: &str) -> Result<String, ReadObjectErrorType> {
+            let commit = self.read_commit(sha)?;
+            return Ok(commit
+                .message()
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_string());
+        }
+
+        /// The commit HEAD points to, or None if HEAD is on a branch with
+        /// no commits yet.
+        pub(crate) fn head_commit(&self) -> Result<Option<Commit>, ReadObjectErrorType> {
+            return match self.ref_resolve("HEAD")? {
+                Some(sha) => Ok(Some(self.read_commit(&sha)?)),
+                None => Ok(None),
+            };
+        }
+
+        /// Read sha and parse it as a commit.
+        pub(crate) fn read_commit(&self, sha: &str) -> Result<Commit, ReadObjectErrorType> {
+            return match self.read_object_typed(sha.to_string())? {
+                ParsedObject::Commit(commit) => Ok(commit),
+                other => Err(ReadObjectErrorType::UnexpectedObjectTypeError(
+                    sha.to_string(),
+                    other.kind(),
+                )),
+            };
+        }
+
+        /// The paths added, deleted or modified going from tree old to tree
+        /// new.
+        pub(crate) fn diff_trees(
+            &self,
+            old: &str,
+            new: &str,
+        ) -> Result<Vec<TreeChange>, ReadObjectErrorType> {
+            return Ok(tree_changes(
+                &self.flatten_tree(old)?,
+                &self.flatten_tree(new)?,
+            ));
+        }
+
+        /// Write the content of object sha to out as `cat-file -p` does:
+        /// trees as a listing of their entries, and anything else as it is
+        /// stored, unless it is a binary blob and out is a terminal.
+        pub(crate) fn cat_pretty(
+            &self,
+            sha: &str,
+            terminal: bool,
+            out: &mut impl Write,
+        ) -> Result<(), ReadObjectErrorType> {
+            let object = self.read_object(sha.to_string())?;
+            if let Some(tree) = object.as_any().downcast_ref::<Tree>() {
+                return write_tree_entries(tree, out);
+            }
+
+            return match object.get_data() {
+                GitObjectData(ObjectKind::Blob, data) => write_blob_content(&data, terminal, out),
+                GitObjectData(_, data) => out.write_all(&data).map_err(ReadObjectErrorType::IO),
+            };
+        }
+
+        /// Write a description of the object sha to out, in a form suited
+        /// to its type. Commits show their headers and indented message, and
+        /// with patch, their changes from their first parent. Tags show their
+        /// headers and message, then the tagged object. Trees list their
+        /// entries, and blobs are written as they are, unless they are
+        /// binary and out is a terminal.
+        pub(crate) fn show(
+            &self,
+            sha: &str,
+            patch: bool,
+            terminal: bool,
+            out: &mut impl Write,
+        ) -> Result<(), ReadObjectErrorType> {
+            let object = self.read_object(sha.to_string())?;
+            let GitObjectData(object_type, data) = object.get_data();
+
+            match object_type {
+                ObjectKind::Commit | ObjectKind::Tag => {
+                    let text = String::from_utf8_lossy(&data);
+                    let (headers, message) = text.split_once("\n\n").unwrap_or((&text, ""));
+                    writeln!(out, "{} {}\n{}\n", object_type, sha, headers)
+                        .map_err(ReadObjectErrorType::IO)?;
+
+                    if object_type == ObjectKind::Commit {
+                        for line in message.lines() {
+                            writeln!(out, "    {}", line).map_err(ReadObjectErrorType::IO)?;
+                        }
+                    } else {
+                        write!(out, "{}", message).map_err(ReadObjectErrorType::IO)?;
+                    }
+                }
+                ObjectKind::Tree => {
+                    writeln!(out, "tree {}\n", sha).map_err(ReadObjectErrorType::IO)?;
+                    let tree = object.as_any().downcast_ref::<Tree>().unwrap();
+                    write_tree_entries(tree, out)?;
+                }
+                ObjectKind::Blob => write_blob_content(&data, terminal, out)?,
+            }
+
+            if let Some(tag) = object.as_any().downcast_ref::<Tag>() {
+                writeln!(out).map_err(ReadObjectErrorType::IO)?;
+                self.show(tag.object(), patch, terminal, out)?;
+            }
+
+            if let (true, Some(commit)) = (patch, object.as_any().downcast_ref::<Commit>()) {
+                let old_files = match commit.parents().first() {
+                    Some(parent) => self.flatten_tree(&self.commit_tree_sha(parent)?)?,
+                    None => BTreeMap::new(),
+                };
+                let new_files = self.flatten_tree(&self.commit_tree_sha(sha)?)?;
+
+                writeln!(out).map_err(ReadObjectErrorType::IO)?;
+                self.write_patch(&old_files, &new_files, false, out)?;
+            }
+
+            return Ok(());
+        }
+
+        /// Write a unified diff of the changes staged in the index, from
+        /// HEAD to the index, if staged is set. Otherwise write one of the
+        /// changes not yet staged, from the index to the worktree. Unmerged
+        /// paths are left out either way.
+        pub(crate) fn diff_index(
+            &self,
+            staged: bool,
+            out: &mut impl Write,
+        ) -> Result<(), ReadObjectErrorType> {
+            let index = self.index()?;
+            let entries: Vec<&IndexEntry> = index
+                .entries
+                .iter()
+                .filter(|entry| entry.stage == 0)
+                .collect();
+            let index_files: BTreeMap<String, (String, String)> = entries
+                .iter()
+                .map(|entry| {
+                    let mode = format!("{:o}", entry.mode);
+                    (entry.path.clone(), (mode, entry.sha.clone()))
+                })
+                .collect();
+
+            if staged {
+                let head = match self.head_commit()? {
+                    Some(commit) => self.flatten_tree(
+                        commit
+                            .tree()
+                            .ok_or(ReadObjectErrorType::TreeNotFoundError)?,
+                    )?,
+                    None => BTreeMap::new(),
+                };
+                return self.write_patch(&head, &index_files, false, out);
+            }
+
+            // Only files whose stat data has changed need to be read, as
+            // for status. Modes aren't shown in patches, so are kept.
+            let index_mtime = self.index_mtime();
+            let mut worktree = BTreeMap::new();
+            for entry in entries {
+                let (mode, sha) = &index_files[&entry.path];
+                let sha = match self.worktree_change(entry, index_mtime)? {
+                    Change::Deleted => continue,
+                    Change::Unmodified => sha.clone(),
+                    _ => self.hash_blob(self.read_worktree_file(Path::new(&entry.path))?.1)?,
+                };
+                worktree.insert(entry.path.clone(), (mode.clone(), sha));
+            }
+
+            return self.write_patch(&index_files, &worktree, true, out);
+        }
+
+        /// Write a unified diff of every file changed between two
+        /// flattened trees. The new files are read from the worktree rather
+        /// than the repository if new_in_worktree is set.
+        fn write_patch(
+            &self,
+            old: &BTreeMap<String, (String, String)>,
+            new: &BTreeMap<String, (String, String)>,
+            new_in_worktree: bool,
+            out: &mut impl Write,
+        ) -> Result<(), ReadObjectErrorType> {
+            let read_blob =
+                |entry: Option<&(String, String)>| -> Result<String, ReadObjectErrorType> {
+                    let Some((_, sha)) = entry else {
+                        return Ok(String::new());
+                    };
+                    let object = self.read_object(sha.clone())?;
+                    let blob = object.as_any().downcast_ref::<Blob>().ok_or_else(|| {
+                        ReadObjectErrorType::UnexpectedObjectTypeError(
+                            sha.clone(),
+                            object.get_data().0,
+                        )
+                    })?;
+                    return Ok(String::from_utf8_lossy(blob.bytes()).to_string());
+                };
+
+            for change in tree_changes(old, new) {
+                let (path, old_name, new_name) = match &change {
+                    TreeChange::Added(path) => {
+                        (path, "/dev/null".to_string(), format!("b/{}", path))
+                    }
+                    TreeChange::Deleted(path) => {
+                        (path, format!("a/{}", path), "/dev/null".to_string())
+                    }
+                    TreeChange::Modified(path) => {
+                        (path, format!("a/{}", path), format!("b/{}", path))
+                    }
+                };
+
+                let new_content = match new.get(path) {
+                    Some(_) if new_in_worktree => {
+                        let (_, data) = self.read_worktree_file(Path::new(path))?;
+                        String::from_utf8_lossy(&data).to_string()
+                    }
+                    entry => read_blob(entry)?,
+                };
+
+                writeln!(out, "diff --git a/{} b/{}", path, path)
+                    .map_err(ReadObjectErrorType::IO)?;
+                write!(
+                    out,
+                    "{}",
+                    unified_diff(
+                        &read_blob(old.get(path))?,
+                        &new_content,
+                        &old_name,
+                        &new_name
+                    )
+                )
+                .map_err(ReadObjectErrorType::IO)?;
+            }
+
+            return Ok(());
+        }
+
+        /// The entries of tree sha which paths name, with each leaf's path
+        /// given from the root of the tree, as `git ls-tree` lists them.
+        ///
+        /// A path names the entry at that path, or if it ends in a `/`, or is
+        /// empty, the entries in that directory. Listing no paths lists the
+        /// entries at the root of the tree.
+        pub(crate) fn ls_tree(
+            &self,
+            sha: &str,
+            paths: &[String],
+        ) -> Result<Vec<Leaf>, ReadObjectErrorType> {
+            let root = [String::new()];
+            let paths = match paths.is_empty() {
+                true => &root[..],
+                false => paths,
+            };
+
+            let mut leaves = Vec::new();
+            self.ls_tree_into(sha, "", paths, &mut leaves)?;
+            return Ok(leaves);
+        }
+
+        fn ls_tree_into(
+            &self,
+            sha: &str,
+            prefix: &str,
+            paths: &[String],
+            leaves: &mut Vec<Leaf>,
+        ) -> Result<(), ReadObjectErrorType> {
+            let ParsedObject::Tree(tree) = self.read_object_typed(sha.to_string())? else {
+                return Err(ReadObjectErrorType::TreeNotFoundError);
+            };
+
+            for leaf in &tree {
+                let path = format!("{}{}", prefix, leaf.path());
+                if paths
+                    .iter()
+                    .any(|wanted| *wanted == path || wanted == prefix)
+                {
+                    leaves.push(Leaf(
+                        leaf.mode().to_string(),
+                        path.clone(),
+                        leaf.sha().to_string(),
+                    ));
+                }
+
+                let dir = format!("{}/", path);
+                if leaf.mode() == "40000" && paths.iter().any(|wanted| wanted.starts_with(&dir)) {
+                    self.ls_tree_into(leaf.sha(), &dir, paths, leaves)?;
+                }
+            }
+
+            return Ok(());
+        }
+
+        /// The commit which last changed each line of path, as it is in
+        /// commit.
+        ///
+        /// History is followed back through a parent with the same version
+        /// of path, if there is one, or else the first parent. Lines which
+        /// the diff against that parent's version shows being added belong
+        /// to the commit, as do all those left when path doesn't exist in
+        /// the parent, or there is no parent.
+        pub(crate) fn blame(
+            &self,
+            commit: &str,
+            path: &str,
+        ) -> Result<Vec<BlameLine>, ReadObjectErrorType> {
+            let mut blob = self.tree_entry(commit, path)?.ok_or_else(|| {
+                ReadObjectErrorType::ObjectNotFoundError(format!("{}:{}", commit, path))
+            })?;
+            let text = self.blob_text(&blob)?;
+            let mut owners = vec![None; text.lines().count()];
+
+            // The lines yet to be blamed, by their number in the version
+            // blamed and their number in the version being looked at
+            let mut pending: Vec<(usize, usize)> = (0..owners.len()).map(|n| (n, n)).collect();
+            let mut commit = commit.to_string();
+            while !pending.is_empty() {
+                let mut parent = None;
+                for sha in self.read_commit(&commit)?.parents() {
+                    let parent_blob = self.tree_entry(sha, path)?;
+                    if parent_blob.as_ref() == Some(&blob) {
+                        parent = Some((sha.clone(), blob.clone()));
+                        break;
+                    }
+                    if parent.is_none() {
+                        parent = parent_blob.map(|parent_blob| (sha.clone(), parent_blob));
+                    }
+                }
+
+                let Some((parent, parent_blob)) = parent else {
+                    for (n, _) in pending.drain(..) {
+                        owners[n] = Some(commit.clone());
+                    }
+                    break;
+                };
+
+                if parent_blob != blob {
+                    let (old, new) = (self.blob_text(&parent_blob)?, self.blob_text(&blob)?);
+                    let old: Vec<&str> = old.lines().collect();
+                    let new: Vec<&str> = new.lines().collect();
+
+                    // Where each line was in the parent's version, if it
+                    // was there at all
+                    let mut origins = vec![None; new.len()];
+                    let (mut i, mut j) = (0, 0);
+                    for edit in diff_lines(&old, &new) {
+                        match edit {
+                            Edit::Equal(_) => {
+                                origins[j] = Some(i);
+                                i += 1;
+                                j += 1;
+                            }
+                            Edit::Delete(_) => i += 1,
+                            Edit::Insert(_) => j += 1,
+                        }
+                    }
+
+                    pending.retain_mut(|(n, line)| match origins[*line] {
+                        Some(origin) => {
+                            *line = origin;
+                            true
+                        }
+                        None => {
+                            owners[*n] = Some(commit.clone());
+                            false
+                        }
+                    });
+                }
+
+                commit = parent;
+                blob = parent_blob;
+            }
+
+            return Ok(text
+                .lines()
+                .zip(owners)
+                .map(|(line, owner)| BlameLine {
+                    commit: owner.unwrap(),
+                    content: line.to_string(),
+                })
+                .collect());
+        }
+
+        /// The content of blob sha, with any invalid UTF-8 replaced.
+        fn blob_text(&self, sha: &str) -> Result<String, ReadObjectErrorType> {
+            return match self.read_object_typed(sha.to_string())? {
+                ParsedObject::Blob(blob) => Ok(String::from_utf8_lossy(blob.bytes()).to_string()),
+                other => Err(ReadObjectErrorType::UnexpectedObjectTypeError(
+                    sha.to_string(),
+                    other.kind(),
+                )),
+            };
+        }
+
+        /// Switch the worktree to target, which is either a branch name or any
+        /// other name for a commit. Switching to a branch makes HEAD a
+        /// symbolic ref to it; switching to anything else detaches HEAD at
+        /// the commit.
+        ///
+        /// Files belonging to the current HEAD are replaced by those of
+        /// target. Local modifications that would be lost cause the switch
+        /// to fail before anything is changed.
+        pub(crate) fn switch(&self, target: &str) -> Result<(), ReadObjectErrorType> {
+            let branch = format!("refs/heads/{}", target);
+            let (sha, head) = match self.ref_resolve(&branch)? {
+                Some(sha) => (sha, Head::Symbolic(branch)),
+                None => {
+                    let sha =
+                        self.object_find(target.to_string(), Some(ObjectKind::Commit), None)?;
+                    (sha.clone(), Head::Detached(sha))
+                }
+            };
+
+            let old_tree = match self.head_commit()? {
+                Some(old) => Some(
+                    old.tree()
+                        .ok_or(ReadObjectErrorType::TreeNotFoundError)?
+                        .to_string(),
+                ),
+                None => None,
+            };
+            self.worktree_replace(old_tree.as_deref(), &self.commit_tree_sha(&sha)?)?;
+
+            return self.update_head(&head);
+        }
+
+        /// Replace the files of tree old, if there is one, in the worktree
+        /// with those of tree new, and the index with new's entries. Local
+        /// modifications that would be lost cause this to fail before
+        /// anything is changed.
+        fn worktree_replace(
+            &self,
+            old: Option<&str>,
+            new: &str,
+        ) -> Result<(), ReadObjectErrorType> {
+            let old_files = match old {
+                Some(old) => self.flatten_tree(old)?,
+                None => BTreeMap::new(),
+            };
+            let new_files = self.flatten_tree(new)?;
+
+            // Only files which are unmodified relative to old, or which
+            // already match new, may be touched
+            for path in old_files.keys().chain(new_files.keys()) {
+                let dest = self.worktree.join(path);
+                if !dest.is_file() {
+                    continue;
+                }
+
+                let (_, data) = self.read_worktree_file(Path::new(path))?;
+                let current = self.hash_blob(data)?;
+                let matches = |files: &BTreeMap<String, (String, String)>| {
+                    return files.get(path).is_some_and(|(_, sha)| *sha == current);
+                };
+                if !matches(&old_files) && !matches(&new_files) {
+                    return Err(ReadObjectErrorType::CheckoutConflictError(dest));
+                }
+            }
+
+            for path in old_files.keys() {
+                let dest = self.worktree.join(path);
+                if dest.is_file() {
+                    fs::remove_file(&dest).map_err(ReadObjectErrorType::IO)?;
+                }
+
+                // Clean up directories left empty, stopping at the worktree
+                let mut parent = dest.parent();
+                while let Some(dir) = parent {
+                    if dir == self.worktree || fs::remove_dir(dir).is_err() {
+                        break;
+                    }
+                    parent = dir.parent();
+                }
+            }
+
+            return self.checkout_worktree(new, false);
+        }
+
+        /// Write a commit of tree with the given parents and message, as
+        /// `git commit-tree` does, returning its SHA. The committer, and
+        /// the author unless one is given, are found by identity.
+        pub(crate) fn commit_tree(
+            &self,
+            tree: &str,
+            parents: &[String],
+            author: Option<Identity>,
+            message: &str,
+        ) -> Result<String, ReadObjectErrorType> {
+            let author = match author {
+                Some(author) => author,
+                None => self.identity(IdentityRole::Author)?,
+            };
+            let committer = self.identity(IdentityRole::Committer)?;
+
+            return self.write_commit(tree, parents, &author, &committer, message);
+        }
+
+        /// Write a commit object of tree, returning its SHA. Its headers
+        /// are written in the order git writes them, so the same commit
+        /// always hashes the same.
+        pub(crate) fn write_commit(
+            &self,
+            tree: &str,
+            parents: &[String],
+            author: &Identity,
+            committer: &Identity,
+            message: &str,
+        ) -> Result<String, ReadObjectErrorType> {
+            let mut kvlm = BTreeMap::new();
+            kvlm.insert("tree".to_string(), vec![tree.to_string()]);
+            if !parents.is_empty() {
+                kvlm.insert("parent".to_string(), parents.to_vec());
+            }
+            kvlm.insert("author".to_string(), vec![author.to_string()]);
+            kvlm.insert("committer".to_string(), vec![committer.to_string()]);
+            kvlm.insert(String::new(), vec![message.to_string()]);
+
+            let commit = Commit::new(
+                Some(self.clone()),
+                GitObjectData::kvlm_serialize(ObjectKind::Commit, &kvlm),
+            );
+            return GitObject::write_object(Box::new(commit), None);
+        }
+
+        /// Merge commit target into HEAD, which must be on a commit.
+        ///
+        /// If HEAD is behind target it is fast-forwarded. Otherwise the
+        /// trees of HEAD, target and their merge base are merged path by
+        /// path: a path changed on one side only takes that side's version,
+        /// and a path changed differently on both sides is a conflict. A
+        /// clean merge is committed, with HEAD and target as its parents.
+        /// A conflicted one is left in the worktree and the index, with the
+        /// base, HEAD and target versions of each conflicting path staged
+        /// as stages 1, 2 and 3, and HEAD's version in the worktree.
+        pub(crate) fn merge(&self, target: &str) -> Result<MergeOutcome, ReadObjectErrorType> {
+            let ours = self
+                .ref_resolve("HEAD")?
+                .ok_or_else(|| ReadObjectErrorType::ObjectNotFoundError("HEAD".to_string()))?;
+            let theirs = self.object_find(target.to_string(), Some(ObjectKind::Commit), None)?;
+            let bases = self.merge_bases(&ours, &theirs)?;
+            if bases.contains(&theirs) {
+                return Ok(MergeOutcome::UpToDate);
+            }
+
+            let ours_tree = self.commit_tree_sha(&ours)?;
+            if bases.contains(&ours) {
+                self.worktree_replace(Some(&ours_tree), &self.commit_tree_sha(&theirs)?)?;
+                let message = format!("merge {}: Fast-forward", target);
+                self.ref_update("HEAD", &theirs, &message, false)?;
+                return Ok(MergeOutcome::FastForward(theirs));
+            }
+
+            let base_files = match bases.first() {
+                Some(base) => self.flatten_tree(&self.commit_tree_sha(base)?)?,
+                None => BTreeMap::new(),
+            };
+            let ours_files = self.flatten_tree(&ours_tree)?;
+            let theirs_files = self.flatten_tree(&self.commit_tree_sha(&theirs)?)?;
+
+            let (merged, conflicts) = merge_files(&base_files, &ours_files, &theirs_files);
+            let tree = self.build_tree(&merged, None)?;
+            self.worktree_replace(Some(&ours_tree), &tree)?;
+
+            if !conflicts.is_empty() {
+                let mut index = self.index()?;
+                index
+                    .entries
+                    .retain(|entry| !conflicts.contains(&entry.path));
+                for path in &conflicts {
+                    let sides = [&base_files, &ours_files, &theirs_files];
+                    for (stage, files) in (1..).zip(sides) {
+                        if let Some((mode, sha)) = files.get(path) {
+                            index.entries.push(IndexEntry {
+                                mode: u32::from_str_radix(mode, 8).unwrap(),
+                                sha: sha.clone(),
+                                path: path.clone(),
+                                stage,
+                                ..Default::default()
+                            });
+                        }
+                    }
+                }
+                index.sort();
+                self.write_index(&index)?;
+
+                return Ok(MergeOutcome::Conflicted(conflicts));
+            }
+
+            let message = match self.ref_read(&format!("refs/heads/{}", target))? {
+                Some(_) => format!("Merge branch '{}'\n", target),
+                None => format!("Merge commit '{}'\n", target),
+            };
+            let commit = self.commit_tree(&tree, &[ours, theirs], None, &message)?;
+            let message = format!("merge {}: Merge made by the 'three-way' strategy.", target);
+            self.ref_update("HEAD", &commit, &message, false)?;
+
+            return Ok(MergeOutcome::Merged(commit));
+        }
+
+        /// Apply the changes commit sha made to its first parent onto HEAD,
+        /// as `git cherry-pick` does, committing the result with the
+        /// commit's author and message and returning the new commit's SHA.
+        ///
+        /// If HEAD changed a path the commit changes differently, nothing
+        /// is changed and the conflicting paths are reported.
+        pub(crate) fn cherry_pick(&self, sha: &str) -> Result<String, ReadObjectErrorType> {
+            let head = self
+                .ref_resolve("HEAD")?
+                .ok_or_else(|| ReadObjectErrorType::ObjectNotFoundError("HEAD".to_string()))?;
+            let commit = self.read_commit(sha)?;
+
+            let base_files = match commit.parents().first() {
+                Some(parent) => self.flatten_tree(&self.commit_tree_sha(parent)?)?,
+                None => BTreeMap::new(),
+            };
+            let head_tree = self.commit_tree_sha(&head)?;
+            let head_files = self.flatten_tree(&head_tree)?;
+            let commit_files = self.flatten_tree(&self.commit_tree_sha(sha)?)?;
+
+            let (picked, conflicts) = merge_files(&base_files, &head_files, &commit_files);
+            if !conflicts.is_empty() {
+                return Err(ReadObjectErrorType::ApplyConflictError(conflicts));
+            }
+
+            let tree = self.build_tree(&picked, None)?;
+            self.worktree_replace(Some(&head_tree), &tree)?;
+            let author = commit
+                .kvlm
+                .get("author")
+                .and_then(|author| Identity::parse(&author[0]));
+            let picked = self.commit_tree(&tree, &[head], author, commit.message())?;
+            let summary = commit.message().lines().next().unwrap_or_default();
+            self.ref_update("HEAD", &picked, &format!("cherry-pick: {}", summary), false)?;
+
+            return Ok(picked);
+        }
+
+        /// The SHA of the tree of commit sha.
+        pub(crate) fn commit_tree_sha(&self, sha: &str) -> Result<String, ReadObjectErrorType> {
+            let commit = self.read_commit(sha)?;
+
+            return Ok(commit
+                .tree()
+                .ok_or(ReadObjectErrorType::TreeNotFoundError)?
+                .to_string());
+        }
+
+        /// Compute the SHA data would have as a blob, without storing it.
+        fn hash_blob(&self, data: Vec<u8>) -> Result<String, ReadObjectErrorType> {
+            let blob = Blob::new(Some(self.clone()), GitObjectData(ObjectKind::Blob, data));
+            return GitObject::write_object(Box::new(blob), Some(false));
+        }
+
+        /// Write the contents of tree into path, creating it if need be.
+        ///
+        /// Unless forced, nothing is written if any file already present
+        /// under path would be overwritten with different content. Files
+        /// which aren't in tree are left alone either way.
+        pub(crate) fn tree_checkout(
+            &self,
+            tree: &Tree,
+            path: &Path,
+            force: bool,
+        ) -> Result<(), ReadObjectErrorType> {
+            if !force {
+                self.tree_check_conflicts(tree, path)?;
+            }
+
+            create_dir_all(path).map_err(ReadObjectErrorType::IO)?;
+            return self.tree_write(tree, path);
+        }
+
+        /// Check out tree sha into the worktree, as tree_checkout does, and
+        /// replace the index with its entries, so that the worktree, the
+        /// index and the tree all agree afterwards. The stat data of each
+        /// file written is recorded, so status needn't hash them.
+        pub(crate) fn checkout_worktree(
+            &self,
+            sha: &str,
+            force: bool,
+        ) -> Result<(), ReadObjectErrorType> {
+            let ParsedObject::Tree(tree) = self.read_object_typed(sha.to_string())? else {
+                return Err(ReadObjectErrorType::TreeNotFoundError);
+            };
+            self.tree_checkout(&tree, &self.worktree, force)?;
+
+            let mut index = self.read_tree(sha)?;
+            for entry in &mut index.entries {
+                // Submodules aren't checked out, so keep no stat data
+                let Ok(metadata) = self.worktree.join(&entry.path).symlink_metadata() else {
+                    continue;
+                };
+                if metadata.is_dir() {
+                    continue;
+                }
+
+                let mode = entry.mode;
+                *entry = IndexEntry::new(entry.path.clone(), entry.sha.clone(), &metadata);
+                entry.mode = mode;
+            }
+
+            return self.write_index(&index);
+        }
+
+        /// Walk tree and fail with a CheckoutConflictError for the first
+        /// destination that exists on disk with content differing from the
+        /// object it would be replaced with.
+        fn tree_check_conflicts(
+            &self,
+            tree: &Tree,
+            path: &Path,
+        ) -> Result<(), ReadObjectErrorType> {
+            // Subtrees wait on a worklist rather than being recursed into,
+            // so however deep the tree, the stack can't overflow
+            let mut pending = Vec::new();
+            self.tree_check_entries(tree, path, &mut pending)?;
+            while let Some((tree, path)) = pending.pop() {
+                self.tree_check_entries(&tree, &path, &mut pending)?;
+            }
+
+            return Ok(());
+        }
+
+        /// Check the entries directly in tree for conflicts, as
+        /// tree_check_conflicts does, adding the subtrees present on disk to
+        /// pending to be checked in turn.
+        fn tree_check_entries(
+            &self,
+            tree: &Tree,
+            path: &Path,
+            pending: &mut Vec<(Tree, PathBuf)>,
+        ) -> Result<(), ReadObjectErrorType> {
+            for leaf in tree {
+                let dest = path.join(leaf.path());
+                if dest.symlink_metadata().is_err() {
+                    continue;
+                }
+
+                // A submodule's commit isn't here, only the directory it
+                // would be checked out into
+                if leaf.mode() == "160000" {
+                    if !dest.is_dir() {
+                        return Err(ReadObjectErrorType::CheckoutConflictError(dest));
+                    }
+                    continue;
+                }
+
+                match self.read_object_typed(leaf.sha().to_string())? {
+                    ParsedObject::Tree(tree) => {
+                        if !dest.is_dir() {
+                            return Err(ReadObjectErrorType::CheckoutConflictError(dest));
+                        }
+
+                        pending.push((tree, dest));
+                    }
+                    object => {
+                        if !dest.is_file() {
+                            return Err(ReadObjectErrorType::CheckoutConflictError(dest));
+                        }
+
+                        let existing = fs::read(&dest).map_err(ReadObjectErrorType::IO)?;
+                        let ParsedObject::Blob(blob) = object else {
+                            return Err(ReadObjectErrorType::CheckoutConflictError(dest));
+                        };
+                        if existing != self.smudge_file(blob.bytes()) {
+                            return Err(ReadObjectErrorType::CheckoutConflictError(dest));
+                        }
+                    }
+                }
+            }
+
+            return Ok(());
+        }
+
+        fn tree_write(&self, tree: &Tree, path: &Path) -> Result<(), ReadObjectErrorType> {
+            // As in tree_check_conflicts, subtrees are written from a
+            // worklist rather than by recursion
+            let mut pending = Vec::new();
+            self.tree_write_entries(tree, path, &mut pending)?;
+            while let Some((tree, path)) = pending.pop() {
+                self.tree_write_entries(&tree, &path, &mut pending)?;
+            }
+
+            return Ok(());
+        }
+
+        /// Write the entries directly in tree into path, creating the
+        /// directory for each subtree and adding it to pending to be filled
+        /// in turn.
+        fn tree_write_entries(
+            &self,
+            tree: &Tree,
+            path: &Path,
+            pending: &mut Vec<(Tree, PathBuf)>,
+        ) -> Result<(), ReadObjectErrorType> {
+            for leaf in tree {
+                let dest = path.join(leaf.path());
+
+                // A forced checkout may find a file where a directory belongs,
+                // or the other way round
+                let existing = dest.symlink_metadata().ok();
+
+                // Like git, a submodule is left as an empty directory, as its
+                // commit is in another repository
+                if leaf.mode() == "160000" {
+                    if existing.is_some_and(|existing| !existing.is_dir()) {
+                        fs::remove_file(&dest).map_err(ReadObjectErrorType::IO)?;
+                    }
+                    create_dir_all(&dest).map_err(ReadObjectErrorType::IO)?;
+                    continue;
+                }
+
+                match self.read_object_typed(leaf.sha().to_string())? {
+                    ParsedObject::Tree(tree) => {
+                        if existing.is_some_and(|existing| !existing.is_dir()) {
+                            fs::remove_file(&dest).map_err(ReadObjectErrorType::IO)?;
+                        }
+                        create_dir_all(&dest).map_err(ReadObjectErrorType::IO)?;
+                        pending.push((tree, dest));
+                    }
+                    object => {
+                        if existing.is_some_and(|existing| existing.is_dir()) {
+                            fs::remove_dir_all(&dest).map_err(ReadObjectErrorType::IO)?;
+                        }
+                        let ParsedObject::Blob(blob) = object else {
+                            return Err(ReadObjectErrorType::UnexpectedObjectTypeError(
+                                leaf.sha().to_string(),
+                                object.kind(),
+                            ));
+                        };
+                        let mut f = File::create(&dest).map_err(ReadObjectErrorType::IO)?;
+                        let data = match leaf.mode() {
+                            "120000" => blob.bytes().to_vec(),
+                            _ => self.smudge_file(blob.bytes()),
+                        };
+                        f.write_all(&data).map_err(ReadObjectErrorType::IO)?;
+                        set_file_mode(&dest, leaf.mode())?;
+                    }
+                };
+            }
+
+            return Ok(());
+        }
+    }
+
+    /// Statistics reported by count-objects. Sizes are in bytes: the disk
+    /// used by loose objects, as disk_usage gives it, and the length of
+    /// packs.
+    #[derive(Debug, Default, PartialEq)]
+    pub(crate) struct ObjectCounts {
+        pub(crate) count: usize,
+        pub(crate) size: u64,
+        pub(crate) in_pack: usize,
+        pub(crate) packs: usize,
+        pub(crate) size_pack: u64,
+    }
+
+    /// A problem reported by fsck.
+    #[derive(Debug, PartialEq)]
+    pub(crate) enum FsckProblem {
+        /// The loose object named by the first SHA hashes to the second
+        HashMismatch(String, String),
+        /// The header of the loose object gives the wrong content length
+        LengthMismatch(String),
+        /// The loose object can't be read at all
+        Corrupt(String, String),
+        BadPack(PathBuf, String),
+        /// An object another refers to which doesn't exist, with the type
+        /// it is referred to as
+        Missing(ObjectKind, String),
+        /// An object nothing refers to, with its type
+        Dangling(ObjectKind, String),
+    }
+
+    impl FsckProblem {
+        /// Whether the problem is corruption, rather than mere untidiness.
+        pub(crate) fn is_error(&self) -> bool {
+            return !matches!(self, FsckProblem::Dangling(_, _));
+        }
+    }
+
+    impl fmt::Display for FsckProblem {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            return match self {
+                FsckProblem::HashMismatch(sha, actual) => write!(
+                    f,
+                    "error: hash mismatch for {} (content hashes to {})",
+                    sha, actual
+                ),
+                FsckProblem::LengthMismatch(sha) => {
+                    write!(f, "error: {}: header length does not match content", sha)
+                }
+                FsckProblem::Corrupt(sha, reason) => write!(f, "error: {}: {}", sha, reason),
+                FsckProblem::BadPack(path, reason) => {
+                    write!(f, "error: {}: {}", path.display(), reason)
+                }
+                FsckProblem::Missing(object_type, sha) => {
+                    write!(f, "missing {} {}", object_type, sha)
+                }
+                FsckProblem::Dangling(object_type, sha) => {
+                    write!(f, "dangling {} {}", object_type, sha)
+                }
+            };
+        }
+    }
+
+    /// A line of a file, and the commit which last changed it.
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct BlameLine {
+        pub(crate) commit: String,
+        pub(crate) content: String,
+    }
+
+    /// The line ending conversion core.autocrlf asks for: none, CRLF to
+    /// LF when storing files, or that and LF to CRLF when checking them out.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum AutoCrlf {
+        False,
+        Input,
+        True,
+    }
+
+    /// How a path has changed, either in the index since HEAD or in the
+    /// worktree since the index.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub(crate) enum Change {
+        Unmodified,
+        Added,
+        Modified,
+        Deleted,
+        /// The path has conflicts from a merge
+        Unmerged,
+        Untracked,
+    }
+
+    impl Change {
+        /// The letter git's short status shows for the change.
+        pub(crate) fn code(&self) -> char {
+            return match self {
+                Change::Unmodified => ' ',
+                Change::Added => 'A',
+                Change::Modified => 'M',
+                Change::Deleted => 'D',
+                Change::Unmerged => 'U',
+                Change::Untracked => '?',
+            };
+        }
+    }
+
+    /// A path's changes in the index and in the worktree, as reported by
+    /// status.
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct FileStatus {
+        pub(crate) path: String,
+        pub(crate) staged: Change,
+        pub(crate) unstaged: Change,
+    }
+
+    /// Which paths ls-files lists.
+    #[derive(Debug, Default, Clone, Copy)]
+    pub(crate) struct LsFilesFilter {
+        /// Every path in the index
+        pub(crate) cached: bool,
+        /// Untracked files
+        pub(crate) others: bool,
+        /// Paths whose file differs from the index, or is missing
+        pub(crate) modified: bool,
+        /// Paths whose file is missing
+        pub(crate) deleted: bool,
+    }
+
+    /// The line `git status --porcelain` would print for the path.
+    impl fmt::Display for FileStatus {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            return write!(
+                f,
+                "{}{} {}",
+                self.staged.code(),
+                self.unstaged.code(),
+                self.path
+            );
+        }
+    }
+
+    /// Which of a commit's identities to look up: git lets whoever wrote a
+    /// change differ from whoever committed it.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub(crate) enum IdentityRole {
+        Author,
+        Committer,
+    }
+
+    impl IdentityRole {
+        pub(crate) fn name(&self) -> &'static str {
+            return match self {
+                IdentityRole::Author => "author",
+                IdentityRole::Committer => "committer",
+            };
+        }
+
+        /// The prefix of the environment variables overriding this identity.
+        fn env_prefix(&self) -> &'static str {
+            return match self {
+                IdentityRole::Author => "GIT_AUTHOR",
+                IdentityRole::Committer => "GIT_COMMITTER",
+            };
+        }
+    }
+
+    /// A name, email address and time, as recorded in a commit's author and
+    /// committer lines.
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct Identity {
+        pub(crate) name: String,
+        pub(crate) email: String,
+        pub(crate) timestamp: i64,
+        pub(crate) timezone: String,
+    }
+
+    impl Identity {
+        /// Parse an author or committer line, such as
+        /// `A U Thor <author@example.com> 1600000000 +0000`.
+        pub(crate) fn parse(line: &str) -> Option<Identity> {
+            let mut parts = line.rsplitn(3, ' ');
+            let timezone = parts.next()?.to_string();
+            let timestamp = parts.next()?.parse().ok()?;
+            let (name, email) = parts.next()?.strip_suffix('>')?.split_once(" <")?;
+
+            return Some(Identity {
+                name: name.to_string(),
+                email: email.to_string(),
+                timestamp,
+                timezone,
+            });
+        }
+    }
+
+    impl fmt::Display for Identity {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            return write!(
+                f,
+                "{} <{}> {} {}",
+                self.name, self.email, self.timestamp, self.timezone
+            );
+        }
+    }
+
+    /// What merging a commit into HEAD did.
+    #[derive(Debug, PartialEq)]
+    pub(crate) enum MergeOutcome {
+        /// HEAD already contains the commit, so nothing was done
+        UpToDate,
+        /// HEAD was moved forward to the commit
+        FastForward(String),
+        /// This merge commit was made
+        Merged(String),
+        /// These paths were changed differently on both sides, so the merge
+        /// was left to be finished by hand
+        Conflicted(Vec<String>),
+    }
+
+    /// One movement of a ref, as recorded in its reflog.
+    #[derive(Debug, PartialEq)]
+    pub(crate) struct ReflogEntry {
+        pub(crate) old: String,
+        pub(crate) new: String,
+        /// The name and email of whoever moved the ref
+        pub(crate) who: String,
+        pub(crate) timestamp: i64,
+        pub(crate) timezone: String,
+        pub(crate) message: String,
+    }
+
+    /// A commit as written by Repository::log_json.
+    #[derive(Serialize)]
+    struct LogEntry {
+        sha: String,
+        parents: Vec<String>,
+        author: String,
+        committer: String,
+        /// The author timestamp in seconds since the epoch
+        date: i64,
+        message: String,
+    }
+
+    /// Iterator over the files in the worktree, created by
+    /// Repository::walk_worktree.
+    pub(crate) struct WorktreeWalk {
+        repo: Repository,
+        rules: IgnoreRules,
+        /// Directories still to be read, relative to the root of the
+        /// worktree
+        pending: Vec<PathBuf>,
+        /// Files found but not yet yielded
+        files: Vec<PathBuf>,
+    }
+
+    impl WorktreeWalk {
+        /// Add the patterns of the .gitignore file in dir, if it has one,
+        /// or of .git/info/exclude for the root, which .gitignore overrides.
+        fn read_ignore_file(&mut self, dir: &Path) {
+            let base = dir.to_string_lossy().replace(MAIN_SEPARATOR, "/");
+            if dir.as_os_str().is_empty() {
+                let exclude = self.repo.repo_path(&["info", "exclude"]);
+                if let Ok(text) = fs::read_to_string(exclude) {
+                    self.rules.add(&text, &base);
+                }
+            }
+
+            let gitignore = self.repo.worktree.join(dir).join(".gitignore");
+            if let Ok(text) = fs::read_to_string(gitignore) {
+                self.rules.add(&text, &base);
+            }
+        }
+
+        fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+            let path = path.to_string_lossy().replace(MAIN_SEPARATOR, "/");
+            return self.rules.is_ignored(&path, is_dir);
+        }
+
+        /// Queue up the entries of dir, skipping what is ignored, .git, and
+        /// any directory holding a repository of its own.
+        fn read_dir(&mut self, dir: &Path) -> Result<(), ReadObjectErrorType> {
+            self.read_ignore_file(dir);
+
+            let full = self.repo.worktree.join(dir);
+            let mut entries = Vec::new();
+            for entry in full.read_dir().map_err(ReadObjectErrorType::IO)? {
+                let entry = entry.map_err(ReadObjectErrorType::IO)?;
+                if entry.file_name() == ".git" {
+                    continue;
+                }
+
+                let is_dir = entry.file_type().map_err(ReadObjectErrorType::IO)?.is_dir();
+                let path = dir.join(entry.file_name());
+                if self.is_ignored(&path, is_dir) {
+                    continue;
+                }
+                if is_dir && entry.path().join(".git").exists() {
+                    continue;
+                }
+                entries.push((path, is_dir));
+            }
+
+            // Visit entries in order of name, as they are popped off the end
+            entries.sort();
+            for (path, is_dir) in entries.into_iter().rev() {
+                match is_dir {
+                    true => self.pending.push(path),
+                    false => self.files.push(path),
+                }
+            }
+
+            return Ok(());
+        }
+    }
+
+    impl Iterator for WorktreeWalk {
+        type Item = Result<PathBuf, ReadObjectErrorType>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            loop {
+                if let Some(file) = self.files.pop() {
+                    return Some(Ok(file));
+                }
+
+                let dir = self.pending.pop()?;
+                if let Err(e) = self.read_dir(&dir) {
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+
+    /// Iterator over the ancestry of one or more commits, created by
+    /// Repository::walk_history.
+    pub(crate) struct HistoryWalk {
+        repo: Repository,
+        /// Commits waiting to be visited, newest (then first queued) first
+        queue: BinaryHeap<(i64, Reverse<usize>, String)>,
+        seen: HashSet<String>,
+        sequence: usize,
+    }
+
+    impl HistoryWalk {
+        fn push(&mut self, sha: String) {
+            if !self.seen.insert(sha.clone()) {
+                return;
+            }
+
+            // Commits which can't be read still need visiting, so the error
+            // surfaces when they are yielded rather than being lost here
+            let timestamp = self
+                .repo
+                .read_commit(&sha)
+                .map(|commit| commit.commit_time())
+                .unwrap_or_default();
+
+            self.queue.push((timestamp, Reverse(self.sequence), sha));
+            self.sequence += 1;
+        }
+    }
+
+    impl Iterator for HistoryWalk {
+        type Item = Result<String, ReadObjectErrorType>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let (_, _, sha) = self.queue.pop()?;
+            let commit = match self.repo.read_commit(&sha) {
+                Ok(commit) => commit,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if commit.has_parent() {
+                for parent in commit.parents().to_vec() {
+                    self.push(parent);
+                }
+            }
+
+            return Some(Ok(sha));
+        }
+    }
+
+    /// Merge flattened trees ours and theirs, which both descend from base,
+    /// path by path. A path changed on one side only takes that side's
+    /// version, and one changed differently on both sides keeps ours and is
+    /// returned as a conflict.
+    fn merge_files(
+        base: &BTreeMap<String, (String, String)>,
+        ours: &BTreeMap<String, (String, String)>,
+        theirs: &BTreeMap<String, (String, String)>,
+    ) -> (BTreeMap<String, (String, String)>, Vec<String>) {
+        let mut merged = ours.clone();
+        let mut conflicts = Vec::new();
+        let paths: BTreeSet<&String> = base
+            .keys()
+            .chain(ours.keys())
+            .chain(theirs.keys())
+            .collect();
+        for path in paths {
+            let (base, ours, theirs) = (base.get(path), ours.get(path), theirs.get(path));
+            if ours == theirs || base == theirs {
+                continue;
+            }
+            if base != ours {
+                conflicts.push(path.clone());
+                continue;
+            }
+
+            match theirs {
+                Some(entry) => merged.insert(path.clone(), entry.clone()),
+                None => merged.remove(path),
+            };
+        }
+
+        return (merged, conflicts);
+    }
+
+    /// Whether data looks binary rather than text, as git judges it: by a
+    /// NUL among its first 8000 bytes.
+    fn is_binary(data: &[u8]) -> bool {
+        return data.iter().take(8000).any(|byte| *byte == 0);
+    }
+
+    /// Write blob data to out as it is, or if it is binary and out is a
+    /// terminal, which it would garble, just a note of its size.
+    fn write_blob_content(
+        data: &[u8],
+        terminal: bool,
+        out: &mut impl Write,
+    ) -> Result<(), ReadObjectErrorType> {
+        if terminal && is_binary(data) {
+            return writeln!(out, "Binary file ({} bytes)", data.len())
+                .map_err(ReadObjectErrorType::IO);
+        }
+
+        return out.write_all(data).map_err(ReadObjectErrorType::IO);
+    }
+
+    /// Write a line per entry of tree to out, as `ls-tree` lists them.
+    fn write_tree_entries(tree: &Tree, out: &mut impl Write) -> Result<(), ReadObjectErrorType> {
+        for leaf in tree {
+            let entry_type = match leaf.mode() {
+                "40000" => ObjectKind::Tree,
+                "160000" => ObjectKind::Commit,
+                _ => ObjectKind::Blob,
+            };
+            writeln!(
+                out,
+                "{} {} {}\t{}",
+                leaf.padded_mode(),
+                entry_type,
+                leaf.sha(),
+                leaf.path()
+            )
+            .map_err(ReadObjectErrorType::IO)?;
+        }
+
+        return Ok(());
+    }
+
+    /// Split a name like `HEAD~2` or `HEAD^` into the revision, the
+    /// operator and its count, which is 1 if not given, if it is one.
+    fn ancestry_suffix(name: &str) -> Option<(&str, char, usize)> {
+        let pos = name.rfind(['^', '~'])?;
+        let (rev, suffix) = name.split_at(pos);
+        let count = &suffix[1..];
+        if rev.is_empty() || !count.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let n = match count {
+            "" => 1,
+            _ => count.parse().ok()?,
+        };
+        return Some((rev, suffix.chars().next()?, n));
+    }
+
+    /// Split a name like `HEAD@{2}` into the ref and the number of moves
+    /// ago, if it is one.
+    fn reflog_selector(name: &str) -> Option<(&str, usize)> {
+        let (reference, n) = name.strip_suffix('}')?.rsplit_once("@{")?;
+        return Some((reference, n.parse().ok()?));
+    }
+
+    /// The unambiguous short form of a full ref name, e.g. refs/heads/master
+    /// to master.
+    pub(crate) fn shorten_ref(reference: &str) -> &str {
+        return ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+            .iter()
+            .find_map(|prefix| reference.strip_prefix(prefix))
+            .unwrap_or(reference);
+    }
+
+    /// Check name is a valid ref name, as `git check-ref-format` does, so
+    /// that it can't name a file outside the refs it's meant to be among.
+    ///
+    /// No component may be empty, start with `.` or end with `.lock`, and
+    /// the name may not contain `..`, `@{`, control characters, spaces or
+    /// any of `~^:?*[\`. Like git's porcelain, a leading `-` is refused too.
+    fn check_ref_name(name: &str) -> Result<(), ReadObjectErrorType> {
+        let valid = !name.starts_with('-')
+            && name != "@"
+            && !name.ends_with('.')
+            && !name.contains("..")
+            && !name.contains("@{")
+            && !name
+                .chars()
+                .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+            && name.split('/').all(|component| {
+                !component.is_empty()
+                    && !component.starts_with('.')
+                    && !component.ends_with(".lock")
+            });
+
+        return match valid {
+            true => Ok(()),
+            false => Err(ReadObjectErrorType::InvalidRefError(name.to_string())),
+        };
+    }
+
+    /// Whether the ref name matches pattern, as for_each_ref describes.
+    fn ref_matches(pattern: &str, name: &str) -> bool {
+        if !pattern.contains(['*', '?']) {
+            let prefix = pattern.trim_end_matches('/');
+            return name == prefix
+                || name
+                    .strip_prefix(prefix)
+                    .is_some_and(|rest| rest.starts_with('/'));
+        }
+
+        return glob_matches(pattern.as_bytes(), name.as_bytes());
+    }
+
+    fn glob_matches(pattern: &[u8], name: &[u8]) -> bool {
+        return match (pattern.first(), name.first()) {
+            (None, _) => name.is_empty(),
+            (Some(b'*'), _) => {
+                // Try every split of name into the part * matches and the
+                // rest, stopping at the first slash
+                glob_matches(&pattern[1..], name)
+                    || (name.first().is_some_and(|c| *c != b'/')
+                        && glob_matches(pattern, &name[1..]))
+            }
+            (Some(b'?'), Some(c)) => *c != b'/' && glob_matches(&pattern[1..], &name[1..]),
+            (Some(p), Some(c)) => p == c && glob_matches(&pattern[1..], &name[1..]),
+            (Some(_), None) => false,
+        };
+    }
+
+    /// Order commits with each child before its parents, as `git
+    /// show-branch` lists them: a tip is followed by as much of its history
+    /// as can be shown before the next tip. Only parents which are
+    /// themselves among commits are followed.
+    fn graph_order(commits: &[String], parents: &HashMap<String, Vec<String>>) -> Vec<String> {
+        let mut children: HashMap<&str, usize> = HashMap::new();
+        for sha in commits {
+            for parent in &parents[sha] {
+                *children.entry(parent).or_insert(0) += 1;
+            }
+        }
+
+        // A stack, so each commit's parents go before anything older
+        let mut stack = commits
+            .iter()
+            .filter(|sha| !children.contains_key(sha.as_str()))
+            .rev()
+            .collect::<Vec<&String>>();
+        let mut ordered = Vec::with_capacity(commits.len());
+        while let Some(sha) = stack.pop() {
+            for parent in &parents[sha] {
+                if !parents.contains_key(parent) {
+                    continue;
+                }
+                let count = children.get_mut(parent.as_str()).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    stack.push(parent);
+                }
+            }
+            ordered.push(sha.clone());
+        }
+
+        return ordered;
+    }
+
+    /// Names for commits in terms of refs, whose tips are the commits
+    /// in tips, as `git show-branch` gives them: first each tip, then
+    /// first-parent ancestors as `<ref>^` and `<ref>~<n>`, then anything
+    /// left through the other parents of named commits, as `<name>^<n>`.
+    fn show_branch_names(
+        commits: &[String],
+        parents: &HashMap<String, Vec<String>>,
+        refs: &[String],
+        tips: &[String],
+    ) -> HashMap<String, String> {
+        // Each commit's ref, or other named commit, and how many first
+        // parents back from it the commit is
+        let mut names: HashMap<&str, (String, usize)> = HashMap::new();
+        for (name, sha) in refs.iter().zip(tips) {
+            names.entry(sha).or_insert((name.clone(), 0));
+        }
+
+        let display = |(head, generation): &(String, usize)| {
+            return match generation {
+                0 => head.clone(),
+                1 => format!("{}^", head),
+                n => format!("{}~{}", head, n),
+            };
+        };
+
+        let mut named = true;
+        while named {
+            named = false;
+            for sha in commits {
+                let mut sha = sha.as_str();
+                while let Some((head, generation)) = names.get(sha).cloned() {
+                    let Some(parent) = parents.get(sha).and_then(|p| p.first()) else {
+                        break;
+                    };
+                    if !parents.contains_key(parent) || names.contains_key(parent.as_str()) {
+                        break;
+                    }
+                    names.insert(parent, (head, generation + 1));
+                    sha = parent;
+                    named = true;
+                }
+            }
+        }
+
+        named = true;
+        while named {
+            named = false;
+            for sha in commits {
+                let Some(name) = names.get(sha.as_str()).map(display) else {
+                    continue;
+                };
+                for (n, parent) in parents[sha.as_str()].iter().enumerate() {
+                    if !parents.contains_key(parent) || names.contains_key(parent.as_str()) {
+                        continue;
+                    }
+                    let parent_name = match n {
+                        0 => format!("{}^", name),
+                        n => format!("{}^{}", name, n + 1),
+                    };
+                    names.insert(parent, (parent_name, 0));
+                    named = true;
+                }
+            }
+        }
+
+        return names
+            .iter()
+            .map(|(sha, name)| (sha.to_string(), display(name)))
+            .collect();
+    }
+
+    /// Escape text for use inside a quoted DOT string. Backslashes would
+    /// otherwise start graphviz escapes, such as `\l`, and quotes end the
+    /// string; line breaks become graphviz's own.
+    fn dot_escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '"' => escaped.push_str("\\\""),
+                '\n' => escaped.push_str("\\n"),
+                '\r' => {}
+                c => escaped.push(c),
+            }
+        }
+
+        return escaped;
+    }
+
+    /// Turn a failure to produce any items into an iterator yielding just
+    /// the error.
+    fn flatten_results<T>(
+        result: Result<Vec<T>, ReadObjectErrorType>,
+    ) -> std::vec::IntoIter<Result<T, ReadObjectErrorType>> {
+        return match result {
+            Ok(items) => items.into_iter().map(Ok).collect::<Vec<_>>().into_iter(),
+            Err(e) => vec![Err(e)].into_iter(),
+        };
+    }
+
+    /// The file in the objects directory dir the loose object sha would be
+    /// stored in, in a directory named after the first two digits of its
+    /// SHA, whichever hash the repository uses.
+    fn loose_object_file(dir: &Path, sha: &str) -> PathBuf {
+        return dir.join(&sha[0..2]).join(&sha[2..]);
+    }
+
+    /// The path of every packfile in the pack directory of the objects
+    /// directory dir, in order.
+    fn pack_paths(dir: &Path) -> Result<Vec<PathBuf>, ReadObjectErrorType> {
+        let dir = dir.join("pack");
+        if !dir.is_dir() {
+            return Ok(vec![]);
+        }
+
+        let mut paths = Vec::new();
+        for entry in dir.read_dir().map_err(ReadObjectErrorType::IO)? {
+            let path = entry.map_err(ReadObjectErrorType::IO)?.path();
+            if path.extension().is_some_and(|ext| ext == "pack") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        return Ok(paths);
+    }
+
+    /// Write data to path by way of a "<path>.lock" file in the same
+    /// directory, which is renamed over path once fully written. The lock
+    /// is only taken if nobody else holds it, and is removed if the write
+    /// fails.
+    fn write_atomic(path: &Path, data: &[u8]) -> Result<(), ReadObjectErrorType> {
+        let mut lock = path.as_os_str().to_owned();
+        lock.push(".lock");
+        let lock = PathBuf::from(lock);
+
+        // Like git, an existing lock means someone else is writing the file
+        let mut f = match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock)
+        {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(ReadObjectErrorType::LockExistsError(lock));
+            }
+            Err(e) => return Err(ReadObjectErrorType::IO(e)),
+        };
+
+        let written = f
+            .write_all(data)
+            .and_then(|_| f.sync_all())
+            .and_then(|_| fs::rename(&lock, path));
+        if let Err(e) = written {
+            let _ = fs::remove_file(&lock);
+            return Err(ReadObjectErrorType::IO(e));
+        }
+
+        return Ok(());
+    }
+
+    /// Apply the permissions recorded in a tree entry's mode to a checked-out
+    /// file: executable entries (100755) get 0o755, everything else 0o644.
+    #[cfg(unix)]
+    fn set_file_mode(path: &Path, mode: &str) -> Result<(), ReadObjectErrorType> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let permissions = match mode {
+            "100755" => 0o755,
+            _ => 0o644,
+        };
+
+        return set_permissions(path, Permissions::from_mode(permissions))
+            .map_err(ReadObjectErrorType::IO);
+    }
+
+    #[cfg(not(unix))]
+    fn set_file_mode(_path: &Path, _mode: &str) -> Result<(), ReadObjectErrorType> {
+        return Ok(());
+    }
+
+    /// The space a file takes up on disk, which like git is counted in
+    /// the blocks allocated to it where the platform says.
+    #[cfg(unix)]
+    fn disk_usage(metadata: &fs::Metadata) -> u64 {
+        use std::os::unix::fs::MetadataExt;
+
+        return metadata.blocks() * 512;
+    }
+
+    #[cfg(not(unix))]
+    fn disk_usage(metadata: &fs::Metadata) -> u64 {
+        return metadata.len();
+    }
+
+    #[cfg(test)]
+    pub(crate) mod tests {
+        use flate2::write::ZlibEncoder;
+        use tempfile::TempDir;
+
+        use super::*;
+
+        fn create_repo() -> (TempDir, Repository) {
+            let dir = tempfile::tempdir().unwrap();
+            Repository::repo_create(dir.path(), HashAlgo::Sha1).unwrap();
+            let mut repo = Repository::new(dir.path(), false);
+            // Whoever runs the tests mustn't affect them
+            repo.fallback_config.clear();
+
+            return (dir, repo);
+        }
+
+        /// Store raw, an object's header and content, as the loose object
+        /// sha without checking one against the other, to make objects
+        /// which write_object never would.
+        pub(crate) fn write_raw_object(repo: &Repository, sha: &str, raw: &[u8]) {
+            let path = repo.loose_object_path(sha);
+            create_dir_all(path.parent().unwrap()).unwrap();
+            let mut encoder = ZlibEncoder::new(File::create(path).unwrap(), Compression::default());
+            encoder.write_all(raw).unwrap();
+            encoder.finish().unwrap();
+        }
+
+        fn write_blob(repo: &Repository, content: &[u8]) -> String {
+            let blob = GitObject::new(
+                Some(repo.clone()),
+                GitObjectData(ObjectKind::Blob, content.to_vec()),
+            );
+
+            return GitObject::write_object(blob, None).unwrap();
+        }
+
+        fn write_tree(repo: &Repository, items: Vec<Leaf>) -> String {
+            return GitObject::write_object(Box::new(tree_of(repo, items)), None).unwrap();
+        }
+
+        fn write_commit(repo: &Repository, parents: &[&str], message: &str) -> String {
+            let tree = write_tree(repo, vec![]);
+            return write_commit_with_tree(repo, &tree, parents, message);
+        }
+
+        fn write_commit_with_tree(
+            repo: &Repository,
+            tree: &str,
+            parents: &[&str],
+            message: &str,
+        ) -> String {
+            let mut kvlm = BTreeMap::new();
+            kvlm.insert("tree".to_string(), vec![tree.to_string()]);
+            if !parents.is_empty() {
+                kvlm.insert(
+                    "parent".to_string(),
+                    parents.iter().map(|p| p.to_string()).collect(),
+                );
+            }
+            kvlm.insert("".to_string(), vec![message.to_string()]);
+
+            let GitObjectData(_, data) = GitObjectData::kvlm_serialize(ObjectKind::Commit, &kvlm);
+            let commit =
+                GitObject::new(Some(repo.clone()), GitObjectData(ObjectKind::Commit, data));
+
+            return GitObject::write_object(commit, None).unwrap();
+        }
+
+        /// Write a commit with an author and committer, both at timestamp.
+        fn write_commit_at(
+            repo: &Repository,
+            parents: &[&str],
+            message: &str,
+            timestamp: i64,
+        ) -> String {
+            let mut data = format!("tree {}\n", write_tree(repo, vec![]));
+            for parent in parents {
+                data += &format!("parent {}\n", parent);
+            }
+            data += &format!(
+                "author A U Thor <author@example.com> {} +0000\n\
+                 committer C O Mitter <committer@example.com> {} +0000\n\n{}",
+                timestamp, timestamp, message
+            );
+
+            let commit = GitObject::new(
+                Some(repo.clone()),
+                GitObjectData(ObjectKind::Commit, data.into_bytes()),
+            );
+            return GitObject::write_object(commit, None).unwrap();
+        }
+
+        fn write_tag(repo: &Repository, target: &str, name: &str) -> String {
+            let data = format!(
+                "object {}\ntype commit\ntag {}\ntagger Test <test@example.com> 0 +0000\n\n{}\n",
+                target, name, name
+            );
+            let tag = GitObject::new(
+                Some(repo.clone()),
+                GitObjectData(ObjectKind::Tag, data.into_bytes()),
+            );
+            let sha = GitObject::write_object(tag, None).unwrap();
+            repo.ref_write(&format!("refs/tags/{}", name), &sha)
+                .unwrap();
+            return sha;
+        }
+
+        /// Point the current branch at a new commit.
+        fn set_head(repo: &Repository, sha: &str) {
+            let branch = repo.current_branch().unwrap().unwrap();
+            repo.branch_create(&branch, sha, true).unwrap();
+        }
+
+        fn tree_of(repo: &Repository, items: Vec<Leaf>) -> Tree {
+            let mut tree = Tree::new(Some(repo.clone()), GitObjectData(ObjectKind::Tree, vec![]));
+            tree.items = items;
+
+            return tree;
+        }
+
+        #[cfg(unix)]
+        #[test]
+        fn test_checkout_restores_executable_mode() -> Result<(), ReadObjectErrorType> {
+            use std::os::unix::fs::PermissionsExt;
+
+            let (dir, repo) = create_repo();
+            let script = write_blob(&repo, b"#!/bin/sh\necho hello\n");
+            let readme = write_blob(&repo, b"hello\n");
+            let tree = tree_of(
+                &repo,
+                vec![
+                    Leaf("100644".to_string(), "README".to_string(), readme),
+                    Leaf("100755".to_string(), "run.sh".to_string(), script),
+                ],
+            );
+
+            let out = dir.path().join("out");
+            create_dir_all(&out).map_err(ReadObjectErrorType::IO)?;
+            repo.tree_checkout(&tree, &out, false)?;
+
+            let mode = |name: &str| out.join(name).metadata().unwrap().permissions().mode() & 0o777;
+            assert_eq!(0o755, mode("run.sh"));
+            assert_eq!(0o644, mode("README"));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_checkout_refuses_to_overwrite_conflicting_file() -> Result<(), ReadObjectErrorType>
+        {
+            let (dir, repo) = create_repo();
+            let readme = write_blob(&repo, b"from the tree\n");
+            let other = write_blob(&repo, b"another file\n");
+            let tree = tree_of(
+                &repo,
+                vec![
+                    Leaf("100644".to_string(), "README".to_string(), readme),
+                    Leaf("100644".to_string(), "other".to_string(), other),
+                ],
+            );
+
+            let out = dir.path().join("out");
+            create_dir_all(&out).map_err(ReadObjectErrorType::IO)?;
+            fs::write(out.join("README"), b"local changes\n").map_err(ReadObjectErrorType::IO)?;
+
+            let result = repo.tree_checkout(&tree, &out, false);
+
+            match result {
+                Err(ReadObjectErrorType::CheckoutConflictError(path)) => {
+                    assert_eq!(out.join("README"), path)
+                }
+                other => panic!("Expected a checkout conflict, got {:?}", other),
+            }
+            assert_eq!(
+                b"local changes\n".to_vec(),
+                fs::read(out.join("README")).map_err(ReadObjectErrorType::IO)?
+            );
+            assert!(
+                !out.join("other").exists(),
+                "Checkout wrote files before failing"
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_checkout_allows_identical_existing_file() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let readme = write_blob(&repo, b"same\n");
+            let tree = tree_of(
+                &repo,
+                vec![Leaf("100644".to_string(), "README".to_string(), readme)],
+            );
+
+            let out = dir.path().join("out");
+            create_dir_all(&out).map_err(ReadObjectErrorType::IO)?;
+            fs::write(out.join("README"), b"same\n").map_err(ReadObjectErrorType::IO)?;
+
+            repo.tree_checkout(&tree, &out, false)?;
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_write_tree_dry_run_writes_nothing() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let mut index = Index::default();
+            for path in ["README", "src/lib.rs"] {
+                index.entries.push(IndexEntry {
+                    mode: 0o100644,
+                    sha: write_blob(&repo, path.as_bytes()),
+                    path: path.to_string(),
+                    ..Default::default()
+                });
+            }
+            let blobs = repo.loose_objects()?;
+
+            let tree = repo.write_tree(&index, Some(false))?;
+            assert_eq!(blobs, repo.loose_objects()?);
+            assert!(!repo.has_object(&tree));
+
+            assert_eq!(tree, repo.write_tree(&index, None)?);
+            assert_eq!(blobs.len() + 2, repo.loose_objects()?.len());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_read_tree_round_trips_write_tree() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let mut index = Index::default();
+            for path in ["README", "src/lib.rs", "src/bin/main.rs", "src.txt", "z"] {
+                index.entries.push(IndexEntry {
+                    mtime: (1665000000, 0),
+                    mode: 0o100644,
+                    sha: write_blob(&repo, path.as_bytes()),
+                    path: path.to_string(),
+                    ..Default::default()
+                });
+            }
+            index.entries[0].mode = 0o100755;
+            index.sort();
+
+            let tree = repo.write_tree(&index, None)?;
+            let read = repo.read_tree(&tree)?;
+
+            let staged = |index: &Index| -> Vec<(String, u32, String)> {
+                return index
+                    .entries
+                    .iter()
+                    .map(|entry| (entry.path.clone(), entry.mode, entry.sha.clone()))
+                    .collect();
+            };
+            assert_eq!(staged(&index), staged(&read));
+            assert_eq!((0, 0), read.entries[0].mtime);
+
+            // The trees are the ones git would write, with src/ sorted
+            // after src.txt
+            let ParsedObject::Tree(root) = repo.read_object_typed(tree)? else {
+                panic!("not a tree");
+            };
+            let names: Vec<&str> = root.iter().map(|leaf| leaf.path()).collect();
+            assert_eq!(vec!["README", "src.txt", "src", "z"], names);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_build_tree_writes_nested_trees() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let files = BTreeMap::from(
+                [
+                    ("README", "100644", &b"hello\n"[..]),
+                    ("src/bin/main.rs", "100644", b"fn main() {}\n"),
+                    ("src/lib.rs", "100755", b"lib\n"),
+                ]
+                .map(|(path, mode, content)| {
+                    let sha = write_blob(&repo, content);
+                    return (path.to_string(), (mode.to_string(), sha));
+                }),
+            );
+
+            let tree = repo.build_tree(&files, None)?;
+
+            // As git write-tree gives for the same files
+            assert_eq!("7cb3edc3ab970c8603b33ee8bf4a4bb91d31c15e", tree);
+            assert_eq!(files, repo.flatten_tree(&tree)?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_flatten_tree_joins_nested_paths() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let mut index = Index::default();
+            for (path, mode) in [
+                ("README", 0o100644),
+                ("bin/run", 0o100755),
+                ("src/lib.rs", 0o100644),
+                ("src/util/mod.rs", 0o100644),
+            ] {
+                index.entries.push(IndexEntry {
+                    mode,
+                    sha: write_blob(&repo, path.as_bytes()),
+                    path: path.to_string(),
+                    ..Default::default()
+                });
+            }
+            let tree = repo.write_tree(&index, None)?;
+
+            let files = repo.flatten_tree(&tree)?;
+
+            let expected: BTreeMap<String, (String, String)> = index
+                .entries
+                .iter()
+                .map(|entry| {
+                    let mode = format!("{:o}", entry.mode);
+                    return (entry.path.clone(), (mode, entry.sha.clone()));
+                })
+                .collect();
+            assert_eq!(expected, files);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_checkout_creates_missing_destination() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let readme = write_blob(&repo, b"hello\n");
+            let tree = tree_of(
+                &repo,
+                vec![Leaf("100644".to_string(), "README".to_string(), readme)],
+            );
+
+            let out = dir.path().join("nested/out");
+            repo.tree_checkout(&tree, &out, false)?;
+
+            assert_eq!(
+                b"hello\n".to_vec(),
+                fs::read(out.join("README")).map_err(ReadObjectErrorType::IO)?
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_checkout_leaves_submodules_empty() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let readme = write_blob(&repo, b"hello\n");
+            // The submodule's commit isn't in this repository
+            let tree = tree_of(
+                &repo,
+                vec![
+                    Leaf("100644".to_string(), "README".to_string(), readme),
+                    Leaf(
+                        "160000".to_string(),
+                        "vendor".to_string(),
+                        "1234567890abcdef1234567890abcdef12345678".to_string(),
+                    ),
+                ],
+            );
+
+            let out = dir.path().join("out");
+            repo.tree_checkout(&tree, &out, false)?;
+            assert!(out.join("README").is_file());
+            assert_eq!(
+                0,
+                fs::read_dir(out.join("vendor"))
+                    .map_err(ReadObjectErrorType::IO)?
+                    .count()
+            );
+
+            // A populated submodule is left alone
+            fs::write(out.join("vendor/lib.rs"), "").map_err(ReadObjectErrorType::IO)?;
+            repo.tree_checkout(&tree, &out, false)?;
+            assert!(out.join("vendor/lib.rs").is_file());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_checkout_deeply_nested_tree_on_small_stack() -> Result<(), ReadObjectErrorType> {
+            // As deep as paths allow, and far deeper than a stack this size
+            // could recurse
+            const DEPTH: usize = 1500;
+            let (dir, repo) = create_repo();
+            let mut sha = write_tree(
+                &repo,
+                vec![Leaf(
+                    "100644".to_string(),
+                    "leaf".to_string(),
+                    write_blob(&repo, b"bottom\n"),
+                )],
+            );
+            for _ in 0..DEPTH {
+                sha = write_tree(&repo, vec![Leaf("40000".to_string(), "d".to_string(), sha)]);
+            }
+            let ParsedObject::Tree(tree) = repo.read_object_typed(sha.clone())? else {
+                panic!("{} is not a tree", sha);
+            };
+
+            let out = dir.path().join("out");
+            let checkout = thread::Builder::new()
+                .stack_size(256 * 1024)
+                .spawn(move || -> Result<(), ReadObjectErrorType> {
+                    repo.tree_checkout(&tree, &out, false)?;
+                    // Now every path exists, so is checked for conflicts
+                    repo.tree_checkout(&tree, &out, false)?;
+                    return Ok(());
+                })
+                .map_err(ReadObjectErrorType::IO)?;
+            checkout.join().unwrap()?;
+
+            let leaf = dir
+                .path()
+                .join("out")
+                .join(["d"; DEPTH].join("/"))
+                .join("leaf");
+            assert_eq!(
+                b"bottom\n".to_vec(),
+                fs::read(leaf).map_err(ReadObjectErrorType::IO)?
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_forced_checkout_overwrites_tracked_files() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let readme = write_blob(&repo, b"from the tree\n");
+            let lib = write_blob(&repo, b"fn main() {}\n");
+            let src = write_tree(
+                &repo,
+                vec![Leaf("100644".to_string(), "lib.rs".to_string(), lib)],
+            );
+            let tree = tree_of(
+                &repo,
+                vec![
+                    Leaf("100644".to_string(), "README".to_string(), readme),
+                    Leaf("40000".to_string(), "src".to_string(), src),
+                ],
+            );
+
+            let out = dir.path().join("out");
+            create_dir_all(out.join("README")).map_err(ReadObjectErrorType::IO)?;
+            fs::write(out.join("src"), b"a file where a directory belongs\n")
+                .map_err(ReadObjectErrorType::IO)?;
+            fs::write(out.join("untracked"), b"keep me\n").map_err(ReadObjectErrorType::IO)?;
+
+            assert!(matches!(
+                repo.tree_checkout(&tree, &out, false),
+                Err(ReadObjectErrorType::CheckoutConflictError(_))
+            ));
+
+            repo.tree_checkout(&tree, &out, true)?;
+
+            let read = |path: &str| fs::read(out.join(path)).map_err(ReadObjectErrorType::IO);
+            assert_eq!(b"from the tree\n".to_vec(), read("README")?);
+            assert_eq!(b"fn main() {}\n".to_vec(), read("src/lib.rs")?);
+            assert_eq!(b"keep me\n".to_vec(), read("untracked")?);
+
+            return Ok(());
+        }
+
+        fn stage(repo: &Repository, dir: &Path, path: &str, content: &[u8]) -> IndexEntry {
+            fs::write(dir.join(path), content).unwrap();
+            return IndexEntry {
+                mode: 0o100644,
+                size: content.len() as u32,
+                sha: write_blob(repo, content),
+                path: path.to_string(),
+                ..Default::default()
+            };
+        }
+
+        #[test]
+        fn test_mv_renames_staged_file() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let hello = stage(&repo, dir.path(), "hello.txt", b"Hello, world!\n");
+            let lorem = stage(&repo, dir.path(), "lorem.txt", b"Lorem ipsum\n");
+            repo.write_index(&Index {
+                entries: vec![hello.clone(), lorem.clone()],
+            })?;
+
+            repo.mv(Path::new("hello.txt"), Path::new("z.txt"), false)?;
+
+            assert!(!dir.path().join("hello.txt").exists());
+            assert_eq!(
+                "Hello, world!\n",
+                fs::read_to_string(dir.path().join("z.txt")).map_err(ReadObjectErrorType::IO)?
+            );
+            let moved = IndexEntry {
+                path: "z.txt".to_string(),
+                ..hello
+            };
+            assert_eq!(vec![lorem, moved], repo.index()?.entries);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_mv_refuses_untracked_source_and_existing_destination(
+        ) -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let hello = stage(&repo, dir.path(), "hello.txt", b"Hello, world!\n");
+            let lorem = stage(&repo, dir.path(), "lorem.txt", b"Lorem ipsum\n");
+            repo.write_index(&Index {
+                entries: vec![hello.clone(), lorem],
+            })?;
+            fs::write(dir.path().join("untracked.txt"), "").map_err(ReadObjectErrorType::IO)?;
+
+            assert!(matches!(
+                repo.mv(Path::new("untracked.txt"), Path::new("new.txt"), false),
+                Err(ReadObjectErrorType::PathNotTrackedError(_))
+            ));
+            assert!(matches!(
+                repo.mv(Path::new("hello.txt"), Path::new("lorem.txt"), false),
+                Err(ReadObjectErrorType::PathExistsError(_))
+            ));
+
+            // Forcing it replaces the destination, in the index too
+            repo.mv(Path::new("hello.txt"), Path::new("lorem.txt"), true)?;
+            let moved = IndexEntry {
+                path: "lorem.txt".to_string(),
+                ..hello
+            };
+            assert_eq!(vec![moved], repo.index()?.entries);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_status_porcelain_lines() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let write = |path: &str, content: &str| {
+                let path = dir.path().join(path);
+                create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, content).unwrap();
+            };
+            let files = ["deleted.txt", "modified.txt", "src/lib.rs", "unchanged.txt"];
+            for path in files {
+                write(path, path);
+            }
+            repo.add(&files.map(PathBuf::from))?;
+            let tree = repo.write_tree(&repo.index()?, None)?;
+            set_head(
+                &repo,
+                &write_commit_with_tree(&repo, &tree, &[], "Initial commit\n"),
+            );
+            assert_eq!(vec![] as Vec<FileStatus>, repo.status()?);
+
+            write("added.txt", "added");
+            repo.add(&[PathBuf::from("added.txt")])?;
+            write("added.txt", "added, then changed");
+            write("modified.txt", "changed");
+            let mut index = repo.index()?;
+            index.entries.retain(|entry| entry.path != "deleted.txt");
+            repo.write_index(&index)?;
+            fs::remove_file(dir.path().join("deleted.txt")).unwrap();
+            fs::remove_file(dir.path().join("src/lib.rs")).unwrap();
+            write("notes.txt", "notes");
+            write("src/new.rs", "new");
+            write("build/out/main.o", "object");
+
+            let lines: Vec<String> = repo.status()?.iter().map(|s| s.to_string()).collect();
+
+            assert_eq!(
+                vec![
+                    "AM added.txt",
+                    "D  deleted.txt",
+                    " M modified.txt",
+                    " D src/lib.rs",
+                    "?? build/",
+                    "?? notes.txt",
+                    "?? src/new.rs",
+                ],
+                lines
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_ls_files_others_and_modified() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let write = |path: &str, content: &str| {
+                let path = dir.path().join(path);
+                create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, content).unwrap();
+            };
+            let files = ["deleted.txt", "modified.txt", "src/lib.rs", "unchanged.txt"];
+            for path in files {
+                write(path, path);
+            }
+            repo.add(&files.map(PathBuf::from))?;
+
+            write("modified.txt", "changed");
+            fs::remove_file(dir.path().join("deleted.txt")).unwrap();
+            write(".gitignore", "*.o\n");
+            write("notes.txt", "notes");
+            write("src/new.rs", "new");
+            write("src/main.o", "object");
+
+            let ls_files = |filter| repo.ls_files(filter);
+            assert_eq!(
+                vec![".gitignore", "notes.txt", "src/new.rs"],
+                ls_files(LsFilesFilter {
+                    others: true,
+                    ..Default::default()
+                })?
+            );
+            assert_eq!(
+                vec!["deleted.txt", "modified.txt"],
+                ls_files(LsFilesFilter {
+                    modified: true,
+                    ..Default::default()
+                })?
+            );
+            assert_eq!(
+                vec!["deleted.txt", "deleted.txt", "modified.txt"],
+                ls_files(LsFilesFilter {
+                    modified: true,
+                    deleted: true,
+                    ..Default::default()
+                })?
+            );
+            assert_eq!(
+                files.to_vec(),
+                ls_files(LsFilesFilter {
+                    cached: true,
+                    ..Default::default()
+                })?
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_walk_worktree_skips_ignored_directories() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let write = |path: &str, content: &str| {
+                let path = dir.path().join(path);
+                create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, content).unwrap();
+            };
+            write(".gitignore", "target/\n*.log\n");
+            write("src/main.rs", "main");
+            write("src/generated.rs", "generated");
+            write("src/keep.log", "keep");
+            write("src/.gitignore", "generated.rs\n!keep.log\n");
+            write("debug.log", "debug");
+            write("target/debug/wyag", "binary");
+            write("target/.gitignore", "!*\n");
+            write("vendor/lib/.git/HEAD", "ref: refs/heads/master\n");
+            write("vendor/lib/lib.rs", "lib");
+
+            let files = repo
+                .walk_worktree()
+                .collect::<Result<Vec<PathBuf>, ReadObjectErrorType>>()?;
+
+            assert_eq!(
+                vec![
+                    ".gitignore",
+                    "src/.gitignore",
+                    "src/keep.log",
+                    "src/main.rs"
+                ]
+                .into_iter()
+                .map(PathBuf::from)
+                .collect::<Vec<_>>(),
+                files
+            );
+            assert_eq!(
+                vec![] as Vec<PathBuf>,
+                repo.walk_worktree_from(Path::new("target"))
+                    .collect::<Result<Vec<PathBuf>, ReadObjectErrorType>>()?
+            );
+
+            return Ok(());
+        }
+
+        /// Write a commit of a tree holding files, given as (path, content)
+        /// pairs, returning its SHA.
+        fn commit_files(
+            repo: &Repository,
+            parents: &[&str],
+            files: &[(&str, &str)],
+            message: &str,
+        ) -> String {
+            let mut index = Index::default();
+            for (path, content) in files {
+                index.entries.push(IndexEntry {
+                    mode: 0o100644,
+                    sha: write_blob(repo, content.as_bytes()),
+                    path: path.to_string(),
+                    ..Default::default()
+                });
+            }
+            index.sort();
+            let tree = repo.write_tree(&index, None).unwrap();
+
+            return write_commit_with_tree(repo, &tree, parents, message);
+        }
+
+        #[test]
+        fn test_merge_fast_forwards() -> Result<(), ReadObjectErrorType> {
+            let (dir, mut repo) = create_repo();
+            repo.config.setstr("user", "name", Some("Config User"));
+            repo.config
+                .setstr("user", "email", Some("config@example.com"));
+            let base = commit_files(&repo, &[], &[("README", "base")], "Base\n");
+            let next = commit_files(
+                &repo,
+                &[&base],
+                &[("README", "next"), ("src/lib.rs", "lib")],
+                "Next\n",
+            );
+            repo.ref_write("refs/heads/master", &base)?;
+            repo.ref_write("refs/heads/topic", &next)?;
+            repo.checkout_worktree(&repo.commit_tree_sha(&base)?, false)?;
+
+            assert_eq!(
+                MergeOutcome::FastForward(next.clone()),
+                repo.merge("topic")?
+            );
+
+            assert_eq!(Some(next.clone()), repo.ref_resolve("refs/heads/master")?);
+            assert_eq!(
+                "next",
+                fs::read_to_string(dir.path().join("README")).unwrap()
+            );
+            assert_eq!(
+                "lib",
+                fs::read_to_string(dir.path().join("src/lib.rs")).unwrap()
+            );
+            assert_eq!(vec![] as Vec<FileStatus>, repo.status()?);
+            assert_eq!(MergeOutcome::UpToDate, repo.merge(&base)?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_merge_combines_changes_to_different_paths() -> Result<(), ReadObjectErrorType> {
+            let (dir, mut repo) = create_repo();
+            repo.config.setstr("user", "name", Some("Config User"));
+            repo.config
+                .setstr("user", "email", Some("config@example.com"));
+            let base = commit_files(
+                &repo,
+                &[],
+                &[
+                    ("README", "base"),
+                    ("old.txt", "old"),
+                    ("src/lib.rs", "lib"),
+                ],
+                "Base\n",
+            );
+            let ours = commit_files(
+                &repo,
+                &[&base],
+                &[
+                    ("README", "ours"),
+                    ("old.txt", "old"),
+                    ("src/lib.rs", "lib"),
+                ],
+                "Ours\n",
+            );
+            let theirs = commit_files(
+                &repo,
+                &[&base],
+                &[
+                    ("README", "base"),
+                    ("src/lib.rs", "theirs"),
+                    ("src/new.rs", "new"),
+                ],
+                "Theirs\n",
+            );
+            repo.ref_write("refs/heads/master", &ours)?;
+            repo.ref_write("refs/heads/topic", &theirs)?;
+            repo.checkout_worktree(&repo.commit_tree_sha(&ours)?, false)?;
+
+            let MergeOutcome::Merged(merge) = repo.merge("topic")? else {
+                panic!("not merged cleanly");
+            };
+
+            let commit = repo.read_commit(&merge)?;
+            assert_eq!([ours, theirs], commit.parents());
+            assert_eq!("Merge branch 'topic'\n", commit.message());
+            assert_eq!(Some(merge.clone()), repo.ref_resolve("HEAD")?);
+            let files: Vec<(String, String)> = repo
+                .flatten_tree(&repo.commit_tree_sha(&merge)?)?
+                .into_keys()
+                .map(|path| {
+                    let content = fs::read_to_string(dir.path().join(&path)).unwrap();
+                    return (path, content);
+                })
+                .collect();
+            assert_eq!(
+                vec![
+                    ("README".to_string(), "ours".to_string()),
+                    ("src/lib.rs".to_string(), "theirs".to_string()),
+                    ("src/new.rs".to_string(), "new".to_string()),
+                ],
+                files
+            );
+            assert!(!dir.path().join("old.txt").exists());
+            assert_eq!(vec![] as Vec<FileStatus>, repo.status()?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_merge_stages_conflicting_paths() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let base = commit_files(&repo, &[], &[("README", "base")], "Base\n");
+            let ours = commit_files(&repo, &[&base], &[("README", "ours")], "Ours\n");
+            let theirs = commit_files(&repo, &[&base], &[("README", "theirs")], "Theirs\n");
+            repo.ref_write("refs/heads/master", &ours)?;
+            repo.checkout_worktree(&repo.commit_tree_sha(&ours)?, false)?;
+
+            assert_eq!(
+                MergeOutcome::Conflicted(vec!["README".to_string()]),
+                repo.merge(&theirs)?
+            );
+
+            assert_eq!(Some(ours), repo.ref_resolve("HEAD")?);
+            let stages: Vec<u8> = repo.index()?.entries.iter().map(|e| e.stage).collect();
+            assert_eq!(vec![1, 2, 3], stages);
+            assert_eq!(
+                vec!["UU README".to_string()],
+                repo.status()?
+                    .iter()
+                    .map(|status| status.to_string())
+                    .collect::<Vec<String>>()
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_cherry_pick_applies_added_file() -> Result<(), ReadObjectErrorType> {
+            let (dir, mut repo) = create_repo();
+            repo.config.setstr("user", "name", Some("Config User"));
+            repo.config
+                .setstr("user", "email", Some("config@example.com"));
+            let base = commit_files(&repo, &[], &[("README", "base")], "Base\n");
+            let head = commit_files(&repo, &[&base], &[("README", "head")], "Head\n");
+            let topic = commit_files(&repo, &[&base], &[("README", "base")], "Topic\n");
+            let draft = commit_files(
+                &repo,
+                &[&topic],
+                &[("README", "base"), ("src/new.rs", "new")],
+                "Add new.rs\n",
+            );
+            let data = format!(
+                "tree {}\nparent {}\n\
+                 author A U Thor <author@example.com> 1665000000 +0100\n\
+                 committer A U Thor <author@example.com> 1665000000 +0100\n\n\
+                 Add new.rs\n",
+                repo.commit_tree_sha(&draft)?,
+                topic
+            );
+            let pick = GitObject::write_object(
+                GitObject::new(
+                    Some(repo.clone()),
+                    GitObjectData(ObjectKind::Commit, data.into_bytes()),
+                ),
+                None,
+            )?;
+            repo.ref_write("refs/heads/master", &head)?;
+            repo.checkout_worktree(&repo.commit_tree_sha(&head)?, false)?;
+
+            let picked = repo.cherry_pick(&pick)?;
+
+            let commit = repo.read_commit(&picked)?;
+            assert_eq!([head], commit.parents());
+            assert_eq!("Add new.rs\n", commit.message());
+            assert_eq!("1665000000 +0100", commit.author_date());
+            assert_eq!("Config User <config@example.com>", commit.committer());
+            assert_eq!(Some(picked.clone()), repo.ref_resolve("HEAD")?);
+            let files: Vec<String> = repo
+                .flatten_tree(&repo.commit_tree_sha(&picked)?)?
+                .into_keys()
+                .collect();
+            assert_eq!(vec!["README", "src/new.rs"], files);
+            assert_eq!(
+                "head",
+                fs::read_to_string(dir.path().join("README")).unwrap()
+            );
+            assert_eq!(
+                "new",
+                fs::read_to_string(dir.path().join("src/new.rs")).unwrap()
+            );
+            assert_eq!(vec![] as Vec<FileStatus>, repo.status()?);
+
+            // Picking a change HEAD made differently changes nothing
+            let change = commit_files(&repo, &[&base], &[("README", "other")], "Other\n");
+            assert!(matches!(
+                repo.cherry_pick(&change),
+                Err(ReadObjectErrorType::ApplyConflictError(paths)) if paths == ["README"]
+            ));
+            assert_eq!(Some(picked), repo.ref_resolve("HEAD")?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_checkout_worktree_leaves_status_clean() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let files = ["README", "src/lib.rs", "src/bin/main.rs"];
+            for path in files {
+                let path = dir.path().join(path);
+                create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, "content").unwrap();
+            }
+            repo.add(&files.map(PathBuf::from))?;
+            let tree = repo.write_tree(&repo.index()?, None)?;
+            set_head(
+                &repo,
+                &write_commit_with_tree(&repo, &tree, &[], "Initial commit\n"),
+            );
+
+            // Start from a worktree and index which both disagree with HEAD
+            fs::remove_file(dir.path().join("src/lib.rs")).unwrap();
+            fs::remove_file(repo.repo_path(&["index"])).unwrap();
+
+            repo.checkout_worktree(&tree, false)?;
+
+            assert_eq!(vec![] as Vec<FileStatus>, repo.status()?);
+            let index = repo.index()?;
+            assert_eq!(3, index.entries.len());
+            assert!(index.entries.iter().all(|entry| entry.size == 7));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_status_hashes_only_when_stat_data_differs() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let path = dir.path().join("hello.txt");
+            let set_mtime = |content: &str, mtime: u64| {
+                let mut file = File::create(&path).unwrap();
+                file.write_all(content.as_bytes()).unwrap();
+                file.set_modified(UNIX_EPOCH + std::time::Duration::from_secs(mtime))
+                    .unwrap();
+            };
+            set_mtime("Hello, world!\n", 1_600_000_000);
+            repo.add(&[PathBuf::from("hello.txt")])?;
+            let tree = repo.write_tree(&repo.index()?, None)?;
+            set_head(
+                &repo,
+                &write_commit_with_tree(&repo, &tree, &[], "Initial commit\n"),
+            );
+            assert_eq!(vec![] as Vec<FileStatus>, repo.status()?);
+
+            // Touching the file means hashing it, which finds it unchanged
+            set_mtime("Hello, world!\n", 1_700_000_000);
+            assert_eq!(vec![] as Vec<FileStatus>, repo.status()?);
+
+            set_mtime("Hello, World!\n", 1_700_000_000);
+            assert_eq!(
+                vec![" M hello.txt".to_string()],
+                repo.status()?
+                    .iter()
+                    .map(|status| status.to_string())
+                    .collect::<Vec<String>>()
+            );
+
+            // Whereas a file whose size and mtime are those staged isn't
+            // read at all, just as with git
+            set_mtime("Hello, World!\n", 1_600_000_000);
+            assert_eq!(vec![] as Vec<FileStatus>, repo.status()?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_diff_index_shows_unstaged_changes() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            fs::write(dir.path().join("hello.txt"), "Hello, world!\n")
+                .map_err(ReadObjectErrorType::IO)?;
+            repo.add(&[PathBuf::from("hello.txt")])?;
+            fs::write(dir.path().join("hello.txt"), "Hello, World!\n")
+                .map_err(ReadObjectErrorType::IO)?;
+
+            let mut unstaged = Vec::new();
+            repo.diff_index(false, &mut unstaged)?;
+            let mut staged = Vec::new();
+            repo.diff_index(true, &mut staged)?;
+
+            assert_eq!(
+                "diff --git a/hello.txt b/hello.txt\n\
+                 --- a/hello.txt\n\
+                 +++ b/hello.txt\n\
+                 @@ -1 +1 @@\n\
+                 -Hello, world!\n\
+                 +Hello, World!\n",
+                String::from_utf8_lossy(&unstaged)
+            );
+            assert!(String::from_utf8_lossy(&staged).contains("+Hello, world!\n"));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_diff_index_staged_compares_head_with_index() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let head = commit_files(&repo, &[], &[("hello.txt", "Hello, world!\n")], "Initial\n");
+            set_head(&repo, &head);
+            repo.checkout_worktree(&repo.commit_tree_sha(&head)?, false)?;
+            fs::write(dir.path().join("hello.txt"), "Hello, World!\n")
+                .map_err(ReadObjectErrorType::IO)?;
+            repo.add(&[PathBuf::from("hello.txt")])?;
+
+            let mut staged = Vec::new();
+            repo.diff_index(true, &mut staged)?;
+            let mut unstaged = Vec::new();
+            repo.diff_index(false, &mut unstaged)?;
+
+            assert_eq!(
+                "diff --git a/hello.txt b/hello.txt\n\
+                 --- a/hello.txt\n\
+                 +++ b/hello.txt\n\
+                 @@ -1 +1 @@\n\
+                 -Hello, world!\n\
+                 +Hello, World!\n",
+                String::from_utf8_lossy(&staged)
+            );
+            assert_eq!("", String::from_utf8_lossy(&unstaged));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_autocrlf_round_trips_crlf_file() -> Result<(), ReadObjectErrorType> {
+            let (dir, mut repo) = create_repo();
+            repo.config.setstr("core", "autocrlf", Some("true"));
+            let path = dir.path().join("hello.txt");
+            fs::write(&path, b"Hello,\r\nworld!\r\n").map_err(ReadObjectErrorType::IO)?;
+
+            repo.add(&[PathBuf::from("hello.txt")])?;
+
+            let sha = repo.index()?.entries[0].sha.clone();
+            match repo.read_object_typed(sha)? {
+                ParsedObject::Blob(blob) => assert_eq!(b"Hello,\nworld!\n", blob.bytes()),
+                _ => panic!("hello.txt should be a blob"),
+            }
+            // Staged, but unmodified since
+            assert_eq!(
+                vec!["A  hello.txt".to_string()],
+                repo.status()?
+                    .iter()
+                    .map(|status| status.to_string())
+                    .collect::<Vec<String>>()
+            );
+
+            let tree = repo.write_tree(&repo.index()?, None)?;
+            fs::remove_file(&path).map_err(ReadObjectErrorType::IO)?;
+            repo.checkout_worktree(&tree, false)?;
+
+            assert_eq!(
+                b"Hello,\r\nworld!\r\n".to_vec(),
+                fs::read(&path).map_err(ReadObjectErrorType::IO)?
+            );
+            assert_eq!(
+                vec!["A  hello.txt".to_string()],
+                repo.status()?
+                    .iter()
+                    .map(|status| status.to_string())
+                    .collect::<Vec<String>>()
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_add_stages_directory_of_many_files() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let mut expected = BTreeMap::new();
+            for i in 0..100 {
+                let path = format!("src/{}/file-{}.txt", i % 7, i);
+                let content = format!("File number {}\n", i);
+                create_dir_all(dir.path().join(&path).parent().unwrap())
+                    .map_err(ReadObjectErrorType::IO)?;
+                fs::write(dir.path().join(&path), &content).map_err(ReadObjectErrorType::IO)?;
+                expected.insert(path, content);
+            }
+            let stale = stage(&repo, dir.path(), "README", b"old\n");
+            repo.write_index(&Index {
+                entries: vec![stale],
+            })?;
+            fs::write(dir.path().join("README"), "new\n").map_err(ReadObjectErrorType::IO)?;
+            expected.insert("README".to_string(), "new\n".to_string());
+
+            repo.add(&[PathBuf::from("src"), PathBuf::from("README")])?;
+
+            let index = repo.index()?;
+            let paths: Vec<&String> = index.entries.iter().map(|entry| &entry.path).collect();
+            assert_eq!(expected.keys().collect::<Vec<_>>(), paths);
+            for entry in &index.entries {
+                let content = &expected[&entry.path];
+                assert_eq!(object_sha(ObjectKind::Blob, content.as_bytes()), entry.sha);
+                assert_eq!(content.len() as u32, entry.size);
+                assert_eq!(0o100644, entry.mode);
+                let GitObjectData(_, data) = repo.read_object(entry.sha.clone())?.get_data();
+                assert_eq!(content.as_bytes(), data);
+            }
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_branch_list_marks_current_branch() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            set_head(&repo, &commit);
+            repo.branch_create("feature/x", &commit, false)?;
+
+            let branches = repo.ref_list("refs/heads")?;
+
+            assert_eq!(
+                vec!["refs/heads/feature/x", "refs/heads/master"],
+                branches.keys().collect::<Vec<_>>()
+            );
+            assert_eq!(Some("master".to_string()), repo.current_branch()?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_head_commit_reads_commit_on_branch() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let readme = write_blob(&repo, b"hello\n");
+            let tree = write_tree(
+                &repo,
+                vec![Leaf("100644".to_string(), "README".to_string(), readme)],
+            );
+            let commit = write_commit_with_tree(&repo, &tree, &[], "Initial commit\n");
+            set_head(&repo, &commit);
+
+            let head = repo.head_commit()?.expect("HEAD should have a commit");
+
+            assert_eq!(Some(tree.as_str()), head.tree());
+            assert_eq!("Initial commit\n", head.message());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_head_commit_is_none_on_unborn_branch() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+
+            assert!(repo.head_commit()?.is_none());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_branch_create_from_head() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            set_head(&repo, &commit);
+
+            let start = repo.object_find("HEAD".to_string(), None, None)?;
+            repo.branch_create("topic", &start, false)?;
+
+            assert_eq!(Some(commit.clone()), repo.ref_resolve("refs/heads/topic")?);
+            assert_eq!(commit, repo.object_find("topic".to_string(), None, None)?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_branch_create_refuses_existing_without_force() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let first = write_commit(&repo, &[], "First\n");
+            let second = write_commit(&repo, &[&first], "Second\n");
+            repo.branch_create("topic", &first, false)?;
+
+            match repo.branch_create("topic", &second, false) {
+                Err(ReadObjectErrorType::BranchExistsError(name)) => assert_eq!("topic", name),
+                other => panic!("Expected BranchExistsError, got {:?}", other),
+            }
+            repo.branch_create("topic", &second, true)?;
+            assert_eq!(Some(second), repo.ref_resolve("refs/heads/topic")?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_branch_delete() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            repo.branch_create("topic", &commit, false)?;
+
+            repo.branch_delete("topic")?;
+
+            assert_eq!(None, repo.ref_resolve("refs/heads/topic")?);
+            assert!(matches!(
+                repo.branch_delete("topic"),
+                Err(ReadObjectErrorType::BranchNotFoundError(_))
+            ));
+
+            repo.branch_create("master", &commit, false)?;
+            assert!(matches!(
+                repo.branch_delete("master"),
+                Err(ReadObjectErrorType::BranchCheckedOutError(_, _))
+            ));
+            assert_eq!(Some(commit), repo.ref_resolve("refs/heads/master")?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_tag_delete_loose_and_packed() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            let annotated = write_tag(&repo, &commit, "v2.0");
+            repo.tag_create("v1.0", &commit)?;
+            fs::write(
+                repo.repo_path(&["packed-refs"]),
+                format!(
+                    "# pack-refs with: peeled fully-peeled sorted\n\
+                     {} refs/heads/master\n{} refs/tags/v2.0\n^{}\n",
+                    commit, annotated, commit
+                ),
+            )
+            .map_err(ReadObjectErrorType::IO)?;
+
+            assert_eq!(vec!["v1.0", "v2.0"], repo.tag_list(None)?);
+            assert_eq!(commit, repo.tag_delete("v1.0")?);
+            assert_eq!(None, repo.ref_resolve("refs/tags/v1.0")?);
+            assert_eq!(annotated, repo.tag_delete("v2.0")?);
+            assert_eq!(
+                format!(
+                    "# pack-refs with: peeled fully-peeled sorted\n{} refs/heads/master\n",
+                    commit
+                ),
+                fs::read_to_string(repo.repo_path(&["packed-refs"]))
+                    .map_err(ReadObjectErrorType::IO)?
+            );
+            assert!(matches!(
+                repo.tag_delete("v1.0"),
+                Err(ReadObjectErrorType::TagNotFoundError(name)) if name == "v1.0"
+            ));
+            assert!(repo.tag_list(None)?.is_empty());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_tag_list_filters_by_glob() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            for name in ["v1.0", "v1.1", "v2.0", "release"] {
+                repo.tag_create(name, &commit)?;
+            }
+
+            assert_eq!(
+                vec!["release", "v1.0", "v1.1", "v2.0"],
+                repo.tag_list(None)?
+            );
+            assert_eq!(vec!["v1.0", "v1.1"], repo.tag_list(Some("v1.*"))?);
+            assert_eq!(vec!["v1.0", "v2.0"], repo.tag_list(Some("v?.0"))?);
+            assert!(matches!(
+                repo.tag_create("v1.0", &commit),
+                Err(ReadObjectErrorType::TagExistsError(_))
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_switch_between_branches() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let shared = write_blob(&repo, b"shared\n");
+            let main_only = write_blob(&repo, b"main\n");
+            let topic_only = write_blob(&repo, b"topic\n");
+            let main_tree = write_tree(
+                &repo,
+                vec![
+                    Leaf("100644".to_string(), "main.txt".to_string(), main_only),
+                    Leaf(
+                        "100644".to_string(),
+                        "shared.txt".to_string(),
+                        shared.clone(),
+                    ),
+                ],
+            );
+            let lib_tree = write_tree(
+                &repo,
+                vec![Leaf(
+                    "100644".to_string(),
+                    "topic.txt".to_string(),
+                    topic_only,
+                )],
+            );
+            let topic_tree = write_tree(
+                &repo,
+                vec![
+                    Leaf("40000".to_string(), "lib".to_string(), lib_tree),
+                    Leaf("100644".to_string(), "shared.txt".to_string(), shared),
+                ],
+            );
+            let main = write_commit_with_tree(&repo, &main_tree, &[], "Main\n");
+            let topic = write_commit_with_tree(&repo, &topic_tree, &[&main], "Topic\n");
+            set_head(&repo, &main);
+            repo.branch_create("topic", &topic, false)?;
+            repo.switch("master")?;
+
+            repo.switch("topic")?;
+
+            let worktree = dir.path();
+            assert!(!worktree.join("main.txt").exists());
+            assert!(worktree.join("shared.txt").exists());
+            assert_eq!(
+                b"topic\n".to_vec(),
+                fs::read(worktree.join("lib").join("topic.txt"))
+                    .map_err(ReadObjectErrorType::IO)?
+            );
+            assert_eq!(
+                "ref: refs/heads/topic\n",
+                fs::read_to_string(worktree.join(".git").join("HEAD"))
+                    .map_err(ReadObjectErrorType::IO)?
+            );
+
+            repo.switch("master")?;
+
+            assert!(worktree.join("main.txt").exists());
+            assert!(!worktree.join("lib").exists());
+            assert_eq!(Some("master".to_string()), repo.current_branch()?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_switch_detaches_onto_commit() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let first = write_commit(&repo, &[], "First\n");
+            let second = write_commit(&repo, &[&first], "Second\n");
+            set_head(&repo, &second);
+
+            repo.switch(&first)?;
+
+            assert_eq!(
+                format!("{}\n", first),
+                fs::read_to_string(dir.path().join(".git").join("HEAD"))
+                    .map_err(ReadObjectErrorType::IO)?
+            );
+            assert_eq!(None, repo.current_branch()?);
+            assert_eq!(Some(first), repo.ref_resolve("HEAD")?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_switch_refuses_to_discard_local_changes() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let v1 = write_blob(&repo, b"v1\n");
+            let v2 = write_blob(&repo, b"v2\n");
+            let tree1 = write_tree(
+                &repo,
+                vec![Leaf("100644".to_string(), "file".to_string(), v1)],
+            );
+            let tree2 = write_tree(
+                &repo,
+                vec![Leaf("100644".to_string(), "file".to_string(), v2)],
+            );
+            let first = write_commit_with_tree(&repo, &tree1, &[], "First\n");
+            let second = write_commit_with_tree(&repo, &tree2, &[&first], "Second\n");
+            set_head(&repo, &first);
+            repo.branch_create("second", &second, false)?;
+            repo.switch("master")?;
+            fs::write(dir.path().join("file"), b"local\n").map_err(ReadObjectErrorType::IO)?;
+
+            assert!(matches!(
+                repo.switch("second"),
+                Err(ReadObjectErrorType::CheckoutConflictError(_))
+            ));
+            assert_eq!(Some("master".to_string()), repo.current_branch()?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_switch_with_autocrlf() -> Result<(), ReadObjectErrorType> {
+            let (dir, mut repo) = create_repo();
+            repo.config.setstr("core", "autocrlf", Some("true"));
+            let first = commit_files(&repo, &[], &[("file", "v1\n")], "First\n");
+            let second = commit_files(&repo, &[&first], &[("file", "v2\n")], "Second\n");
+            set_head(&repo, &first);
+            repo.branch_create("second", &second, false)?;
+            repo.switch("master")?;
+
+            // The CRLF checked out for v1 doesn't count as a local change
+            repo.switch("second")?;
+
+            assert_eq!(
+                b"v2\r\n".to_vec(),
+                fs::read(dir.path().join("file")).map_err(ReadObjectErrorType::IO)?
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_ref_write_round_trips_branch() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+
+            repo.ref_write("refs/heads/nested/branch", &commit)?;
+
+            let path = dir.path().join(".git/refs/heads/nested/branch");
+            assert_eq!(
+                format!("{}\n", commit),
+                fs::read_to_string(&path).map_err(ReadObjectErrorType::IO)?
+            );
+            assert!(!dir
+                .path()
+                .join(".git/refs/heads/nested/branch.lock")
+                .exists());
+            assert_eq!(Some(commit), repo.ref_resolve("refs/heads/nested/branch")?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_update_head_symbolic_and_detached() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            repo.ref_write("refs/heads/topic", &commit)?;
+
+            repo.update_head(&Head::Symbolic("refs/heads/topic".to_string()))?;
+            assert_eq!(Some("topic".to_string()), repo.current_branch()?);
+            assert_eq!(Some(commit.clone()), repo.ref_resolve("HEAD")?);
+
+            repo.update_head(&Head::Detached(commit.clone()))?;
+            assert_eq!(None, repo.current_branch()?);
+            assert_eq!(Some(commit), repo.ref_resolve("HEAD")?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_symbolic_ref_reads_head() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+
+            // HEAD is symbolic even before the branch it names exists
+            assert_eq!("refs/heads/master", repo.symbolic_ref("HEAD")?);
+
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            repo.update_head(&Head::Detached(commit))?;
+            assert!(matches!(
+                repo.symbolic_ref("HEAD"),
+                Err(ReadObjectErrorType::NotASymbolicRefError(_))
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_abbrev_ref_names_branch_or_detached_head() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            repo.ref_write("refs/heads/topic", &commit)?;
+            repo.ref_write("refs/tags/v1.0", &commit)?;
+
+            repo.update_head(&Head::Symbolic("refs/heads/topic".to_string()))?;
+            assert_eq!("topic", repo.abbrev_ref("HEAD")?);
+
+            repo.update_head(&Head::Detached(commit))?;
+            assert_eq!("HEAD", repo.abbrev_ref("HEAD")?);
+
+            assert_eq!("topic", repo.abbrev_ref("refs/heads/topic")?);
+            assert_eq!("v1.0", repo.abbrev_ref("v1.0")?);
+            assert!(matches!(
+                repo.abbrev_ref("missing"),
+                Err(ReadObjectErrorType::ObjectNotFoundError(_))
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_ref_write_refuses_a_locked_ref() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            let lock = repo.git_dir.join("refs/heads/master.lock");
+            fs::write(&lock, b"someone else's\n").map_err(ReadObjectErrorType::IO)?;
+
+            assert!(matches!(
+                repo.ref_write("refs/heads/master", &commit),
+                Err(ReadObjectErrorType::LockExistsError(path)) if path == lock
+            ));
+            // The other writer's lock is left alone
+            assert_eq!(
+                b"someone else's\n".to_vec(),
+                fs::read(&lock).map_err(ReadObjectErrorType::IO)?
+            );
+            assert_eq!(None, repo.ref_resolve("refs/heads/master")?);
+
+            fs::remove_file(&lock).map_err(ReadObjectErrorType::IO)?;
+            repo.ref_write("refs/heads/master", &commit)?;
+            assert_eq!(Some(commit.clone()), repo.ref_resolve("refs/heads/master")?);
+            assert!(!lock.exists());
+
+            // A lock which can't be renamed into place isn't left behind
+            assert!(repo.ref_write("refs/heads", &commit).is_err());
+            assert!(!repo.git_dir.join("refs/heads.lock").exists());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_check_ref_name() {
+            for name in ["HEAD", "refs/heads/master", "refs/tags/v1.0", "topic/a-b_c"] {
+                assert!(check_ref_name(name).is_ok(), "{}", name);
+            }
+            for name in [
+                "",
+                "../../config",
+                "refs/heads/../../x",
+                "/refs/heads/x",
+                "refs//heads",
+                "refs/heads/",
+                "-topic",
+                "refs/heads/.hidden",
+                "refs/heads/x.lock",
+                "refs/heads/x.",
+                "x@{1}",
+                "@",
+                "a b",
+                "a\tb",
+                "a~1",
+                "a^",
+                "a:b",
+                "a?",
+                "a*",
+                "a[",
+                "a\\b",
+            ] {
+                assert!(
+                    matches!(
+                        check_ref_name(name),
+                        Err(ReadObjectErrorType::InvalidRefError(_))
+                    ),
+                    "{:?}",
+                    name
+                );
+            }
+        }
+
+        #[test]
+        fn test_refs_cannot_be_written_outside_refs() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            let config = fs::read(repo.git_dir.join("config")).map_err(ReadObjectErrorType::IO)?;
+
+            assert!(matches!(
+                repo.ref_update("../../../escaped", &commit, "update", false),
+                Err(ReadObjectErrorType::InvalidRefError(_))
+            ));
+            assert!(matches!(
+                repo.branch_create("../../config", &commit, true),
+                Err(ReadObjectErrorType::InvalidRefError(_))
+            ));
+            assert!(matches!(
+                repo.symbolic_ref_set("../HEAD", "refs/heads/master"),
+                Err(ReadObjectErrorType::InvalidRefError(_))
+            ));
+            assert!(matches!(
+                repo.object_find("../../config".to_string(), None, None),
+                Err(ReadObjectErrorType::ObjectNotFoundError(_))
+            ));
+
+            assert!(!dir.path().parent().unwrap().join("escaped").exists());
+            assert_eq!(
+                config,
+                fs::read(repo.git_dir.join("config")).map_err(ReadObjectErrorType::IO)?
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_symbolic_ref_cycles_are_an_error() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            repo.symbolic_ref_set("refs/heads/a", "refs/heads/b")?;
+            repo.symbolic_ref_set("refs/heads/b", "refs/heads/a")?;
+
+            assert!(matches!(
+                repo.ref_resolve("refs/heads/a"),
+                Err(ReadObjectErrorType::SymbolicRefDepthError(_))
+            ));
+            assert!(matches!(
+                repo.ref_update("refs/heads/a", &commit, "update", false),
+                Err(ReadObjectErrorType::SymbolicRefDepthError(_))
+            ));
+
+            // A chain as long as git allows still resolves
+            repo.ref_write("refs/heads/c0", &commit)?;
+            for n in 1..=MAX_SYMREF_DEPTH {
+                let target = format!("refs/heads/c{}", n - 1);
+                repo.symbolic_ref_set(&format!("refs/heads/c{}", n), &target)?;
+            }
+            let last = format!("refs/heads/c{}", MAX_SYMREF_DEPTH);
+            assert_eq!(Some(commit.clone()), repo.ref_resolve(&last)?);
+            repo.ref_update(&last, &commit, "update", false)?;
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_symbolic_ref_sets_head() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+
+            repo.symbolic_ref_set("HEAD", "refs/heads/foo")?;
+
+            assert_eq!("refs/heads/foo", repo.symbolic_ref("HEAD")?);
+            assert_eq!(Some("foo".to_string()), repo.current_branch()?);
+            assert!(matches!(
+                repo.symbolic_ref_set("HEAD", "foo"),
+                Err(ReadObjectErrorType::InvalidRefError(_))
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_walk_history_visits_merge_ancestry_once() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit(&repo, &[], "Root\n");
+            let left = write_commit(&repo, &[&root], "Left\n");
+            let right = write_commit(&repo, &[&root], "Right\n");
+            let merge = write_commit(&repo, &[&left, &right], "Merge\n");
+            let unrelated = write_commit(&repo, &[], "Unrelated\n");
+
+            let walked = repo
+                .walk_history(std::slice::from_ref(&merge), HashSet::new())
+                .collect::<Result<Vec<String>, ReadObjectErrorType>>()?;
+
+            assert_eq!(4, walked.len(), "{:?}", walked);
+            assert_eq!(merge, walked[0]);
+            assert_eq!(root, walked[3]);
+            for sha in [&left, &right] {
+                assert_eq!(1, walked.iter().filter(|w| w == &sha).count());
+            }
+            assert!(!walked.contains(&unrelated));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_merge_bases_of_fork() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit(&repo, &[], "Root\n");
+            let base = write_commit(&repo, &[&root], "Base\n");
+            let left = write_commit(&repo, &[&base], "Left\n");
+            let left = write_commit(&repo, &[&left], "Left again\n");
+            let right = write_commit(&repo, &[&base], "Right\n");
+
+            assert_eq!(vec![base.clone()], repo.merge_bases(&left, &right)?);
+            assert_eq!(vec![base], repo.merge_bases(&right, &left)?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_merge_bases_of_linear_history() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit(&repo, &[], "Root\n");
+            let middle = write_commit(&repo, &[&root], "Middle\n");
+            let tip = write_commit(&repo, &[&middle], "Tip\n");
+
+            assert_eq!(vec![middle.clone()], repo.merge_bases(&tip, &middle)?);
+            assert_eq!(vec![middle.clone()], repo.merge_bases(&middle, &tip)?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_merge_bases_of_root_commit() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit(&repo, &[], "Root\n");
+            let left = write_commit(&repo, &[&root], "Left\n");
+            let right = write_commit(&repo, &[&root], "Right\n");
+            let unrelated = write_commit(&repo, &[], "Unrelated\n");
+
+            assert_eq!(vec![root], repo.merge_bases(&left, &right)?);
+            assert!(repo.merge_bases(&left, &unrelated)?.is_empty());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_merge_bases_of_criss_cross() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit(&repo, &[], "Root\n");
+            let left = write_commit(&repo, &[&root], "Left\n");
+            let right = write_commit(&repo, &[&root], "Right\n");
+            let left_merge = write_commit(&repo, &[&left, &right], "Merge right\n");
+            let right_merge = write_commit(&repo, &[&right, &left], "Merge left\n");
+
+            let mut bases = repo.merge_bases(&left_merge, &right_merge)?;
+            bases.sort();
+            let mut expected = vec![left, right];
+            expected.sort();
+            assert_eq!(expected, bases);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_show_commit_with_patch() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let old = write_blob(&repo, b"one\n");
+            let new = write_blob(&repo, b"one\ntwo\n");
+            let leaf =
+                |sha: &str| Leaf("100644".to_string(), "numbers".to_string(), sha.to_string());
+            let old_tree = write_tree(&repo, vec![leaf(&old)]);
+            let new_tree = write_tree(&repo, vec![leaf(&new)]);
+            let parent = write_commit_with_tree(&repo, &old_tree, &[], "One\n");
+            let commit =
+                write_commit_with_tree(&repo, &new_tree, &[&parent], "Two\n\nAdd a line\n");
+
+            let mut out = Vec::new();
+            repo.show(&commit, true, false, &mut out)?;
+
+            assert_eq!(
+                format!(
+                    "commit {}\ntree {}\nparent {}\n\n    Two\n    \n    Add a line\n\n\
+                     diff --git a/numbers b/numbers\n--- a/numbers\n+++ b/numbers\n\
+                     @@ -1 +1,2 @@\n one\n+two\n",
+                    commit, new_tree, parent
+                ),
+                String::from_utf8(out).unwrap()
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_binary_blobs_summarised_on_terminals() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let binary = write_blob(&repo, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+            let text = write_blob(&repo, b"hello\n");
+            let pretty = |sha: &str, terminal: bool| -> Result<Vec<u8>, ReadObjectErrorType> {
+                let mut out = Vec::new();
+                repo.cat_pretty(sha, terminal, &mut out)?;
+                return Ok(out);
+            };
+
+            assert_eq!(b"Binary file (16 bytes)\n".to_vec(), pretty(&binary, true)?);
+            assert_eq!(
+                b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec(),
+                pretty(&binary, false)?
+            );
+            assert_eq!(b"hello\n".to_vec(), pretty(&text, true)?);
+
+            let mut out = Vec::new();
+            repo.show(&binary, false, true, &mut out)?;
+            assert_eq!(b"Binary file (16 bytes)\n".to_vec(), out);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_show_tag_then_tagged_commit() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Release\n");
+            let tag = write_tag(&repo, &commit, "v1.0");
+
+            let mut out = Vec::new();
+            repo.show(&tag, false, false, &mut out)?;
+
+            let out = String::from_utf8(out).unwrap();
+            assert!(
+                out.starts_with(&format!(
+                    "tag {}\nobject {}\ntype commit\ntag v1.0\n",
+                    tag, commit
+                )),
+                "{}",
+                out
+            );
+            assert!(out.contains("\n\nv1.0\n\n"), "{}", out);
+            assert!(out.contains(&format!("\n\ncommit {}\n", commit)), "{}", out);
+            assert!(out.ends_with("    Release\n"), "{}", out);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_object_find_resolves_tree_paths() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let blob = write_blob(&repo, b"fn main() {}\n");
+            let src = write_tree(
+                &repo,
+                vec![Leaf(
+                    "100644".to_string(),
+                    "main.rs".to_string(),
+                    blob.clone(),
+                )],
+            );
+            let root = write_tree(
+                &repo,
+                vec![Leaf("40000".to_string(), "src".to_string(), src.clone())],
+            );
+            let commit = write_commit_with_tree(&repo, &root, &[], "Initial\n");
+            set_head(&repo, &commit);
+
+            assert_eq!(
+                blob,
+                repo.object_find("HEAD:src/main.rs".to_string(), None, None)?
+            );
+            assert_eq!(src, repo.object_find(format!("{}:src", root), None, None)?);
+            assert_eq!(root, repo.object_find("HEAD:".to_string(), None, None)?);
+            assert!(matches!(
+                repo.object_find("HEAD:src/missing.rs".to_string(), None, None),
+                Err(ReadObjectErrorType::ObjectNotFoundError(_))
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_cat_file_by_rev_and_path() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = commit_files(&repo, &[], &[("README", "committed\n")], "Initial\n");
+            set_head(&repo, &commit);
+            let mut index = repo.read_tree(&repo.commit_tree_sha(&commit)?)?;
+            index.entries[0].sha = write_blob(&repo, b"staged\n");
+            repo.write_index(&index)?;
+            let cat = |name: &str| -> Result<String, ReadObjectErrorType> {
+                let sha = repo.object_find(name.to_string(), None, None)?;
+                let mut out = Vec::new();
+                repo.cat_pretty(&sha, false, &mut out)?;
+                return Ok(String::from_utf8(out).unwrap());
+            };
+
+            assert_eq!("committed\n", cat("HEAD:README")?);
+            assert_eq!("staged\n", cat(":README")?);
+            assert_eq!(None, repo.resolve_path("", "missing")?);
+            assert!(matches!(
+                cat("HEAD:missing"),
+                Err(ReadObjectErrorType::ObjectNotFoundError(name)) if name == "HEAD:missing"
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_worktree_relative_from_nested_directory() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let nested = dir.path().join("src").join("bin");
+            create_dir_all(&nested).unwrap();
+
+            assert_eq!(
+                PathBuf::from("src/bin/main.rs"),
+                repo.worktree_relative_to(Path::new("main.rs"), &nested)?
+            );
+            assert_eq!(
+                PathBuf::from("src/lib.rs"),
+                repo.worktree_relative_to(Path::new("../lib.rs"), &nested)?
+            );
+            assert_eq!(
+                PathBuf::from("README"),
+                repo.worktree_relative_to(&dir.path().join("README"), &nested)?
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_worktree_relative_rejects_outside_paths() {
+            let (dir, repo) = create_repo();
+            let outside = tempfile::tempdir().unwrap();
+
+            assert!(matches!(
+                repo.worktree_relative_to(&outside.path().join("file"), dir.path()),
+                Err(ReadObjectErrorType::PathOutsideRepositoryError(_))
+            ));
+            assert!(matches!(
+                repo.worktree_relative_to(Path::new("../elsewhere"), dir.path()),
+                Err(ReadObjectErrorType::PathOutsideRepositoryError(_))
+            ));
+            assert!(matches!(
+                repo.worktree_relative_to(Path::new(".git/config"), dir.path()),
+                Err(ReadObjectErrorType::PathOutsideRepositoryError(_))
+            ));
+        }
+
+        #[test]
+        fn test_reflog_reads_newest_first() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            create_dir_all(repo.repo_path(&["logs"])).unwrap();
+            fs::copy(
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/reflog-HEAD"),
+                repo.repo_path(&["logs", "HEAD"]),
+            )
+            .unwrap();
+
+            let reflog = repo.reflog("HEAD")?;
+
+            assert_eq!(3, reflog.len());
+            assert_eq!(
+                ReflogEntry {
+                    old: "15d5816c70fa28446d886015d58d8ecc70e287c5".to_string(),
+                    new: "2d6ac6de4ae88c4f6bffac16c4b3fd3a2af0a1bb".to_string(),
+                    who: "A U Thor <author@example.com>".to_string(),
+                    timestamp: 1665000120,
+                    timezone: "+0100".to_string(),
+                    message: "checkout: moving from master to 2d6ac6de".to_string(),
+                },
+                reflog[0]
+            );
+            assert_eq!("0".repeat(40), reflog[2].old);
+            assert_eq!("commit (initial): Add hello.txt", reflog[2].message);
+            assert!(repo.reflog("refs/heads/master")?.is_empty());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_object_find_reads_reflog_selectors() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            create_dir_all(repo.repo_path(&["logs"])).unwrap();
+            fs::copy(
+                Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/reflog-HEAD"),
+                repo.repo_path(&["logs", "HEAD"]),
+            )
+            .unwrap();
+            let find = |name: &str| repo.object_find(name.to_string(), None, None);
+
+            assert_eq!(
+                "2d6ac6de4ae88c4f6bffac16c4b3fd3a2af0a1bb",
+                find("HEAD@{0}")?
+            );
+            assert_eq!(
+                "15d5816c70fa28446d886015d58d8ecc70e287c5",
+                find("HEAD@{1}")?
+            );
+            assert!(matches!(
+                find("HEAD@{3}"),
+                Err(ReadObjectErrorType::ReflogOutOfRangeError(reference, 3)) if reference == "HEAD"
+            ));
+
+            set_head(&repo, &write_commit(&repo, &[], "Initial commit\n"));
+            assert!(matches!(
+                find("master@{0}"),
+                Err(ReadObjectErrorType::MissingReflogError(reference))
+                    if reference == "refs/heads/master"
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_object_find_walks_first_parents_with_tilde() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit(&repo, &[], "Root commit\n");
+            let middle = write_commit(&repo, &[&root], "Middle commit\n");
+            let tip = write_commit(&repo, &[&middle], "Tip commit\n");
+            set_head(&repo, &tip);
+            let find = |name: &str| repo.object_find(name.to_string(), None, None);
+
+            assert_eq!(root, find("HEAD~2")?);
+            assert_eq!(middle, find("HEAD~")?);
+            assert_eq!(tip, find("HEAD~0")?);
+            assert_eq!(root, find(&format!("{}~1", middle))?);
+            assert_eq!(root, find("HEAD~1~1")?);
+            assert!(matches!(
+                find("HEAD~3"),
+                Err(ReadObjectErrorType::ObjectNotFoundError(name)) if name == "HEAD~3"
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_object_find_selects_parents_with_caret() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit(&repo, &[], "Root commit\n");
+            let topic = write_commit(&repo, &[&root], "Topic commit\n");
+            let main = write_commit(&repo, &[&root], "Main commit\n");
+            let merge = write_commit(&repo, &[&main, &topic], "Merge branch 'topic'\n");
+            let find = |name: &str| repo.object_find(name.to_string(), None, None);
+
+            assert_eq!(main, find(&format!("{}^", merge))?);
+            assert_eq!(topic, find(&format!("{}^2", merge))?);
+            assert_eq!(merge, find(&format!("{}^0", merge))?);
+            assert_eq!(root, find(&format!("{}^2^", merge))?);
+            assert!(matches!(
+                find(&format!("{}^3", merge)),
+                Err(ReadObjectErrorType::ObjectNotFoundError(_))
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_show_branch_marks_which_branches_contain_commits() -> Result<(), ReadObjectErrorType>
+        {
+            let (_dir, repo) = create_repo();
+            let base = write_commit_at(&repo, &[], "Base\n", 1000);
+            let main = write_commit_at(&repo, &[&base], "Main\n", 2000);
+            let topic_first = write_commit_at(&repo, &[&base], "Topic one\n", 3000);
+            let topic = write_commit_at(&repo, &[&topic_first], "Topic two\n", 4000);
+            set_head(&repo, &main);
+            repo.branch_create("topic", &topic, false)?;
+
+            let mut out = Vec::new();
+            repo.show_branch(&["master".to_string(), "topic".to_string()], &mut out)?;
+
+            assert_eq!(
+                "* [master] Main\n\
+                 \x20! [topic] Topic two\n\
+                 --\n\
+                 \x20+ [topic] Topic two\n\
+                 \x20+ [topic^] Topic one\n\
+                 *  [master] Main\n\
+                 *+ [topic~2] Base\n",
+                String::from_utf8(out).unwrap()
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_ref_update_appends_to_reflogs() -> Result<(), ReadObjectErrorType> {
+            let (_dir, mut repo) = create_repo();
+            repo.config.setstr("user", "name", Some("Config User"));
+            repo.config
+                .setstr("user", "email", Some("config@example.com"));
+            let first = write_blob(&repo, b"first");
+            let second = write_blob(&repo, b"second");
+
+            // Committing on the current branch moves both it and HEAD
+            repo.ref_update("HEAD", &first, "commit (initial): First", false)?;
+            repo.ref_update("refs/heads/master", &second, "commit: Second", false)?;
+
+            let head = repo.reflog("HEAD")?;
+            assert_eq!(2, head.len());
+            assert_eq!(
+                (second.as_str(), first.as_str(), "commit: Second"),
+                (
+                    head[0].new.as_str(),
+                    head[0].old.as_str(),
+                    head[0].message.as_str()
+                )
+            );
+            assert_eq!("0".repeat(40), head[1].old);
+            assert_eq!(head, repo.reflog("refs/heads/master")?);
+
+            // Tags are only logged when asked
+            repo.ref_update("refs/tags/v1.0", &first, "", false)?;
+            assert!(repo.reflog("refs/tags/v1.0")?.is_empty());
+            repo.ref_update("refs/tags/v2.0", &second, "release", true)?;
+            assert_eq!("release", repo.reflog("refs/tags/v2.0")?[0].message);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_reflogs_keep_one_line_per_entry_and_go_with_their_ref(
+        ) -> Result<(), ReadObjectErrorType> {
+            let (_dir, mut repo) = create_repo();
+            repo.config.setstr("user", "name", Some("Config User"));
+            repo.config
+                .setstr("user", "email", Some("config@example.com"));
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+
+            let message = "commit: Subject\n\n  Body line\r\n\tmore\n";
+            repo.ref_update("refs/heads/topic", &commit, message, false)?;
+            repo.ref_update("refs/tags/v1.0", &commit, "tag\nv1.0", true)?;
+            let reflog = repo.reflog("refs/heads/topic")?;
+            assert_eq!(1, reflog.len());
+            assert_eq!("commit: Subject Body line more", reflog[0].message);
+            assert_eq!("tag v1.0", repo.reflog("refs/tags/v1.0")?[0].message);
+
+            repo.branch_delete("topic")?;
+            repo.tag_delete("v1.0")?;
+            assert!(!repo.git_dir.join("logs/refs/heads/topic").exists());
+            assert!(!repo.git_dir.join("logs/refs/tags/v1.0").exists());
+
+            // A branch made again with the same name starts a new reflog
+            repo.ref_update("refs/heads/topic", &commit, "branch: Created", false)?;
+            assert_eq!(1, repo.reflog("refs/heads/topic")?.len());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_abbreviate_is_unambiguous() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let sha = write_blob(&repo, b"Hello, world!\n");
+
+            assert_eq!(&sha[0..7], repo.abbreviate(&sha, 7)?);
+            assert_eq!(&sha[0..4], repo.abbreviate(&sha, 0)?);
+
+            // Only the name of an object matters, so a neighbour sharing
+            // the first nine digits can be made without finding one
+            let flipped = if &sha[9..10] == "0" { "1" } else { "0" };
+            let neighbour = format!("{}{}{}", &sha[2..9], flipped, &sha[10..]);
+            File::create(repo.loose_object_path(&sha).with_file_name(neighbour)).unwrap();
+
+            let short = repo.abbreviate(&sha, 7)?;
+            assert_eq!(&sha[0..10], short);
+            assert_eq!(vec![sha.clone()], repo.objects_with_prefix(&short)?);
+
+            // A longer abbreviation can still be asked for
+            assert_eq!(&sha[0..12], repo.abbreviate(&sha, 12)?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_verify_revision_needs_exactly_one_object() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            set_head(&repo, &commit);
+
+            assert_eq!(commit, repo.verify_revision(&commit)?);
+            assert_eq!(commit, repo.verify_revision("master")?);
+
+            // A second object sharing the first seven digits
+            let flipped = if &commit[7..8] == "0" { "1" } else { "0" };
+            let neighbour = format!("{}{}{}", &commit[2..7], flipped, &commit[8..]);
+            File::create(repo.loose_object_path(&commit).with_file_name(neighbour)).unwrap();
+            assert!(matches!(
+                repo.verify_revision(&commit[0..7]),
+                Err(ReadObjectErrorType::AmbiguousObjectError(name, candidates))
+                    if name == commit[0..7] && candidates.len() == 2
+            ));
+
+            assert!(matches!(
+                repo.verify_revision("refs/heads/missing"),
+                Err(ReadObjectErrorType::NotARevisionError(name)) if name == "refs/heads/missing"
+            ));
+            let absent = "0".repeat(40);
+            assert!(matches!(
+                repo.verify_revision(&absent),
+                Err(ReadObjectErrorType::NotARevisionError(_))
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_object_find_peels_tags() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let tree = write_tree(&repo, vec![]);
+            let commit = write_commit_with_tree(&repo, &tree, &[], "Release\n");
+            let tag = write_tag(&repo, &commit, "v1.0");
+            let find = |fmt: Option<ObjectKind>, follow: Option<bool>| {
+                repo.object_find("v1.0".to_string(), fmt, follow)
+            };
+
+            assert_eq!(tag, find(None, None)?);
+            assert_eq!(tag, find(Some(ObjectKind::Tag), None)?);
+            assert_eq!(commit, find(Some(ObjectKind::Commit), None)?);
+            assert_eq!(tree, find(Some(ObjectKind::Tree), None)?);
+            assert!(matches!(
+                find(Some(ObjectKind::Commit), Some(false)),
+                Err(ReadObjectErrorType::UnexpectedObjectTypeError(_, _))
+            ));
+            assert!(matches!(
+                find(Some(ObjectKind::Blob), None),
+                Err(ReadObjectErrorType::UnexpectedObjectTypeError(_, _))
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_switch_to_annotated_tag_detaches_at_commit() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let blob = write_blob(&repo, b"Hello, world!\n");
+            let tree = write_tree(
+                &repo,
+                vec![Leaf("100644".to_string(), "hello.txt".to_string(), blob)],
+            );
+            let commit = write_commit_with_tree(&repo, &tree, &[], "Release\n");
+            write_tag(&repo, &commit, "v1.0");
+
+            repo.switch("v1.0")?;
+
+            assert_eq!(Some(Head::Detached(commit)), repo.ref_read("HEAD")?);
+            assert_eq!(
+                "Hello, world!\n",
+                fs::read_to_string(dir.path().join("hello.txt")).unwrap()
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_describe_counts_commits_since_tag() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit(&repo, &[], "Root\n");
+            let released = write_commit(&repo, &[&root], "Release\n");
+            write_tag(&repo, &released, "v1.0");
+            repo.ref_write("refs/tags/light", &root)?;
+            let fix = write_commit(&repo, &[&released], "Fix\n");
+            let tip = write_commit(&repo, &[&fix], "Feature\n");
+
+            assert_eq!(
+                format!("v1.0-2-g{}", &tip[0..7]),
+                repo.describe(&tip, false)?
+            );
+            assert_eq!("v1.0", repo.describe(&released, false)?);
+            assert!(matches!(
+                repo.describe(&root, false),
+                Err(ReadObjectErrorType::NoTagsError(_))
+            ));
+            assert_eq!("light", repo.describe(&root, true)?);
+
+            // An annotated tag wins over a lightweight one on the same
+            // commit, whichever sorts first
+            repo.ref_write("refs/tags/a-light", &released)?;
+            assert_eq!(
+                format!("v1.0-2-g{}", &tip[0..7]),
+                repo.describe(&tip, true)?
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_write_commit_records_fields_in_git_order() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let tree = write_tree(&repo, vec![]);
+            let author = Identity::parse("A U Thor <author@example.com> 1600000000 +0000").unwrap();
+            let committer = Identity {
+                name: "C O Mitter".to_string(),
+                email: "committer@example.com".to_string(),
+                timestamp: 1_600_000_100,
+                timezone: "+0100".to_string(),
+            };
+
+            let sha = repo.write_commit(&tree, &[], &author, &committer, "Initial commit\n")?;
+
+            // As git commit-tree writes it, given the same identities
+            assert_eq!("d0223815204805a4b86fd153a73bedbeb14ae7d6", sha);
+            let commit = repo.read_commit(&sha)?;
+            assert_eq!(Some(tree.as_str()), commit.tree());
+            assert!(commit.parents().is_empty());
+            assert_eq!("A U Thor <author@example.com>", commit.author());
+            assert_eq!("C O Mitter <committer@example.com>", commit.committer());
+            assert_eq!(1_600_000_100, commit.commit_time());
+            assert_eq!("Initial commit\n", commit.message());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_identity_prefers_environment_over_config() -> Result<(), ReadObjectErrorType> {
+            let (_dir, mut repo) = create_repo();
+            repo.config.setstr("user", "name", Some("Config User"));
+            repo.config
+                .setstr("user", "email", Some("config@example.com"));
+            let env = BTreeMap::from([
+                ("GIT_AUTHOR_NAME", "A U Thor"),
+                ("GIT_AUTHOR_DATE", "1665000000 +0100"),
+            ]);
+            let env = |name: &str| env.get(name).map(|value| value.to_string());
+
+            let author = repo.identity_from(IdentityRole::Author, env)?;
+            assert_eq!(
+                "A U Thor <config@example.com> 1665000000 +0100",
+                author.to_string()
+            );
+
+            // The committer is unaffected by the author's variables
+            let committer = repo.identity_from(IdentityRole::Committer, env)?;
+            assert_eq!("Config User", committer.name);
+            assert_eq!("+0000", committer.timezone);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_identity_falls_back_to_global_config() -> Result<(), ReadObjectErrorType> {
+            let (_dir, mut repo) = create_repo();
+            let no_env = |_: &str| None;
+            let mut global = Ini::new();
+            global.setstr("user", "name", Some("Global User"));
+            global.setstr("user", "email", Some("global@example.com"));
+
+            assert!(matches!(
+                repo.identity_from(IdentityRole::Author, no_env),
+                Err(ReadObjectErrorType::NoIdentityError(IdentityRole::Author))
+            ));
+
+            repo.fallback_config = vec![global];
+            let identity = repo.identity_from(IdentityRole::Author, no_env)?;
+            assert_eq!("Global User", identity.name);
+            assert_eq!("global@example.com", identity.email);
+
+            repo.config.setstr("user", "name", Some("Config User"));
+            let identity = repo.identity_from(IdentityRole::Author, no_env)?;
+            assert_eq!("Config User", identity.name);
+            assert_eq!("global@example.com", identity.email);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_reflog_identity_falls_back_to_login() -> Result<(), ReadObjectErrorType> {
+            let (_dir, mut repo) = create_repo();
+            let env = BTreeMap::from([
+                ("LOGNAME", "someone"),
+                ("HOSTNAME", "example.com"),
+                ("GIT_COMMITTER_DATE", "1665000000 +0100"),
+            ]);
+            let env = |name: &str| env.get(name).map(|value| value.to_string());
+
+            assert_eq!(
+                "someone <someone@example.com> 1665000000 +0100",
+                repo.reflog_identity_from(env)?.to_string()
+            );
+
+            repo.config.setstr("user", "name", Some("Config User"));
+            assert_eq!(
+                "Config User <someone@example.com> 1665000000 +0100",
+                repo.reflog_identity_from(env)?.to_string()
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_config_falls_back_to_global_then_system() -> Result<(), ReadObjectErrorType> {
+            let (dir, mut repo) = create_repo();
+            let home = dir.path().join("home");
+            create_dir_all(&home).unwrap();
+            fs::write(
+                home.join(".gitconfig"),
+                "[user]\n\tname = Global User\n[core]\n\tcompression = 9\n",
+            )
+            .unwrap();
+            let system = dir.path().join("gitconfig");
+            fs::write(
+                &system,
+                "[user]\n\tname = System User\n\temail = system@example.com\n",
+            )
+            .unwrap();
+            let env = |name: &str| (name == "HOME").then(|| home.to_string_lossy().to_string());
+
+            repo.fallback_config = Repository::fallback_config_from(env, &system);
+            repo.config.setstr("core", "compression", Some("0"));
+
+            let identity = repo.identity_from(IdentityRole::Author, |_| None)?;
+            assert_eq!("Global User", identity.name);
+            assert_eq!("system@example.com", identity.email);
+            assert_eq!(
+                Some("0".to_string()),
+                repo.config_get("core", "compression")
+            );
+            assert_eq!(None, repo.config_get("init", "defaultBranch"));
+
+            // The system config can be turned off
+            let env = |name: &str| (name == "GIT_CONFIG_NOSYSTEM").then(|| "1".to_string());
+            repo.fallback_config = Repository::fallback_config_from(env, &system);
+            assert_eq!(None, repo.config_get("user", "email"));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_open_reports_why_a_repository_cannot_be_opened() {
+            let dir = tempfile::tempdir().unwrap();
+            assert!(matches!(
+                Repository::open(dir.path()),
+                Err(ReadObjectErrorType::NotARepositoryError(path)) if path == dir.path()
+            ));
+
+            create_dir_all(dir.path().join(".git")).unwrap();
+            assert!(matches!(
+                Repository::open(dir.path()),
+                Err(ReadObjectErrorType::MissingConfigError(_))
+            ));
+
+            let write_config = |config: &str| {
+                fs::write(dir.path().join(".git/config"), config).unwrap();
+            };
+            write_config("[core]\n\trepositoryformatversion = 2\n");
+            assert!(matches!(
+                Repository::open(dir.path()),
+                Err(ReadObjectErrorType::UnsupportedVersionError(Some(version))) if version == "2"
+            ));
+
+            write_config(
+                "[core]\n\trepositoryformatversion = 1\n\
+                 [extensions]\n\tobjectFormat = md5\n",
+            );
+            assert!(matches!(
+                Repository::open(dir.path()),
+                Err(ReadObjectErrorType::UnknownObjectFormatError(format)) if format == "md5"
+            ));
+
+            write_config("[core]\n\trepositoryformatversion = 0\n");
+            assert!(Repository::open(dir.path()).is_ok());
+        }
+
+        #[test]
+        fn test_repo_create_twice_keeps_existing_repository() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            set_head(&repo, &commit);
+            fs::write(dir.path().join("README"), "hello\n").map_err(ReadObjectErrorType::IO)?;
+
+            let existing = Repository::repo_create(dir.path(), HashAlgo::Sha1)
+                .map_err(ReadObjectErrorType::IO)?;
+
+            assert!(existing);
+            assert_eq!(Some(commit.clone()), repo.ref_resolve("HEAD")?);
+            assert!(repo.has_object(&commit));
+            assert!(dir.path().join("README").exists());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_path_accessors_point_into_repository() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            set_head(&repo, &commit);
+            let git_dir = dir.path().join(".git");
+
+            assert_eq!(dir.path(), repo.worktree());
+            assert_eq!(git_dir, repo.git_dir());
+            assert_eq!(git_dir.join("objects"), repo.objects_dir());
+            let object = repo.loose_object_path(&commit);
+            assert!(object.starts_with(repo.objects_dir()) && object.is_file());
+            assert_eq!(git_dir.join("HEAD"), repo.ref_path("HEAD"));
+            assert_eq!(
+                format!("{}\n", commit),
+                fs::read_to_string(repo.ref_path("refs/heads/master"))
+                    .map_err(ReadObjectErrorType::IO)?
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_repo_find_recognises_bare_repository() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            set_head(&repo, &commit);
+
+            // A bare repository is just the contents of a .git directory
+            let bare_dir = tempfile::tempdir().unwrap();
+            let bare = bare_dir.path().join("repo.git");
+            fs::rename(dir.path().join(".git"), &bare).map_err(ReadObjectErrorType::IO)?;
+            create_dir_all(bare.join("refs/tags")).map_err(ReadObjectErrorType::IO)?;
+
+            let found =
+                Repository::repo_find(bare.join("refs/tags").to_string_lossy().to_string(), None)
+                    .map_err(ReadObjectErrorType::IO)?
+                    .unwrap();
+
+            let canonical = bare.canonicalize().map_err(ReadObjectErrorType::IO)?;
+            assert_eq!(canonical, found.worktree);
+            assert_eq!(canonical, found.git_dir);
+            assert_eq!(
+                commit,
+                found.object_find("HEAD".to_string(), Some(ObjectKind::Commit), None)?
+            );
+            assert_eq!(ObjectKind::Commit, found.read_object(commit)?.get_data().0);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_repo_find_uses_git_dir_and_work_tree_from_environment(
+        ) -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let blob = write_blob(&repo, b"Hello, world!\n");
+            let elsewhere = tempfile::tempdir().unwrap();
+            let git_dir = dir.path().join(".git").to_string_lossy().to_string();
+            let find = |vars: &[(&str, &str)]| {
+                let vars: BTreeMap<String, String> = vars
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect();
+                return Repository::repo_find_from(
+                    elsewhere.path().to_string_lossy().to_string(),
+                    Some(false),
+                    |name| vars.get(name).cloned(),
+                )
+                .unwrap();
+            };
+
+            assert!(find(&[]).is_none());
+
+            // Without a GIT_WORK_TREE, the current directory is the worktree
+            let found = find(&[("GIT_DIR", &git_dir)]).unwrap();
+            assert_eq!(
+                b"Hello, world!\n".to_vec(),
+                found.read_object(blob.clone())?.get_data().1
+            );
+            let elsewhere_path = elsewhere
+                .path()
+                .canonicalize()
+                .map_err(ReadObjectErrorType::IO)?;
+            assert_eq!(elsewhere_path, found.worktree);
+
+            let worktree = dir.path().to_string_lossy().to_string();
+            let found = find(&[("GIT_DIR", &git_dir), ("GIT_WORK_TREE", &worktree)]).unwrap();
+            assert_eq!(
+                dir.path().canonicalize().map_err(ReadObjectErrorType::IO)?,
+                found.worktree
+            );
+            assert_eq!(ObjectKind::Blob, found.read_object(blob)?.get_data().0);
+
+            // A GIT_DIR which isn't a repository is an error, not a panic
+            let not_a_repo = elsewhere.path().to_string_lossy().to_string();
+            assert!(Repository::repo_find_from(worktree, Some(false), |name| {
+                (name == "GIT_DIR").then(|| not_a_repo.clone())
+            })
+            .is_err());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_repo_clone_copies_objects_and_refs() -> Result<(), ReadObjectErrorType> {
+            let (source_dir, source) = create_repo();
+            let pack_dir = source_dir.path().join(".git/objects/pack");
+            create_dir_all(&pack_dir).map_err(ReadObjectErrorType::IO)?;
+            for ext in ["pack", "idx"] {
+                let name = format!("pack-b94941be983091701f3c5aa2af12ca1fdf7e9ac2.{}", ext);
+                fs::copy(
+                    Path::new(env!("CARGO_MANIFEST_DIR"))
+                        .join("tests/fixtures")
+                        .join(&name),
+                    pack_dir.join(name),
+                )
+                .map_err(ReadObjectErrorType::IO)?;
+            }
+
+            let hello = write_blob(&source, b"Hello, world!\n");
+            let readme = write_blob(&source, b"Read me\n");
+            let docs = write_tree(
+                &source,
+                vec![Leaf("100644".to_string(), "README".to_string(), readme)],
+            );
+            let tree = write_tree(
+                &source,
+                vec![
+                    Leaf("40000".to_string(), "docs".to_string(), docs),
+                    Leaf("100644".to_string(), "hello.txt".to_string(), hello),
+                ],
+            );
+            let commit = write_commit_with_tree(&source, &tree, &[], "Initial commit\n");
+            source.ref_write("refs/heads/master", &commit)?;
+            source.ref_write("refs/heads/topic", &commit)?;
+            let tag = write_tag(&source, &commit, "v1");
+
+            let dest_dir = tempfile::tempdir().unwrap();
+            let dest = dest_dir.path().join("clone");
+            let repo = Repository::repo_clone(&source, &dest)?;
+
+            assert_eq!(
+                "Hello, world!\n",
+                fs::read_to_string(dest.join("hello.txt")).map_err(ReadObjectErrorType::IO)?
+            );
+            assert_eq!(
+                "Read me\n",
+                fs::read_to_string(dest.join("docs/README")).map_err(ReadObjectErrorType::IO)?
+            );
+            assert_eq!(Some("master".to_string()), repo.current_branch()?);
+            assert_eq!(Some(commit.clone()), repo.ref_resolve("HEAD")?);
+            assert_eq!(
+                "refs/remotes/origin/master",
+                repo.symbolic_ref("refs/remotes/origin/HEAD")?
+            );
+            assert_eq!(
+                vec!["refs/remotes/origin/master", "refs/remotes/origin/topic"],
+                repo.ref_list("refs/remotes/origin")?
+                    .into_keys()
+                    .filter(|name| name != "refs/remotes/origin/HEAD")
+                    .collect::<Vec<String>>()
+            );
+            assert_eq!(Some(tag), repo.ref_resolve("refs/tags/v1")?);
+
+            // The index matches what was checked out
+            assert_eq!(2, repo.index()?.entries.len());
+            assert_eq!(vec![] as Vec<FileStatus>, repo.status()?);
+
+            // Packed objects come across as well as loose ones
+            let GitObjectData(object_type, _) = repo
+                .read_object("af5626b4a114abcb82d63db7c8082c3c4756e51b".to_string())?
+                .get_data();
+            assert_eq!(ObjectKind::Blob, object_type);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_repo_clone_copies_packed_refs() -> Result<(), ReadObjectErrorType> {
+            let (_source_dir, source) = create_repo();
+            let commit = write_commit(&source, &[], "Initial commit\n");
+            let tag = write_tag(&source, &commit, "v1");
+            fs::write(
+                source.repo_path(&["packed-refs"]),
+                format!(
+                    "# pack-refs with: peeled fully-peeled sorted\n\
+                     {} refs/heads/master\n{} refs/heads/topic\n{} refs/tags/v1\n^{}\n",
+                    commit, commit, tag, commit
+                ),
+            )
+            .map_err(ReadObjectErrorType::IO)?;
+            fs::remove_file(source.ref_path("refs/tags/v1")).map_err(ReadObjectErrorType::IO)?;
+
+            let dest_dir = tempfile::tempdir().unwrap();
+            let repo = Repository::repo_clone(&source, &dest_dir.path().join("clone"))?;
+
+            assert_eq!(Some("master".to_string()), repo.current_branch()?);
+            assert_eq!(Some(commit.clone()), repo.ref_resolve("HEAD")?);
+            assert_eq!(Some(commit), repo.ref_resolve("refs/remotes/origin/topic")?);
+            assert_eq!(Some(tag), repo.ref_resolve("refs/tags/v1")?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_for_each_ref_formats_matching_refs() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Initial commit\n");
+            repo.ref_write("refs/heads/master", &commit)?;
+            repo.ref_write("refs/heads/topic/one", &commit)?;
+            let tag = write_tag(&repo, &commit, "v1");
+
+            let mut out = Vec::new();
+            repo.for_each_ref(
+                Some("refs/heads/*"),
+                "%(refname) %(objecttype) %(objectname)",
+                &mut out,
+            )?;
+            assert_eq!(
+                format!("refs/heads/master commit {}\n", commit),
+                String::from_utf8(out).map_err(ReadObjectErrorType::FromUtf8Error)?
+            );
+
+            let mut out = Vec::new();
+            repo.for_each_ref(Some("refs/tags"), "%(objecttype) %(objectname)", &mut out)?;
+            assert_eq!(
+                format!("tag {}\n", tag),
+                String::from_utf8(out).map_err(ReadObjectErrorType::FromUtf8Error)?
+            );
+
+            assert!(matches!(
+                repo.for_each_ref(None, "%(upstream)", &mut Vec::new()),
+                Err(ReadObjectErrorType::InvalidFormatError(_))
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_refs_read_from_packed_refs() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let first = write_commit(&repo, &[], "Initial commit\n");
+            let second = write_commit(&repo, &[&first], "Second commit\n");
+            let tag = write_tag(&repo, &first, "v1.0");
+            fs::write(
+                repo.repo_path(&["packed-refs"]),
+                format!(
+                    "# pack-refs with: peeled fully-peeled sorted\n\
+                     {} refs/heads/master\n{} refs/heads/topic\n{} refs/tags/v1.0\n^{}\n",
+                    first, first, tag, first
+                ),
+            )
+            .map_err(ReadObjectErrorType::IO)?;
+            // A loose ref is newer than the packed one it shadows
+            repo.ref_write("refs/heads/topic", &second)?;
+
+            assert_eq!(Some(first.clone()), repo.ref_resolve("HEAD")?);
+            assert_eq!(
+                Some(Head::Detached(tag.clone())),
+                repo.ref_read("refs/tags/v1.0")?
+            );
+            assert_eq!(
+                vec![
+                    ("refs/heads/master".to_string(), first.clone()),
+                    ("refs/heads/topic".to_string(), second.clone()),
+                ],
+                repo.ref_list("refs/heads")?.into_iter().collect::<Vec<_>>()
+            );
+
+            let mut out = Vec::new();
+            repo.for_each_ref(None, "%(refname) %(objecttype)", &mut out)?;
+            assert_eq!(
+                "refs/heads/master commit\nrefs/heads/topic commit\nrefs/tags/v1.0 tag\n",
+                String::from_utf8(out).map_err(ReadObjectErrorType::FromUtf8Error)?
+            );
+
+            assert!(matches!(
+                repo.branch_create("master", &second, false),
+                Err(ReadObjectErrorType::BranchExistsError(_))
+            ));
+            repo.update_head(&Head::Symbolic("refs/heads/topic".to_string()))?;
+            repo.branch_delete("master")?;
+            assert_eq!(None, repo.ref_resolve("refs/heads/master")?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_ls_tree_lists_named_paths() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let blob = write_blob(&repo, b"fn main() {}\n");
+            let leaf = |mode: &str, path: &str, sha: &str| {
+                Leaf(mode.to_string(), path.to_string(), sha.to_string())
+            };
+            let bin = write_tree(&repo, vec![leaf("100644", "main.rs", &blob)]);
+            let src = write_tree(
+                &repo,
+                vec![leaf("40000", "bin", &bin), leaf("100644", "lib.rs", &blob)],
+            );
+            let root = write_tree(
+                &repo,
+                vec![leaf("100644", "README", &blob), leaf("40000", "src", &src)],
+            );
+            let paths = |paths: &[&str]| -> Result<Vec<String>, ReadObjectErrorType> {
+                let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+                return Ok(repo
+                    .ls_tree(&root, &paths)?
+                    .iter()
+                    .map(|leaf| leaf.path().to_string())
+                    .collect());
+            };
+
+            assert_eq!(vec!["README", "src"], paths(&[])?);
+            // A directory is listed itself, unless asked for its contents
+            assert_eq!(vec!["src"], paths(&["src"])?);
+            assert_eq!(vec!["src/bin", "src/lib.rs"], paths(&["src/"])?);
+            assert_eq!(vec!["src/bin/main.rs"], paths(&["src/bin/main.rs"])?);
+            assert_eq!(
+                vec!["README", "src/lib.rs"],
+                paths(&["src/lib.rs", "README"])?
+            );
+            assert!(paths(&["missing"])?.is_empty());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_blame_attributes_lines_to_commits() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = |parents: &[&str], poem: &str, other: &str| {
+                let tree = write_tree(
+                    &repo,
+                    vec![
+                        Leaf(
+                            "100644".to_string(),
+                            "other".to_string(),
+                            write_blob(&repo, other.as_bytes()),
+                        ),
+                        Leaf(
+                            "100644".to_string(),
+                            "poem".to_string(),
+                            write_blob(&repo, poem.as_bytes()),
+                        ),
+                    ],
+                );
+                return write_commit_with_tree(&repo, &tree, parents, "Commit\n");
+            };
+            let first = commit(&[], "one\ntwo\nthree\n", "a");
+            let second = commit(&[&first], "one\n2\nthree\n", "a");
+            // Commits which leave the file alone are passed over
+            let unrelated = commit(&[&second], "one\n2\nthree\n", "b");
+            let third = commit(&[&unrelated], "one\n2\nthree\nfour\n", "b");
+
+            let blame = repo.blame(&third, "poem")?;
+
+            assert_eq!(
+                vec![
+                    (first.as_str(), "one"),
+                    (second.as_str(), "2"),
+                    (first.as_str(), "three"),
+                    (third.as_str(), "four"),
+                ],
+                blame
+                    .iter()
+                    .map(|line| (line.commit.as_str(), line.content.as_str()))
+                    .collect::<Vec<(&str, &str)>>()
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_log_medium_marks_merges() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit_at(&repo, &[], "Root commit\n", 1_600_000_000);
+            let topic = write_commit_at(&repo, &[&root], "Topic commit\n", 1_600_000_100);
+            let merge = write_commit_at(
+                &repo,
+                &[&root, &topic],
+                "Merge branch 'topic'\n",
+                1_600_000_200,
+            );
+
+            let mut out = Vec::new();
+            repo.log_medium(
+                std::slice::from_ref(&merge),
+                &[],
+                None,
+                DateFormat::Raw,
+                false,
+                &mut out,
+            )?;
+            let log = String::from_utf8(out).map_err(ReadObjectErrorType::FromUtf8Error)?;
+
+            assert!(
+                log.starts_with(&format!(
+                    "commit {}\nMerge: {} {}\nAuthor: A U Thor <author@example.com>\n\
+                     Date:   1600000200 +0000\n\n    Merge branch 'topic'\n\ncommit {}\n",
+                    merge,
+                    &root[0..7],
+                    &topic[0..7],
+                    topic
+                )),
+                "{}",
+                log
+            );
+            assert_eq!(1, log.matches("Merge: ").count(), "{}", log);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_log_json_describes_each_commit() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit_at(&repo, &[], "Root commit\n", 1_600_000_000);
+            let child = write_commit_at(&repo, &[&root], "Child commit\n\nBody\n", 1_600_000_100);
+
+            let mut out = Vec::new();
+            repo.log_json(std::slice::from_ref(&child), &[], &mut out)?;
+            let log: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+            let entries = log.as_array().unwrap();
+            assert_eq!(2, entries.len());
+            assert_eq!(child, entries[0]["sha"]);
+            assert_eq!(serde_json::json!([root]), entries[0]["parents"]);
+            assert_eq!("A U Thor <author@example.com>", entries[0]["author"]);
+            assert_eq!(
+                "C O Mitter <committer@example.com>",
+                entries[0]["committer"]
+            );
+            assert_eq!(1_600_000_100, entries[0]["date"]);
+            assert_eq!("Child commit\n\nBody\n", entries[0]["message"]);
+            assert_eq!(root, entries[1]["sha"]);
+            assert_eq!(serde_json::json!([]), entries[1]["parents"]);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_diff_trees_reports_rename_as_delete_and_add() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let hello = write_blob(&repo, b"Hello, world!\n");
+            let readme = write_blob(&repo, b"Read me\n");
+            let readme_edited = write_blob(&repo, b"Read me, please\n");
+            let leaf = |path: &str, sha: &str| {
+                Leaf("100644".to_string(), path.to_string(), sha.to_string())
+            };
+            let old = write_tree(
+                &repo,
+                vec![leaf("README", &readme), leaf("hello.txt", &hello)],
+            );
+            let new = write_tree(
+                &repo,
+                vec![leaf("README", &readme_edited), leaf("greeting.txt", &hello)],
+            );
+
+            assert_eq!(
+                vec![
+                    TreeChange::Modified("README".to_string()),
+                    TreeChange::Added("greeting.txt".to_string()),
+                    TreeChange::Deleted("hello.txt".to_string()),
+                ],
+                repo.diff_trees(&old, &new)?
+            );
+            assert!(repo.diff_trees(&old, &old)?.is_empty());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_log_lists_only_commits_touching_path() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let add = commit_files(&repo, &[], &[("README", "one")], "Add README\n");
+            let other = commit_files(
+                &repo,
+                &[&add],
+                &[("README", "one"), ("src/lib.rs", "lib")],
+                "Add lib.rs\n",
+            );
+            let change = commit_files(
+                &repo,
+                &[&other],
+                &[("README", "two"), ("src/lib.rs", "lib")],
+                "Change README\n",
+            );
+            let tip = commit_files(
+                &repo,
+                &[&change],
+                &[("README", "two"), ("src/lib.rs", "lib 2")],
+                "Change lib.rs\n",
+            );
+
+            let logged = |paths: &[&str]| -> Result<Vec<String>, ReadObjectErrorType> {
+                let paths: Vec<String> = paths.iter().map(|path| path.to_string()).collect();
+                let mut out = Vec::new();
+                repo.log_json(std::slice::from_ref(&tip), &paths, &mut out)?;
+                let entries: Vec<serde_json::Value> = serde_json::from_slice(&out).unwrap();
+                return Ok(entries
+                    .iter()
+                    .map(|entry| entry["sha"].as_str().unwrap().to_string())
+                    .collect());
+            };
+
+            assert_eq!(vec![change.clone(), add.clone()], logged(&["README"])?);
+            assert_eq!(vec![tip.clone(), other.clone()], logged(&["src"])?);
+            assert_eq!(
+                vec![tip.clone(), change, other, add],
+                logged(&["src/lib.rs", "README"])?
+            );
+            assert!(logged(&["missing"])?.is_empty());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_log_all_walks_every_ref_once() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit(&repo, &[], "Root\n");
+            let base = write_commit(&repo, &[&root], "Base\n");
+            let left = write_commit(&repo, &[&base], "Left\n");
+            let right = write_commit(&repo, &[&base], "Right\n");
+            repo.ref_write("refs/heads/master", &left)?;
+            repo.ref_write("refs/heads/topic", &right)?;
+            write_tag(&repo, &base, "v1.0");
+
+            // The tag is peeled, and HEAD adds nothing new
+            let tips = repo.ref_tips()?;
+            assert_eq!(vec![left.clone(), right.clone(), base.clone()], tips);
+
+            let mut out = Vec::new();
+            repo.log_json(&tips, &[], &mut out)?;
+            let entries: Vec<serde_json::Value> = serde_json::from_slice(&out).unwrap();
+            let mut logged: Vec<&str> = entries
+                .iter()
+                .map(|entry| entry["sha"].as_str().unwrap())
+                .collect();
+            logged.sort();
+
+            let mut expected = vec![root.as_str(), base.as_str(), left.as_str(), right.as_str()];
+            expected.sort();
+            assert_eq!(expected, logged);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_log_graphviz_escapes_labels() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let commit = write_commit(&repo, &[], "Say \"hello\" to C:\\wyag\r\n");
+
+            let mut out = Vec::new();
+            repo.log_graphviz(std::slice::from_ref(&commit), &[], &mut out)?;
+            let dot = String::from_utf8(out).map_err(ReadObjectErrorType::FromUtf8Error)?;
+
+            assert_eq!(
+                format!(
+                    "digraph wyaglog{{c_{} [label=\"{} Say \\\"hello\\\" to C:\\\\wyag\"];}}",
+                    commit,
+                    &commit[0..7]
+                ),
+                dot
+            );
+            assert_eq!("one\\ntwo", dot_escape("one\r\ntwo"));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_log_graphviz_declares_root_commit() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit(&repo, &[], "Root commit\n\nWith a body\n");
+            let child = write_commit(&repo, &[&root], "Child commit\n");
+
+            let mut out = Vec::new();
+            repo.log_graphviz(std::slice::from_ref(&child), &[], &mut out)?;
+            let dot = String::from_utf8(out).map_err(ReadObjectErrorType::FromUtf8Error)?;
+
+            let body = dot
+                .strip_prefix("digraph wyaglog{")
+                .and_then(|dot| dot.strip_suffix('}'))
+                .expect("Not a digraph");
+            let statements: Vec<&str> = body.split_terminator(';').collect();
+            assert!(statements
+                .contains(&format!("c_{} [label=\"{} Root commit\"]", root, &root[0..7]).as_str()));
+            assert!(statements.contains(
+                &format!("c_{} [label=\"{} Child commit\"]", child, &child[0..7]).as_str()
+            ));
+            assert!(statements.contains(&format!("c_{} -> c_{}", child, root).as_str()));
+            assert_eq!(3, statements.len(), "{:?}", statements);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_compression_level_changes_size_but_not_sha() {
+            let content = b"All work and no play makes Jack a dull boy.\n".repeat(100);
+            let write_at = |level: &str| {
+                let (_dir, mut repo) = create_repo();
+                repo.config.setstr("core", "compression", Some(level));
+                let sha = write_blob(&repo, &content);
+                let path = repo.loose_object_path(&sha);
+
+                return (sha, path.metadata().unwrap().len());
+            };
+
+            let (stored_sha, stored_size) = write_at("0");
+            let (compressed_sha, compressed_size) = write_at("9");
+
+            assert_eq!(stored_sha, compressed_sha);
+            assert!(compressed_size < stored_size);
+        }
+
+        #[test]
+        fn test_compression_prefers_loose_compression_and_rejects_bad_levels() {
+            let (_dir, mut repo) = create_repo();
+            assert_eq!(
+                Compression::fast().level(),
+                repo.compression().unwrap().level()
+            );
+
+            repo.config.setstr("core", "compression", Some("9"));
+            assert_eq!(9, repo.compression().unwrap().level());
+            repo.config.setstr("core", "looseCompression", Some("0"));
+            assert_eq!(0, repo.compression().unwrap().level());
+            repo.config.setstr("core", "looseCompression", Some("-1"));
+            assert_eq!(
+                Compression::default().level(),
+                repo.compression().unwrap().level()
+            );
+
+            for bad in ["10", "-2", "fast"] {
+                repo.config.setstr("core", "looseCompression", Some(bad));
+                assert!(matches!(
+                    repo.compression(),
+                    Err(ReadObjectErrorType::InvalidConfigValueError(key, value))
+                        if key == "core.loosecompression" && value == bad
+                ));
+            }
+
+            // Nothing is written with a bad level
+            let blob = GitObject::new(
+                Some(repo.clone()),
+                GitObjectData(ObjectKind::Blob, b"Hello, world!\n".to_vec()),
+            );
+            assert!(GitObject::write_object(blob, None).is_err());
+            assert_eq!(0, repo.count_objects().unwrap().count);
+        }
+
+        #[test]
+        fn test_write_object_leaves_existing_object_alone() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let sha = write_blob(&repo, b"Hello, world!\n");
+
+            // Were the object rewritten, this would be replaced
+            let path = repo.loose_object_path(&sha);
+            let fanout = path.parent().unwrap();
+            fs::write(&path, b"sentinel").map_err(ReadObjectErrorType::IO)?;
+
+            assert_eq!(sha, write_blob(&repo, b"Hello, world!\n"));
+            assert_eq!(
+                b"sentinel".to_vec(),
+                fs::read(&path).map_err(ReadObjectErrorType::IO)?
+            );
+            // Nor is a temporary file left behind
+            assert_eq!(
+                1,
+                fanout.read_dir().map_err(ReadObjectErrorType::IO)?.count()
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_write_object_failure_is_an_error_and_cleans_up() -> Result<(), ReadObjectErrorType>
+        {
+            let (_dir, repo) = create_repo();
+            let content = b"Hello, world!\n".to_vec();
+            let sha = object_sha(ObjectKind::Blob, &content);
+
+            // A directory where the object should go can't be renamed over
+            let path = repo.loose_object_path(&sha);
+            create_dir_all(&path).map_err(ReadObjectErrorType::IO)?;
+            fs::write(path.join("in-the-way"), b"").map_err(ReadObjectErrorType::IO)?;
+
+            let blob = GitObject::new(Some(repo.clone()), GitObjectData(ObjectKind::Blob, content));
+            assert!(matches!(
+                GitObject::write_object(blob, None),
+                Err(ReadObjectErrorType::IO(_))
+            ));
+            assert_eq!(
+                1,
+                path.parent()
+                    .unwrap()
+                    .read_dir()
+                    .map_err(ReadObjectErrorType::IO)?
+                    .count()
+            );
+
+            // Nor does staging a file with that content panic
+            fs::write(repo.worktree.join("hello.txt"), b"Hello, world!\n")
+                .map_err(ReadObjectErrorType::IO)?;
+            assert!(repo.stage_file(Path::new("hello.txt")).is_err());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_count_objects_counts_loose_objects() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            assert_eq!(ObjectCounts::default(), repo.count_objects()?);
+
+            let shas = [
+                write_blob(&repo, b"one\n"),
+                write_blob(&repo, b"two\n"),
+                write_blob(&repo, b"three\n"),
+            ];
+            // Writing an existing object again doesn't add a new one
+            write_blob(&repo, b"one\n");
+
+            let expected_size: u64 = shas
+                .iter()
+                .map(|sha| disk_usage(&repo.loose_object_path(sha).metadata().unwrap()))
+                .sum();
+
+            let counts = repo.count_objects()?;
+            assert_eq!(3, counts.count);
+            assert_eq!(expected_size, counts.size);
+            assert_eq!(0, counts.packs);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_count_objects_sizes_packs_with_their_indexes() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let pack_dir = dir.path().join(".git/objects/pack");
+            create_dir_all(&pack_dir).map_err(ReadObjectErrorType::IO)?;
+            for ext in ["pack", "idx"] {
+                let name = format!("pack-b94941be983091701f3c5aa2af12ca1fdf7e9ac2.{}", ext);
+                fs::copy(
+                    Path::new(env!("CARGO_MANIFEST_DIR"))
+                        .join("tests/fixtures")
+                        .join(&name),
+                    pack_dir.join(&name),
+                )
+                .map_err(ReadObjectErrorType::IO)?;
+            }
+
+            let counts = repo.count_objects()?;
+            assert_eq!(1, counts.packs);
+            assert_eq!(7, counts.in_pack);
+            assert_eq!(738 + 1268, counts.size_pack);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_read_object_falls_back_to_packs() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let pack_dir = dir.path().join(".git/objects/pack");
+            create_dir_all(&pack_dir).map_err(ReadObjectErrorType::IO)?;
+            let name = "pack-b94941be983091701f3c5aa2af12ca1fdf7e9ac2.pack";
+            fs::copy(
+                Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("tests/fixtures")
+                    .join(name),
+                pack_dir.join(name),
+            )
+            .map_err(ReadObjectErrorType::IO)?;
+
+            let blob = repo.read_object("af5626b4a114abcb82d63db7c8082c3c4756e51b".to_string())?;
+            let GitObjectData(object_type, data) = blob.get_data();
+
+            assert_eq!(ObjectKind::Blob, object_type);
+            assert_eq!(b"Hello, world!\n".to_vec(), data);
+            assert_eq!(7, repo.count_objects()?.in_pack);
+            assert!(matches!(
+                repo.read_object("0000000000000000000000000000000000000000".to_string()),
+                Err(ReadObjectErrorType::ObjectNotFoundError(_))
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_packs_are_opened_once_and_looked_for_again_on_a_miss(
+        ) -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let pack_dir = dir.path().join(".git/objects/pack");
+            create_dir_all(&pack_dir).map_err(ReadObjectErrorType::IO)?;
+            for ext in ["pack", "idx"] {
+                let name = format!("pack-b94941be983091701f3c5aa2af12ca1fdf7e9ac2.{}", ext);
+                fs::copy(
+                    Path::new(env!("CARGO_MANIFEST_DIR"))
+                        .join("tests/fixtures")
+                        .join(&name),
+                    pack_dir.join(name),
+                )
+                .map_err(ReadObjectErrorType::IO)?;
+            }
+            repo.read_object("af5626b4a114abcb82d63db7c8082c3c4756e51b".to_string())?;
+
+            // Every clone of the repository shares the packs opened
+            let clone = repo.clone();
+            let opened = repo.packs_with_alternates(false)?;
+            assert_eq!(1, opened.len());
+            assert!(Arc::ptr_eq(
+                &opened[0],
+                &clone.packs_with_alternates(false)?[0]
+            ));
+
+            // A pack written afterwards is found once nothing else has it
+            let blob = write_blob(&repo, b"Packed later\n");
+            repo.pack_objects(
+                std::slice::from_ref(&blob),
+                &pack_dir.join("pack").to_string_lossy(),
+            )?;
+            fs::remove_file(repo.loose_object_path(&blob)).map_err(ReadObjectErrorType::IO)?;
+            assert_eq!(1, clone.packs_with_alternates(false)?.len());
+            let GitObjectData(_, data) = clone.read_object(blob)?.get_data();
+            assert_eq!(b"Packed later\n".to_vec(), data);
+            assert_eq!(2, repo.packs_with_alternates(false)?.len());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_has_object_checks_loose_and_packed_objects() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let pack_dir = dir.path().join(".git/objects/pack");
+            create_dir_all(&pack_dir).map_err(ReadObjectErrorType::IO)?;
+            for ext in ["pack", "idx"] {
+                let name = format!("pack-b94941be983091701f3c5aa2af12ca1fdf7e9ac2.{}", ext);
+                fs::copy(
+                    Path::new(env!("CARGO_MANIFEST_DIR"))
+                        .join("tests/fixtures")
+                        .join(&name),
+                    pack_dir.join(&name),
+                )
+                .map_err(ReadObjectErrorType::IO)?;
+            }
+            let loose = write_blob(&repo, b"loose");
+
+            assert!(repo.has_object(&loose));
+            assert!(repo.has_object("af5626b4a114abcb82d63db7c8082c3c4756e51b"));
+            assert!(!repo.has_object(&"0".repeat(40)));
+            assert!(!repo.has_object("af5626b4"));
+
+            // An object already packed isn't written again as a loose one
+            let packed = write_blob(&repo, b"Hello, world!\n");
+            assert_eq!("af5626b4a114abcb82d63db7c8082c3c4756e51b", packed);
+            assert!(!repo.loose_object_path(&packed).parent().unwrap().exists());
+
+            // The index was read when the pack was opened, and isn't again
+            fs::write(
+                pack_dir.join("pack-b94941be983091701f3c5aa2af12ca1fdf7e9ac2.idx"),
+                b"garbage",
+            )
+            .map_err(ReadObjectErrorType::IO)?;
+            assert!(repo.has_object("7f68d45bf7b349c1de891e5c21970a3affc20bbd"));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_objects_borrowed_from_alternates() -> Result<(), ReadObjectErrorType> {
+            let (shared_dir, shared) = create_repo();
+            let pack_dir = shared_dir.path().join(".git/objects/pack");
+            create_dir_all(&pack_dir).map_err(ReadObjectErrorType::IO)?;
+            for ext in ["pack", "idx"] {
+                let name = format!("pack-b94941be983091701f3c5aa2af12ca1fdf7e9ac2.{}", ext);
+                fs::copy(
+                    Path::new(env!("CARGO_MANIFEST_DIR"))
+                        .join("tests/fixtures")
+                        .join(&name),
+                    pack_dir.join(&name),
+                )
+                .map_err(ReadObjectErrorType::IO)?;
+            }
+            let loose = write_blob(&shared, b"borrowed");
+            let packed = "af5626b4a114abcb82d63db7c8082c3c4756e51b";
+
+            let (_dir, repo) = create_repo();
+            assert!(!repo.has_object(&loose));
+            assert!(!repo.has_object(packed));
+
+            let alternates = repo.objects_dir().join("info/alternates");
+            create_dir_all(alternates.parent().unwrap()).map_err(ReadObjectErrorType::IO)?;
+            fs::write(
+                &alternates,
+                format!("# shared objects\n{}\n", shared.objects_dir().display()),
+            )
+            .map_err(ReadObjectErrorType::IO)?;
+
+            assert!(repo.has_object(&loose));
+            assert!(repo.has_object(packed));
+            match repo.read_object_typed(loose.clone())? {
+                ParsedObject::Blob(blob) => assert_eq!(b"borrowed", blob.bytes()),
+                _ => panic!("{} should be a blob", loose),
+            }
+            assert_eq!((ObjectKind::Blob, 14), repo.stat_object(packed)?);
+            assert_eq!(
+                loose,
+                repo.object_find(loose[0..7].to_string(), None, None)?
+            );
+
+            // Borrowed objects aren't copied into the repository
+            assert_eq!(loose, write_blob(&repo, b"borrowed"));
+            assert!(!repo.loose_object_path(&loose).parent().unwrap().exists());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_iter_objects_yields_loose_and_packed_objects() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let pack_dir = dir.path().join(".git/objects/pack");
+            create_dir_all(&pack_dir).map_err(ReadObjectErrorType::IO)?;
+            for extension in ["pack", "idx"] {
+                let name = format!(
+                    "pack-b94941be983091701f3c5aa2af12ca1fdf7e9ac2.{}",
+                    extension
+                );
+                fs::copy(
+                    Path::new(env!("CARGO_MANIFEST_DIR"))
+                        .join("tests/fixtures")
+                        .join(&name),
+                    pack_dir.join(&name),
+                )
+                .map_err(ReadObjectErrorType::IO)?;
+            }
+            let loose = write_blob(&repo, b"Only loose\n");
+
+            let objects = repo.iter_objects().collect::<Result<Vec<_>, _>>()?;
+
+            assert_eq!(8, objects.len());
+            assert_eq!((loose, ObjectKind::Blob), objects[0]);
+            let kinds: BTreeMap<String, ObjectKind> = objects.into_iter().collect();
+            // The delta takes the kind of its base
+            assert_eq!(
+                Some(&ObjectKind::Blob),
+                kinds.get("7f68d45bf7b349c1de891e5c21970a3affc20bbd")
+            );
+            let count = |kind| kinds.values().filter(|k| **k == kind).count();
+            assert_eq!(2, count(ObjectKind::Commit));
+            assert_eq!(2, count(ObjectKind::Tree));
+            assert_eq!(4, count(ObjectKind::Blob));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_read_object_rejects_object_longer_than_declared() -> Result<(), ReadObjectErrorType>
+        {
+            let (_dir, repo) = create_repo();
+            let sha = "0123456789abcdef0123456789abcdef01234567";
+            write_raw_object(
+                &repo,
+                sha,
+                &[b"blob 3\x00".as_slice(), &[b'a'; 1 << 20]].concat(),
+            );
+
+            assert!(matches!(
+                repo.read_object(sha.to_string()),
+                Err(ReadObjectErrorType::MalformedObjectError(_, _))
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_read_object_reports_malformed_commits() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let sha = "0123456789abcdef0123456789abcdef01234567";
+
+            // The last header has no newline, let alone a blank line after
+            let data = b"tree 4f0ae14e719a707c91ad1885e38bd18e7d214629";
+            write_raw_object(
+                &repo,
+                sha,
+                &[format!("commit {}\x00", data.len()).as_bytes(), data].concat(),
+            );
+
+            assert!(matches!(
+                repo.read_object(sha.to_string()),
+                Err(ReadObjectErrorType::InvalidKvlmError(
+                    _,
+                    ObjectKind::Commit,
+                    KvlmParseError::Unterminated(0)
+                ))
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_read_object_reports_unknown_object_type() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let sha = "0123456789abcdef0123456789abcdef01234567";
+            write_raw_object(&repo, sha, b"foobar 3\x00abc");
+
+            let unknown = |result: Result<_, ReadObjectErrorType>| {
+                matches!(
+                    result,
+                    Err(ReadObjectErrorType::UnknownObjectKindError(kind)) if kind == "foobar"
+                )
+            };
+            assert!(unknown(repo.read_object(sha.to_string()).map(|_| ())));
+            assert!(unknown(repo.stat_object(sha).map(|_| ())));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_read_object_typed_matches_each_kind() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let blob = write_blob(&repo, b"Hello, world!\n");
+            let tree = write_tree(
+                &repo,
+                vec![Leaf(
+                    "100644".to_string(),
+                    "hello.txt".to_string(),
+                    blob.clone(),
+                )],
+            );
+            let commit = write_commit_with_tree(&repo, &tree, &[], "Initial commit\n");
+            let tag = write_tag(&repo, &commit, "v1.0");
+
+            match repo.read_object_typed(blob)? {
+                ParsedObject::Blob(blob) => assert_eq!(b"Hello, world!\n", blob.bytes()),
+                other => panic!("expected a blob, got a {}", other.kind()),
+            }
+            match repo.read_object_typed(tree)? {
+                ParsedObject::Tree(tree) => assert!(tree.get("hello.txt").is_some()),
+                other => panic!("expected a tree, got a {}", other.kind()),
+            }
+            match repo.read_object_typed(commit.clone())? {
+                ParsedObject::Commit(commit) => assert_eq!("Initial commit\n", commit.message()),
+                other => panic!("expected a commit, got a {}", other.kind()),
+            }
+            match repo.read_object_typed(tag)? {
+                ParsedObject::Tag(tag) => assert_eq!(commit, tag.object()),
+                other => panic!("expected a tag, got a {}", other.kind()),
+            }
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_gc_packs_loose_objects() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let blob = write_blob(&repo, b"Hello, world!\n");
+            let tree = write_tree(
+                &repo,
+                vec![Leaf(
+                    "100644".to_string(),
+                    "hello.txt".to_string(),
+                    blob.clone(),
+                )],
+            );
+            let commit = write_commit_with_tree(&repo, &tree, &[], "Initial commit\n");
+
+            assert_eq!(3, repo.gc()?);
+
+            let counts = repo.count_objects()?;
+            assert_eq!(0, counts.count);
+            assert_eq!(1, counts.packs);
+            assert_eq!(3, counts.in_pack);
+            assert_eq!(ObjectKind::Commit, repo.read_object(commit)?.get_data().0);
+            assert_eq!(ObjectKind::Tree, repo.read_object(tree)?.get_data().0);
+            assert_eq!(
+                b"Hello, world!\n".to_vec(),
+                repo.read_object(blob)?.get_data().1
+            );
+
+            // Running again has nothing left to do
+            assert_eq!(0, repo.gc()?);
+            assert_eq!(1, repo.count_objects()?.packs);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_gc_packs_objects_as_stored() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let tree = write_tree(&repo, vec![]);
+            // A header out of the usual order isn't kept in place when
+            // the commit is parsed and written out again
+            let raw = format!(
+                "tree {}\nauthor A U Thor <author@example.com> 0 +0000\n\
+                 committer A U Thor <author@example.com> 0 +0000\n\
+                 x-custom value\nencoding UTF-8\n\nMessage\n",
+                tree
+            );
+            let commit = object_sha(ObjectKind::Commit, raw.as_bytes());
+            write_raw_object(
+                &repo,
+                &commit,
+                &[
+                    format!("commit {}\x00", raw.len()).as_bytes(),
+                    raw.as_bytes(),
+                ]
+                .concat(),
+            );
+
+            assert_eq!(2, repo.gc()?);
+            assert_eq!(
+                (ObjectKind::Commit, raw.into_bytes()),
+                repo.read_raw_object(&commit)?
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_gc_removes_a_pack_that_cannot_be_read_back() {
+            let (_dir, repo) = create_repo();
+            let blob = write_blob(&repo, b"Hello, world!\n");
+            // Stored under a SHA its content doesn't hash to
+            let corrupt = "0123456789abcdef0123456789abcdef01234567";
+            write_raw_object(&repo, corrupt, b"blob 6\x00Lorem\n");
+
+            assert!(matches!(
+                repo.gc(),
+                Err(ReadObjectErrorType::InvalidPackError(_))
+            ));
+            assert_eq!(0, repo.count_objects().unwrap().packs);
+            let pack_dir = repo.objects_dir().join("pack");
+            assert_eq!(0, fs::read_dir(pack_dir).unwrap().count());
+            assert!(repo.loose_object_path(&blob).is_file());
+            assert!(repo.loose_object_path(corrupt).is_file());
+        }
+
+        #[test]
+        fn test_reachable_objects_from_commits_and_tags() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let hello = write_blob(&repo, b"Hello, world!\n");
+            let lorem = write_blob(&repo, b"Lorem ipsum\n");
+            let subtree = write_tree(
+                &repo,
+                vec![Leaf(
+                    "100644".to_string(),
+                    "lorem.txt".to_string(),
+                    lorem.clone(),
+                )],
+            );
+            let root_tree = write_tree(
+                &repo,
+                vec![
+                    Leaf("100644".to_string(), "hello.txt".to_string(), hello.clone()),
+                    Leaf("40000".to_string(), "docs".to_string(), subtree.clone()),
+                ],
+            );
+            let first = write_commit_with_tree(&repo, &subtree, &[], "First\n");
+            let second = write_commit_with_tree(&repo, &root_tree, &[&first], "Second\n");
+            let tag = write_tag(&repo, &second, "v1.0");
+            write_blob(&repo, b"Unreachable\n");
+
+            let expected: HashSet<String> =
+                HashSet::from([lorem.clone(), subtree.clone(), first.clone()]);
+            assert_eq!(
+                expected,
+                repo.reachable_objects(std::slice::from_ref(&first))?
+            );
+
+            let expected: HashSet<String> = HashSet::from([
+                hello,
+                lorem,
+                subtree,
+                root_tree,
+                first,
+                second.clone(),
+                tag.clone(),
+            ]);
+            assert_eq!(expected, repo.reachable_objects(&[tag, second])?);
+            assert!(repo.reachable_objects(&[])?.is_empty());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_prune_removes_only_unreachable_objects() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let blob = write_blob(&repo, b"Hello, world!\n");
+            let tree = write_tree(
+                &repo,
+                vec![Leaf(
+                    "100644".to_string(),
+                    "hello.txt".to_string(),
+                    blob.clone(),
+                )],
+            );
+            let commit = write_commit_with_tree(&repo, &tree, &[], "Initial commit\n");
+            let tagged = write_commit(&repo, &[], "Only tagged\n");
+            let tag = write_tag(&repo, &tagged, "v1.0");
+            set_head(&repo, &commit);
+            repo.ref_write("refs/tags/v1.0", &tag)?;
+            let dangling = write_blob(&repo, b"Nothing refers to me\n");
+
+            assert_eq!(vec![dangling.clone()], repo.prune(true)?);
+            assert_eq!(7, repo.count_objects()?.count);
+
+            assert_eq!(vec![dangling.clone()], repo.prune(false)?);
+            assert_eq!(6, repo.count_objects()?.count);
+            assert!(matches!(
+                repo.read_object(dangling),
+                Err(ReadObjectErrorType::ObjectNotFoundError(_))
+            ));
+            assert!(repo.prune(false)?.is_empty());
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_prune_keeps_packed_refs_staged_blobs_and_reflogs() -> Result<(), ReadObjectErrorType>
+        {
+            let (_dir, repo) = create_repo();
+            let packed = write_commit(&repo, &[], "Only in packed-refs\n");
+            fs::write(
+                repo.repo_path(&["packed-refs"]),
+                format!("{} refs/heads/master\n", packed),
+            )
+            .map_err(ReadObjectErrorType::IO)?;
+            let staged = write_blob(&repo, b"Staged but never committed\n");
+            repo.write_index(&Index {
+                entries: vec![IndexEntry {
+                    mode: 0o100644,
+                    sha: staged.clone(),
+                    path: "staged.txt".to_string(),
+                    ..Default::default()
+                }],
+            })?;
+            let logged = write_commit(&repo, &[], "Only in the reflog\n");
+            repo.ref_update("HEAD", &logged, "commit: Only in the reflog", false)?;
+            repo.ref_update("HEAD", &packed, "reset: moving to HEAD~", false)?;
+            let dangling = write_blob(&repo, b"Nothing refers to me\n");
+
+            assert_eq!(vec![dangling], repo.prune(false)?);
+            for sha in [packed, staged, logged] {
+                assert!(repo.has_object(&sha));
+            }
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_unpack_objects_writes_loose_objects() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let pack = Pack::open(
+                &Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("tests/fixtures/pack-b94941be983091701f3c5aa2af12ca1fdf7e9ac2.pack"),
+            )?;
+
+            let written = repo.unpack_objects(&pack)?;
+
+            assert_eq!(7, written.len());
+            assert_eq!(7, repo.count_objects()?.count);
+            let GitObjectData(object_type, data) = repo
+                .read_object("7f68d45bf7b349c1de891e5c21970a3affc20bbd".to_string())?
+                .get_data();
+            assert_eq!(ObjectKind::Blob, object_type);
+            assert_eq!(
+                "7f68d45bf7b349c1de891e5c21970a3affc20bbd",
+                object_sha(object_type, &data)
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_pack_objects_round_trips() -> Result<(), ReadObjectErrorType> {
+            let (dir, repo) = create_repo();
+            let blob = write_blob(&repo, b"Hello, world!\n");
+            let big = write_blob(&repo, &[b'x'; 5000]);
+            let tree = write_tree(
+                &repo,
+                vec![Leaf(
+                    "100644".to_string(),
+                    "hello.txt".to_string(),
+                    blob.clone(),
+                )],
+            );
+            let commit = write_commit_with_tree(&repo, &tree, &[], "Initial commit");
+            let shas = vec![commit, tree, blob, big];
+
+            let base = dir.path().join("out").display().to_string();
+            let checksum = repo.pack_objects(&shas, &base)?;
+
+            let pack = Pack::open(Path::new(&format!("{}-{}.pack", base, checksum)))?;
+            pack.verify_checksum()?;
+            assert_eq!(4, pack.object_count());
+            for sha in &shas {
+                let GitObjectData(object_type, data) =
+                    repo.read_object(sha.to_string())?.get_data();
+                assert!(pack.find_offset(sha).is_some());
+                assert_eq!(Some((object_type, data)), pack.find_object(sha)?);
+            }
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_fsck_reports_corrupt_object() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let sha = write_blob(&repo, b"Hello, world!\n");
+            write_raw_object(&repo, &sha, b"blob 14\x00Hello, World!\n");
+
+            let problems = repo.fsck()?;
+
+            assert_eq!(
+                vec![FsckProblem::HashMismatch(
+                    sha,
+                    object_sha(ObjectKind::Blob, b"Hello, World!\n")
+                )],
+                problems
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_verified_read_rejects_renamed_object() -> Result<(), ReadObjectErrorType> {
+            let (_dir, mut repo) = create_repo();
+            let hello = write_blob(&repo, b"Hello, world!\n");
+            let lorem = write_blob(&repo, b"Lorem ipsum\n");
+            fs::rename(
+                repo.loose_object_path(&lorem),
+                repo.loose_object_path(&hello),
+            )
+            .unwrap();
+
+            // Unverified, the object is taken at its name
+            assert!(repo.read_object_typed(hello.clone()).is_ok());
+
+            repo.set_verify_objects(true);
+            assert!(matches!(
+                repo.read_object_typed(hello.clone()),
+                Err(ReadObjectErrorType::HashMismatchError(sha, actual))
+                    if sha == hello && actual == lorem
+            ));
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_fsck_reports_dangling_commit() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let root = write_commit(&repo, &[], "Initial commit");
+            let head = write_commit(&repo, &[&root], "Second commit");
+            set_head(&repo, &head);
+            let dangling = write_commit(&repo, &[&root], "Abandoned commit");
+
+            let problems = repo.fsck()?;
+
+            assert_eq!(
+                vec![FsckProblem::Dangling(ObjectKind::Commit, dangling)],
+                problems
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_fsck_reports_missing_objects() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let missing_blob = object_sha(ObjectKind::Blob, b"Never written\n");
+            let tree = write_tree(
+                &repo,
+                vec![Leaf(
+                    "100644".to_string(),
+                    "gone.txt".to_string(),
+                    missing_blob.clone(),
+                )],
+            );
+            let missing_parent = object_sha(ObjectKind::Commit, b"Never written either\n");
+            let head = write_commit_with_tree(&repo, &tree, &[&missing_parent], "Commit\n");
+            set_head(&repo, &head);
+
+            // Missing objects are reported in order of SHA
+            let mut expected = vec![
+                (missing_blob, ObjectKind::Blob),
+                (missing_parent, ObjectKind::Commit),
+            ];
+            expected.sort();
+            assert_eq!(
+                expected
+                    .into_iter()
+                    .map(|(sha, object_type)| FsckProblem::Missing(object_type, sha))
+                    .collect::<Vec<_>>(),
+                repo.fsck()?
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_fsck_keeps_index_and_reflog_objects_from_dangling(
+        ) -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let staged = write_blob(&repo, b"Staged but never committed\n");
+            repo.write_index(&Index {
+                entries: vec![IndexEntry {
+                    mode: 0o100644,
+                    sha: staged,
+                    path: "staged.txt".to_string(),
+                    ..Default::default()
+                }],
+            })?;
+            let logged = write_commit(&repo, &[], "Only in the reflog\n");
+            let head = write_commit(&repo, &[], "Current\n");
+            repo.ref_update("HEAD", &logged, "commit: Only in the reflog", false)?;
+            repo.ref_update("HEAD", &head, "reset: moving to HEAD~", false)?;
+            let dangling = write_blob(&repo, b"Nothing refers to me\n");
+
+            assert_eq!(
+                vec![FsckProblem::Dangling(ObjectKind::Blob, dangling)],
+                repo.fsck()?
+            );
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_fsck_reports_object_longer_than_declared() -> Result<(), ReadObjectErrorType> {
+            let (_dir, repo) = create_repo();
+            let sha = object_sha(ObjectKind::Blob, b"Hello");
+            write_raw_object(&repo, &sha, b"blob 5\x00Hello, world!\n");
+
+            assert_eq!(vec![FsckProblem::LengthMismatch(sha)], repo.fsck()?);
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_loose_objects_read_from_where_written() -> Result<(), ReadObjectErrorType> {
+            for algo in [HashAlgo::Sha1, HashAlgo::Sha256] {
+                let dir = tempfile::tempdir().unwrap();
+                Repository::repo_create(dir.path(), algo).unwrap();
+                let repo = Repository::new(dir.path(), false);
+
+                let sha = write_blob(&repo, b"Hello, world!\n");
+                let path = repo.loose_object_path(&sha);
+                assert!(path.starts_with(repo.objects_dir()) && path.is_file());
+                assert_eq!(Some(path), repo.find_loose_object(&sha));
+                assert_eq!((ObjectKind::Blob, 14), repo.stat_object(&sha)?);
+            }
+
+            return Ok(());
+        }
+
+        #[test]
+        fn test_sha256_repository_names_objects_with_sha256() -> Result<(), ReadObjectErrorType> {
+            let dir = tempfile::tempdir().unwrap();
+            Repository::repo_create(dir.path(), HashAlgo::Sha256).unwrap();
+            let repo = Repository::new(dir.path(), false);
+            assert_eq!(HashAlgo::Sha256, repo.hash_algo());
+
+            // As given by `git hash-object` in a sha256 repository
+            let blob = write_blob(&repo, b"Hello, world!\n");
+            assert_eq!(
+                "7506cbcf4c572be9e06a1fed35ac5b1df8b5a74d26c07f022648e5d95a9f6f2a",
+                blob
+            );
+            assert!(repo.loose_object_path(&blob).is_file());
+
+            // Trees store the full 32 byte SHA
+            let tree = write_tree(
+                &repo,
+                vec![Leaf(
+                    "100644".to_string(),
+                    "hello.txt".to_string(),
+                    blob.clone(),
+                )],
+            );
+            assert_eq!(64, tree.len());
+            let tree = repo.read_object(tree)?;
+            let tree = tree.as_any().downcast_ref::<Tree>().unwrap();
+            assert_eq!(blob, tree.get("hello.txt").unwrap().sha());
 
             return Ok(());
         }